@@ -0,0 +1,83 @@
+//! Command-line arguments of the running image, so that every tool parses them the same way.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use uefi::prelude::*;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::shell_params::ShellParameters;
+use uefi::Result;
+
+/// Where arguments returned by [args_with_source] come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// Typed in or scripted in UEFI shell, even if the `default` arguments are used
+    Shell,
+    /// Load options of image, e.g. of a `Boot####` entry
+    LoadOptions,
+    /// The `default` arguments if not launched from shell
+    Default,
+}
+
+/// Arguments passed by UEFI shell, or load options of image split in shell syntax
+/// if not launched from shell. The first argument is the image name.
+///
+/// `default` is used instead if no argument other than the image name is passed, e.g. split
+/// at compile time by [uefi_shell_split::split_const!].
+/// Fails with `INVALID_PARAMETER` if no argument can be found.
+pub fn args(bt: &BootServices, default: Option<&[&str]>) -> Result<Vec<String>> {
+    args_with_source(bt, default).map(|(argv, _)| argv)
+}
+
+/// Same as [args] but also returns where the arguments come from, e.g. to only interact
+/// with user when launched from shell
+pub fn args_with_source(
+    bt: &BootServices,
+    default: Option<&[&str]>,
+) -> Result<(Vec<String>, Source)> {
+    let image = bt.open_protocol_exclusive::<LoadedImage>(bt.image_handle())?;
+    let sh_params = bt
+        .open_protocol_exclusive::<ShellParameters>(bt.image_handle())
+        .ok();
+
+    let (mut argv, mut source): (Vec<String>, _) = if let Some(sh_params) = sh_params {
+        let argv = sh_params
+            .args()
+            .map(|arg| {
+                let mut buf = String::new();
+                arg.as_str_in_buf(&mut buf).unwrap();
+                buf
+            })
+            .collect();
+        (argv, Source::Shell)
+    } else if let Ok(load_options) = image.load_options_as_cstr16() {
+        let mut load_options_str = String::new();
+        load_options_str.reserve(load_options.num_chars());
+        if load_options.as_str_in_buf(&mut load_options_str).is_ok() {
+            let argv = uefi_shell_split::split(load_options_str.as_str());
+            (argv, Source::LoadOptions)
+        } else {
+            (Vec::new(), Source::LoadOptions)
+        }
+    } else {
+        (Vec::new(), Source::LoadOptions)
+    };
+    if argv.len() <= 1 {
+        if let Some(default) = default {
+            argv = default.iter().map(|arg| arg.to_string()).collect();
+            // still invoked by user in shell, only the arguments are filled in
+            if source != Source::Shell {
+                source = Source::Default;
+            }
+        }
+    }
+    if argv.is_empty() {
+        log::error!("Command-line options not passed");
+        return Err(Status::INVALID_PARAMETER.into());
+    }
+    Ok((argv, source))
+}
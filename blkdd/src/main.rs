@@ -0,0 +1,437 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+use core::slice;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::println;
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::media::block::BlockIO;
+use uefi::proto::media::file::{File, FileInfo, FileMode, RegularFile};
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType};
+use uefi_cli::{find_command, print_hexdump, App, ArgsError, Size};
+use uefi_devpath::{device_path_from_shell_text, open_shell_path};
+
+const APP: App = App {
+    help: "\
+Usage: {name} <COMMAND> [OPTIONS] DEVICE [FILE]
+
+  Read, write or dump raw blocks of block device DEVICE
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+
+Commands:
+  read                  Read blocks into FILE, FILE is overwritten
+  write                 Write content of FILE to blocks, bytes of the last
+                        block beyond end of FILE are kept
+  dump                  Print blocks in hex and ASCII
+
+Options:
+  -s, --start LBA       First block to access, defaults to 0
+  -n, --count NUM       Number of blocks to access, defaults to the rest of
+                        DEVICE, or blocks FILE spans for `write`
+
+DEVICE is a number listed by blkinfo, a device path text or a shell mapping.
+LBA and NUM are decimal, or hex prefixed with \"0x\".
+
+EXAMPLE:
+  * Dump partition table of device 0
+  {name} dump -n 2 0
+  * Back up first MiB of disk mapped as BLK1
+  {name} read -n 2048 BLK1: first-mib.img
+",
+    version: uefi_cli::version!(),
+};
+
+/// Blocks are read and written in chunks of at most this size
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Clone, Copy)]
+enum CommandType {
+    Read,
+    Write,
+    Dump,
+}
+
+enum Command<'a> {
+    NoOp,
+    Run {
+        command_type: CommandType,
+        device: &'a str,
+        file: Option<&'a str>,
+        start: u64,
+        count: Option<u64>,
+    },
+}
+
+/// Decimal, or hex prefixed with "0x"
+fn parse_u64(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("read", CommandType::Read),
+        ("write", CommandType::Write),
+        ("dump", CommandType::Dump),
+    ];
+
+    let mut command_type = None;
+    let mut device = None;
+    let mut file = None;
+    let mut start = 0;
+    let mut count = None;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('s') | Arg::Long("start") | Arg::Short('n') | Arg::Long("count") => {
+                let value = opts.value()?;
+                let Some(num) = parse_u64(value) else {
+                    println!("Invalid number {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                if let Arg::Short('s') | Arg::Long("start") = arg {
+                    start = num;
+                } else {
+                    count = Some(num);
+                }
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(cmd) if command_type.is_none() => {
+                command_type = Some(find_command(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?);
+            }
+            Arg::Positional(dev) if device.is_none() => {
+                device = Some(dev);
+            }
+            Arg::Positional(path) if file.is_none() => {
+                file = Some(path);
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(command_type) = command_type else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    let Some(device) = device else {
+        println!("DEVICE not specified");
+        return Err(ArgsError::Invalid);
+    };
+    match (command_type, file) {
+        (CommandType::Read | CommandType::Write, None) => {
+            println!("FILE not specified");
+            return Err(ArgsError::Invalid);
+        }
+        (CommandType::Dump, Some(file)) => {
+            return Err(ArgsError::Unexpected(Arg::Positional(file)));
+        }
+        _ => {}
+    }
+    Ok(Command::Run {
+        command_type,
+        device,
+        file,
+        start,
+        count,
+    })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::Run {
+            command_type,
+            device,
+            file,
+            start,
+            count,
+        }) => run(bt, command_type, device, file, start, count),
+    };
+
+    res.status()
+}
+
+/// Buffer aligned to IoAlign of media as BlockIo requires
+struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+impl AlignedBuffer {
+    fn new(size: usize, align: u32) -> uefi::Result<Self> {
+        let Ok(layout) = Layout::from_size_align(size, (align as usize).max(1)) else {
+            log::error!("invalid IoAlign {}", align);
+            return Err(Status::UNSUPPORTED.into());
+        };
+        let ptr = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .unwrap_or_else(|| handle_alloc_error(layout));
+        Ok(Self { ptr, layout })
+    }
+}
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// BlockIO of `device`, index of BlockIO handles or path to one
+fn open_block_io<'a>(
+    bt: &'a BootServices,
+    device: &str,
+) -> uefi::Result<ScopedProtocol<'a, BlockIO>> {
+    let handle = match device.parse::<usize>() {
+        Ok(index) => {
+            let handles = bt.locate_handle_buffer(SearchType::from_proto::<BlockIO>())?;
+            let Some(&handle) = handles.get(index) else {
+                println!("No block device {}", index);
+                return Err(Status::NOT_FOUND.into());
+            };
+            handle
+        }
+        Err(_) => {
+            let dp = device_path_from_shell_text(bt, device)?;
+            let mut remaining: &DevicePath = &dp;
+            let handle = bt.locate_device_path::<BlockIO>(&mut remaining)?;
+            if remaining.node_iter().next().is_some() {
+                println!("{} is not a block device", device);
+                return Err(Status::INVALID_PARAMETER.into());
+            }
+            handle
+        }
+    };
+    // not exclusive to keep file systems on it connected
+    unsafe {
+        bt.open_protocol::<BlockIO>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+fn run(
+    bt: &BootServices,
+    command_type: CommandType,
+    device: &str,
+    file: Option<&str>,
+    start: u64,
+    count: Option<u64>,
+) -> uefi::Result {
+    let mut block_io = open_block_io(bt, device).map_err(|e| {
+        println!("Failed to open block device {}: {}", device, e.status());
+        e
+    })?;
+    let media = block_io.media();
+    if !media.is_media_present() {
+        println!("No media present in {}", device);
+        return Status::NO_MEDIA.to_result();
+    }
+    let num_blocks = media.last_block() + 1;
+    let block_size = media.block_size() as usize;
+    let mut buffer = AlignedBuffer::new(
+        (BUFFER_SIZE / block_size).max(1) * block_size,
+        media.io_align(),
+    )?;
+
+    match (command_type, file) {
+        (CommandType::Write, Some(path)) => {
+            let file = open_shell_path(bt, path, FileMode::Read)?.into_regular_file();
+            let Some(mut file) = file else {
+                println!("{} is not a file", path);
+                return Status::INVALID_PARAMETER.to_result();
+            };
+            let file_size = file.get_boxed_info::<FileInfo>()?.file_size();
+            let count = count.unwrap_or((file_size + block_size as u64 - 1) / block_size as u64);
+            check_range(start, count, num_blocks)?;
+            let size = file_size.min(count * block_size as u64);
+            write_blocks(&mut block_io, &mut buffer, &mut file, start, size)?;
+            println!("Wrote {} to {} at block {}", Size(size), device, start);
+        }
+        (_, file) => {
+            let count = count.unwrap_or(num_blocks.saturating_sub(start));
+            check_range(start, count, num_blocks)?;
+            let mut output = match file {
+                Some(path) => Some(create_file(bt, path)?),
+                None => None,
+            };
+            read_blocks(
+                &block_io,
+                &mut buffer,
+                start,
+                count,
+                |offset, data| match &mut output {
+                    Some(file) => file.write(data).map_err(|e| e.to_err_without_payload()),
+                    None => {
+                        print_hexdump(offset, data);
+                        Ok(())
+                    }
+                },
+            )?;
+            if let Some(mut file) = output {
+                file.flush()?;
+                println!(
+                    "Read {} blocks ({}) from {}",
+                    count,
+                    Size(count * block_size as u64),
+                    device
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_range(start: u64, count: u64, num_blocks: u64) -> uefi::Result {
+    if start
+        .checked_add(count)
+        .map_or(true, |end| end > num_blocks)
+    {
+        println!(
+            "Blocks {}+{} out of range of {} blocks",
+            start, count, num_blocks
+        );
+        return Status::INVALID_PARAMETER.to_result();
+    }
+    Ok(())
+}
+
+/// Create or truncate file at `path`
+fn create_file(bt: &BootServices, path: &str) -> uefi::Result<RegularFile> {
+    open_shell_path(bt, path, FileMode::CreateReadWrite)?.delete()?;
+    let Some(file) = open_shell_path(bt, path, FileMode::CreateReadWrite)?.into_regular_file()
+    else {
+        println!("{} is not a file", path);
+        return Err(Status::INVALID_PARAMETER.into());
+    };
+    Ok(file)
+}
+
+/// Read `count` blocks from `start` in chunks, `f` is called with byte offset and data of each
+fn read_blocks<F>(
+    block_io: &BlockIO,
+    buffer: &mut [u8],
+    start: u64,
+    count: u64,
+    mut f: F,
+) -> uefi::Result
+where
+    F: FnMut(u64, &[u8]) -> uefi::Result,
+{
+    let media = block_io.media();
+    let block_size = media.block_size() as u64;
+    let chunk_blocks = buffer.len() as u64 / block_size;
+    let end = start + count;
+    let mut lba = start;
+    while lba < end {
+        let n = (end - lba).min(chunk_blocks);
+        let chunk = &mut buffer[..(n * block_size) as usize];
+        block_io
+            .read_blocks(media.media_id(), lba, chunk)
+            .map_err(|e| {
+                println!("Failed to read blocks {}+{}: {}", lba, n, e.status());
+                e
+            })?;
+        f(lba * block_size, chunk)?;
+        lba += n;
+    }
+    Ok(())
+}
+
+/// Write `size` bytes of `file` to blocks from `start`
+fn write_blocks(
+    block_io: &mut BlockIO,
+    buffer: &mut [u8],
+    file: &mut RegularFile,
+    start: u64,
+    size: u64,
+) -> uefi::Result {
+    let media_id = block_io.media().media_id();
+    let block_size = block_io.media().block_size() as u64;
+    let chunk_blocks = buffer.len() as u64 / block_size;
+    let mut lba = start;
+    let mut remaining = size;
+    while remaining != 0 {
+        let len = remaining.min(chunk_blocks * block_size) as usize;
+        let n = (len as u64 + block_size - 1) / block_size;
+        let chunk = &mut buffer[..(n * block_size) as usize];
+        // keep data of the last block beyond end of file
+        if len < chunk.len() {
+            let tail = &mut chunk[((n - 1) * block_size) as usize..];
+            block_io.read_blocks(media_id, lba + n - 1, tail)?;
+        }
+        let mut filled = 0;
+        while filled < len {
+            let read = file.read(&mut chunk[filled..len])?;
+            if read == 0 {
+                println!("File is truncated while writing");
+                return Status::ABORTED.to_result();
+            }
+            filled += read;
+        }
+        block_io.write_blocks(media_id, lba, chunk).map_err(|e| {
+            println!("Failed to write blocks {}+{}: {}", lba, n, e.status());
+            e
+        })?;
+        lba += n;
+        remaining -= len as u64;
+    }
+    block_io.flush_blocks()
+}
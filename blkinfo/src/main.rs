@@ -0,0 +1,214 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::media::block::BlockIO;
+use uefi::proto::media::disk::DiskIo;
+use uefi::proto::media::partition::PartitionInfo;
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType};
+use uefi::{println, Identify};
+use uefi_cli::{App, ArgsError, Size};
+use uefi_loopdrv::{LoopInfo, LoopProtocol};
+
+const APP: App = App {
+    help: "\
+Usage: {name} [OPTIONS]
+
+  List block devices with their sizes, flags, partitions and device paths
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+  -d, --disks           Do not list partitions
+  -f, --full            Print device paths in full instead of display form
+
+Flags:
+  P  logical partition        R  removable media
+  O  read-only                N  no media present
+  D  DiskIo available
+
+Devices are numbered in order of BlockIo handles, loop devices of loopdrv are
+named `loopN`.
+
+EXAMPLE:
+  * List disks only
+  {name} -d
+",
+    version: uefi_cli::version!(),
+};
+
+enum Command {
+    NoOp,
+    List { disks_only: bool, full_path: bool },
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(mut argv_iter: I) -> Result<Command, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    let mut disks_only = false;
+    let mut full_path = false;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('d') | Arg::Long("disks") => {
+                disks_only = true;
+            }
+            Arg::Short('f') | Arg::Long("full") => {
+                full_path = true;
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    Ok(Command::List {
+        disks_only,
+        full_path,
+    })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::List {
+            disks_only,
+            full_path,
+        }) => list_block_devices(bt, disks_only, full_path),
+    };
+
+    res.status()
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others are using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> uefi::Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+/// `loopN` if `handle` is a loop device
+fn loop_name(bt: &BootServices, handle: Handle) -> Option<String> {
+    let loop_pt = get_protocol::<LoopProtocol>(bt, handle).ok()?;
+    let mut info = LoopInfo::default();
+    unsafe {
+        (loop_pt.get_info)(loop_pt.get_mut()?, &mut info)
+            .to_result()
+            .ok()?;
+    }
+    Some(format!("loop{}", info.unit_number))
+}
+
+fn device_path_text(bt: &BootServices, handle: Handle, full_path: bool) -> Option<String> {
+    let dp = get_protocol::<DevicePath>(bt, handle).ok()?;
+    let text = dp
+        .to_string(bt, DisplayOnly(!full_path), AllowShortcuts(!full_path))
+        .ok()?;
+    Some(text.to_string())
+}
+
+fn list_block_devices(bt: &BootServices, disks_only: bool, full_path: bool) -> uefi::Result {
+    let handles = bt.locate_handle_buffer(SearchType::from_proto::<BlockIO>())?;
+
+    println!(
+        "{:>3} {:<7} {:>10} {:>6} {:<5} DEVICE PATH",
+        "#", "NAME", "SIZE", "BLOCK", "FLAGS"
+    );
+    for (index, &handle) in handles.iter().enumerate() {
+        let block_io = get_protocol::<BlockIO>(bt, handle)?;
+        let media = block_io.media();
+        if disks_only && media.is_logical_partition() {
+            continue;
+        }
+        let has_disk_io = bt
+            .protocols_per_handle(handle)
+            .map(|p| p.protocols().contains(&&DiskIo::GUID))
+            .unwrap_or(false);
+
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        let flags: String = [
+            flag(media.is_logical_partition(), 'P'),
+            flag(media.is_removable_media(), 'R'),
+            flag(media.is_read_only(), 'O'),
+            flag(!media.is_media_present(), 'N'),
+            flag(has_disk_io, 'D'),
+        ]
+        .iter()
+        .collect();
+        let size = if media.is_media_present() {
+            Size((media.last_block() + 1) * u64::from(media.block_size())).to_string()
+        } else {
+            "-".to_string()
+        };
+
+        println!(
+            "{:>3} {:<7} {:>10} {:>6} {:<5} {}",
+            index,
+            loop_name(bt, handle).as_deref().unwrap_or("-"),
+            size,
+            media.block_size(),
+            flags,
+            device_path_text(bt, handle, full_path)
+                .as_deref()
+                .unwrap_or("-")
+        );
+
+        let Ok(partition) = get_protocol::<PartitionInfo>(bt, handle) else {
+            continue;
+        };
+        if let Some(entry) = partition.gpt_partition_entry() {
+            let (type_guid, guid) = (entry.partition_type_guid.0, entry.unique_partition_guid);
+            println!("{:>3} gpt type {} guid {}", "", type_guid, guid);
+        } else if let Some(record) = partition.mbr_partition_record() {
+            println!("{:>3} mbr type 0x{:02x}", "", record.os_indicator.0);
+        }
+    }
+    Ok(())
+}
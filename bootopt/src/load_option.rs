@@ -0,0 +1,103 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::{mem, slice};
+
+use uefi::proto::device_path::DevicePath;
+
+/// Boot manager tries the option only if it's active
+pub const LOAD_OPTION_ACTIVE: u32 = 0x0000_0001;
+
+/// `EFI_LOAD_OPTION` stored in `Boot####` variables
+#[derive(Debug, Default)]
+pub struct LoadOption {
+    pub attributes: u32,
+    /// UCS-2 description without the terminating null
+    pub description: Vec<u16>,
+    /// One or more packed device paths, the first one is the path of boot image
+    pub file_path_list: Vec<u8>,
+    pub optional_data: Vec<u8>,
+}
+
+/// Whether `data` is a sequence of device path nodes ending with end entire node
+fn is_valid_device_path_list(data: &[u8]) -> bool {
+    let mut rest = data;
+    let mut end_entire = false;
+    while rest.len() >= 4 {
+        let len = usize::from(u16::from_le_bytes([rest[2], rest[3]]));
+        if len < 4 || len > rest.len() {
+            return false;
+        }
+        end_entire = rest[0] == 0x7f && rest[1] == 0xff;
+        rest = &rest[len..];
+    }
+    rest.is_empty() && end_entire
+}
+
+/// Bytes of `dp` including the end node
+pub fn device_path_bytes(dp: &DevicePath) -> &[u8] {
+    unsafe { slice::from_raw_parts(dp.as_ffi_ptr().cast::<u8>(), mem::size_of_val(dp)) }
+}
+
+impl LoadOption {
+    /// Returns `None` if `data` is malformed
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let attributes = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?);
+        let path_len = usize::from(u16::from_le_bytes(data.get(4..6)?.try_into().ok()?));
+        let mut description = Vec::new();
+        let mut pos = 6;
+        loop {
+            let c = u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?);
+            pos += 2;
+            if c == 0 {
+                break;
+            }
+            description.push(c);
+        }
+        let file_path_list = data.get(pos..pos + path_len)?;
+        if !is_valid_device_path_list(file_path_list) {
+            return None;
+        }
+        Some(Self {
+            attributes,
+            description,
+            file_path_list: file_path_list.to_vec(),
+            optional_data: data[pos + path_len..].to_vec(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(
+            6 + (self.description.len() + 1) * 2
+                + self.file_path_list.len()
+                + self.optional_data.len(),
+        );
+        data.extend(self.attributes.to_le_bytes());
+        data.extend(
+            u16::try_from(self.file_path_list.len())
+                .unwrap()
+                .to_le_bytes(),
+        );
+        for c in self.description.iter().chain([&0]) {
+            data.extend(c.to_le_bytes());
+        }
+        data.extend(&self.file_path_list);
+        data.extend(&self.optional_data);
+        data
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.attributes & LOAD_OPTION_ACTIVE != 0
+    }
+
+    #[inline]
+    pub fn description(&self) -> String {
+        String::from_utf16_lossy(&self.description)
+    }
+
+    /// Path of boot image, validated on parsing
+    #[inline]
+    pub fn file_path(&self) -> &DevicePath {
+        unsafe { DevicePath::from_ffi_ptr(self.file_path_list.as_ptr().cast()) }
+    }
+}
@@ -0,0 +1,485 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+mod load_option;
+
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::table::runtime::{VariableAttributes, VariableVendor};
+use uefi::{println, CStr16, CString16};
+use uefi_cli::{find_command, parse_hex, App, ArgsError};
+use uefi_devpath::device_path_from_shell_text;
+
+use load_option::{LoadOption, LOAD_OPTION_ACTIVE};
+
+const MIN_UEFI_REVISION: uefi::table::Revision = uefi::table::Revision::EFI_2_00;
+
+const APP: App = App {
+    help: "\
+Usage: {name} <COMMAND> [OPTIONS] [NUM]
+
+  Manage Boot#### boot options of UEFI boot manager
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+
+Commands:
+  list                  List boot options in boot order
+  create                Create boot option with --desc and --path
+  delete                Delete boot option NUM and remove it from boot order
+  order                 Move boot options NUM, comma separated, to front of
+                        boot order in the given order
+  enable                Mark boot option NUM active
+  disable               Mark boot option NUM inactive
+  edit                  Edit description, path or optional data of option NUM
+
+Options:
+  -d, --desc DESC       Description of boot option
+  -p, --path FILE       Path of boot image, e.g. `FS0:\\EFI\\BOOT\\BOOTX64.EFI`
+  -x, --hex HEX         Set optional data to HEX bytes
+  -t, --text TEXT       Set optional data to UCS-2 TEXT, e.g. kernel command line
+      --no-data         Remove optional data
+      --first           Put created boot option first in boot order
+      --inactive        Create inactive boot option
+
+NUM is hex number of boot option, `Boot` prefix is optional.
+
+EXAMPLE:
+  * Create boot option for Linux kernel with EFI stub
+  {name} create -d Linux -p vmlinuz-linux -t \"root=/dev/sda2 initrd=\\initramfs-linux.img\"
+  * Boot Boot0003 first
+  {name} order 0003
+",
+    version: uefi_cli::version!(),
+};
+
+const BOOT_ORDER: &CStr16 = cstr16!("BootOrder");
+const BOOT_CURRENT: &CStr16 = cstr16!("BootCurrent");
+const BOOT_NEXT: &CStr16 = cstr16!("BootNext");
+
+const BOOT_VAR_ATTRIBUTES: VariableAttributes = VariableAttributes::NON_VOLATILE
+    .union(VariableAttributes::BOOTSERVICE_ACCESS)
+    .union(VariableAttributes::RUNTIME_ACCESS);
+
+/// Changes to boot option, `None` for unchanged
+#[derive(Default)]
+struct OptionEdit<'a> {
+    description: Option<&'a str>,
+    path: Option<&'a str>,
+    optional_data: Option<Vec<u8>>,
+}
+
+enum Command<'a> {
+    NoOp,
+    List,
+    Create {
+        edit: OptionEdit<'a>,
+        first: bool,
+        active: bool,
+    },
+    Delete(u16),
+    Order(Vec<u16>),
+    SetActive(u16, bool),
+    Edit(u16, OptionEdit<'a>),
+}
+
+/// Parse hex number of boot option, optionally prefixed with `Boot`
+fn parse_boot_num(num: &str) -> Option<u16> {
+    let num = num.strip_prefix("Boot").unwrap_or(num);
+    if num.is_empty() || num.len() > 4 || !num.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u16::from_str_radix(num, 16).ok()
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum CommandType {
+        List,
+        Create,
+        Delete,
+        Order,
+        Enable,
+        Disable,
+        Edit,
+    }
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("list", CommandType::List),
+        ("create", CommandType::Create),
+        ("delete", CommandType::Delete),
+        ("order", CommandType::Order),
+        ("enable", CommandType::Enable),
+        ("disable", CommandType::Disable),
+        ("edit", CommandType::Edit),
+    ];
+
+    let mut command_type = None;
+    let mut nums = None;
+    let mut edit = OptionEdit::default();
+    let mut first = false;
+    let mut active = true;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('d') | Arg::Long("desc") => {
+                edit.description = Some(opts.value()?);
+            }
+            Arg::Short('p') | Arg::Long("path") => {
+                edit.path = Some(opts.value()?);
+            }
+            Arg::Short('x') | Arg::Long("hex") => {
+                let hex = opts.value()?;
+                let Some(bytes) = parse_hex(hex) else {
+                    println!("Invalid hex data: {}", hex);
+                    return Err(ArgsError::Invalid);
+                };
+                edit.optional_data = Some(bytes);
+            }
+            Arg::Short('t') | Arg::Long("text") => {
+                let text = opts.value()?;
+                let data = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                edit.optional_data = Some(data);
+            }
+            Arg::Long("no-data") => {
+                edit.optional_data = Some(Vec::new());
+            }
+            Arg::Long("first") => {
+                first = true;
+            }
+            Arg::Long("inactive") => {
+                active = false;
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(cmd) if command_type.is_none() => {
+                command_type = Some(find_command(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?);
+            }
+            Arg::Positional(n) if nums.is_none() => {
+                let parsed: Option<Vec<u16>> = n.split(',').map(parse_boot_num).collect();
+                let Some(parsed) = parsed else {
+                    println!("Invalid boot option number: {}", n);
+                    return Err(ArgsError::Invalid);
+                };
+                nums = Some(parsed);
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(command_type) = command_type else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    match command_type {
+        CommandType::List => return Ok(Command::List),
+        CommandType::Create => {
+            if edit.description.is_none() || edit.path.is_none() {
+                println!("Both --desc and --path are required to create boot option");
+                return Err(ArgsError::Invalid);
+            }
+            return Ok(Command::Create {
+                edit,
+                first,
+                active,
+            });
+        }
+        _ => {}
+    }
+
+    let Some(nums) = nums else {
+        println!("Boot option NUM not specified");
+        return Err(ArgsError::Invalid);
+    };
+    if command_type == CommandType::Order {
+        return Ok(Command::Order(nums));
+    }
+    let &[num] = nums.as_slice() else {
+        println!("Only one boot option NUM is accepted");
+        return Err(ArgsError::Invalid);
+    };
+    Ok(match command_type {
+        CommandType::Delete => Command::Delete(num),
+        CommandType::Enable => Command::SetActive(num, true),
+        CommandType::Disable => Command::SetActive(num, false),
+        CommandType::Edit => Command::Edit(num, edit),
+        _ => unreachable!(),
+    })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+    let rt = system_table.runtime_services();
+
+    if system_table.uefi_revision() < MIN_UEFI_REVISION {
+        log::error!(
+            "system UEFI revision {} smaller than required {}",
+            system_table.uefi_revision(),
+            MIN_UEFI_REVISION
+        );
+        return Status::INCOMPATIBLE_VERSION;
+    }
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::List) => list_options(bt, rt),
+        Ok(Command::Create {
+            edit,
+            first,
+            active,
+        }) => create_option(bt, rt, edit, first, active),
+        Ok(Command::Delete(num)) => delete_option(rt, num),
+        Ok(Command::Order(nums)) => reorder_options(rt, &nums),
+        Ok(Command::SetActive(num, active)) => edit_option(rt, num, |option| {
+            if active {
+                option.attributes |= LOAD_OPTION_ACTIVE;
+            } else {
+                option.attributes &= !LOAD_OPTION_ACTIVE;
+            }
+            Ok(())
+        }),
+        Ok(Command::Edit(num, edit)) => edit_option(rt, num, |option| apply_edit(bt, option, edit)),
+    };
+
+    res.status()
+}
+
+fn boot_var_name(num: u16) -> CString16 {
+    CString16::try_from(format!("Boot{:04X}", num).as_str()).unwrap()
+}
+
+/// Read a variable of boot manager containing a single or an array of `UINT16`
+fn get_u16_array(rt: &RuntimeServices, name: &CStr16) -> uefi::Result<Vec<u16>> {
+    match rt.get_variable_boxed(name, &VariableVendor::GLOBAL_VARIABLE) {
+        Ok((data, _)) => Ok(data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect()),
+        Err(e) if e.status() == Status::NOT_FOUND => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn set_boot_order(rt: &RuntimeServices, order: &[u16]) -> uefi::Result {
+    let data: Vec<u8> = order.iter().flat_map(|n| n.to_le_bytes()).collect();
+    rt.set_variable(
+        BOOT_ORDER,
+        &VariableVendor::GLOBAL_VARIABLE,
+        BOOT_VAR_ATTRIBUTES,
+        &data,
+    )
+    .map_err(|e| {
+        println!("Failed to set BootOrder: {}", e.status());
+        e
+    })
+}
+
+/// Numbers of all `Boot####` variables, in ascending order
+fn boot_nums(rt: &RuntimeServices) -> uefi::Result<Vec<u16>> {
+    let mut nums: Vec<u16> = rt
+        .variable_keys()?
+        .iter()
+        .filter(|key| key.vendor.0 == VariableVendor::GLOBAL_VARIABLE.0)
+        .filter_map(|key| {
+            let name = key.name().ok()?.to_string();
+            let num = name.strip_prefix("Boot")?;
+            // #### of Boot#### is uppercase hex digits per spec
+            if num.len() != 4 || !num.bytes().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F')) {
+                return None;
+            }
+            u16::from_str_radix(num, 16).ok()
+        })
+        .collect();
+    nums.sort_unstable();
+    Ok(nums)
+}
+
+fn get_option(rt: &RuntimeServices, num: u16) -> uefi::Result<LoadOption> {
+    let (data, _) = rt
+        .get_variable_boxed(&boot_var_name(num), &VariableVendor::GLOBAL_VARIABLE)
+        .map_err(|e| {
+            println!("Failed to get Boot{:04X}: {}", num, e.status());
+            e
+        })?;
+    LoadOption::parse(&data).ok_or_else(|| {
+        println!("Boot{:04X} is malformed", num);
+        Status::COMPROMISED_DATA.into()
+    })
+}
+
+fn set_option(rt: &RuntimeServices, num: u16, option: &LoadOption) -> uefi::Result {
+    rt.set_variable(
+        &boot_var_name(num),
+        &VariableVendor::GLOBAL_VARIABLE,
+        BOOT_VAR_ATTRIBUTES,
+        &option.to_bytes(),
+    )
+    .map_err(|e| {
+        println!("Failed to set Boot{:04X}: {}", num, e.status());
+        e
+    })
+}
+
+fn list_options(bt: &BootServices, rt: &RuntimeServices) -> uefi::Result {
+    let order = get_u16_array(rt, BOOT_ORDER)?;
+    let current = get_u16_array(rt, BOOT_CURRENT)?;
+    let next = get_u16_array(rt, BOOT_NEXT)?;
+    if let Some(current) = current.first() {
+        println!("BootCurrent: {:04X}", current);
+    }
+    if let Some(next) = next.first() {
+        println!("BootNext: {:04X}", next);
+    }
+    let order_str: Vec<_> = order.iter().map(|n| format!("{:04X}", n)).collect();
+    println!("BootOrder: {}", order_str.join(","));
+
+    let mut nums = order.clone();
+    nums.extend(boot_nums(rt)?.into_iter().filter(|n| !order.contains(n)));
+    for num in nums {
+        let Ok(option) = get_option(rt, num) else {
+            continue;
+        };
+        println!(
+            "Boot{:04X}{} {}",
+            num,
+            if option.is_active() { "*" } else { " " },
+            option.description()
+        );
+        let path = option
+            .file_path()
+            .to_string(bt, DisplayOnly(false), AllowShortcuts(false))
+            .ok();
+        match path {
+            Some(path) => println!("    {}", path),
+            None => println!("    <unknown device path>"),
+        }
+        if !option.optional_data.is_empty() {
+            println!("    Optional data: {} bytes", option.optional_data.len());
+        }
+    }
+    Ok(())
+}
+
+fn apply_edit(bt: &BootServices, option: &mut LoadOption, edit: OptionEdit) -> uefi::Result {
+    if let Some(description) = edit.description {
+        option.description = description.encode_utf16().collect();
+    }
+    if let Some(path) = edit.path {
+        let dp = device_path_from_shell_text(bt, path).map_err(|e| {
+            println!("Failed to get device path of {}: {}", path, e.status());
+            e
+        })?;
+        option.file_path_list = load_option::device_path_bytes(&dp).to_vec();
+    }
+    if let Some(data) = edit.optional_data {
+        option.optional_data = data;
+    }
+    Ok(())
+}
+
+fn create_option(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    edit: OptionEdit,
+    first: bool,
+    active: bool,
+) -> uefi::Result {
+    let nums = boot_nums(rt)?;
+    let Some(num) = (0..=u16::MAX).find(|n| nums.binary_search(n).is_err()) else {
+        println!("No free boot option number");
+        return Status::OUT_OF_RESOURCES.to_result();
+    };
+
+    let mut option = LoadOption::default();
+    apply_edit(bt, &mut option, edit)?;
+    if active {
+        option.attributes |= LOAD_OPTION_ACTIVE;
+    }
+    set_option(rt, num, &option)?;
+
+    let mut order = get_u16_array(rt, BOOT_ORDER)?;
+    if first {
+        order.insert(0, num);
+    } else {
+        order.push(num);
+    }
+    set_boot_order(rt, &order)?;
+    println!("Created Boot{:04X}", num);
+    Ok(())
+}
+
+fn delete_option(rt: &RuntimeServices, num: u16) -> uefi::Result {
+    rt.delete_variable(&boot_var_name(num), &VariableVendor::GLOBAL_VARIABLE)
+        .map_err(|e| {
+            println!("Failed to delete Boot{:04X}: {}", num, e.status());
+            e
+        })?;
+    let mut order = get_u16_array(rt, BOOT_ORDER)?;
+    if order.contains(&num) {
+        order.retain(|n| *n != num);
+        set_boot_order(rt, &order)?;
+    }
+    Ok(())
+}
+
+fn reorder_options(rt: &RuntimeServices, nums: &[u16]) -> uefi::Result {
+    let existing = boot_nums(rt)?;
+    if let Some(num) = nums.iter().find(|n| existing.binary_search(n).is_err()) {
+        println!("Boot{:04X} does not exist", num);
+        return Status::NOT_FOUND.to_result();
+    }
+    let old_order = get_u16_array(rt, BOOT_ORDER)?;
+    let mut order = Vec::with_capacity(old_order.len() + nums.len());
+    for num in nums.iter().chain(&old_order) {
+        if !order.contains(num) {
+            order.push(*num);
+        }
+    }
+    set_boot_order(rt, &order)
+}
+
+fn edit_option<F>(rt: &RuntimeServices, num: u16, f: F) -> uefi::Result
+where
+    F: FnOnce(&mut LoadOption) -> uefi::Result,
+{
+    let mut option = get_option(rt, num)?;
+    f(&mut option)?;
+    set_option(rt, num, &option)
+}
@@ -0,0 +1,53 @@
+// Build metadata shared by build scripts of binaries, included with `include!`.
+// Sets `UEFI_TOYS_GIT_COMMIT`, `UEFI_TOYS_BUILD_TARGET` and `UEFI_TOYS_BUILD_DATE`
+// environment variables for `env!` of the crate being built.
+
+/// Short commit hash of workspace, suffixed with "-dirty" if there are uncommitted changes
+fn git_commit() -> Option<String> {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
+    };
+    let commit = git(&["rev-parse", "--short", "HEAD"])?;
+    let dirty =
+        git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|s| !s.is_empty());
+    Some(if dirty { commit + "-dirty" } else { commit })
+}
+
+/// UTC date of `SOURCE_DATE_EPOCH` for reproducible builds, or now
+fn build_date() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn emit_build_info() {
+    let commit = git_commit().unwrap_or_else(|| "unknown".to_owned());
+    let target = std::env::var("TARGET").unwrap();
+    println!("cargo:rustc-env=UEFI_TOYS_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=UEFI_TOYS_BUILD_TARGET={}", target);
+    println!("cargo:rustc-env=UEFI_TOYS_BUILD_DATE={}", build_date());
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/index");
+}
@@ -0,0 +1,188 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::mem;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::table::boot::LoadImageSource;
+use uefi::{println, CString16};
+use uefi_cli::{App, ArgsError};
+use uefi_devpath::device_path_from_shell_text;
+
+const APP: App = App {
+    help: "\
+Usage: {name} [OPTIONS] IMAGE [-- ARGS...]
+
+  Load EFI application IMAGE and start it with ARGS as load options
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+  -n, --no-name         Do not pass IMAGE as the first argument, e.g. for Linux
+                        kernel with EFI stub
+  -s, --status          Print exit status of IMAGE once it returns
+
+IMAGE is a file path or a device path text. ARGS are quoted in shell syntax,
+use `--` to pass ARGS starting with `-`.
+
+EXAMPLE:
+  * Boot Linux kernel with EFI stub
+  {name} -n vmlinuz-linux -- root=/dev/sda2 initrd=\\initramfs-linux.img
+  * Boot from loop device attached by lopatch and mapped as FS1
+  {name} FS1:\\EFI\\BOOT\\BOOTX64.EFI
+",
+    version: uefi_cli::version!(),
+};
+
+enum Command<'a> {
+    NoOp,
+    Chain {
+        image: &'a str,
+        args: Vec<&'a str>,
+        no_name: bool,
+        print_status: bool,
+    },
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    let mut image = None;
+    let mut args = Vec::new();
+    let mut no_name = false;
+    let mut print_status = false;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('n') | Arg::Long("no-name") => {
+                no_name = true;
+            }
+            Arg::Short('s') | Arg::Long("status") => {
+                print_status = true;
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(path) if image.is_none() => {
+                image = Some(path);
+            }
+            Arg::Positional(arg) => {
+                args.push(arg);
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(image) = image else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    Ok(Command::Chain {
+        image,
+        args,
+        no_name,
+        print_status,
+    })
+}
+
+#[entry]
+fn main(handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::Chain {
+            image,
+            args,
+            no_name,
+            print_status,
+        }) => chain(bt, handle, image, &args, no_name, print_status),
+    };
+
+    res.status()
+}
+
+fn chain(
+    bt: &BootServices,
+    parent: Handle,
+    image: &str,
+    args: &[&str],
+    no_name: bool,
+    print_status: bool,
+) -> uefi::Result {
+    let dp = device_path_from_shell_text(bt, image).map_err(|e| {
+        println!("Failed to get device path of {}: {}", image, e.status());
+        e
+    })?;
+    let child = bt
+        .load_image(
+            parent,
+            LoadImageSource::FromDevicePath {
+                device_path: &dp,
+                from_boot_manager: false,
+            },
+        )
+        .map_err(|e| {
+            println!("Failed to load {}: {}", image, e.status());
+            e
+        })?;
+    mem::drop(dp);
+
+    let name = (!no_name).then_some(image);
+    let command_line = uefi_shell_split::join(name.iter().chain(args));
+    log::debug!("load options: {}", command_line);
+    let Ok(load_options) = CString16::try_from(command_line.as_str()) else {
+        println!("Arguments contain characters not in UCS-2");
+        let _ = bt.unload_image(child);
+        return Status::INVALID_PARAMETER.to_result();
+    };
+
+    match bt.open_protocol_exclusive::<LoadedImage>(child) {
+        Ok(mut loaded_image) => unsafe {
+            // kept alive until the image returns
+            let size = mem::size_of_val(load_options.to_u16_slice_with_nul());
+            loaded_image.set_load_options(load_options.as_ptr().cast(), size as u32);
+        },
+        Err(e) => {
+            let _ = bt.unload_image(child);
+            return Err(e);
+        }
+    }
+
+    let res = bt.start_image(child);
+    if print_status {
+        println!("{} returned {}", image, res.status());
+    }
+    res
+}
@@ -0,0 +1,174 @@
+//! Command-line conventions shared by tools in the workspace: help and version flags,
+//! subcommand lookup, exit status of argument and command errors, parsing of common
+//! argument values and progress reporting of long-running operations.
+
+#![no_std]
+
+extern crate alloc;
+
+mod progress;
+mod size;
+mod status;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Write};
+
+use getargs::Arg;
+use uefi::{print, println, Status};
+
+pub use progress::{ConOutProgress, NoProgress, Progress};
+pub use size::{parse_size, Size};
+pub use status::parse_status;
+
+/// Error of parsing command-line arguments, tools print it and exit with [ArgsError::status]
+#[derive(Debug)]
+pub enum ArgsError<'a> {
+    Invalid,
+    Unexpected(Arg<&'a str>),
+    GetArgs(getargs::Error<&'a str>),
+}
+impl Display for ArgsError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GetArgs(e) => e.fmt(f),
+            Self::Unexpected(arg) => write!(f, "Unexpected argument {}", arg),
+            Self::Invalid => f.write_str("Invalid argument"),
+        }
+    }
+}
+impl<'a> From<getargs::Error<&'a str>> for ArgsError<'a> {
+    fn from(e: getargs::Error<&'a str>) -> Self {
+        Self::GetArgs(e)
+    }
+}
+impl ArgsError<'_> {
+    /// `INVALID_PARAMETER` for all of them, as commands of UEFI shell exit with on bad
+    /// arguments
+    #[inline]
+    pub fn status(&self) -> Status {
+        Status::INVALID_PARAMETER
+    }
+}
+
+/// Print error of a command, if any, and return exit status of it
+pub fn exit_status(res: uefi_error::Result) -> Status {
+    match res {
+        Ok(()) => Status::SUCCESS,
+        Err(e) => {
+            println!("Error: {}", e);
+            e.status()
+        }
+    }
+}
+
+/// Version of the calling crate with build metadata set by `build/version.rs`,
+/// e.g. "0.1.0 (1a2b3c4 x86_64-unknown-uefi 2023-09-30)"
+#[macro_export]
+macro_rules! version {
+    () => {
+        ::core::concat!(
+            ::core::env!("CARGO_PKG_VERSION"),
+            " (",
+            ::core::env!("UEFI_TOYS_GIT_COMMIT"),
+            " ",
+            ::core::env!("UEFI_TOYS_BUILD_TARGET"),
+            " ",
+            ::core::env!("UEFI_TOYS_BUILD_DATE"),
+            ")"
+        )
+    };
+}
+
+/// Help and version of a tool
+pub struct App {
+    /// Help text, `{name}` is replaced with the invoked image name
+    pub help: &'static str,
+    pub version: &'static str,
+}
+impl App {
+    pub fn print_help(&self, name: &str) {
+        let mut parts = self.help.split("{name}");
+        if let Some(part) = parts.next() {
+            print!("{}", part);
+        }
+        for part in parts {
+            print!("{}{}", name, part);
+        }
+        println!();
+    }
+
+    pub fn print_version(&self, name: &str) {
+        println!("{} {}", name, self.version);
+    }
+
+    /// Handle `-h, --help` and `-V, --version`, returns whether `arg` is one of them
+    pub fn handle_common(&self, name: &str, arg: &Arg<&str>) -> bool {
+        match arg {
+            Arg::Short('h') | Arg::Long("help") => self.print_help(name),
+            Arg::Short('V') | Arg::Long("version") => self.print_version(name),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Look up subcommand `cmd` in `commands` case-insensitively
+pub fn find_command<T: Copy>(commands: &[(&str, T)], cmd: &str) -> Option<T> {
+    commands
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(cmd))
+        .map(|(_, v)| *v)
+}
+
+/// Look up subcommand `cmd` in `commands` case-insensitively, or by a prefix of only one
+/// of them, e.g. `os-rec` for `os-recovery`
+pub fn find_command_prefix<T: Copy>(commands: &[(&str, T)], cmd: &str) -> Option<T> {
+    if let Some(v) = find_command(commands, cmd) {
+        return Some(v);
+    }
+    let mut matches = commands
+        .iter()
+        .filter(|(name, _)| name.len() >= cmd.len() && name[..cmd.len()].eq_ignore_ascii_case(cmd));
+    match (matches.next(), matches.next()) {
+        (Some((_, v)), None) if !cmd.is_empty() => Some(*v),
+        _ => None,
+    }
+}
+
+/// Parse hex string optionally prefixed with "0x" into bytes
+pub fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Print `data` in lines of 16 bytes in hex and ASCII, addressed from `offset`
+pub fn print_hexdump(offset: u64, data: &[u8]) {
+    let mut line = String::new();
+    for (i, chunk) in data.chunks(16).enumerate() {
+        line.clear();
+        let _ = write!(line, "{:08x}:", offset + i as u64 * 16);
+        for b in chunk {
+            let _ = write!(line, " {:02x}", b);
+        }
+        for _ in chunk.len()..16 {
+            line.push_str("   ");
+        }
+        line.push_str("  ");
+        line.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,89 @@
+use super::*;
+
+/// Progress of long-running operations in stages, each of some units of work
+pub trait Progress {
+    /// Start stage `label` of `total` units, 0 if total is unknown
+    fn begin(&mut self, label: &str, total: u64);
+    /// `done` units of current stage are completed
+    fn update(&mut self, done: u64);
+    /// Current stage is completed
+    fn finish(&mut self);
+}
+
+/// Reports nothing, for quiet mode
+pub struct NoProgress;
+impl Progress for NoProgress {
+    fn begin(&mut self, _label: &str, _total: u64) {}
+    fn update(&mut self, _done: u64) {}
+    fn finish(&mut self) {}
+}
+
+/// Width of bar in characters
+const BAR_WIDTH: u64 = 30;
+/// Redraw interval of stages of unknown total in units
+const UNKNOWN_TOTAL_STEP: u64 = 32;
+
+/// Progress bar redrawn in place on console, or a counter if total is unknown.
+/// It's only redrawn if changed as console output could be slow.
+#[derive(Default)]
+pub struct ConOutProgress {
+    label: String,
+    total: u64,
+    done: u64,
+    /// percentage, or done units if total is unknown, last drawn
+    drawn: Option<u64>,
+}
+
+impl ConOutProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, force: bool) {
+        let state = if self.total == 0 {
+            self.done
+        } else {
+            self.done.min(self.total) * 100 / self.total
+        };
+        let due = match self.drawn {
+            None => true,
+            Some(_) if self.total == 0 => state % UNKNOWN_TOTAL_STEP == 0,
+            Some(drawn) => drawn != state,
+        };
+        if !force && !due {
+            return;
+        }
+        self.drawn = Some(state);
+
+        if self.total == 0 {
+            print!("\r{} {}", self.label, self.done);
+            return;
+        }
+        let filled = state * BAR_WIDTH / 100;
+        let mut bar = String::new();
+        bar.extend((0..BAR_WIDTH).map(|i| if i < filled { '#' } else { '-' }));
+        print!("\r{} [{}] {:>3}%", self.label, bar, state);
+    }
+}
+
+impl Progress for ConOutProgress {
+    fn begin(&mut self, label: &str, total: u64) {
+        self.label.clear();
+        self.label.push_str(label);
+        self.total = total;
+        self.done = 0;
+        self.drawn = None;
+        self.draw(true);
+    }
+    fn update(&mut self, done: u64) {
+        self.done = done;
+        self.draw(false);
+    }
+    fn finish(&mut self) {
+        if self.total != 0 {
+            self.done = self.total;
+        }
+        self.draw(true);
+        println!();
+    }
+}
@@ -0,0 +1,89 @@
+use super::*;
+
+/// Size in bytes displayed in the largest binary unit it has an integral part of
+pub struct Size(pub u64);
+impl Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 >> (10 * (unit + 1)) != 0 {
+            unit += 1;
+        }
+        if unit == 0 {
+            return f.pad(&format!("{} B", self.0));
+        }
+        // one decimal place, truncated
+        let scaled = (self.0 >> (10 * unit - 4)) * 10 >> 4;
+        f.pad(&format!("{}.{} {}", scaled / 10, scaled % 10, UNITS[unit]))
+    }
+}
+
+/// Parse size in bytes with optional case-insensitive binary unit suffix, e.g. "512",
+/// "4K", "16MiB" or "1gb". Returns [None] if invalid or overflowed.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let end = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let num: u64 = size[..end].parse().ok()?;
+    let suffix = size[end..].to_ascii_uppercase();
+    let unit = suffix
+        .strip_suffix("IB")
+        .or_else(|| suffix.strip_suffix('B'))
+        .unwrap_or(&suffix);
+    let shift = match unit {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return None,
+    };
+    num.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(Some(512), parse_size("512"));
+        assert_eq!(Some(512), parse_size("512B"));
+        assert_eq!(Some(4 << 10), parse_size("4K"));
+        assert_eq!(Some(4 << 10), parse_size("4KB"));
+        assert_eq!(Some(16 << 20), parse_size("16MiB"));
+        assert_eq!(Some(1 << 30), parse_size("1G"));
+        assert_eq!(Some(2 << 40), parse_size("2TiB"));
+        assert_eq!(Some(0), parse_size("0M"));
+        assert_eq!(None, parse_size("1P"));
+        assert_eq!(None, parse_size("1KiBs"));
+        assert_eq!(None, parse_size("1 K"));
+        assert_eq!(None, parse_size("K"));
+        assert_eq!(None, parse_size(""));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert_eq!(Some(1 << 30), parse_size("1gb"));
+        assert_eq!(Some(1 << 30), parse_size("1gib"));
+        assert_eq!(Some(3 << 20), parse_size("3mIb"));
+        assert_eq!(Some(8), parse_size("8b"));
+    }
+
+    #[test]
+    fn fractions_and_signs() {
+        assert_eq!(None, parse_size("1.5G"));
+        assert_eq!(None, parse_size(".5K"));
+        assert_eq!(None, parse_size("-1"));
+        assert_eq!(None, parse_size("+1"));
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(Some(u64::MAX), parse_size("18446744073709551615"));
+        assert_eq!(None, parse_size("18446744073709551616"));
+        assert_eq!(Some(((1 << 24) - 1) << 40), parse_size("16777215T"));
+        assert_eq!(None, parse_size("16777216T"));
+        assert_eq!(None, parse_size("17179869184G"));
+    }
+}
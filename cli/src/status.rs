@@ -0,0 +1,56 @@
+use super::*;
+
+/// Statuses of UEFI specification by name, for [parse_status]
+const STATUSES: &[(&str, Status)] = &[
+    ("SUCCESS", Status::SUCCESS),
+    ("WARN_UNKNOWN_GLYPH", Status::WARN_UNKNOWN_GLYPH),
+    ("WARN_DELETE_FAILURE", Status::WARN_DELETE_FAILURE),
+    ("WARN_WRITE_FAILURE", Status::WARN_WRITE_FAILURE),
+    ("WARN_BUFFER_TOO_SMALL", Status::WARN_BUFFER_TOO_SMALL),
+    ("WARN_STALE_DATA", Status::WARN_STALE_DATA),
+    ("WARN_FILE_SYSTEM", Status::WARN_FILE_SYSTEM),
+    ("WARN_RESET_REQUIRED", Status::WARN_RESET_REQUIRED),
+    ("LOAD_ERROR", Status::LOAD_ERROR),
+    ("INVALID_PARAMETER", Status::INVALID_PARAMETER),
+    ("UNSUPPORTED", Status::UNSUPPORTED),
+    ("BAD_BUFFER_SIZE", Status::BAD_BUFFER_SIZE),
+    ("BUFFER_TOO_SMALL", Status::BUFFER_TOO_SMALL),
+    ("NOT_READY", Status::NOT_READY),
+    ("DEVICE_ERROR", Status::DEVICE_ERROR),
+    ("WRITE_PROTECTED", Status::WRITE_PROTECTED),
+    ("OUT_OF_RESOURCES", Status::OUT_OF_RESOURCES),
+    ("VOLUME_CORRUPTED", Status::VOLUME_CORRUPTED),
+    ("VOLUME_FULL", Status::VOLUME_FULL),
+    ("NO_MEDIA", Status::NO_MEDIA),
+    ("MEDIA_CHANGED", Status::MEDIA_CHANGED),
+    ("NOT_FOUND", Status::NOT_FOUND),
+    ("ACCESS_DENIED", Status::ACCESS_DENIED),
+    ("NO_RESPONSE", Status::NO_RESPONSE),
+    ("NO_MAPPING", Status::NO_MAPPING),
+    ("TIMEOUT", Status::TIMEOUT),
+    ("NOT_STARTED", Status::NOT_STARTED),
+    ("ALREADY_STARTED", Status::ALREADY_STARTED),
+    ("ABORTED", Status::ABORTED),
+    ("ICMP_ERROR", Status::ICMP_ERROR),
+    ("TFTP_ERROR", Status::TFTP_ERROR),
+    ("PROTOCOL_ERROR", Status::PROTOCOL_ERROR),
+    ("INCOMPATIBLE_VERSION", Status::INCOMPATIBLE_VERSION),
+    ("SECURITY_VIOLATION", Status::SECURITY_VIOLATION),
+    ("CRC_ERROR", Status::CRC_ERROR),
+    ("END_OF_MEDIA", Status::END_OF_MEDIA),
+    ("END_OF_FILE", Status::END_OF_FILE),
+    ("INVALID_LANGUAGE", Status::INVALID_LANGUAGE),
+    ("COMPROMISED_DATA", Status::COMPROMISED_DATA),
+    ("IP_ADDRESS_CONFLICT", Status::IP_ADDRESS_CONFLICT),
+    ("HTTP_ERROR", Status::HTTP_ERROR),
+];
+
+/// Parse status by name like `DEVICE_ERROR`, optionally prefixed with `EFI_`, or by hex
+/// code prefixed with `0x` like `0x8000000000000007`
+pub fn parse_status(status: &str) -> Option<Status> {
+    if let Some(hex) = status.strip_prefix("0x") {
+        return usize::from_str_radix(hex, 16).ok().map(Status);
+    }
+    let name = status.strip_prefix("EFI_").unwrap_or(status);
+    find_command(STATUSES, name)
+}
@@ -0,0 +1,352 @@
+//! Streaming writer of cpio archives in newc format,
+//! see <https://man.archlinux.org/man/cpio.5#New_ASCII_Format>
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use core::mem;
+
+use bytemuck::{Pod, Zeroable};
+
+/// Mask of file type bits in mode
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFDIR: u32 = 0o040000;
+
+const MAGIC: &[u8; 6] = b"070701";
+const TRAILER: &str = "TRAILER!!!";
+
+/// Destination of archive data
+pub trait Sink {
+    type Error;
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    type Error = S::Error;
+    #[inline]
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        (**self).write_all(data)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Sink for Vec<u8> {
+    type Error = core::convert::Infallible;
+    #[inline]
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct NewcHeader {
+    magic: [u8; 6],
+    ino: [u8; 8],
+    mode: [u8; 8],
+    uid: [u8; 8],
+    gid: [u8; 8],
+    n_link: [u8; 8],
+    mtime: [u8; 8],
+    file_size: [u8; 8],
+    dev_major: [u8; 8],
+    dev_minor: [u8; 8],
+    rdev_major: [u8; 8],
+    rdev_minor: [u8; 8],
+    name_size: [u8; 8],
+    check: [u8; 8],
+}
+
+fn write_hex(buf: &mut [u8; 8], mut value: u32) {
+    for i in buf.iter_mut().rev() {
+        let m = (value % 16) as u8;
+        *i = if m < 10 { m + b'0' } else { m - 10 + b'a' };
+        value >>= 4;
+    }
+}
+
+#[inline]
+fn padding_of(size: usize) -> usize {
+    (4 - size % 4) % 4
+}
+
+/// Metadata of an archive entry, `mode` includes file type bits
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: u32,
+    pub file_size: u32,
+}
+
+pub struct Writer<S> {
+    sink: S,
+    next_ino: u32,
+    /// data of current entry not yet written
+    remaining: u32,
+    /// padding after data of current entry
+    data_padding: usize,
+}
+
+impl<S: Sink> Writer<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            next_ino: 1,
+            remaining: 0,
+            data_padding: 0,
+        }
+    }
+
+    /// Write header of `entry`, its data of `file_size` bytes is then written
+    /// with [Writer::write_data] before the next entry
+    pub fn begin_entry(&mut self, entry: &Entry) -> Result<(), S::Error> {
+        assert_eq!(0, self.remaining, "data of previous entry not written");
+        let n_link = if entry.mode & S_IFMT == S_IFDIR { 2 } else { 1 };
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.write_header(entry, ino, n_link)?;
+        self.remaining = entry.file_size;
+        self.data_padding = padding_of(entry.file_size as usize);
+        self.pad_if_done()
+    }
+
+    pub fn write_data(&mut self, data: &[u8]) -> Result<(), S::Error> {
+        assert!(
+            data.len() <= self.remaining as usize,
+            "more data than file size"
+        );
+        self.sink.write_all(data)?;
+        self.remaining -= data.len() as u32;
+        self.pad_if_done()
+    }
+
+    /// Write `entry` with whole `data` at once, `file_size` of `entry` is ignored
+    pub fn write_file(&mut self, entry: &Entry, data: &[u8]) -> Result<(), S::Error> {
+        let file_size = u32::try_from(data.len()).expect("file too large for cpio");
+        self.begin_entry(&Entry {
+            file_size,
+            ..*entry
+        })?;
+        self.write_data(data)
+    }
+
+    /// Write symbolic link `entry` pointing to `target`, file type bits of `mode` are ignored
+    pub fn write_symlink(&mut self, entry: &Entry, target: &str) -> Result<(), S::Error> {
+        let mode = S_IFLNK | (entry.mode & !S_IFMT);
+        self.write_file(&Entry { mode, ..*entry }, target.as_bytes())
+    }
+
+    /// Write trailer entry unless `trailer` is false, e.g. for archives to be concatenated
+    pub fn finish(mut self, trailer: bool) -> Result<S, S::Error> {
+        assert_eq!(0, self.remaining, "data of last entry not written");
+        if trailer {
+            let entry = Entry {
+                name: TRAILER,
+                ..Default::default()
+            };
+            self.write_header(&entry, 0, 1)?;
+        }
+        Ok(self.sink)
+    }
+
+    fn write_header(&mut self, entry: &Entry, ino: u32, n_link: u32) -> Result<(), S::Error> {
+        let mut header = NewcHeader::zeroed();
+        bytemuck::bytes_of_mut(&mut header).fill(b'0');
+        header.magic = *MAGIC;
+        write_hex(&mut header.ino, ino);
+        write_hex(&mut header.mode, entry.mode);
+        write_hex(&mut header.uid, entry.uid);
+        write_hex(&mut header.gid, entry.gid);
+        write_hex(&mut header.n_link, n_link);
+        write_hex(&mut header.mtime, entry.mtime);
+        write_hex(&mut header.file_size, entry.file_size);
+        write_hex(&mut header.name_size, (entry.name.len() + 1) as u32);
+        self.sink.write_all(bytemuck::bytes_of(&header))?;
+        self.sink.write_all(entry.name.as_bytes())?;
+        // name is null terminated and padded along with header
+        let padding = padding_of(mem::size_of::<NewcHeader>() + entry.name.len() + 1);
+        self.sink.write_all(&[0; 4][..1 + padding])
+    }
+
+    fn pad_if_done(&mut self) -> Result<(), S::Error> {
+        if self.remaining != 0 || self.data_padding == 0 {
+            return Ok(());
+        }
+        let padding = mem::take(&mut self.data_padding);
+        self.sink.write_all(&[0; 3][..padding])
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    const HEADER_SIZE: usize = mem::size_of::<NewcHeader>();
+
+    /// Fields of header at start of `data` as (ino, mode, n_link, file_size, name)
+    fn parse_header(data: &[u8]) -> (u32, u32, u32, u32, &str) {
+        let field = |i: usize| {
+            let hex = std::str::from_utf8(&data[6 + i * 8..][..8]).unwrap();
+            u32::from_str_radix(hex, 16).unwrap()
+        };
+        assert_eq!(MAGIC, &data[..6]);
+        let name_size = field(11) as usize;
+        assert_eq!(0, data[HEADER_SIZE + name_size - 1]);
+        let name = std::str::from_utf8(&data[HEADER_SIZE..][..name_size - 1]).unwrap();
+        (field(0), field(1), field(4), field(6), name)
+    }
+
+    fn archive(f: impl FnOnce(&mut Writer<Vec<u8>>)) -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new());
+        f(&mut writer);
+        writer.finish(true).unwrap()
+    }
+
+    #[test]
+    fn single_file() {
+        let data = archive(|w| {
+            let entry = Entry {
+                name: "init",
+                mode: S_IFREG | 0o755,
+                uid: 1000,
+                gid: 100,
+                mtime: 0x65000000,
+                ..Default::default()
+            };
+            w.write_file(&entry, b"#!/bin/sh\n").unwrap();
+        });
+
+        let mut expected = Vec::new();
+        expected
+            .extend_from_slice(b"07070100000001000081ed000003e80000006400000001650000000000000a");
+        expected.extend_from_slice(b"0000000000000000000000000000000000000005");
+        expected.extend_from_slice(b"00000000init\0\0");
+        expected.extend_from_slice(b"#!/bin/sh\n\0\0");
+        expected
+            .extend_from_slice(b"07070100000000000000000000000000000000000000010000000000000000");
+        expected.extend_from_slice(b"000000000000000000000000000000000000000b");
+        expected.extend_from_slice(b"00000000TRAILER!!!\0\0\0\0");
+        assert_eq!(
+            String::from_utf8_lossy(&expected),
+            String::from_utf8_lossy(&data)
+        );
+    }
+
+    #[test]
+    fn entries_aligned() {
+        let names = ["a", "ab", "abc", "abcd", "abcde"];
+        let data = archive(|w| {
+            for (i, name) in names.iter().enumerate() {
+                let entry = Entry {
+                    name,
+                    mode: S_IFREG | 0o644,
+                    ..Default::default()
+                };
+                w.write_file(&entry, &b"12345"[..i]).unwrap();
+            }
+        });
+
+        let mut pos = 0;
+        for (i, name) in names.iter().chain(&[TRAILER]).enumerate() {
+            assert_eq!(0, pos % 4);
+            let (ino, _, _, file_size, parsed) = parse_header(&data[pos..]);
+            assert_eq!(*name, parsed);
+            if i < names.len() {
+                assert_eq!(i as u32 + 1, ino);
+                assert_eq!(i as u32, file_size);
+            }
+            pos += HEADER_SIZE + name.len() + 1;
+            pos += padding_of(pos);
+            assert_eq!(
+                &b"12345"[..file_size as usize],
+                &data[pos..][..file_size as usize]
+            );
+            pos += file_size as usize;
+            pos += padding_of(pos);
+        }
+        assert_eq!(data.len(), pos);
+    }
+
+    #[test]
+    fn dir_and_symlink() {
+        let data = archive(|w| {
+            let dir = Entry {
+                name: "usr/bin",
+                mode: S_IFDIR | 0o755,
+                ..Default::default()
+            };
+            w.begin_entry(&dir).unwrap();
+            let link = Entry {
+                name: "bin",
+                mode: S_IFREG | 0o777,
+                ..Default::default()
+            };
+            w.write_symlink(&link, "usr/bin").unwrap();
+        });
+
+        let (_, mode, n_link, file_size, name) = parse_header(&data);
+        assert_eq!(
+            (S_IFDIR | 0o755, 2, 0, "usr/bin"),
+            (mode, n_link, file_size, name)
+        );
+
+        // name sizes of both are 2 bytes short of alignment
+        let pos = HEADER_SIZE + 8 + 2;
+        let (_, mode, n_link, file_size, name) = parse_header(&data[pos..]);
+        assert_eq!(
+            (S_IFLNK | 0o777, 1, 7, "bin"),
+            (mode, n_link, file_size, name)
+        );
+        let pos = pos + HEADER_SIZE + 4 + 2;
+        assert_eq!(b"usr/bin\0", &data[pos..][..8]);
+    }
+
+    #[test]
+    fn streaming_and_no_trailer() {
+        let entry = Entry {
+            name: "data",
+            mode: S_IFREG | 0o644,
+            file_size: 7,
+            ..Default::default()
+        };
+        let mut whole = Writer::new(Vec::new());
+        whole.write_file(&entry, b"1234567").unwrap();
+        let whole = whole.finish(false).unwrap();
+
+        let mut buffer = Vec::new();
+        let mut chunked = Writer::new(&mut buffer);
+        chunked.begin_entry(&entry).unwrap();
+        for chunk in [&b"12"[..], b"", b"345", b"67"] {
+            chunked.write_data(chunk).unwrap();
+        }
+        chunked.finish(false).unwrap();
+
+        assert_eq!(whole, buffer);
+        assert!(!buffer
+            .windows(TRAILER.len())
+            .any(|w| w == TRAILER.as_bytes()));
+    }
+
+    #[test]
+    #[should_panic(expected = "more data than file size")]
+    fn excess_data() {
+        archive(|w| {
+            let entry = Entry {
+                name: "data",
+                file_size: 1,
+                ..Default::default()
+            };
+            w.begin_entry(&entry).unwrap();
+            w.write_data(b"12").unwrap();
+        });
+    }
+}
@@ -0,0 +1,166 @@
+//! Conversion of file paths and device path texts given on command-line to device paths,
+//! with or without UEFI shell.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod normalize;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::{mem, ptr, slice};
+
+use uefi::prelude::*;
+use uefi::proto::device_path::text::DevicePathFromText;
+use uefi::proto::device_path::{DevicePath, DeviceSubType, DeviceType};
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::file::{File, FileAttribute, FileHandle, FileMode};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::{MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::{CStr16, CString16, Handle, Result};
+use uefi_shell::Shell;
+
+pub use normalize::{normalize_path, resolve_path};
+pub use uefi_shell::PoolDevicePath;
+
+/// Size of device path end node
+const END_NODE_SIZE: usize = 4;
+
+/// Open protocol `P` on `handle` without exclusive access, e.g. ones of device of
+/// this image which are also used by others
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+/// Bytes of `dp` including end node
+fn device_path_bytes(dp: &DevicePath) -> &[u8] {
+    unsafe { slice::from_raw_parts(dp.as_ffi_ptr().cast::<u8>(), mem::size_of_val(dp)) }
+}
+
+/// Concatenated file path nodes of `dp`
+fn file_path_text(dp: &DevicePath) -> String {
+    let mut text = String::new();
+    for node in dp.node_iter() {
+        if node.full_type() != (DeviceType::MEDIA, DeviceSubType::MEDIA_FILE_PATH) {
+            continue;
+        }
+        let units = node
+            .data()
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|c| *c != 0);
+        text.push('\\');
+        text.extend(char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+    }
+    normalize_path(&text)
+}
+
+/// Device path from UEFI shell with `path` relative to its current directory
+fn device_path_from_shell<'a>(shell: &Shell<'a>, path: &str) -> Option<PoolDevicePath<'a>> {
+    shell.device_path_from_file_path(&normalize_path(path))
+}
+
+/// Device path of file `path` on device of this image, relative to directory of this image
+fn device_path_from_image_dir<'a>(bt: &'a BootServices, path: &str) -> Result<PoolDevicePath<'a>> {
+    let loaded_image = get_protocol::<LoadedImage>(bt, bt.image_handle())?;
+    let image_path = loaded_image.file_path().map(file_path_text);
+    let image_dir = image_path
+        .as_deref()
+        .and_then(|p| p.rsplit_once('\\'))
+        .map_or("", |(dir, _)| dir);
+    let file_path = resolve_path(image_dir, path);
+    let file_path =
+        CString16::try_from(file_path.as_str()).map_err(|_| Status::INVALID_PARAMETER)?;
+    let device_dp = get_protocol::<DevicePath>(bt, loaded_image.device())?;
+
+    let prefix = device_path_bytes(&device_dp);
+    let prefix = &prefix[..prefix.len() - END_NODE_SIZE];
+    let name = file_path.to_u16_slice_with_nul();
+    let node_size = 4 + mem::size_of_val(name);
+    let Ok(node_len) = u16::try_from(node_size) else {
+        return Err(Status::INVALID_PARAMETER.into());
+    };
+
+    let mut data = Vec::with_capacity(prefix.len() + node_size + END_NODE_SIZE);
+    data.extend_from_slice(prefix);
+    data.extend_from_slice(&[DeviceType::MEDIA.0, DeviceSubType::MEDIA_FILE_PATH.0]);
+    data.extend_from_slice(&node_len.to_le_bytes());
+    data.extend(name.iter().flat_map(|c| c.to_le_bytes()));
+    data.extend_from_slice(&[DeviceType::END.0, DeviceSubType::END_ENTIRE.0, 4, 0]);
+
+    let buffer = bt.allocate_pool(MemoryType::LOADER_DATA, data.len())?;
+    unsafe {
+        ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
+        Ok(PoolDevicePath::from_ffi_ptr(bt, buffer as _))
+    }
+}
+
+/// Device path from device path `text`
+pub fn device_path_from_text<'a>(bt: &'a BootServices, text: &str) -> Result<PoolDevicePath<'a>> {
+    let handle = bt.get_handle_for_protocol::<DevicePathFromText>()?;
+    let text2dp = bt.open_protocol_exclusive::<DevicePathFromText>(handle)?;
+    let text = CString16::try_from(text).map_err(|_| Status::INVALID_PARAMETER)?;
+    // uefi-rs returns the pool allocated device path as borrowed and never frees it,
+    // take it over to free it on drop
+    let dp = text2dp.convert_text_to_device_path(&text)?;
+    Ok(unsafe { PoolDevicePath::from_ffi_ptr(bt, dp.as_ffi_ptr()) })
+}
+
+/// Convert `path` to device path, either a file path relative to current directory of
+/// UEFI shell, in which `/` is accepted as separator, or a device path text. Without
+/// UEFI shell, file path without shell mapping is relative to directory of this image.
+pub fn device_path_from_shell_text<'a>(
+    bt: &'a BootServices,
+    path: &str,
+) -> Result<PoolDevicePath<'a>> {
+    match Shell::locate(bt) {
+        Ok(shell) => {
+            if let Some(dp) = device_path_from_shell(&shell, path) {
+                return Ok(dp);
+            }
+        }
+        // device path texts always have nodes like `PciRoot(0x0)` and shell mappings
+        // like `FS0:` are unknown without UEFI shell
+        Err(_) if !path.contains(['(', ':']) => return device_path_from_image_dir(bt, path),
+        Err(_) => {}
+    }
+    device_path_from_text(bt, path)
+}
+
+/// Open file or directory at `path` accepted by [device_path_from_shell_text]
+pub fn open_shell_path(bt: &BootServices, path: &str, mode: FileMode) -> Result<FileHandle> {
+    let dp = device_path_from_shell_text(bt, path)?;
+    let mut remaining: &DevicePath = &dp;
+    let fs_device = bt.locate_device_path::<SimpleFileSystem>(&mut remaining)?;
+    let mut fs = get_protocol::<SimpleFileSystem>(bt, fs_device)?;
+
+    // root of file system if no file path node left
+    let mut file_path = cstr16!("\\");
+    if let Some(node) = remaining.node_iter().next() {
+        if node.full_type() != (DeviceType::MEDIA, DeviceSubType::MEDIA_FILE_PATH) {
+            log::error!("{} is not a media file device path", path);
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        file_path = unsafe { CStr16::from_ptr(node.data().as_ptr() as _) };
+    }
+    fs.open_volume()?
+        .open(file_path, mode, FileAttribute::empty())
+        .map_err(|e| {
+            log::error!("failed to open {}, {}", file_path, e.status());
+            e
+        })
+}
@@ -0,0 +1,90 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const SEPARATORS: [char; 2] = ['/', '\\'];
+
+/// Split leading shell mapping like `FS0:` off `path`
+fn split_mapping(path: &str) -> (&str, &str) {
+    match path.find(':') {
+        Some(idx) if !path[..idx].contains(SEPARATORS) => path.split_at(idx + 1),
+        _ => ("", path),
+    }
+}
+
+/// Normalize file `path` with `\` as separator, `/` is also accepted. Empty and `.`
+/// components are removed and `..` removes the previous one without going above root.
+/// Leading shell mapping like `FS0:` is kept as is, empty relative path becomes `.`.
+pub fn normalize_path(path: &str) -> String {
+    let (mapping, path) = split_mapping(path);
+    let absolute = path.starts_with(SEPARATORS);
+    let mut parts = Vec::new();
+    for part in path.split(SEPARATORS) {
+        match part {
+            "" | "." => {}
+            ".." => match parts.last() {
+                Some(&last) if last != ".." => {
+                    parts.pop();
+                }
+                _ if !absolute => parts.push(".."),
+                _ => {}
+            },
+            _ => parts.push(part),
+        }
+    }
+    let root = if absolute { "\\" } else { "" };
+    if mapping.is_empty() && !absolute && parts.is_empty() {
+        return String::from(".");
+    }
+    format!("{}{}{}", mapping, root, parts.join("\\"))
+}
+
+/// Normalized `path` resolved against directory `base` unless it is absolute
+/// or has a shell mapping
+pub fn resolve_path(base: &str, path: &str) -> String {
+    if path.starts_with(SEPARATORS) || !split_mapping(path).0.is_empty() {
+        normalize_path(path)
+    } else {
+        normalize_path(&format!("{}\\{}", base, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        let cases = [
+            ("", "."),
+            ("./", "."),
+            ("a/..", "."),
+            ("a/b/../c", r"a\c"),
+            (r"/a//.\b/", r"\a\b"),
+            ("../../x", r"..\..\x"),
+            (r"a\..\..\x", r"..\x"),
+            (r"\..\x", r"\x"),
+            ("FS0:/EFI/../boot", r"FS0:\boot"),
+            (r"FS0:\..", r"FS0:\"),
+            ("FS0:x/./y", r"FS0:x\y"),
+            (r"a\b:c", r"a\b:c"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(expected, normalize_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let cases = [
+            (r"\EFI\tools", "../BOOT/x.efi", r"\EFI\BOOT\x.efi"),
+            (r"\EFI\tools", "x.efi", r"\EFI\tools\x.efi"),
+            (r"\EFI\tools", "/abs", r"\abs"),
+            (r"\EFI\tools", r"FS1:\a", r"FS1:\a"),
+            ("", "x.efi", r"\x.efi"),
+        ];
+        for (base, path, expected) in cases {
+            assert_eq!(expected, resolve_path(base, path), "{} {}", base, path);
+        }
+    }
+}
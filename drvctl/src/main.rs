@@ -0,0 +1,325 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::{
+    LoadImageSource, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType,
+};
+use uefi::{guid, println, Guid};
+use uefi_cli::{find_command, App, ArgsError};
+use uefi_devpath::device_path_from_shell_text;
+use uefi_error::{Context, Error, Result};
+
+const APP: App = App {
+    help: "\
+Usage: {name} <COMMAND> [OPTIONS] [TARGET]
+
+  Load, start and unload driver images, and connect or disconnect drivers of
+  controllers
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+
+Commands:
+  load IMAGE            Load driver IMAGE and start it, prints handle of image
+  start HANDLE          Start image HANDLE loaded with --no-start
+  unload HANDLE         Unload image HANDLE, the driver must support unloading
+  drivers               List handles and file paths of driver images
+  connect [TARGET]      Connect drivers to controller TARGET, or to all
+                        controllers if omitted
+  disconnect TARGET     Disconnect drivers from controller TARGET
+  reconnect TARGET      Disconnect drivers from controller TARGET and connect
+                        them again recursively
+
+Options:
+  -n, --no-start        Load IMAGE without starting it
+  -r, --recursive       Also connect drivers to child controllers created
+
+HANDLE is an address like 0x7e6a1b98 as printed by `load` and `drivers`.
+TARGET is a HANDLE, or a device path text or shell mapping like BLK1: of the
+controller.
+
+EXAMPLE:
+  * Load loopdrv, then unload it after loop devices are detached
+  {name} load loopdrv.efi
+  {name} unload 0x7e6a1b98
+  * Reconnect drivers of a disk
+  {name} reconnect BLK3:
+",
+    version: uefi_cli::version!(),
+};
+
+/// Driver binding protocol, drivers are images installing it
+const DRIVER_BINDING_GUID: Guid = guid!("18a031ab-b443-4d1a-a5c0-0c09261e9f71");
+
+#[derive(Clone, Copy)]
+enum CommandType {
+    Load,
+    Start,
+    Unload,
+    Drivers,
+    Connect,
+    Disconnect,
+    Reconnect,
+}
+
+enum Command<'a> {
+    NoOp,
+    Load {
+        image: &'a str,
+        no_start: bool,
+    },
+    Start(&'a str),
+    Unload(&'a str),
+    Drivers,
+    Connect {
+        target: Option<&'a str>,
+        recursive: bool,
+    },
+    Disconnect(&'a str),
+    Reconnect(&'a str),
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> core::result::Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("load", CommandType::Load),
+        ("start", CommandType::Start),
+        ("unload", CommandType::Unload),
+        ("drivers", CommandType::Drivers),
+        ("connect", CommandType::Connect),
+        ("disconnect", CommandType::Disconnect),
+        ("reconnect", CommandType::Reconnect),
+    ];
+
+    let mut command_type = None;
+    let mut target = None;
+    let mut no_start = false;
+    let mut recursive = false;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('n') | Arg::Long("no-start") => no_start = true,
+            Arg::Short('r') | Arg::Long("recursive") => recursive = true,
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(cmd) if command_type.is_none() => {
+                command_type = Some(find_command(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?);
+            }
+            Arg::Positional(value) if target.is_none() => target = Some(value),
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(command_type) = command_type else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    match (command_type, target) {
+        (CommandType::Drivers, None) => Ok(Command::Drivers),
+        (CommandType::Drivers, Some(value)) => Err(ArgsError::Unexpected(Arg::Positional(value))),
+        (CommandType::Connect, target) => Ok(Command::Connect { target, recursive }),
+        (_, None) => {
+            println!("TARGET not specified");
+            Err(ArgsError::Invalid)
+        }
+        (CommandType::Load, Some(image)) => Ok(Command::Load { image, no_start }),
+        (CommandType::Start, Some(handle)) => Ok(Command::Start(handle)),
+        (CommandType::Unload, Some(handle)) => Ok(Command::Unload(handle)),
+        (CommandType::Disconnect, Some(target)) => Ok(Command::Disconnect(target)),
+        (CommandType::Reconnect, Some(target)) => Ok(Command::Reconnect(target)),
+    }
+}
+
+#[entry]
+fn main(handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => Ok(()),
+        Ok(Command::Load { image, no_start }) => load(bt, handle, image, no_start),
+        Ok(Command::Start(image)) => parse_handle(bt, image).and_then(|h| start(bt, h)),
+        Ok(Command::Unload(image)) => parse_handle(bt, image).and_then(|h| {
+            bt.unload_image(h)
+                .with_context(|| format!("failed to unload image {}", image))
+        }),
+        Ok(Command::Drivers) => list_drivers(bt),
+        Ok(Command::Connect { target, recursive }) => match target {
+            Some(target) => {
+                find_controller(bt, target).and_then(|h| connect(bt, h, target, recursive))
+            }
+            None => connect_all(bt, recursive),
+        },
+        Ok(Command::Disconnect(target)) => {
+            find_controller(bt, target).and_then(|h| disconnect(bt, h, target))
+        }
+        Ok(Command::Reconnect(target)) => find_controller(bt, target).and_then(|h| {
+            disconnect(bt, h, target)?;
+            connect(bt, h, target, true)
+        }),
+    };
+
+    uefi_cli::exit_status(res)
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others are using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> uefi::Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+/// Handle of address `value` in hex, it must be a handle in the handle database
+fn parse_handle(bt: &BootServices, value: &str) -> Result<Handle> {
+    let invalid = || {
+        Error::msg(
+            Status::INVALID_PARAMETER,
+            format!("invalid handle {}", value),
+        )
+    };
+    let hex = value.strip_prefix("0x").ok_or_else(invalid)?;
+    let addr = usize::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    let handles = bt
+        .locate_handle_buffer(SearchType::AllHandles)
+        .context("failed to list handles")?;
+    handles
+        .iter()
+        .copied()
+        .find(|h| h.as_ptr() as usize == addr)
+        .ok_or_else(|| Error::msg(Status::NOT_FOUND, format!("handle {} not found", value)))
+}
+
+/// Handle of controller `target`, either a handle or the device of a device path
+fn find_controller(bt: &BootServices, target: &str) -> Result<Handle> {
+    if target.starts_with("0x") {
+        return parse_handle(bt, target);
+    }
+    let dp = device_path_from_shell_text(bt, target)
+        .with_context(|| format!("failed to resolve {}", target))?;
+    let mut remaining: &DevicePath = &dp;
+    let handle = bt
+        .locate_device_path::<DevicePath>(&mut remaining)
+        .with_context(|| format!("no device of {}", target))?;
+    // a handle of a parent device is not the controller
+    if remaining.node_iter().next().is_some() {
+        let msg = format!("no device of {}", target);
+        return Err(Error::msg(Status::NOT_FOUND, msg));
+    }
+    Ok(handle)
+}
+
+fn load(bt: &BootServices, parent: Handle, image: &str, no_start: bool) -> Result {
+    let dp = device_path_from_shell_text(bt, image)
+        .with_context(|| format!("failed to resolve {}", image))?;
+    let handle = bt
+        .load_image(
+            parent,
+            LoadImageSource::FromDevicePath {
+                device_path: &dp,
+                from_boot_manager: false,
+            },
+        )
+        .with_context(|| format!("failed to load {}", image))?;
+    println!("Loaded {} as 0x{:x}", image, handle.as_ptr() as usize);
+    if no_start {
+        return Ok(());
+    }
+    start(bt, handle)
+}
+
+fn start(bt: &BootServices, image: Handle) -> Result {
+    // drivers return once initialized
+    bt.start_image(image)
+        .with_context(|| format!("image 0x{:x} failed to start", image.as_ptr() as usize))
+}
+
+fn list_drivers(bt: &BootServices) -> Result {
+    let handles = bt
+        .locate_handle_buffer(SearchType::ByProtocol(&DRIVER_BINDING_GUID))
+        .context("failed to list drivers")?;
+    for &handle in handles.iter() {
+        let path = get_protocol::<LoadedImage>(bt, handle)
+            .ok()
+            .and_then(|image| {
+                let path = image.file_path()?;
+                path.to_string(bt, DisplayOnly(true), AllowShortcuts(true))
+                    .ok()
+            })
+            .unwrap_or_default();
+        println!("0x{:x} {}", handle.as_ptr() as usize, path);
+    }
+    Ok(())
+}
+
+fn connect(bt: &BootServices, controller: Handle, target: &str, recursive: bool) -> Result {
+    bt.connect_controller(controller, None, None, recursive)
+        .with_context(|| format!("failed to connect drivers to {}", target))
+}
+
+fn connect_all(bt: &BootServices, recursive: bool) -> Result {
+    let handles = bt
+        .locate_handle_buffer(SearchType::AllHandles)
+        .context("failed to list handles")?;
+    let mut connected = 0;
+    for &handle in handles.iter() {
+        // NOT_FOUND for handles no driver manages
+        if bt.connect_controller(handle, None, None, recursive).is_ok() {
+            connected += 1;
+        }
+    }
+    println!("Connected drivers to {} controllers", connected);
+    Ok(())
+}
+
+fn disconnect(bt: &BootServices, controller: Handle, target: &str) -> Result {
+    bt.disconnect_controller(controller, None, None)
+        .with_context(|| format!("failed to disconnect drivers from {}", target))
+}
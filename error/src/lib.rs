@@ -0,0 +1,144 @@
+//! Error of [Status] with context of operations failed, so a failure deep in a call chain
+//! surfaces as e.g. "failed to open boot.iso: failed to read sector 16: DEVICE_ERROR"
+//! instead of a bare status logged somewhere and returned elsewhere.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+
+use uefi::Status;
+
+pub type Result<T = (), E = Error> = core::result::Result<T, E>;
+
+/// [Status] of a failure and descriptions of operations failed by it
+#[derive(Clone, PartialEq, Eq)]
+pub struct Error {
+    status: Status,
+    /// innermost first
+    context: Vec<String>,
+}
+
+impl Error {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            context: Vec::new(),
+        }
+    }
+
+    /// Error of `status` with description `msg`, e.g. of a check failed
+    pub fn msg(status: Status, msg: impl Display) -> Self {
+        Self::new(status).context(msg)
+    }
+
+    #[inline]
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Add description of operation failed by this error
+    pub fn context(mut self, context: impl Display) -> Self {
+        self.context.push(context.to_string());
+        self
+    }
+
+    /// Descriptions of operations failed, outermost first
+    pub fn chain(&self) -> impl Iterator<Item = &str> {
+        self.context.iter().rev().map(String::as_str)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for context in self.chain() {
+            write!(f, "{}: ", context)?;
+        }
+        write!(f, "{:?}", self.status)
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        Self::new(status)
+    }
+}
+
+impl<T: Debug> From<uefi::Error<T>> for Error {
+    fn from(e: uefi::Error<T>) -> Self {
+        Self::new(e.status())
+    }
+}
+
+/// Context is dropped, for returning through interfaces of [uefi::Result]
+impl From<Error> for uefi::Error {
+    fn from(e: Error) -> Self {
+        e.status.into()
+    }
+}
+
+/// Attach context to errors of results
+pub trait Context<T> {
+    fn context(self, context: impl Display) -> Result<T>;
+    /// Like [Context::context] but only formats the context on error
+    fn with_context<C: Display>(self, f: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for core::result::Result<T, E> {
+    fn context(self, context: impl Display) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+    fn with_context<C: Display>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!("NOT_FOUND", Error::new(Status::NOT_FOUND).to_string());
+        let e =
+            Error::msg(Status::UNSUPPORTED, "not a ISO9660").context("failed to patch boot.iso");
+        assert_eq!(
+            "failed to patch boot.iso: not a ISO9660: UNSUPPORTED",
+            e.to_string()
+        );
+        assert_eq!(
+            vec!["failed to patch boot.iso", "not a ISO9660"],
+            e.chain().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn result_context() {
+        let res: core::result::Result<(), Status> = Err(Status::NOT_FOUND);
+        let e = res
+            .context("failed to open file")
+            .with_context(|| "failed to load image")
+            .unwrap_err();
+        assert_eq!(Status::NOT_FOUND, e.status());
+        assert_eq!(
+            "failed to load image: failed to open file: NOT_FOUND",
+            e.to_string()
+        );
+
+        let res: uefi::Result<u32> = Ok(1);
+        assert_eq!(Ok(1), res.context("unused"));
+        assert_eq!(
+            Status::ABORTED,
+            uefi::Error::from(Error::msg(Status::ABORTED, "x")).status()
+        );
+    }
+}
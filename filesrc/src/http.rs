@@ -0,0 +1,357 @@
+use super::*;
+
+use alloc::ffi::CString;
+use alloc::format;
+use core::ffi::c_void;
+use core::fmt;
+
+use uefi::proto::unsafe_protocol;
+use uefi::{CStr16, CString16, Char16};
+use uefi_raw::table::boot::{EventType, Tpl};
+use uefi_raw::{Event as RawEvent, Handle as RawHandle};
+
+use crate::url::{content_range_length, url_host};
+
+#[repr(C)]
+#[derive(Debug)]
+#[unsafe_protocol("bdc8e6af-d9bc-4379-a72a-e0c4e75dae1c")]
+pub struct HttpServiceBindingProtocol {
+    pub create_child:
+        unsafe extern "efiapi" fn(this: *mut Self, child_handle: *mut RawHandle) -> Status,
+    pub destroy_child:
+        unsafe extern "efiapi" fn(this: *mut Self, child_handle: RawHandle) -> Status,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+#[unsafe_protocol("7a59b29b-910b-4171-8242-a85a0df25b5b")]
+pub struct HttpProtocol {
+    pub get_mode_data:
+        unsafe extern "efiapi" fn(this: *mut Self, config_data: *mut HttpConfigData) -> Status,
+    pub configure:
+        unsafe extern "efiapi" fn(this: *mut Self, config_data: *const HttpConfigData) -> Status,
+    pub request: unsafe extern "efiapi" fn(this: *mut Self, token: *mut HttpToken) -> Status,
+    pub cancel: unsafe extern "efiapi" fn(this: *mut Self, token: *mut HttpToken) -> Status,
+    pub response: unsafe extern "efiapi" fn(this: *mut Self, token: *mut HttpToken) -> Status,
+    pub poll: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+}
+
+const HTTP_VERSION_11: u32 = 1;
+const HTTP_METHOD_GET: u32 = 0;
+/// `HTTP_STATUS_206_PARTIAL_CONTENT` of `EFI_HTTP_STATUS_CODE`
+const HTTP_STATUS_206_PARTIAL_CONTENT: u32 = 9;
+
+#[repr(C)]
+pub struct HttpV4AccessPoint {
+    pub use_default_address: bool,
+    pub local_address: [u8; 4],
+    pub local_subnet: [u8; 4],
+    pub local_port: u16,
+}
+
+#[repr(C)]
+pub struct HttpConfigData {
+    pub http_version: u32,
+    pub time_out_millisec: u32,
+    pub local_address_is_ipv6: bool,
+    pub access_point: *mut HttpV4AccessPoint,
+}
+
+#[repr(C)]
+pub struct HttpRequestData {
+    pub method: u32,
+    pub url: *const Char16,
+}
+
+#[repr(C)]
+pub struct HttpResponseData {
+    pub status_code: u32,
+}
+
+#[repr(C)]
+pub struct HttpHeader {
+    pub field_name: *const u8,
+    pub field_value: *const u8,
+}
+
+#[repr(C)]
+pub struct HttpMessage {
+    /// request or response data
+    pub data: *mut c_void,
+    pub header_count: usize,
+    pub headers: *mut HttpHeader,
+    pub body_length: usize,
+    pub body: *mut c_void,
+}
+
+#[repr(C)]
+pub struct HttpToken {
+    pub event: RawEvent,
+    pub status: Status,
+    pub message: *mut HttpMessage,
+}
+
+const TIMEOUT_MS: u32 = 5000;
+const POLL_INTERVAL_US: usize = 100;
+
+fn get_boot_service_raw(bt: &BootServices) -> &uefi_raw::table::boot::BootServices {
+    unsafe { &*(bt as *const BootServices as *const _) }
+}
+
+/// Pointer to protocol `P` on `handle`, which stays valid until the handle is destroyed
+unsafe fn get_protocol_mut<P: ProtocolPointer>(
+    bt: &BootServices,
+    handle: Handle,
+) -> Result<*mut P> {
+    let mut pt = get_protocol::<P>(bt, handle)?;
+    pt.get_mut()
+        .map(|r| r as *mut _)
+        .ok_or_else(|| Status::UNSUPPORTED.into())
+}
+
+/// HTTP child created on NIC handle
+struct HttpClient<'a> {
+    bt: &'a BootServices,
+    nic: Handle,
+    child: Handle,
+    http: *mut HttpProtocol,
+    url: CString16,
+    host: CString,
+}
+
+impl<'a> HttpClient<'a> {
+    fn new(bt: &'a BootServices, nic: Option<Handle>, url: &CStr16) -> Result<Self> {
+        let invalid_err = || uefi::Error::new(Status::INVALID_PARAMETER, ());
+
+        let url_str = String::from(url);
+        let Some(host) = url_host(&url_str) else {
+            log::error!("invalid URL {}", url);
+            return Err(invalid_err());
+        };
+        let host = CString::new(host).map_err(|_| invalid_err())?;
+
+        let nic = match nic {
+            Some(h) => h,
+            None => bt.get_handle_for_protocol::<HttpServiceBindingProtocol>()?,
+        };
+        let child = unsafe {
+            let sb = &mut *get_protocol_mut::<HttpServiceBindingProtocol>(bt, nic)?;
+            let mut child: RawHandle = ptr::null_mut();
+            (sb.create_child)(sb, &mut child).to_result()?;
+            Handle::from_ptr(child).ok_or_else(invalid_err)?
+        };
+
+        let mut client = Self {
+            bt,
+            nic,
+            child,
+            http: ptr::null_mut(),
+            url: url.into(),
+            host,
+        };
+        unsafe {
+            client.http = get_protocol_mut::<HttpProtocol>(bt, child)?;
+            let mut access_point = HttpV4AccessPoint {
+                use_default_address: true,
+                local_address: [0; 4],
+                local_subnet: [0; 4],
+                local_port: 0,
+            };
+            let config = HttpConfigData {
+                http_version: HTTP_VERSION_11,
+                time_out_millisec: TIMEOUT_MS,
+                local_address_is_ipv6: false,
+                access_point: &mut access_point,
+            };
+            ((*client.http).configure)(client.http, &config).to_result()?;
+        }
+        Ok(client)
+    }
+
+    /// Run `token` submitted by `f` to completion
+    unsafe fn wait_token<F>(&self, token: &mut HttpToken, f: F) -> Result
+    where
+        F: FnOnce(*mut HttpProtocol, *mut HttpToken) -> Status,
+    {
+        let bt = get_boot_service_raw(self.bt);
+        (bt.create_event)(
+            EventType::empty(),
+            Tpl::CALLBACK,
+            None,
+            ptr::null_mut(),
+            &mut token.event,
+        )
+        .to_result()?;
+        token.status = Status::NOT_READY;
+
+        let mut res = f(self.http, token).to_result();
+        if res.is_ok() {
+            let mut elapsed_us = 0;
+            while ptr::read_volatile(&token.status) == Status::NOT_READY {
+                if elapsed_us >= TIMEOUT_MS as usize * 1000 {
+                    let _ = ((*self.http).cancel)(self.http, token);
+                    res = Status::TIMEOUT.to_result();
+                    break;
+                }
+                let _ = ((*self.http).poll)(self.http);
+                (bt.stall)(POLL_INTERVAL_US);
+                elapsed_us += POLL_INTERVAL_US;
+            }
+            if res.is_ok() {
+                res = token.status.to_result();
+            }
+        }
+        (bt.close_event)(token.event);
+        res
+    }
+
+    /// GET `buffer.len()` bytes from `offset`, returns complete length of resource.
+    /// Part of `buffer` beyond end of resource is zeroed.
+    fn get_range(&self, offset: u64, buffer: &mut [u8]) -> Result<u64> {
+        let last = offset + buffer.len() as u64 - 1;
+        let range = CString::new(format!("bytes={}-{}", offset, last)).unwrap();
+        let mut headers = [
+            HttpHeader {
+                field_name: b"Host\0".as_ptr(),
+                field_value: self.host.as_ptr().cast(),
+            },
+            HttpHeader {
+                field_name: b"Range\0".as_ptr(),
+                field_value: range.as_ptr().cast(),
+            },
+        ];
+        let mut request_data = HttpRequestData {
+            method: HTTP_METHOD_GET,
+            url: self.url.as_ptr(),
+        };
+        let mut message = HttpMessage {
+            data: ptr::addr_of_mut!(request_data).cast(),
+            header_count: headers.len(),
+            headers: headers.as_mut_ptr(),
+            body_length: 0,
+            body: ptr::null_mut(),
+        };
+        let mut token = HttpToken {
+            event: ptr::null_mut(),
+            status: Status::NOT_READY,
+            message: &mut message,
+        };
+        unsafe { self.wait_token(&mut token, |http, token| ((*http).request)(http, token))? };
+
+        // response headers and first part of body
+        let mut response_data = HttpResponseData { status_code: 0 };
+        let mut message = HttpMessage {
+            data: ptr::addr_of_mut!(response_data).cast(),
+            header_count: 0,
+            headers: ptr::null_mut(),
+            body_length: buffer.len(),
+            body: buffer.as_mut_ptr().cast(),
+        };
+        token.message = &mut message;
+        unsafe { self.wait_token(&mut token, |http, token| ((*http).response)(http, token))? };
+
+        let mut length = None;
+        unsafe {
+            let headers = core::slice::from_raw_parts(message.headers, message.header_count);
+            for header in headers {
+                let name = core::ffi::CStr::from_ptr(header.field_name.cast());
+                if name.to_bytes().eq_ignore_ascii_case(b"content-range") {
+                    let value = core::ffi::CStr::from_ptr(header.field_value.cast());
+                    length = value.to_str().ok().and_then(content_range_length);
+                }
+                let _ = self.bt.free_pool(header.field_name.cast_mut());
+                let _ = self.bt.free_pool(header.field_value.cast_mut());
+            }
+            if !message.headers.is_null() {
+                let _ = self.bt.free_pool(message.headers.cast());
+            }
+        }
+        if response_data.status_code != HTTP_STATUS_206_PARTIAL_CONTENT {
+            log::error!(
+                "range request not satisfied, status {}",
+                response_data.status_code
+            );
+            return Status::UNSUPPORTED.to_result();
+        }
+        let Some(length) = length else {
+            log::error!("no valid Content-Range in response");
+            return Status::PROTOCOL_ERROR.to_result();
+        };
+
+        // rest of body
+        let mut received = message.body_length;
+        let remaining = usize::try_from(length.saturating_sub(offset)).unwrap_or(usize::MAX);
+        let expected = buffer.len().min(remaining);
+        while received < expected {
+            let rest = &mut buffer[received..expected];
+            let mut message = HttpMessage {
+                data: ptr::null_mut(),
+                header_count: 0,
+                headers: ptr::null_mut(),
+                body_length: rest.len(),
+                body: rest.as_mut_ptr().cast(),
+            };
+            token.message = &mut message;
+            unsafe { self.wait_token(&mut token, |http, token| ((*http).response)(http, token))? };
+            if message.body_length == 0 {
+                log::error!("received {} of {} bytes", received, expected);
+                return Status::END_OF_FILE.to_result();
+            }
+            received += message.body_length;
+        }
+        buffer[expected..].fill(0);
+        Ok(length)
+    }
+}
+
+impl Drop for HttpClient<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.http.is_null() {
+                let _ = ((*self.http).configure)(self.http, ptr::null());
+            }
+            if let Ok(sb) = get_protocol_mut::<HttpServiceBindingProtocol>(self.bt, self.nic) {
+                let _ = ((*sb).destroy_child)(sb, self.child.as_ptr());
+            }
+        }
+    }
+}
+
+/// Resource of HTTP(S) URL read with range requests, the server must support them
+pub struct HttpSource<'a> {
+    client: HttpClient<'a>,
+    size: u64,
+}
+
+impl<'a> HttpSource<'a> {
+    /// Connect to `url` through HTTP service of `nic`, or of the first NIC if [None]
+    pub fn new(bt: &'a BootServices, nic: Option<Handle>, url: &CStr16) -> Result<Self> {
+        let client = HttpClient::new(bt, nic, url)?;
+        let size = client.get_range(0, &mut [0])?;
+        Ok(Self { client, size })
+    }
+}
+
+impl Source for HttpSource<'_> {
+    #[inline]
+    fn size(&self) -> u64 {
+        self.size
+    }
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result {
+        check_bounds(self.size, offset, buffer.len())?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        self.client.get_range(offset, buffer)?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for HttpSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpSource")
+            .field("url", &self.client.url)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
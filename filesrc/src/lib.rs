@@ -0,0 +1,252 @@
+//! Sized data sources readable at any offset, from files on file systems, LoadFile and
+//! LoadFile2 protocols or HTTP(S) URLs, opened by paths given on command-line.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod http;
+mod url;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ptr;
+
+use uefi::prelude::*;
+use uefi::proto::device_path::{DevicePath, FfiDevicePath};
+use uefi::proto::media::file::{File, FileInfo, FileMode, RegularFile};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::{unsafe_protocol, ProtocolPointer};
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::{CString16, Result, ResultExt};
+use uefi_devpath::{device_path_from_shell_text, open_shell_path};
+
+pub use http::HttpSource;
+pub use url::is_http_url;
+
+/// Sized data readable at any offset
+pub trait Source {
+    /// Size of data in bytes
+    fn size(&self) -> u64;
+
+    /// Read exactly `buffer.len()` bytes at `offset`, fails with `END_OF_FILE` if
+    /// out of bounds of [Source::size]
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result;
+
+    /// Read all data into `buffer` of exactly [Source::size] bytes
+    fn read_to_end(&mut self, buffer: &mut [u8]) -> Result {
+        if buffer.len() as u64 != self.size() {
+            return Status::BAD_BUFFER_SIZE.to_result();
+        }
+        self.read_at(0, buffer)
+    }
+}
+
+impl<S: Source + ?Sized> Source for &mut S {
+    #[inline]
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+    #[inline]
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result {
+        (**self).read_at(offset, buffer)
+    }
+}
+
+fn check_bounds(size: u64, offset: u64, len: usize) -> Result {
+    match offset.checked_add(len as u64) {
+        Some(end) if end <= size => Ok(()),
+        _ => Status::END_OF_FILE.to_result(),
+    }
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others may be using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+impl Source for Vec<u8> {
+    #[inline]
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result {
+        check_bounds(self.size(), offset, buffer.len())?;
+        buffer.copy_from_slice(&self[offset as usize..][..buffer.len()]);
+        Ok(())
+    }
+}
+
+/// Part of `size` bytes of another source starting at `offset`
+pub struct Window<S> {
+    inner: S,
+    offset: u64,
+    size: u64,
+}
+
+impl<S: Source> Window<S> {
+    pub fn new(inner: S, offset: u64, size: u64) -> Result<Self> {
+        match offset.checked_add(size) {
+            Some(end) if end <= inner.size() => Ok(Self {
+                inner,
+                offset,
+                size,
+            }),
+            _ => Err(Status::END_OF_FILE.into()),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Source> Source for Window<S> {
+    #[inline]
+    fn size(&self) -> u64 {
+        self.size
+    }
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result {
+        check_bounds(self.size, offset, buffer.len())?;
+        self.inner.read_at(self.offset + offset, buffer)
+    }
+}
+
+/// Regular file on a file system
+pub struct FileSource {
+    file: RegularFile,
+    size: u64,
+}
+
+impl FileSource {
+    pub fn new(mut file: RegularFile) -> Result<Self> {
+        let info = file.get_boxed_info::<FileInfo>()?;
+        Ok(Self {
+            file,
+            size: info.file_size(),
+        })
+    }
+
+    pub fn into_inner(self) -> RegularFile {
+        self.file
+    }
+}
+
+impl Source for FileSource {
+    #[inline]
+    fn size(&self) -> u64 {
+        self.size
+    }
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result {
+        check_bounds(self.size, offset, buffer.len())?;
+        self.file.set_position(offset)?;
+        let mut pos = 0;
+        while pos < buffer.len() {
+            let len = self.file.read(&mut buffer[pos..]).discard_errdata()?;
+            if len == 0 {
+                return Status::END_OF_FILE.to_result();
+            }
+            pos += len;
+        }
+        Ok(())
+    }
+}
+
+type LoadFileFn = unsafe extern "efiapi" fn(
+    this: *mut c_void,
+    file_path: *const FfiDevicePath,
+    boot_policy: bool,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> Status;
+
+#[repr(C)]
+#[unsafe_protocol("56ec3091-954c-11d2-8e3f-00a0c969723b")]
+struct LoadFileProtocol {
+    load_file: LoadFileFn,
+}
+
+#[repr(C)]
+#[unsafe_protocol("4006c0c1-fcb3-403e-996d-4a6c8724e06d")]
+struct LoadFile2Protocol {
+    load_file: LoadFileFn,
+}
+
+/// Load file at rest of `dp` with `load_fn` of protocol `P` on device of `dp`
+fn load_file_with<P: ProtocolPointer>(
+    bt: &BootServices,
+    dp: &DevicePath,
+    load_fn: impl Fn(&P) -> LoadFileFn,
+) -> Result<Vec<u8>> {
+    let mut remaining = dp;
+    let device = bt.locate_device_path::<P>(&mut remaining)?;
+    let mut pt = get_protocol::<P>(bt, device)?;
+    let Some(pt) = pt.get_mut() else {
+        return Err(Status::UNSUPPORTED.into());
+    };
+    let load = load_fn(pt);
+    let this = pt as *mut P as *mut c_void;
+    let file_path = remaining.as_ffi_ptr();
+
+    let mut size = 0;
+    let status = unsafe { load(this, file_path, false, &mut size, ptr::null_mut()) };
+    match status {
+        Status::SUCCESS => return Ok(Vec::new()),
+        Status::BUFFER_TOO_SMALL => {}
+        _ => return Err(status.into()),
+    }
+    let mut data = vec![0u8; size];
+    unsafe { load(this, file_path, false, &mut size, data.as_mut_ptr().cast()) }.to_result()?;
+    data.truncate(size);
+    Ok(data)
+}
+
+/// Load whole file at `dp` with LoadFile2 or LoadFile protocol, e.g. of network boot
+/// devices or of files provided by other loaders
+pub fn load_file(bt: &BootServices, dp: &DevicePath) -> Result<Vec<u8>> {
+    load_file_with::<LoadFile2Protocol>(bt, dp, |pt| pt.load_file)
+        .or_else(|_| load_file_with::<LoadFileProtocol>(bt, dp, |pt| pt.load_file))
+}
+
+/// Open `path` as source, either a HTTP(S) URL, or a path accepted by
+/// [device_path_from_shell_text] of a file on file system or of a LoadFile(2) device
+pub fn open<'a>(bt: &'a BootServices, path: &str) -> Result<Box<dyn Source + 'a>> {
+    if is_http_url(path) {
+        let url = CString16::try_from(path).map_err(|_| Status::INVALID_PARAMETER)?;
+        return Ok(Box::new(HttpSource::new(bt, None, &url)?));
+    }
+
+    let dp = device_path_from_shell_text(bt, path)?;
+    let mut remaining: &DevicePath = &dp;
+    if bt
+        .locate_device_path::<SimpleFileSystem>(&mut remaining)
+        .is_err()
+    {
+        let data = load_file(bt, &dp).map_err(|e| {
+            log::error!("failed to load {}, {}", path, e.status());
+            e
+        })?;
+        return Ok(Box::new(data));
+    }
+
+    let Some(file) = open_shell_path(bt, path, FileMode::Read)?.into_regular_file() else {
+        log::error!("{} is not a regular file", path);
+        return Err(Status::INVALID_PARAMETER.into());
+    };
+    Ok(Box::new(FileSource::new(file)?))
+}
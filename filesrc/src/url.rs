@@ -0,0 +1,55 @@
+//! Parsing of HTTP URLs and headers
+
+/// Whether `path` is a `http://` or `https://` URL
+pub fn is_http_url(path: &str) -> bool {
+    ["http://", "https://"].iter().any(|scheme| {
+        path.get(..scheme.len())
+            .is_some_and(|p| p.eq_ignore_ascii_case(scheme))
+    })
+}
+
+/// Host portion of `http://host[:port]/path`
+pub fn url_host(url: &str) -> Option<&str> {
+    if !is_http_url(url) {
+        return None;
+    }
+    let (_, rest) = url.split_once("://")?;
+    let host = rest.split('/').next()?;
+    (!host.is_empty()).then_some(host)
+}
+
+/// Complete length in `Content-Range: bytes <first>-<last>/<length>`
+pub fn content_range_length(value: &str) -> Option<u64> {
+    let (_, length) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    length.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_urls() {
+        assert!(is_http_url("http://10.0.2.2/disk.img"));
+        assert!(is_http_url("HTTPS://example.com"));
+        assert!(!is_http_url("ftp://example.com/disk.img"));
+        assert!(!is_http_url("FS0:\\http\\disk.img"));
+        assert!(!is_http_url("http:"));
+
+        assert_eq!(
+            Some("10.0.2.2:8080"),
+            url_host("http://10.0.2.2:8080/a/b.iso")
+        );
+        assert_eq!(Some("example.com"), url_host("https://example.com"));
+        assert_eq!(None, url_host("http:///disk.img"));
+        assert_eq!(None, url_host("tftp://example.com/disk.img"));
+    }
+
+    #[test]
+    fn content_range() {
+        assert_eq!(Some(1024), content_range_length("bytes 0-511/1024"));
+        assert_eq!(Some(7), content_range_length(" bytes 0-0/7 "));
+        assert_eq!(None, content_range_length("bytes 0-511/*"));
+        assert_eq!(None, content_range_length("0-511/1024"));
+    }
+}
@@ -0,0 +1,50 @@
+//! Minimal encoder of uncompressed 24-bit BMP images
+
+use alloc::vec::Vec;
+
+use uefi::proto::console::gop::BltPixel;
+
+/// Size of BITMAPFILEHEADER and BITMAPINFOHEADER
+const HEADER_SIZE: usize = 14 + 40;
+/// 72 DPI in pixels per meter
+const PIXELS_PER_METER: u32 = 2835;
+
+/// Bytes of a row of `width` pixels, padded to multiple of 4 bytes
+fn row_size(width: usize) -> usize {
+    (width * 3 + 3) & !3
+}
+
+/// Encode `pixels` of `width` x `height` in rows from top, returns [None] if too large
+pub fn encode(width: usize, height: usize, pixels: &[BltPixel]) -> Option<Vec<u8>> {
+    assert_eq!(width * height, pixels.len());
+    let image_size = row_size(width).checked_mul(height)?;
+    let file_size = u32::try_from(HEADER_SIZE + image_size).ok()?;
+
+    let mut data = Vec::with_capacity(file_size as usize);
+    // BITMAPFILEHEADER
+    data.extend(b"BM");
+    data.extend(file_size.to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend((HEADER_SIZE as u32).to_le_bytes());
+    // BITMAPINFOHEADER, positive height for rows from bottom
+    data.extend(40u32.to_le_bytes());
+    data.extend((width as i32).to_le_bytes());
+    data.extend((height as i32).to_le_bytes());
+    data.extend(1u16.to_le_bytes());
+    data.extend(24u16.to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend((image_size as u32).to_le_bytes());
+    data.extend(PIXELS_PER_METER.to_le_bytes());
+    data.extend(PIXELS_PER_METER.to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+
+    let padding = row_size(width) - width * 3;
+    for row in pixels.chunks_exact(width.max(1)).rev() {
+        for pixel in row {
+            data.extend([pixel.blue, pixel.green, pixel.red]);
+        }
+        data.extend(core::iter::repeat(0).take(padding));
+    }
+    Some(data)
+}
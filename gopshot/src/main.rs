@@ -0,0 +1,264 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+mod bmp;
+
+use alloc::format;
+use alloc::vec;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::println;
+use uefi::proto::console::gop::{BltOp, BltPixel, BltRegion, GraphicsOutput, Mode, ModeInfo};
+use uefi::proto::media::file::{File, FileMode, RegularFile};
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi_cli::{find_command, App, ArgsError, Size};
+use uefi_devpath::open_shell_path;
+use uefi_error::{Context, Error, Result};
+
+const APP: App = App {
+    help: "\
+Usage: {name} <COMMAND> [ARG]
+
+  List and switch modes of graphics output, or capture screen to a BMP file
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+
+Commands:
+  modes                 List modes, the current one is marked with `*`
+  set MODE              Switch to MODE, either a number listed by `modes` or
+                        resolution like 1024x768
+  shot [FILE]           Capture screen to FILE in BMP format, defaults to
+                        screenshot.bmp next to this image without UEFI shell
+
+EXAMPLE:
+  * Switch to 1280x720 and capture screen to root of ESP
+  {name} set 1280x720
+  {name} shot FS0:\\boot-menu.bmp
+",
+    version: uefi_cli::version!(),
+};
+
+const DEFAULT_FILE: &str = "screenshot.bmp";
+
+#[derive(Clone, Copy)]
+enum CommandType {
+    Modes,
+    Set,
+    Shot,
+}
+
+enum Command<'a> {
+    NoOp,
+    Modes,
+    Set(&'a str),
+    Shot(&'a str),
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> core::result::Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("modes", CommandType::Modes),
+        ("set", CommandType::Set),
+        ("shot", CommandType::Shot),
+    ];
+
+    let mut command_type = None;
+    let mut value = None;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(cmd) if command_type.is_none() => {
+                command_type = Some(find_command(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?);
+            }
+            Arg::Positional(v) if value.is_none() => value = Some(v),
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(command_type) = command_type else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    match (command_type, value) {
+        (CommandType::Modes, None) => Ok(Command::Modes),
+        (CommandType::Set, Some(mode)) => Ok(Command::Set(mode)),
+        (CommandType::Set, None) => {
+            println!("MODE not specified");
+            Err(ArgsError::Invalid)
+        }
+        (CommandType::Shot, file) => Ok(Command::Shot(file.unwrap_or(DEFAULT_FILE))),
+        (_, Some(v)) => Err(ArgsError::Unexpected(Arg::Positional(v))),
+    }
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => Ok(()),
+        Ok(Command::Modes) => list_modes(bt),
+        Ok(Command::Set(mode)) => set_mode(bt, mode),
+        Ok(Command::Shot(file)) => capture(bt, file),
+    };
+
+    uefi_cli::exit_status(res)
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others are using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> uefi::Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+/// Graphics output of console, exclusive access would disconnect the console from it
+fn open_gop(bt: &BootServices) -> Result<ScopedProtocol<GraphicsOutput>> {
+    let handle = bt
+        .get_handle_for_protocol::<GraphicsOutput>()
+        .context("no graphics output available")?;
+    Ok(get_protocol::<GraphicsOutput>(bt, handle)?)
+}
+
+fn is_same_mode(a: &ModeInfo, b: &ModeInfo) -> bool {
+    a.resolution() == b.resolution()
+        && a.pixel_format() == b.pixel_format()
+        && a.stride() == b.stride()
+}
+
+fn list_modes(bt: &BootServices) -> Result {
+    let gop = open_gop(bt)?;
+    let current = gop.current_mode_info();
+    for (index, mode) in gop.modes().enumerate() {
+        let info = mode.info();
+        let (width, height) = info.resolution();
+        let mark = if is_same_mode(info, &current) {
+            '*'
+        } else {
+            ' '
+        };
+        println!(
+            "{} {:>3} {:>5}x{:<5} {:?}, stride {}",
+            mark,
+            index,
+            width,
+            height,
+            info.pixel_format(),
+            info.stride()
+        );
+    }
+    Ok(())
+}
+
+/// Find mode of `index` or resolution `spec`, e.g. "1024x768"
+fn find_mode(gop: &GraphicsOutput, spec: &str) -> Option<Mode> {
+    if let Ok(index) = spec.parse::<usize>() {
+        return gop.modes().nth(index);
+    }
+    let (width, height) = spec.split_once(['x', 'X'])?;
+    let resolution = (width.parse().ok()?, height.parse().ok()?);
+    gop.modes().find(|m| m.info().resolution() == resolution)
+}
+
+fn set_mode(bt: &BootServices, spec: &str) -> Result {
+    let mut gop = open_gop(bt)?;
+    let Some(mode) = find_mode(&gop, spec) else {
+        let msg = format!("mode {} not found", spec);
+        return Err(Error::msg(Status::NOT_FOUND, msg));
+    };
+    gop.set_mode(&mode)
+        .with_context(|| format!("failed to switch to mode {}", spec))?;
+    let (width, height) = gop.current_mode_info().resolution();
+    println!("Switched to {}x{}", width, height);
+    Ok(())
+}
+
+/// Create or truncate file at `path`
+fn create_file(bt: &BootServices, path: &str) -> Result<RegularFile> {
+    open_shell_path(bt, path, FileMode::CreateReadWrite)?.delete()?;
+    let Some(file) = open_shell_path(bt, path, FileMode::CreateReadWrite)?.into_regular_file()
+    else {
+        let msg = format!("{} is not a file", path);
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    };
+    Ok(file)
+}
+
+fn capture(bt: &BootServices, path: &str) -> Result {
+    let mut gop = open_gop(bt)?;
+    let (width, height) = gop.current_mode_info().resolution();
+    // read through BLT as framebuffer is absent in BltOnly modes
+    let mut pixels = vec![BltPixel::new(0, 0, 0); width * height];
+    gop.blt(BltOp::VideoToBltBuffer {
+        buffer: &mut pixels,
+        src: (0, 0),
+        dest: BltRegion::Full,
+        dims: (width, height),
+    })
+    .context("failed to read screen")?;
+    drop(gop);
+
+    let Some(data) = bmp::encode(width, height, &pixels) else {
+        let msg = format!("screen too large {}x{}", width, height);
+        return Err(Error::msg(Status::BAD_BUFFER_SIZE, msg));
+    };
+    let mut file = create_file(bt, path).with_context(|| format!("failed to create {}", path))?;
+    file.write(&data)
+        .with_context(|| format!("failed to write {}", path))?;
+    file.flush()
+        .with_context(|| format!("failed to write {}", path))?;
+    println!(
+        "Captured {}x{} to {} ({})",
+        width,
+        height,
+        path,
+        Size(data.len() as u64)
+    );
+    Ok(())
+}
@@ -0,0 +1,552 @@
+//! Reader of ISO 9660 file systems walking directory records,
+//! with Joliet and Rock Ridge names
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "uefi")]
+mod uefi_backend;
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::{self, Display};
+use core::ops::ControlFlow;
+
+#[cfg(feature = "uefi")]
+pub use uefi_backend::BlockIoBackend;
+
+pub const ISO_BLOCK_SIZE: usize = 2048;
+
+/// Size of root directory record in volume descriptor
+const ROOT_RECORD_SIZE: usize = 34;
+/// Offset of root directory record in volume descriptor
+const ROOT_RECORD_OFFSET: u64 = 156;
+/// Escape sequences of UCS-2 level 1, 2 and 3 of Joliet
+const JOLIET_ESCAPES: &[&[u8]] = &[b"%/@", b"%/C", b"%/E"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NotIso9660,
+    /// Volume descriptor not found
+    NotFound,
+    BufferTooSmall,
+    /// Read beyond end of backend
+    OutOfBounds,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Error::NotIso9660 => "not a ISO9660",
+            Error::NotFound => "volume descriptor not found",
+            Error::BufferTooSmall => "buffer too small for record",
+            Error::OutOfBounds => "read out of bounds",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// Storage the file system is read from
+pub trait Backend {
+    type Error: From<Error>;
+    /// Fill whole `buffer` with data at byte `position`
+    fn read_at(&mut self, position: u64, buffer: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+impl<B: Backend + ?Sized> Backend for &mut B {
+    type Error = B::Error;
+    #[inline]
+    fn read_at(&mut self, position: u64, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        (**self).read_at(position, buffer)
+    }
+}
+
+impl Backend for &[u8] {
+    type Error = Error;
+    fn read_at(&mut self, position: u64, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let data = usize::try_from(position)
+            .ok()
+            .and_then(|pos| self.get(pos..)?.get(..buffer.len()))
+            .ok_or(Error::OutOfBounds)?;
+        buffer.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Source of file identifiers in paths of walked records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Names {
+    /// Identifiers in primary volume, e.g. `VMLINUZ.`
+    #[default]
+    Iso9660,
+    /// UCS-2 identifiers in Joliet supplementary volume
+    Joliet,
+    /// Rock Ridge `NM` entries in primary volume, identifiers of records without
+    /// one are used as is. Entries in continuation areas are not read.
+    RockRidge,
+}
+
+pub struct ISO9660<B> {
+    backend: B,
+    names: Names,
+}
+
+pub struct WalkRecordInfo<'a, 'b, 'c, B> {
+    pub file: &'a mut ISO9660<B>,
+    pub record: &'b [u8],
+    pub record_position: u64,
+    pub record_size: usize,
+    pub extent_position: u64,
+    pub extent_size: usize,
+    pub path: &'c str,
+    pub is_dir: bool,
+    pub file_version: u16,
+}
+
+#[inline]
+fn is_volume_descriptor(vd: &[u8]) -> bool {
+    &vd[1..6] == b"CD001" && vd[6] == 1
+}
+
+fn joliet_name(id: &[u8]) -> String {
+    let units = id.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Name in `NM` entries of system use `area`, none for current or parent directory
+fn rock_ridge_name(mut area: &[u8]) -> Option<String> {
+    let mut name: Option<String> = None;
+    while area.len() >= 4 {
+        let len = area[2] as usize;
+        if len < 4 || len > area.len() || &area[..2] == b"ST" {
+            break;
+        }
+        if &area[..2] == b"NM" && len >= 5 {
+            let flags = area[4];
+            // CURRENT or PARENT
+            if flags & 0b110 != 0 {
+                return None;
+            }
+            let part = String::from_utf8_lossy(&area[5..len]);
+            name.get_or_insert_with(String::new).push_str(&part);
+            // not CONTINUE
+            if flags & 0b1 == 0 {
+                break;
+            }
+        }
+        area = &area[len..];
+    }
+    name
+}
+
+impl<B: Backend> ISO9660<B> {
+    pub fn new(backend: B) -> Result<Self, B::Error> {
+        let mut iso9660 = Self {
+            backend,
+            names: Names::default(),
+        };
+        let mut buffer = [0u8; 7];
+        iso9660.read(16 * ISO_BLOCK_SIZE as u64, &mut buffer)?;
+        if !is_volume_descriptor(&buffer) {
+            return Err(Error::NotIso9660.into());
+        }
+        Ok(iso9660)
+    }
+
+    /// Use `names` for paths of walked records and select the volume
+    /// [ISO9660::find_root_record] returns
+    pub fn with_names(mut self, names: Names) -> Self {
+        self.names = names;
+        self
+    }
+
+    #[inline]
+    pub fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    #[inline]
+    pub fn read(&mut self, position: u64, buffer: &mut [u8]) -> Result<(), B::Error> {
+        self.backend.read_at(position, buffer)
+    }
+
+    /// Position of the first volume descriptor `f` returns true for
+    pub fn find_volume_descriptor<F>(&mut self, mut f: F) -> Result<u64, B::Error>
+    where
+        F: FnMut(&[u8; ISO_BLOCK_SIZE]) -> bool,
+    {
+        let mut buffer = [0u8; ISO_BLOCK_SIZE];
+
+        let mut start = 16;
+        loop {
+            self.read(start * ISO_BLOCK_SIZE as u64, &mut buffer)?;
+            if !is_volume_descriptor(&buffer) {
+                return Err(Error::NotIso9660.into());
+            }
+
+            match buffer[0] {
+                255 => return Err(Error::NotFound.into()),
+                _ if f(&buffer) => break,
+                _ => {}
+            }
+            start += 1;
+        }
+        Ok(start * ISO_BLOCK_SIZE as u64)
+    }
+
+    #[inline]
+    pub fn find_pvd_position(&mut self) -> Result<u64, B::Error> {
+        self.find_volume_descriptor(|vd| vd[0] == 1)
+    }
+
+    #[inline]
+    pub fn find_joliet_position(&mut self) -> Result<u64, B::Error> {
+        self.find_volume_descriptor(|vd| vd[0] == 2 && JOLIET_ESCAPES.contains(&&vd[88..91]))
+    }
+
+    /// Position and size of root directory record of the volume of [Names] in use
+    pub fn find_root_record(&mut self) -> Result<(u64, usize), B::Error> {
+        let vd_pos = match self.names {
+            Names::Joliet => self.find_joliet_position()?,
+            Names::Iso9660 | Names::RockRidge => self.find_pvd_position()?,
+        };
+        Ok((vd_pos + ROOT_RECORD_OFFSET, ROOT_RECORD_SIZE))
+    }
+
+    /// Name of `record` and whether it has a file version suffix
+    fn record_name(&self, record: &[u8]) -> (String, bool) {
+        let id_len = record[32] as usize;
+        let id_slice = &record[33..33 + id_len];
+        // identifiers of current and parent directory are single byte in all volumes
+        if self.names == Names::Joliet && id_len > 1 {
+            return (joliet_name(id_slice), true);
+        }
+        if self.names == Names::RockRidge {
+            // system use area starts at even offset
+            let area_start = 33 + id_len + (1 - id_len % 2);
+            if let Some(name) = record.get(area_start..).and_then(rock_ridge_name) {
+                return (name, false);
+            }
+        }
+        let id_slice = match id_slice.iter().position(|b| *b == 0) {
+            None => id_slice,
+            Some(nul_pos) => &id_slice[..nul_pos],
+        };
+        (String::from_utf8_lossy(id_slice).into_owned(), true)
+    }
+
+    /// Call `f` with record at `record_position` and then, for directories,
+    /// recursively with each record in it. `buffer` holds records read and
+    /// must be at least 255 bytes.
+    pub fn walk_record<T, F>(
+        &mut self,
+        buffer: &mut [u8],
+        record_position: u64,
+        record_size: usize,
+        parent_path: &str,
+        f: &mut F,
+    ) -> Result<ControlFlow<T>, B::Error>
+    where
+        F: FnMut(WalkRecordInfo<B>) -> Result<ControlFlow<T>, B::Error>,
+    {
+        if buffer.len() < u8::MAX as _ {
+            return Err(Error::BufferTooSmall.into());
+        }
+        let record = &mut buffer[..record_size];
+        self.read(record_position, record)?;
+
+        let file_flags = record[25];
+        let is_dir = (file_flags & 0b00000010) != 0;
+        let not_final_record = (file_flags & 0b01000000) != 0;
+        if not_final_record {
+            log::warn!("handling of multi-records file not implemented")
+        }
+
+        let (mut id, versioned) = self.record_name(record);
+        let file_version = if is_dir {
+            0
+        } else if !versioned {
+            1
+        } else {
+            match id.rfind(';') {
+                Some(idx) => {
+                    let version = id[idx + 1..].parse().unwrap_or(1);
+                    id.truncate(idx);
+                    version
+                }
+                None => 1,
+            }
+        };
+
+        let path = if id.is_empty() && parent_path.is_empty() {
+            String::new()
+        } else {
+            let parent_path = parent_path.trim_end_matches('/');
+            let id = id.trim_start_matches('/');
+            format!("{}/{}", parent_path, id)
+        };
+
+        let extent_lba = u32::from_le_bytes(record[2..6].try_into().unwrap()) as u64;
+        let extent_size = u32::from_le_bytes(record[10..14].try_into().unwrap()) as usize;
+        let mut position = extent_lba * ISO_BLOCK_SIZE as u64;
+
+        let flow = f(WalkRecordInfo {
+            file: self,
+            record,
+            record_position,
+            record_size,
+            extent_position: position,
+            extent_size,
+            path: &path,
+            is_dir,
+            file_version,
+        })?;
+        if !is_dir {
+            return Ok(flow);
+        }
+        if let ControlFlow::Break(b) = flow {
+            return Ok(ControlFlow::Break(b));
+        }
+
+        let mut block_num = 0;
+        let num_blocks = (extent_size + ISO_BLOCK_SIZE - 1) / ISO_BLOCK_SIZE;
+        let mut count = 0;
+        while block_num < num_blocks {
+            count += 1;
+
+            let mut size = [0u8; 1];
+            self.read(position, &mut size).map_err(|e| {
+                log::error!("failed to read record size {}", position);
+                e
+            })?;
+            let size = size[0] as usize;
+
+            if size == 0 || (position % ISO_BLOCK_SIZE as u64) + 34 > ISO_BLOCK_SIZE as u64 {
+                block_num += 1;
+                position = (block_num as u64 + extent_lba) * ISO_BLOCK_SIZE as u64;
+                continue;
+            }
+
+            if count > 2 {
+                if let ControlFlow::Break(v) = self.walk_record(buffer, position, size, &path, f)? {
+                    return Ok(ControlFlow::Break(v));
+                }
+            }
+
+            position += size as u64;
+            block_num = ((position / ISO_BLOCK_SIZE as u64) - extent_lba) as usize;
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_DATA: &[u8] = b"hello";
+
+    /// Append directory record, padded to even size
+    fn push_record(dir: &mut Vec<u8>, lba: u32, size: u32, is_dir: bool, id: &[u8], su: &[u8]) {
+        let id_pad = 1 - id.len() % 2;
+        let len = 33 + id.len() + id_pad + su.len();
+        let len = len + len % 2;
+        let start = dir.len();
+        dir.resize(start + len, 0);
+        let record = &mut dir[start..];
+        record[0] = len as u8;
+        record[2..6].copy_from_slice(&lba.to_le_bytes());
+        record[6..10].copy_from_slice(&lba.to_be_bytes());
+        record[10..14].copy_from_slice(&size.to_le_bytes());
+        record[14..18].copy_from_slice(&size.to_be_bytes());
+        record[25] = if is_dir { 2 } else { 0 };
+        record[32] = id.len() as u8;
+        record[33..][..id.len()].copy_from_slice(id);
+        record[33 + id.len() + id_pad..][..su.len()].copy_from_slice(su);
+    }
+
+    fn push_dir_entries(dir: &mut Vec<u8>, lba: u32, parent_lba: u32) {
+        push_record(dir, lba, ISO_BLOCK_SIZE as u32, true, &[0], &[]);
+        push_record(dir, parent_lba, ISO_BLOCK_SIZE as u32, true, &[1], &[]);
+    }
+
+    fn ucs2(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    fn nm(flags: u8, name: &str) -> Vec<u8> {
+        let mut entry = vec![b'N', b'M', 5 + name.len() as u8, 1, flags];
+        entry.extend_from_slice(name.as_bytes());
+        entry
+    }
+
+    /// Image with primary and Joliet volumes of
+    /// `/BOOT/VMLINUZ.;1`, `/BOOT/INITRD.IMG;1` and `/README.TXT;1`,
+    /// the last has Rock Ridge name `readme.txt`
+    fn fixture() -> Vec<u8> {
+        let mut image = vec![0u8; 28 * ISO_BLOCK_SIZE];
+        let block = |lba: usize| lba * ISO_BLOCK_SIZE..(lba + 1) * ISO_BLOCK_SIZE;
+
+        let mut vd = |lba: usize, ty: u8, root_lba: u32| {
+            let vd = &mut image[block(lba)];
+            vd[0] = ty;
+            vd[1..7].copy_from_slice(b"CD001\x01");
+            let mut root = Vec::new();
+            push_record(&mut root, root_lba, ISO_BLOCK_SIZE as u32, true, &[0], &[]);
+            vd[ROOT_RECORD_OFFSET as usize..][..root.len()].copy_from_slice(&root);
+        };
+        vd(16, 1, 20);
+        vd(17, 2, 22);
+        vd(18, 255, 0);
+        image[block(17)][88..91].copy_from_slice(b"%/E");
+
+        // primary root
+        let mut dir = Vec::new();
+        push_dir_entries(&mut dir, 20, 20);
+        push_record(&mut dir, 26, 2 * ISO_BLOCK_SIZE as u32, true, b"BOOT", &[]);
+        let mut su = nm(0b1, "readme");
+        su.extend(nm(0, ".txt"));
+        push_record(
+            &mut dir,
+            24,
+            FILE_DATA.len() as u32,
+            false,
+            b"README.TXT;1",
+            &su,
+        );
+        image[block(20)][..dir.len()].copy_from_slice(&dir);
+
+        // primary BOOT spanning two blocks
+        let mut dir = Vec::new();
+        push_dir_entries(&mut dir, 26, 20);
+        push_record(&mut dir, 24, 3000, false, b"VMLINUZ.;1", &[]);
+        image[block(26)][..dir.len()].copy_from_slice(&dir);
+        let mut dir = Vec::new();
+        push_record(&mut dir, 24, 0, false, b"INITRD.IMG;2", &[]);
+        image[block(27)][..dir.len()].copy_from_slice(&dir);
+
+        // Joliet root and boot
+        let mut dir = Vec::new();
+        push_dir_entries(&mut dir, 22, 22);
+        push_record(
+            &mut dir,
+            23,
+            ISO_BLOCK_SIZE as u32,
+            true,
+            &ucs2("boot"),
+            &[],
+        );
+        push_record(&mut dir, 24, 5, false, &ucs2("readme.txt;1"), &[]);
+        image[block(22)][..dir.len()].copy_from_slice(&dir);
+        let mut dir = Vec::new();
+        push_dir_entries(&mut dir, 23, 22);
+        push_record(&mut dir, 24, 3000, false, &ucs2("vmlinuz;1"), &[]);
+        image[block(23)][..dir.len()].copy_from_slice(&dir);
+
+        image[block(24)][..FILE_DATA.len()].copy_from_slice(FILE_DATA);
+        image
+    }
+
+    /// Walked records as (path, is_dir, file_version, extent_size)
+    fn walk(image: &[u8], names: Names) -> Vec<(String, bool, u16, usize)> {
+        let mut iso9660 = ISO9660::new(image).unwrap().with_names(names);
+        let (record_pos, record_size) = iso9660.find_root_record().unwrap();
+        let mut buffer = [0u8; 255];
+        let mut records = Vec::new();
+        let flow =
+            iso9660.walk_record::<(), _>(&mut buffer, record_pos, record_size, "", &mut |info| {
+                records.push((
+                    info.path.into(),
+                    info.is_dir,
+                    info.file_version,
+                    info.extent_size,
+                ));
+                Ok(ControlFlow::Continue(()))
+            });
+        assert_eq!(Ok(ControlFlow::Continue(())), flow);
+        records
+    }
+
+    fn file(path: &str, file_version: u16, extent_size: usize) -> (String, bool, u16, usize) {
+        (path.into(), false, file_version, extent_size)
+    }
+
+    fn dir(path: &str) -> (String, bool, u16, usize) {
+        (path.into(), true, 0, ISO_BLOCK_SIZE)
+    }
+
+    #[test]
+    fn not_iso9660() {
+        let image = vec![0u8; 20 * ISO_BLOCK_SIZE];
+        assert!(matches!(ISO9660::new(&image[..]), Err(Error::NotIso9660)));
+        assert!(matches!(
+            ISO9660::new(&image[..100]),
+            Err(Error::OutOfBounds)
+        ));
+
+        let mut image = fixture();
+        image[17 * ISO_BLOCK_SIZE + 88] = 0;
+        let mut iso9660 = ISO9660::new(&image[..]).unwrap();
+        assert_eq!(Err(Error::NotFound), iso9660.find_joliet_position());
+        assert_eq!(Ok(16 * ISO_BLOCK_SIZE as u64), iso9660.find_pvd_position());
+    }
+
+    #[test]
+    fn walk_primary() {
+        let records = walk(&fixture(), Names::Iso9660);
+        let expected = [
+            dir(""),
+            (String::from("/BOOT"), true, 0, 2 * ISO_BLOCK_SIZE),
+            file("/BOOT/VMLINUZ.", 1, 3000),
+            file("/BOOT/INITRD.IMG", 2, 0),
+            file("/README.TXT", 1, 5),
+        ];
+        assert_eq!(&expected[..], &records);
+    }
+
+    #[test]
+    fn walk_joliet() {
+        let records = walk(&fixture(), Names::Joliet);
+        let expected = [
+            dir(""),
+            dir("/boot"),
+            file("/boot/vmlinuz", 1, 3000),
+            file("/readme.txt", 1, 5),
+        ];
+        assert_eq!(&expected[..], &records);
+    }
+
+    #[test]
+    fn walk_rock_ridge() {
+        let records = walk(&fixture(), Names::RockRidge);
+        assert_eq!(file("/readme.txt", 1, 5), records[4]);
+        assert_eq!("/BOOT/VMLINUZ.", records[2].0);
+    }
+
+    #[test]
+    fn break_walk() {
+        let image = fixture();
+        let mut iso9660 = ISO9660::new(&image[..]).unwrap();
+        let (record_pos, record_size) = iso9660.find_root_record().unwrap();
+        let mut buffer = [0u8; 255];
+        let flow = iso9660.walk_record(&mut buffer, record_pos, record_size, "", &mut |info| {
+            if info.path != "/README.TXT" {
+                return Ok(ControlFlow::Continue(()));
+            }
+            let mut data = vec![0u8; info.extent_size];
+            info.file.read(info.extent_position, &mut data)?;
+            Ok(ControlFlow::Break(data))
+        });
+        assert_eq!(Ok(ControlFlow::Break(FILE_DATA.to_vec())), flow);
+
+        let mut small = [0u8; 34];
+        let res =
+            iso9660.walk_record::<(), _>(&mut small, record_pos, record_size, "", &mut |_| {
+                Ok(ControlFlow::Continue(()))
+            });
+        assert_eq!(Err(Error::BufferTooSmall), res);
+    }
+}
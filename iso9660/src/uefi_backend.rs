@@ -0,0 +1,68 @@
+use alloc::vec::Vec;
+
+use uefi::proto::media::block::BlockIO;
+use uefi::proto::media::file::{File, RegularFile};
+use uefi::Status;
+
+use crate::{Backend, Error};
+
+impl From<Error> for uefi::Error {
+    fn from(e: Error) -> Self {
+        let status = match e {
+            Error::NotIso9660 => Status::ABORTED,
+            Error::NotFound => Status::NOT_FOUND,
+            Error::BufferTooSmall => Status::BUFFER_TOO_SMALL,
+            Error::OutOfBounds => Status::DEVICE_ERROR,
+        };
+        status.into()
+    }
+}
+
+impl Backend for RegularFile {
+    type Error = uefi::Error;
+    fn read_at(&mut self, position: u64, buffer: &mut [u8]) -> uefi::Result {
+        self.set_position(position)?;
+        if self.read(buffer)? != buffer.len() {
+            log::error!("read underflow");
+            return Status::DEVICE_ERROR.to_result();
+        }
+        Ok(())
+    }
+}
+
+/// Read from blocks of a [BlockIO] device, e.g. a CD-ROM or a loop device
+pub struct BlockIoBackend<'a> {
+    block_io: &'a BlockIO,
+    /// whole blocks covering the last read
+    buffer: Vec<u8>,
+}
+
+impl<'a> BlockIoBackend<'a> {
+    pub fn new(block_io: &'a BlockIO) -> Self {
+        Self {
+            block_io,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl Backend for BlockIoBackend<'_> {
+    type Error = uefi::Error;
+    fn read_at(&mut self, position: u64, buffer: &mut [u8]) -> uefi::Result {
+        let media = self.block_io.media();
+        let block_size = media.block_size() as u64;
+        let start_lba = position / block_size;
+        let end_lba = (position + buffer.len() as u64 + block_size - 1) / block_size;
+        if end_lba > media.last_block() + 1 {
+            return Err(Error::OutOfBounds.into());
+        }
+
+        self.buffer
+            .resize(((end_lba - start_lba) * block_size) as usize, 0);
+        self.block_io
+            .read_blocks(media.media_id(), start_lba, &mut self.buffer)?;
+        let offset = (position - start_lba * block_size) as usize;
+        buffer.copy_from_slice(&self.buffer[offset..][..buffer.len()]);
+        Ok(())
+    }
+}
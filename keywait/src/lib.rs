@@ -0,0 +1,97 @@
+//! Waiting for a key press with timeout, e.g. for "press any key to skip" prompts and
+//! countdowns before irreversible operations.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod names;
+
+use alloc::format;
+use alloc::string::String;
+
+use uefi::prelude::*;
+use uefi::proto::console::text::{Input, Key};
+use uefi::table::boot::{EventType, TimerTrigger, Tpl};
+use uefi::{Event, Result};
+
+/// Timer ticks of 100ns in a second
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// Name of `key`, e.g. `a`, `ENTER` or `F1`
+pub fn key_name(key: &Key) -> String {
+    match key {
+        Key::Printable(c) => {
+            let c = char::from(*c);
+            names::control_char_name(c).map_or_else(|| c.into(), String::from)
+        }
+        Key::Special(scan) => names::scan_code_name(scan.0)
+            .map_or_else(|| format!("SCAN{:#04x}", scan.0), String::from),
+    }
+}
+
+/// Discard keys pressed before
+pub fn flush_keys(stdin: &mut Input) -> Result {
+    while stdin.read_key()?.is_some() {}
+    Ok(())
+}
+
+fn key_event(stdin: &Input) -> Result<Event> {
+    stdin
+        .wait_for_key_event()
+        .ok_or_else(|| Status::UNSUPPORTED.into())
+}
+
+/// Wait for a key for at most `timeout` seconds, or forever if [None].
+/// Returns the key pressed, or [None] on timeout.
+pub fn wait_key(bt: &BootServices, stdin: &mut Input, timeout: Option<u64>) -> Result<Option<Key>> {
+    let Some(timeout) = timeout else {
+        loop {
+            bt.wait_for_event(&mut [key_event(stdin)?])
+                .discard_errdata()?;
+            if let Some(key) = stdin.read_key()? {
+                return Ok(Some(key));
+            }
+        }
+    };
+    countdown(bt, stdin, timeout, |_| {})
+}
+
+/// Wait for a key for `seconds`, calling `tick` with remaining seconds at start and after
+/// each second elapsed. Returns the key pressed, or [None] on timeout.
+pub fn countdown(
+    bt: &BootServices,
+    stdin: &mut Input,
+    seconds: u64,
+    mut tick: impl FnMut(u64),
+) -> Result<Option<Key>> {
+    let timer = unsafe { bt.create_event(EventType::TIMER, Tpl::APPLICATION, None, None)? };
+    let res = countdown_with_timer(bt, stdin, &timer, seconds, &mut tick);
+    let _ = bt.close_event(timer);
+    res
+}
+
+fn countdown_with_timer(
+    bt: &BootServices,
+    stdin: &mut Input,
+    timer: &Event,
+    seconds: u64,
+    tick: &mut dyn FnMut(u64),
+) -> Result<Option<Key>> {
+    bt.set_timer(timer, TimerTrigger::Periodic(TICKS_PER_SECOND))?;
+    let mut remaining = seconds;
+    tick(remaining);
+    while remaining > 0 {
+        let mut events = unsafe { [key_event(stdin)?, timer.unsafe_clone()] };
+        let index = bt.wait_for_event(&mut events).discard_errdata()?;
+        if index == 0 {
+            if let Some(key) = stdin.read_key()? {
+                return Ok(Some(key));
+            }
+        } else {
+            remaining -= 1;
+            tick(remaining);
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,45 @@
+//! Names of keys as printed and stored in shell variables
+
+/// Name of special key of UEFI scan code `scan`
+pub fn scan_code_name(scan: u16) -> Option<&'static str> {
+    const NAMES: &[&str] = &[
+        "UP", "DOWN", "RIGHT", "LEFT", "HOME", "END", "INSERT", "DELETE", "PAGEUP", "PAGEDOWN",
+        "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "ESC",
+    ];
+    let index = usize::from(scan).checked_sub(1)?;
+    NAMES.get(index).copied()
+}
+
+/// Name of printable key `c` that is a control or whitespace character
+pub fn control_char_name(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\r' | '\n' => "ENTER",
+        '\t' => "TAB",
+        '\x08' => "BACKSPACE",
+        ' ' => "SPACE",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_codes() {
+        assert_eq!(None, scan_code_name(0));
+        assert_eq!(Some("UP"), scan_code_name(0x01));
+        assert_eq!(Some("PAGEDOWN"), scan_code_name(0x0a));
+        assert_eq!(Some("F1"), scan_code_name(0x0b));
+        assert_eq!(Some("F12"), scan_code_name(0x16));
+        assert_eq!(Some("ESC"), scan_code_name(0x17));
+        assert_eq!(None, scan_code_name(0x18));
+    }
+
+    #[test]
+    fn control_chars() {
+        assert_eq!(Some("ENTER"), control_char_name('\r'));
+        assert_eq!(Some("SPACE"), control_char_name(' '));
+        assert_eq!(None, control_char_name('a'));
+    }
+}
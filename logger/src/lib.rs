@@ -0,0 +1,216 @@
+//! Logger of tools in the workspace with level and sinks selected at runtime.
+//!
+//! It's configured by a spec of comma separated level and sink names, e.g. "debug,serial,file",
+//! read from UEFI variable `UefiToysLog` of vendor [VENDOR] on init and from command-line later.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::fmt::{self, Write};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use uefi::helpers::system_table;
+use uefi::prelude::*;
+use uefi::proto::console::serial::Serial;
+use uefi::proto::media::file::{File, FileAttribute, FileMode, RegularFile};
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams};
+use uefi::table::runtime::VariableVendor;
+use uefi::{guid, CStr16};
+
+/// Vendor of `UefiToysLog` variable
+pub const VENDOR: VariableVendor = VariableVendor(guid!("e6d863c9-137e-49d9-84f3-474508363174"));
+const VARIABLE_NAME: &CStr16 = cstr16!("UefiToysLog");
+/// Created in root of the file system the image is loaded from, usually the ESP
+const LOG_FILE: &CStr16 = cstr16!("uefi-toys.log");
+
+const SINK_CON_OUT: u8 = 1;
+const SINK_SERIAL: u8 = 1 << 1;
+const SINK_FILE: u8 = 1 << 2;
+
+struct Logger {
+    sinks: AtomicU8,
+    serial: AtomicPtr<Serial>,
+    file: AtomicPtr<RegularFile>,
+    /// records logged while writing to sinks are dropped,
+    /// e.g. by file system driver or loop device backing the log file
+    busy: AtomicBool,
+}
+
+static LOGGER: Logger = Logger {
+    sinks: AtomicU8::new(SINK_CON_OUT),
+    serial: AtomicPtr::new(ptr::null_mut()),
+    file: AtomicPtr::new(ptr::null_mut()),
+    busy: AtomicBool::new(false),
+};
+
+fn write_record<W: Write>(w: &mut W, record: &Record) -> fmt::Result {
+    writeln!(
+        w,
+        "[{:>5}]: {:>12}@{:03}: {}",
+        record.level(),
+        record.file().unwrap_or("<unknown>"),
+        record.line().unwrap_or(0),
+        record.args()
+    )
+}
+
+struct SerialWriter<'a>(&'a mut Serial);
+impl Write for SerialWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.0.write(b"\r\n").map_err(|_| fmt::Error)?;
+            }
+            self.0.write(line.as_bytes()).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+struct FileWriter<'a>(&'a mut RegularFile);
+impl Write for FileWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) || self.busy.swap(true, Ordering::Acquire) {
+            return;
+        }
+        let sinks = self.sinks.load(Ordering::Relaxed);
+        if sinks & SINK_CON_OUT != 0 {
+            let _ = write_record(system_table().stdout(), record);
+        }
+        if sinks & SINK_SERIAL != 0 {
+            if let Some(serial) = unsafe { self.serial.load(Ordering::Relaxed).as_mut() } {
+                let _ = write_record(&mut SerialWriter(serial), record);
+            }
+        }
+        if sinks & SINK_FILE != 0 {
+            if let Some(file) = unsafe { self.file.load(Ordering::Relaxed).as_mut() } {
+                // persist each record in case of crash
+                if write_record(&mut FileWriter(file), record).is_ok() {
+                    let _ = file.flush();
+                }
+            }
+        }
+        self.busy.store(false, Ordering::Release);
+    }
+
+    fn flush(&self) {}
+}
+
+fn open_serial(bt: &BootServices) -> Option<*mut Serial> {
+    let handle = bt.get_handle_for_protocol::<Serial>().ok()?;
+    // shared with console, the interface outlives the scoped protocol
+    let mut serial = unsafe {
+        bt.open_protocol::<Serial>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .ok()?
+    };
+    serial.get_mut().map(|s| s as *mut Serial)
+}
+
+fn open_file(bt: &BootServices) -> Option<RegularFile> {
+    let mut fs = bt.get_image_file_system(bt.image_handle()).ok()?;
+    let mut root = fs.open_volume().ok()?;
+    let mut file = root
+        .open(LOG_FILE, FileMode::CreateReadWrite, FileAttribute::empty())
+        .ok()?
+        .into_regular_file()?;
+    file.set_position(RegularFile::END_OF_FILE).ok()?;
+    Some(file)
+}
+
+/// Apply `spec` of comma separated level (`off`, `error`, `warn`, `info`, `debug` or `trace`)
+/// and sinks (`conout`, `serial` or `file`), sinks are replaced if any is given.
+/// Returns the first unknown item.
+pub fn configure(spec: &str) -> Result<(), &str> {
+    let mut level = None;
+    let mut sinks = 0;
+    for item in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        if let Ok(l) = item.parse::<LevelFilter>() {
+            level = Some(l);
+        } else if item.eq_ignore_ascii_case("conout") {
+            sinks |= SINK_CON_OUT;
+        } else if item.eq_ignore_ascii_case("serial") {
+            sinks |= SINK_SERIAL;
+        } else if item.eq_ignore_ascii_case("file") {
+            sinks |= SINK_FILE;
+        } else {
+            return Err(item);
+        }
+    }
+
+    if let Some(level) = level {
+        log::set_max_level(level);
+    }
+    if sinks == 0 {
+        return Ok(());
+    }
+
+    let st = system_table();
+    let bt = st.boot_services();
+    if sinks & SINK_SERIAL != 0 && LOGGER.serial.load(Ordering::Relaxed).is_null() {
+        match open_serial(bt) {
+            Some(serial) => LOGGER.serial.store(serial, Ordering::Relaxed),
+            None => {
+                log::warn!("no serial port to log to");
+                sinks &= !SINK_SERIAL;
+            }
+        }
+    }
+    if sinks & SINK_FILE != 0 && LOGGER.file.load(Ordering::Relaxed).is_null() {
+        match open_file(bt) {
+            Some(file) => LOGGER
+                .file
+                .store(Box::into_raw(Box::new(file)), Ordering::Relaxed),
+            None => {
+                log::warn!("failed to open log file {}", LOG_FILE);
+                sinks &= !SINK_FILE;
+            }
+        }
+    }
+    LOGGER.sinks.store(sinks, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Install logger logging info level to ConOut, then apply spec in `UefiToysLog` variable if set.
+/// Must be called after [uefi::helpers::init].
+pub fn init() {
+    if log::set_logger(&LOGGER).is_err() {
+        return;
+    }
+    log::set_max_level(LevelFilter::Info);
+
+    let st = system_table();
+    let rt = st.runtime_services();
+    let mut buf = [0u8; 64];
+    let Ok((spec, _)) = rt.get_variable(VARIABLE_NAME, &VENDOR, &mut buf) else {
+        return;
+    };
+    match core::str::from_utf8(spec) {
+        Ok(spec) => {
+            if let Err(item) = configure(spec.trim_end_matches('\0')) {
+                log::warn!("unknown item {} in log spec variable", item);
+            }
+        }
+        Err(_) => log::warn!("log spec variable is not UTF-8"),
+    }
+}
@@ -0,0 +1,88 @@
+//! Parser of INI-like config files, sections of `key = value` lines in order
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+/// Error at a line of config, numbered from 1
+#[derive(Debug)]
+pub struct ConfigError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl ConfigError {
+    pub fn new(line: usize, msg: impl Display) -> Self {
+        Self {
+            line,
+            msg: msg.to_string(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+pub struct Entry<'a> {
+    pub line: usize,
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+pub struct Section<'a> {
+    pub line: usize,
+    pub name: &'a str,
+    /// in order of lines, keys may repeat
+    pub entries: Vec<Entry<'a>>,
+}
+
+/// Split `text` into sections, lines starting with `#` or `;` are comments
+pub fn parse(text: &str) -> Result<Vec<Section<'_>>, ConfigError> {
+    let mut sections: Vec<Section> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                return Err(ConfigError::new(line_no, "missing `]` of section"));
+            };
+            sections.push(Section {
+                line: line_no,
+                name: name.trim(),
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(ConfigError::new(line_no, "expected `key = value`"));
+        };
+        let Some(section) = sections.last_mut() else {
+            return Err(ConfigError::new(line_no, "key outside of section"));
+        };
+        section.entries.push(Entry {
+            line: line_no,
+            key: key.trim(),
+            value: value.trim(),
+        });
+    }
+    Ok(sections)
+}
+
+/// Parse boolean `value` like `yes` or `false`
+pub fn parse_bool(value: &str) -> Option<bool> {
+    const TRUE: &[&str] = &["true", "yes", "on", "1"];
+    const FALSE: &[&str] = &["false", "no", "off", "0"];
+    if TRUE.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        Some(true)
+    } else if FALSE.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        Some(false)
+    } else {
+        None
+    }
+}
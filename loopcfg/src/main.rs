@@ -0,0 +1,410 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+mod config;
+mod step;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use core::{mem, slice};
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::file::{File, FileInfo, FileMode};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::{
+    LoadImageSource, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType,
+};
+use uefi::table::runtime::{ResetType, VariableAttributes};
+use uefi::{println, CString16};
+use uefi_cli::{App, ArgsError, ConOutProgress, NoProgress, Progress};
+use uefi_devpath::{device_path_from_shell_text, device_path_from_text, open_shell_path};
+use uefi_error::{Context, Error, Result};
+use uefi_loopdrv::LoopControlProtocol;
+use uefi_lopatch::command::{attach::attach_loop_device, find_loop_device, open_loop_control};
+
+use step::{Action, AttachStep, ChainStep, OnError, SetVarStep, Step};
+
+const MIN_UEFI_REVISION: uefi::table::Revision = uefi::table::Revision::EFI_2_00;
+
+const APP: App = App {
+    help: "\
+Usage: {name} [OPTIONS] [CONFIG]
+
+  Run steps of CONFIG in order to load drivers, attach and patch images to
+  loop devices, set variables and chainload a boot loader. CONFIG defaults to
+  loopcfg.cfg next to this image without UEFI shell
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+  -n, --dry-run         Check CONFIG and print its steps without running them
+
+Each step is a section of `key = value` lines, lines starting with `#` or `;`
+are comments. Steps and their keys are
+
+  [loopdrv]             Load loopdrv unless it's loaded
+    image = FILE        Defaults to uefi-loopdrv.efi
+  [driver]              Load and start a driver, e.g. a file system driver
+    image = FILE
+  [attach]              Attach image to loop device like lopatch
+    image = FILE        Image file or HTTP(S) URL
+    id, name, size      Options of the same names of lopatch
+    read-only, partitioned, fixed, sparse, quiet = yes|no
+    search, pattern     Files in ISO to patch by actions following them
+    append, replace     FILE to append to or replace data of the files
+    meta-cpio = yes|no  Append mapping metadata as CPIO to the files
+  [setvar]              Set UEFI variable
+    name = NAME
+    guid = GUID         Defaults to EFI global variable
+    attrs = ATTRS       Attributes like vartool, defaults to these of existing
+                        variable or \"nv,bs,rt\"
+    hex, text, ucs2     Data in hex, UTF-8 or UCS-2 text
+    append = yes|no
+  [chain]               Load and start a boot loader
+    image = FILE
+    loop = ID           FILE is on file system of loop device ID
+    args = ARGS         Arguments passed to FILE as is
+    no-name = yes|no    Do not pass FILE as the first argument
+
+Every step also takes `on-error = abort|continue|reboot`, defaults to `abort`.
+
+EXAMPLE:
+  * Boot a Live CD ISO with a CPIO appended to its initramfs
+  [loopdrv]
+  [attach]
+  id = 0
+  image = archlinux.iso
+  read-only = yes
+  search = initramfs-linux.img
+  append = patch-init.cpio
+  meta-cpio = yes
+  [chain]
+  loop = 0
+  image = \\EFI\\BOOT\\BOOTX64.EFI
+  on-error = reboot
+",
+    version: uefi_cli::version!(),
+};
+
+const DEFAULT_CONFIG: &str = "loopcfg.cfg";
+
+enum Command<'a> {
+    NoOp,
+    Run { config: &'a str, dry_run: bool },
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> core::result::Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    let mut config = None;
+    let mut dry_run = false;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('n') | Arg::Long("dry-run") => dry_run = true,
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(path) if config.is_none() => config = Some(path),
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    Ok(Command::Run {
+        config: config.unwrap_or(DEFAULT_CONFIG),
+        dry_run,
+    })
+}
+
+#[entry]
+fn main(handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+    let rt = system_table.runtime_services();
+
+    if system_table.uefi_revision() < MIN_UEFI_REVISION {
+        log::error!(
+            "system UEFI revision {} smaller than required {}",
+            system_table.uefi_revision(),
+            MIN_UEFI_REVISION
+        );
+        return Status::INCOMPATIBLE_VERSION;
+    }
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => Ok(()),
+        Ok(Command::Run { config, dry_run }) => run_config(bt, rt, handle, config, dry_run),
+    };
+
+    uefi_cli::exit_status(res)
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others are using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> uefi::Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+/// Read whole content of file at shell path or device path text of `path` as UTF-8
+fn read_text(bt: &BootServices, path: &str) -> Result<String> {
+    let Some(mut file) = open_shell_path(bt, path, FileMode::Read)?.into_regular_file() else {
+        let msg = format!("{} is not a file", path);
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    };
+    let info = file.get_boxed_info::<FileInfo>()?;
+    let Ok(size) = usize::try_from(info.file_size()) else {
+        return Err(Status::BAD_BUFFER_SIZE.into());
+    };
+    let mut data = vec![0; size];
+    let len = file.read(&mut data)?;
+    data.truncate(len);
+    String::from_utf8(data).map_err(|_| Error::msg(Status::INVALID_PARAMETER, "not UTF-8 text"))
+}
+
+fn run_config(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    parent: Handle,
+    path: &str,
+    dry_run: bool,
+) -> Result {
+    let text = read_text(bt, path).with_context(|| format!("failed to read {}", path))?;
+    // check all steps before running any so a typo does not leave a half done setup
+    let steps = config::parse(&text)
+        .and_then(|sections| step::parse_steps(&sections))
+        .map_err(|e| Error::msg(Status::INVALID_PARAMETER, e))
+        .with_context(|| format!("invalid config {}", path))?;
+
+    let total = steps.len();
+    for (index, step) in steps.iter().enumerate() {
+        println!("[{}/{}] {}", index + 1, total, step);
+        if dry_run {
+            continue;
+        }
+        let Err(e) = run_step(bt, rt, parent, step) else {
+            continue;
+        };
+        let e = e.context(format!("step at line {} failed", step.line));
+        match step.on_error {
+            OnError::Abort => return Err(e),
+            OnError::Continue => println!("Error: {}, continuing", e),
+            OnError::Reboot => {
+                println!("Error: {}, rebooting", e);
+                rt.reset(ResetType::COLD, e.status(), None);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_step(bt: &BootServices, rt: &RuntimeServices, parent: Handle, step: &Step) -> Result {
+    match &step.action {
+        Action::Loopdrv(image) => {
+            if bt.get_handle_for_protocol::<LoopControlProtocol>().is_ok() {
+                println!("loopdrv is loaded, skipped");
+                return Ok(());
+            }
+            load_driver(bt, parent, image)
+        }
+        Action::Driver(image) => load_driver(bt, parent, image),
+        Action::Attach(step) => attach(bt, step),
+        Action::SetVar(step) => set_variable(rt, step),
+        Action::Chain(step) => chain(bt, parent, step),
+    }
+}
+
+fn load_driver(bt: &BootServices, parent: Handle, image: &str) -> Result {
+    let dp = device_path_from_shell_text(bt, image)
+        .with_context(|| format!("failed to resolve {}", image))?;
+    let handle = bt
+        .load_image(
+            parent,
+            LoadImageSource::FromDevicePath {
+                device_path: &dp,
+                from_boot_manager: false,
+            },
+        )
+        .with_context(|| format!("failed to load {}", image))?;
+    // drivers return once initialized
+    bt.start_image(handle)
+        .with_context(|| format!("{} failed to start", image))
+}
+
+fn attach(bt: &BootServices, step: &AttachStep) -> Result {
+    let mut progress: Box<dyn Progress> = if step.quiet {
+        Box::new(NoProgress)
+    } else {
+        Box::new(ConOutProgress::new())
+    };
+    attach_loop_device(
+        bt,
+        step.id,
+        step.read_only,
+        !step.is_parted_disk,
+        !step.is_fixed,
+        step.is_sparse,
+        step.name,
+        step.num_sectors,
+        &step.patch,
+        step.image,
+        progress.as_mut(),
+    )
+    .context("failed to setup loop device")
+}
+
+fn set_variable(rt: &RuntimeServices, step: &SetVarStep) -> Result {
+    // checked on parsing
+    let name = CString16::try_from(step.name).unwrap();
+    let mut attributes = match step.attributes {
+        Some(attributes) => attributes,
+        None => match rt.get_variable_boxed(&name, &step.vendor) {
+            Ok((_, attributes)) => attributes,
+            Err(e) if e.status() == Status::NOT_FOUND => {
+                VariableAttributes::NON_VOLATILE
+                    | VariableAttributes::BOOTSERVICE_ACCESS
+                    | VariableAttributes::RUNTIME_ACCESS
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+    if step.append {
+        attributes |= VariableAttributes::APPEND_WRITE;
+    }
+    rt.set_variable(&name, &step.vendor, attributes, &step.data)
+        .with_context(|| format!("failed to set variable {}", step.name))
+}
+
+/// Bytes of `dp` excluding end node
+fn device_path_nodes(dp: &DevicePath) -> &[u8] {
+    let bytes =
+        unsafe { slice::from_raw_parts(dp.as_ffi_ptr().cast::<u8>(), mem::size_of_val(dp)) };
+    &bytes[..bytes.len() - 4]
+}
+
+/// Device path of file `path` on the first file system of loop device `id`
+fn loop_file_path<'a>(
+    bt: &'a BootServices,
+    id: u32,
+    path: &str,
+) -> Result<uefi_devpath::PoolDevicePath<'a>> {
+    let handle = {
+        let loop_ctl = open_loop_control(bt)?;
+        unsafe { find_loop_device(&loop_ctl, Some(id))? }
+    };
+    // file systems are only on partitions of the loop device once connected
+    if let Err(e) = bt.connect_controller(handle, None, None, true) {
+        log::debug!("connect loop device #{}: {}", id, e.status());
+    }
+
+    let loop_dp = get_protocol::<DevicePath>(bt, handle)?;
+    let prefix = device_path_nodes(&loop_dp);
+    let fs_handles = bt
+        .locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .context("failed to list file systems")?;
+    let fs_dp = fs_handles
+        .iter()
+        .filter_map(|&h| get_protocol::<DevicePath>(bt, h).ok())
+        .find(|dp| device_path_nodes(dp).starts_with(prefix))
+        .ok_or_else(|| {
+            let msg = format!("no file system on loop device #{}", id);
+            Error::msg(Status::NOT_FOUND, msg)
+        })?;
+
+    let fs_text = fs_dp
+        .to_string(bt, DisplayOnly(false), AllowShortcuts(false))
+        .ok()
+        .ok_or_else(|| Error::msg(Status::UNSUPPORTED, "no device path to text protocol"))?;
+    let file_path = path.replace('/', "\\");
+    let separator = if file_path.starts_with('\\') {
+        ""
+    } else {
+        "\\"
+    };
+    let text = format!("{}/{}{}", fs_text, separator, file_path);
+    Ok(device_path_from_text(bt, &text)?)
+}
+
+fn chain(bt: &BootServices, parent: Handle, step: &ChainStep) -> Result {
+    let dp = match step.loop_id {
+        Some(id) => loop_file_path(bt, id, step.image)?,
+        None => device_path_from_shell_text(bt, step.image)
+            .with_context(|| format!("failed to resolve {}", step.image))?,
+    };
+    let child = bt
+        .load_image(
+            parent,
+            LoadImageSource::FromDevicePath {
+                device_path: &dp,
+                from_boot_manager: false,
+            },
+        )
+        .with_context(|| format!("failed to load {}", step.image))?;
+    mem::drop(dp);
+
+    let command_line = step::chain_command_line(step);
+    log::debug!("load options: {}", command_line);
+    let Ok(load_options) = CString16::try_from(command_line.as_str()) else {
+        let _ = bt.unload_image(child);
+        let msg = "arguments contain characters not in UCS-2";
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    };
+    match bt.open_protocol_exclusive::<LoadedImage>(child) {
+        Ok(mut loaded_image) => unsafe {
+            // kept alive until the image returns
+            let size = mem::size_of_val(load_options.to_u16_slice_with_nul());
+            loaded_image.set_load_options(load_options.as_ptr().cast(), size as u32);
+        },
+        Err(e) => {
+            let _ = bt.unload_image(child);
+            return Err(e.into());
+        }
+    }
+
+    bt.start_image(child)
+        .with_context(|| format!("{} returned", step.image))
+}
@@ -0,0 +1,358 @@
+//! Steps of config, validated before any of them runs
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+use uefi::table::runtime::{VariableAttributes, VariableVendor};
+use uefi::{CString16, Guid};
+use uefi_cli::{find_command, parse_hex};
+use uefi_lopatch::{pattern_regex, search_regex, PatchAction, PatchList};
+
+use crate::config::{parse_bool, ConfigError, Entry, Section};
+
+/// Default image of `loopdrv` step, next to this image without UEFI shell
+const DEFAULT_LOOPDRV: &str = "uefi-loopdrv.efi";
+
+/// Short names of variable attributes, as in vartool
+const ATTRIBUTES: &[(&str, VariableAttributes)] = &[
+    ("nv", VariableAttributes::NON_VOLATILE),
+    ("bs", VariableAttributes::BOOTSERVICE_ACCESS),
+    ("rt", VariableAttributes::RUNTIME_ACCESS),
+    ("hr", VariableAttributes::HARDWARE_ERROR_RECORD),
+    ("aw", VariableAttributes::AUTHENTICATED_WRITE_ACCESS),
+    (
+        "at",
+        VariableAttributes::TIME_BASED_AUTHENTICATED_WRITE_ACCESS,
+    ),
+    ("ea", VariableAttributes::ENHANCED_AUTHENTICATED_ACCESS),
+];
+
+/// What to do when a step fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnError {
+    /// Stop running steps and exit with the error
+    Abort,
+    /// Print the error and run next steps
+    Continue,
+    /// Print the error and reboot the system
+    Reboot,
+}
+
+pub struct AttachStep<'a> {
+    pub id: Option<u32>,
+    pub image: &'a str,
+    pub read_only: bool,
+    pub is_parted_disk: bool,
+    pub is_fixed: bool,
+    pub is_sparse: bool,
+    pub name: Option<&'a str>,
+    pub num_sectors: u64,
+    pub patch: PatchList<'a>,
+    pub quiet: bool,
+}
+
+pub struct SetVarStep<'a> {
+    pub name: &'a str,
+    pub vendor: VariableVendor,
+    /// defaults to these of existing variable or "nv,bs,rt"
+    pub attributes: Option<VariableAttributes>,
+    pub data: Vec<u8>,
+    pub append: bool,
+}
+
+pub struct ChainStep<'a> {
+    pub image: &'a str,
+    /// IMAGE is a path on file system of the loop device if set
+    pub loop_id: Option<u32>,
+    pub args: &'a str,
+    pub no_name: bool,
+}
+
+pub enum Action<'a> {
+    /// Load loopdrv unless it's already loaded
+    Loopdrv(&'a str),
+    Driver(&'a str),
+    Attach(AttachStep<'a>),
+    SetVar(SetVarStep<'a>),
+    Chain(ChainStep<'a>),
+}
+
+pub struct Step<'a> {
+    pub line: usize,
+    pub action: Action<'a>,
+    pub on_error: OnError,
+}
+
+impl Display for Step<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            Action::Loopdrv(image) => write!(f, "load loopdrv {}", image),
+            Action::Driver(image) => write!(f, "load driver {}", image),
+            Action::Attach(step) => match step.id {
+                Some(id) => write!(f, "attach {} to loop device #{}", step.image, id),
+                None => write!(f, "attach {} to a free loop device", step.image),
+            },
+            Action::SetVar(step) => write!(f, "set variable {}", step.name),
+            Action::Chain(step) => match step.loop_id {
+                Some(id) => write!(f, "chain {} on loop device #{}", step.image, id),
+                None => write!(f, "chain {}", step.image),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum StepType {
+    Loopdrv,
+    Driver,
+    Attach,
+    SetVar,
+    Chain,
+}
+
+/// Convert `sections` to steps, failing at the first invalid one
+pub fn parse_steps<'a>(sections: &[Section<'a>]) -> Result<Vec<Step<'a>>, ConfigError> {
+    const STEPS: &[(&str, StepType)] = &[
+        ("loopdrv", StepType::Loopdrv),
+        ("driver", StepType::Driver),
+        ("attach", StepType::Attach),
+        ("setvar", StepType::SetVar),
+        ("chain", StepType::Chain),
+    ];
+
+    let mut steps = Vec::new();
+    for section in sections {
+        let Some(step_type) = find_command(STEPS, section.name) else {
+            let msg = format!("unknown step [{}]", section.name);
+            return Err(ConfigError::new(section.line, msg));
+        };
+        let mut on_error = OnError::Abort;
+        let mut entries = Vec::new();
+        for entry in &section.entries {
+            if entry.key.eq_ignore_ascii_case("on-error") {
+                on_error = parse_on_error(entry)?;
+            } else {
+                entries.push(entry);
+            }
+        }
+        let action = match step_type {
+            StepType::Loopdrv => Action::Loopdrv(image_of(section, &entries, true)?),
+            StepType::Driver => Action::Driver(image_of(section, &entries, false)?),
+            StepType::Attach => Action::Attach(parse_attach(section, &entries)?),
+            StepType::SetVar => Action::SetVar(parse_setvar(section, &entries)?),
+            StepType::Chain => Action::Chain(parse_chain(section, &entries)?),
+        };
+        steps.push(Step {
+            line: section.line,
+            action,
+            on_error,
+        });
+    }
+    Ok(steps)
+}
+
+fn unknown_key(entry: &Entry) -> ConfigError {
+    ConfigError::new(entry.line, format!("unknown key {}", entry.key))
+}
+
+fn invalid_value(entry: &Entry) -> ConfigError {
+    let msg = format!("invalid value {} of {}", entry.value, entry.key);
+    ConfigError::new(entry.line, msg)
+}
+
+fn missing_key(section: &Section, key: &str) -> ConfigError {
+    let msg = format!("{} of [{}] not specified", key, section.name);
+    ConfigError::new(section.line, msg)
+}
+
+fn bool_of(entry: &Entry) -> Result<bool, ConfigError> {
+    parse_bool(entry.value).ok_or_else(|| invalid_value(entry))
+}
+
+fn parse_on_error(entry: &Entry) -> Result<OnError, ConfigError> {
+    const POLICIES: &[(&str, OnError)] = &[
+        ("abort", OnError::Abort),
+        ("continue", OnError::Continue),
+        ("reboot", OnError::Reboot),
+    ];
+    find_command(POLICIES, entry.value).ok_or_else(|| invalid_value(entry))
+}
+
+fn image_of<'a>(
+    section: &Section,
+    entries: &[&Entry<'a>],
+    is_loopdrv: bool,
+) -> Result<&'a str, ConfigError> {
+    let mut image = None;
+    for entry in entries {
+        match entry.key {
+            "image" => image = Some(entry.value),
+            _ => return Err(unknown_key(entry)),
+        }
+    }
+    match image {
+        Some(image) => Ok(image),
+        None if is_loopdrv => Ok(DEFAULT_LOOPDRV),
+        None => Err(missing_key(section, "image")),
+    }
+}
+
+/// Add `action` to files matched by the last search or pattern
+fn push_action<'a>(
+    patch: &mut PatchList<'a>,
+    entry: &Entry,
+    action: PatchAction<'a>,
+) -> Result<(), ConfigError> {
+    let Some(last) = patch.last_mut() else {
+        let msg = format!("{} without search or pattern before", entry.key);
+        return Err(ConfigError::new(entry.line, msg));
+    };
+    last.1.push(action);
+    Ok(())
+}
+
+fn parse_attach<'a>(
+    section: &Section,
+    entries: &[&Entry<'a>],
+) -> Result<AttachStep<'a>, ConfigError> {
+    let mut step = AttachStep {
+        id: None,
+        image: "",
+        read_only: false,
+        is_parted_disk: false,
+        is_fixed: false,
+        is_sparse: false,
+        name: None,
+        num_sectors: 0,
+        patch: PatchList::new(),
+        quiet: false,
+    };
+    for entry in entries {
+        match entry.key {
+            "id" => step.id = Some(entry.value.parse().map_err(|_| invalid_value(entry))?),
+            "image" => step.image = entry.value,
+            "read-only" => step.read_only = bool_of(entry)?,
+            "partitioned" => step.is_parted_disk = bool_of(entry)?,
+            "fixed" => step.is_fixed = bool_of(entry)?,
+            "sparse" => step.is_sparse = bool_of(entry)?,
+            "name" => step.name = Some(entry.value),
+            "size" => {
+                let size = uefi_lopatch::parse_size(entry.value);
+                step.num_sectors =
+                    uefi_lopatch::sectors_of_size(size.ok_or_else(|| invalid_value(entry))?);
+            }
+            "quiet" => step.quiet = bool_of(entry)?,
+            "search" | "pattern" => {
+                let re = if entry.key == "search" {
+                    search_regex(entry.value)
+                } else {
+                    pattern_regex(entry.value)
+                };
+                let re = re.map_err(|e| ConfigError::new(entry.line, e))?;
+                step.patch.push((re, Vec::new()));
+            }
+            "append" => push_action(&mut step.patch, entry, PatchAction::Append(entry.value))?,
+            "replace" => push_action(&mut step.patch, entry, PatchAction::Replace(entry.value))?,
+            "meta-cpio" => {
+                if bool_of(entry)? {
+                    push_action(&mut step.patch, entry, PatchAction::MetaCpio)?;
+                }
+            }
+            _ => return Err(unknown_key(entry)),
+        }
+    }
+    if step.image.is_empty() {
+        return Err(missing_key(section, "image"));
+    }
+    step.patch.retain(|i| !i.1.is_empty());
+    Ok(step)
+}
+
+fn parse_setvar<'a>(
+    section: &Section,
+    entries: &[&Entry<'a>],
+) -> Result<SetVarStep<'a>, ConfigError> {
+    let mut name = None;
+    let mut vendor = VariableVendor::GLOBAL_VARIABLE;
+    let mut attributes = None;
+    let mut data = None;
+    let mut append = false;
+    for entry in entries {
+        match entry.key {
+            "name" => {
+                CString16::try_from(entry.value).map_err(|_| invalid_value(entry))?;
+                name = Some(entry.value);
+            }
+            "guid" => {
+                let guid = Guid::try_parse(entry.value).map_err(|_| invalid_value(entry))?;
+                vendor = VariableVendor(guid);
+            }
+            "attrs" => {
+                let mut attrs = VariableAttributes::empty();
+                for item in entry.value.split(',').map(str::trim) {
+                    attrs |= find_command(ATTRIBUTES, item).ok_or_else(|| invalid_value(entry))?;
+                }
+                attributes = Some(attrs);
+            }
+            "hex" => data = Some(parse_hex(entry.value).ok_or_else(|| invalid_value(entry))?),
+            "text" => data = Some(entry.value.as_bytes().to_vec()),
+            "ucs2" => {
+                let text = CString16::try_from(entry.value).map_err(|_| invalid_value(entry))?;
+                let units = text.to_u16_slice_with_nul();
+                data = Some(units.iter().flat_map(|c| c.to_le_bytes()).collect());
+            }
+            "append" => append = bool_of(entry)?,
+            _ => return Err(unknown_key(entry)),
+        }
+    }
+    Ok(SetVarStep {
+        name: name.ok_or_else(|| missing_key(section, "name"))?,
+        vendor,
+        attributes,
+        data: data.ok_or_else(|| missing_key(section, "hex, text or ucs2"))?,
+        append,
+    })
+}
+
+fn parse_chain<'a>(
+    section: &Section,
+    entries: &[&Entry<'a>],
+) -> Result<ChainStep<'a>, ConfigError> {
+    let mut step = ChainStep {
+        image: "",
+        loop_id: None,
+        args: "",
+        no_name: false,
+    };
+    for entry in entries {
+        match entry.key {
+            "image" => step.image = entry.value,
+            "loop" => step.loop_id = Some(entry.value.parse().map_err(|_| invalid_value(entry))?),
+            "args" => step.args = entry.value,
+            "no-name" => step.no_name = bool_of(entry)?,
+            _ => return Err(unknown_key(entry)),
+        }
+    }
+    if step.image.is_empty() {
+        return Err(missing_key(section, "image"));
+    }
+    Ok(step)
+}
+
+/// Command line of chained image, `args` is appended as is
+pub fn chain_command_line(step: &ChainStep) -> String {
+    let mut command_line = if step.no_name {
+        String::new()
+    } else {
+        uefi_shell_split::join([step.image])
+    };
+    if !step.args.is_empty() {
+        if !command_line.is_empty() {
+            command_line.push(' ');
+        }
+        command_line.push_str(step.args);
+    }
+    command_line
+}
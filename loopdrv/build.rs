@@ -1,6 +1,9 @@
 use std::env::var;
 
+include!("../build/version.rs");
+
 fn main() {
+    emit_build_info();
     let target = var("TARGET").unwrap();
     if target.contains("uefi") {
         println!("cargo:rustc-link-arg=/subsystem:EFI_BOOT_SERVICE_DRIVER");
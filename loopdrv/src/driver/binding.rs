@@ -1,7 +1,5 @@
 use super::*;
 
-use uefi_services::system_table;
-
 #[repr(C)]
 #[derive(Debug)]
 #[unsafe_protocol("18a031ab-b443-4d1a-a5c0-0c09261e9f71")]
@@ -61,7 +59,7 @@ unsafe extern "efiapi" fn start(
     }
 
     let _ctx = &mut *container_of!(this, ControlContext, driver_binding);
-    let bt = system_table().as_ref().boot_services();
+    let bt = boot_services();
     let remaining = (!remaining.is_null()).then(|| DevicePath::from_ffi_ptr(remaining));
 
     use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
@@ -72,7 +70,6 @@ unsafe extern "efiapi" fn start(
         remaining
             .map(|i| i
                 .to_string(bt, DisplayOnly(true), AllowShortcuts(false))
-                .unwrap()
                 .unwrap())
             .unwrap_or_default()
     );
@@ -92,6 +89,24 @@ unsafe extern "efiapi" fn stop(
     }
 
     let ctx = &mut *container_of!(this, ControlContext, driver_binding);
+
+    // backing file system of loops is going away
+    if controller != ctx.bus_handle.as_ptr() {
+        let fs_device = Handle::from_ptr(controller).unwrap();
+        let loops: Vec<_> = ctx.loop_list.iter().map(|i| i.2).collect();
+        for loop_ctx in loops {
+            loopback::detach_backing_fs(&mut *loop_ctx, fs_device);
+        }
+        // re-borrow as file targets dropped above release through their own references
+        let ctx = &*container_of!(this, ControlContext, driver_binding);
+        if ctx.backing_fs.iter().any(|i| i.fs_device == fs_device) {
+            log::error!("file system {:?} still in use", fs_device);
+            return Status::DEVICE_ERROR;
+        }
+        log::debug!("stop file system {:?}", fs_device);
+        return Status::SUCCESS;
+    }
+
     let children = core::slice::from_raw_parts(child_handle_buf, num_children);
 
     for &child in children {
@@ -107,7 +122,7 @@ unsafe extern "efiapi" fn stop(
 }
 
 pub fn create_driver_binding(bus_handle: Handle) -> DriverBindingProtocol {
-    let bt = unsafe { system_table().as_ref().boot_services() };
+    let bt = boot_services();
     DriverBindingProtocol {
         supported,
         start,
@@ -23,7 +23,7 @@ unsafe extern "efiapi" fn get_controller_name(
     _language: *const u8,
     controller_name: *mut *const u16,
 ) -> Status {
-    let bt = system_table().as_ref().boot_services();
+    let bt = boot_services();
 
     if let Some(child_handle) = Handle::from_ptr(child_handle) {
         let loop_pt_ptr = match get_protocol_mut::<LoopProtocol>(bt, child_handle) {
@@ -1,9 +1,63 @@
 use super::*;
 
+use uefi::proto::network::IpAddress;
+use uefi::{CStr8, Char8};
+
+/// Extended from the table of [LoopControlProtocolV0] ending at [LoopControlProtocol::remove]
+/// with [LoopControlProtocol::create_blank] and [LoopControlProtocol::attach_tftp_file],
+/// under a new GUID as the layout differs
 #[repr(C)]
 #[derive(Debug)]
-#[unsafe_protocol("ff0e5a30-438e-11ee-9113-2cf05d73e0d3")]
+#[unsafe_protocol("2b662918-6e6c-49bc-872b-e442c5a1150f")]
 pub struct LoopControlProtocol {
+    /// [LoopControlProtocol::REVISION] of the driver, entries appended in later revisions
+    /// must not be called if it's lower
+    pub revision: u64,
+    pub get_free: unsafe extern "efiapi" fn(this: *mut Self, loop_handle: *mut RawHandle) -> Status,
+    pub add: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        unit_number: u32,
+        loop_handle: *mut RawHandle,
+    ) -> Status,
+    pub find: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        unit_number: u32,
+        loop_handle: *mut RawHandle,
+    ) -> Status,
+    pub remove: unsafe extern "efiapi" fn(this: *mut Self, loop_handle: RawHandle) -> Status,
+    /// Setup loopback device of `unit_number` with blank media of `num_sectors`, reads as zero
+    /// if `read_only`, otherwise backed by memory for scratch use.
+    pub create_blank: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        unit_number: u32,
+        num_sectors: u64,
+        read_only: bool,
+        loop_handle: *mut RawHandle,
+    ) -> Status,
+    /// Download `filename` from TFTP server with PXE base code on `pxe_device` into memory
+    /// and attach it to a free loopback device. Use the first PXE base code if `pxe_device`
+    /// is null, and the boot server from DHCP if `server_ip` is null.
+    pub attach_tftp_file: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        pxe_device: RawHandle,
+        server_ip: *const IpAddress,
+        filename: *const Char8,
+        read_only: bool,
+        is_partition: bool,
+        loop_handle: *mut RawHandle,
+    ) -> Status,
+}
+
+impl LoopControlProtocol {
+    pub const REVISION: u64 = 1;
+}
+
+/// The original table kept installed for callers built against it,
+/// entries forward to [LoopControlProtocol] of the same driver
+#[repr(C)]
+#[derive(Debug)]
+#[unsafe_protocol("ff0e5a30-438e-11ee-9113-2cf05d73e0d3")]
+pub struct LoopControlProtocolV0 {
     pub get_free: unsafe extern "efiapi" fn(this: *mut Self, loop_handle: *mut RawHandle) -> Status,
     pub add: unsafe extern "efiapi" fn(
         this: *mut Self,
@@ -139,6 +193,89 @@ unsafe extern "efiapi" fn remove(this: *mut LoopControlProtocol, loop_handle: Ra
     Status::SUCCESS
 }
 
+unsafe extern "efiapi" fn create_blank(
+    this: *mut LoopControlProtocol,
+    unit_number: u32,
+    num_sectors: u64,
+    read_only: bool,
+    loop_handle: *mut RawHandle,
+) -> Status {
+    if this.is_null() || loop_handle.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = &mut *container_of!(this, ControlContext, loop_ctl);
+
+    let res = ctx.loop_list.binary_search_by_key(&unit_number, |i| i.0);
+    let (handle, loop_ctx) = match res {
+        Ok(idx) => {
+            let (_, handle, loop_ctx) = ctx.loop_list[idx];
+            if !(*loop_ctx).is_free() {
+                log::error!("loop {} is in use", unit_number);
+                return Status::ACCESS_DENIED;
+            }
+            (handle, loop_ctx)
+        }
+        Err(_) => match add_loopback(ctx, unit_number) {
+            Err(e) => return e.status(),
+            Ok(h) => {
+                let idx = ctx.loop_list.binary_search_by_key(&unit_number, |i| i.0);
+                (h, ctx.loop_list[idx.unwrap()].2)
+            }
+        },
+    };
+
+    if let Err(e) = loopback::set_blank(&mut *loop_ctx, num_sectors, read_only) {
+        log::error!("{}", e);
+        return e.status();
+    }
+    *loop_handle = handle.as_ptr();
+    Status::SUCCESS
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "efiapi" fn attach_tftp_file(
+    this: *mut LoopControlProtocol,
+    pxe_device: RawHandle,
+    server_ip: *const IpAddress,
+    filename: *const Char8,
+    read_only: bool,
+    is_partition: bool,
+    loop_handle: *mut RawHandle,
+) -> Status {
+    if this.is_null() || filename.is_null() || loop_handle.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let filename = core::ffi::CStr::from_ptr(filename.cast());
+    let Ok(filename) = CStr8::from_bytes_with_nul(filename.to_bytes_with_nul()) else {
+        return Status::INVALID_PARAMETER;
+    };
+
+    let mut handle: RawHandle = ptr::null_mut();
+    match get_free(this, &mut handle) {
+        Status::SUCCESS => {}
+        e => return e,
+    }
+    let ctx = &mut *container_of!(this, ControlContext, loop_ctl);
+    let Some(&(.., loop_ctx)) = ctx.loop_list.iter().find(|i| i.1.as_ptr() == handle) else {
+        return Status::ABORTED;
+    };
+
+    let res = loopback::set_tftp_file(
+        &mut *loop_ctx,
+        read_only,
+        is_partition,
+        Handle::from_ptr(pxe_device),
+        server_ip.as_ref(),
+        filename,
+    );
+    if let Err(e) = res {
+        log::error!("{}", e);
+        return e.status();
+    }
+    *loop_handle = handle;
+    Status::SUCCESS
+}
+
 pub(super) fn remove_children(ctx: &mut ControlContext) -> Result {
     while let Some((_, child, _)) = ctx.loop_list.last() {
         loopback::uninstall_loopback(ctx.bus_handle, *child)?;
@@ -147,11 +284,62 @@ pub(super) fn remove_children(ctx: &mut ControlContext) -> Result {
     Ok(())
 }
 
+/// [LoopControlProtocol] of the driver `this` is installed by, null if `this` is null
+unsafe fn loop_ctl_of_v0(this: *mut LoopControlProtocolV0) -> *mut LoopControlProtocol {
+    if this.is_null() {
+        return ptr::null_mut();
+    }
+    let ctx = &mut *container_of!(this, ControlContext, loop_ctl_v0);
+    ptr::addr_of_mut!(ctx.loop_ctl)
+}
+
+unsafe extern "efiapi" fn get_free_v0(
+    this: *mut LoopControlProtocolV0,
+    loop_handle: *mut RawHandle,
+) -> Status {
+    get_free(loop_ctl_of_v0(this), loop_handle)
+}
+
+unsafe extern "efiapi" fn add_v0(
+    this: *mut LoopControlProtocolV0,
+    unit_number: u32,
+    loop_handle: *mut RawHandle,
+) -> Status {
+    add(loop_ctl_of_v0(this), unit_number, loop_handle)
+}
+
+unsafe extern "efiapi" fn find_v0(
+    this: *mut LoopControlProtocolV0,
+    unit_number: u32,
+    loop_handle: *mut RawHandle,
+) -> Status {
+    find(loop_ctl_of_v0(this), unit_number, loop_handle)
+}
+
+unsafe extern "efiapi" fn remove_v0(
+    this: *mut LoopControlProtocolV0,
+    loop_handle: RawHandle,
+) -> Status {
+    remove(loop_ctl_of_v0(this), loop_handle)
+}
+
+pub fn create_loop_control_v0() -> LoopControlProtocolV0 {
+    LoopControlProtocolV0 {
+        get_free: get_free_v0,
+        add: add_v0,
+        find: find_v0,
+        remove: remove_v0,
+    }
+}
+
 pub fn create_loop_control() -> LoopControlProtocol {
     LoopControlProtocol {
+        revision: LoopControlProtocol::REVISION,
         get_free,
         add,
         find,
         remove,
+        create_blank,
+        attach_tftp_file,
     }
 }
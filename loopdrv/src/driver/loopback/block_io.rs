@@ -1,5 +1,7 @@
 use super::*;
 
+use core::ops::Range;
+
 use loop_pt::SECTOR_SIZE;
 
 pub use uefi_raw::protocol::block::{BlockIoMedia, BlockIoProtocol, Lba};
@@ -17,63 +19,51 @@ unsafe extern "efiapi" fn reset(
     Status::SUCCESS
 }
 
-fn access_blocks<F>(ctx: &mut LoopContext, lba: Lba, buffer: &mut [u8], mut target_cb: F) -> Result
+/// Sectors of a single target I/O, 8MiB
+const CHUNK_MAX_SECTORS: u64 = 16384;
+/// Codes below 0x10000 are reserved for firmware
+const WATCHDOG_CODE: u64 = 0x10000;
+
+/// Walk mapping items covering `total_sectors` sectors from `start_sector`,
+/// `f` receives the item target, sector relative to target and byte range of I/O buffer.
+/// Watchdog timer is reset to `watchdog_timeout` seconds between chunks if not 0.
+fn for_each_chunk<F>(
+    table: &mut [PrivMappingItem],
+    watchdog_timeout: usize,
+    start_sector: u64,
+    total_sectors: u64,
+    mut f: F,
+) -> Result
 where
-    F: FnMut(
-        &mut LoopContext,
-        /* buffer */ &mut [u8],
-        &mut PrivTarget,
-        /* start_sector */ u64,
-        /* num_sectors */ u64,
-    ) -> Result,
+    F: FnMut(&mut dyn Target, u64, Range<usize>) -> Result,
 {
-    let end_sector = if let Some(last) = ctx.table.last() {
-        last.start_sector + last.num_sectors
-    } else {
-        0
+    // log::debug!("access {}+{}", start_sector, total_sectors);
+    let mut cursor = match mapping::resolve(table, start_sector, total_sectors) {
+        Ok(v) => v.with_max_sectors(CHUNK_MAX_SECTORS),
+        Err(e) => {
+            log::error!("buffer region overflows device region: {:?}", e);
+            return Status::INVALID_PARAMETER.to_result();
+        }
     };
-
-    let start_sector = lba * ctx.media.block_size as u64 / SECTOR_SIZE as u64;
-    let total_sectors = (buffer.len() / SECTOR_SIZE) as u64;
-    // log::debug!("read {}+{} {}", start_sector, total_sectors, end_sector);
-    if start_sector + total_sectors > end_sector {
-        log::error!("buffer region overflows device region");
-        return Status::INVALID_PARAMETER.to_result();
-    }
-
-    let upper_bound = ctx
-        .table
-        .partition_point(|x| x.start_sector <= start_sector);
-    // hit if mapping table is empty, unsorted or `start_sector` of first item is not 0
-    assert_ne!(0, upper_bound);
-
-    let mut total_advance: u64 = 0;
-
-    // preserve table structure
-    let mut table = mem::take(&mut ctx.table);
-    for item in &mut table[upper_bound - 1..] {
-        let remaining = total_sectors - total_advance;
-        if remaining == 0 {
-            break;
+    while let Some(chunk) = cursor.next_chunk(table) {
+        let target = table[chunk.index].target.as_target_mut();
+        f(target, chunk.target_sector, chunk.buffer_range())?;
+        if watchdog_timeout != 0 && !cursor.is_done() {
+            let bt = boot_services();
+            if let Err(e) = bt.set_watchdog_timer(watchdog_timeout, WATCHDOG_CODE, None) {
+                log::warn!("failed to reset watchdog timer: {}", e);
+            }
         }
-        let curr_sector = start_sector + total_advance;
-        let item_end_sector = item.start_sector + item.num_sectors;
-        let advance = remaining.min(item_end_sector - curr_sector);
-        let offset = curr_sector - item.start_sector;
-        let target_sector = item.target_start_sector + offset;
-        let item_buffer = &mut buffer[total_advance as usize * SECTOR_SIZE
-            ..(total_advance + advance) as usize * SECTOR_SIZE];
-
-        target_cb(ctx, item_buffer, &mut item.target, target_sector, advance)?;
-
-        total_advance += advance;
     }
-    ctx.table = table;
-
-    assert_eq!(total_advance, total_sectors);
     Ok(())
 }
 
+#[inline]
+fn start_sector_of(ctx: &LoopContext, lba: Lba) -> Result<u64> {
+    mapping::lba_to_sector(lba, ctx.media.block_size)
+        .ok_or_else(|| uefi::Error::new(Status::INVALID_PARAMETER, ()))
+}
+
 unsafe fn validate_blocks_params(
     this: *const BlockIoProtocol,
     media_id: u32,
@@ -114,45 +104,19 @@ unsafe extern "efiapi" fn read_blocks(
             return e;
         }
     }
-    let bt = system_table().as_ref().boot_services();
     let ctx = LoopContext::from_block_io_ptr(this.cast_mut());
+    let _guard = ctx.lock();
     let buffer = core::slice::from_raw_parts_mut(buffer as *mut u8, buffer_size);
-
-    let res = access_blocks(ctx, lba, buffer, |_ctx, buffer, target, sector, num| {
-        match target {
-            PrivTarget::Zero => {
-                buffer.fill(0);
-            }
-            PrivTarget::LoopPool { pool } => {
-                buffer.copy_from_slice(
-                    &pool.data
-                        [sector as usize * SECTOR_SIZE..(sector + num) as usize * SECTOR_SIZE],
-                );
-            }
-            PrivTarget::File {
-                file,
-                fs_device,
-                fs_interface,
-                ..
-            } => {
-                if !validate_handle_protocol(
-                    bt,
-                    fs_device.as_ptr(),
-                    &SimpleFileSystem::GUID,
-                    *fs_interface as _,
-                ) {
-                    log::error!("file device or FS protocol interface changed");
-                    // XXX: notify error?
-                    return Status::DEVICE_ERROR.to_result();
-                }
-                file.set_position(sector * SECTOR_SIZE as u64).unwrap();
-                if file.read(buffer)? != buffer.len() {
-                    log::error!("read underflow");
-                    return Status::DEVICE_ERROR.to_result();
-                }
-            }
-        }
-        Ok(())
+    let total_sectors = mapping::sectors_of_bytes(buffer.len() as u64);
+
+    let res = start_sector_of(ctx, lba).and_then(|start_sector| {
+        for_each_chunk(
+            &mut ctx.table,
+            ctx.watchdog_timeout,
+            start_sector,
+            total_sectors,
+            |target, sector, range| target.read_sectors(sector, &mut buffer[range]),
+        )
     });
     if let Err(e) = res {
         log::error!("failed to read blocks: {}", e);
@@ -173,45 +137,22 @@ unsafe extern "efiapi" fn write_blocks(
         Status::SUCCESS => {}
         e => return e,
     }
-    let bt = system_table().as_ref().boot_services();
     let ctx = LoopContext::from_block_io_ptr(this);
     if ctx.media.read_only {
         return Status::WRITE_PROTECTED;
     }
-    let buffer = core::slice::from_raw_parts_mut(buffer as *mut u8, buffer_size);
-
-    let res = access_blocks(ctx, lba, buffer, |_ctx, buffer, target, sector, num| {
-        match target {
-            PrivTarget::Zero => log::warn!("writing to virtual zero block, discard"),
-            PrivTarget::LoopPool { pool } => {
-                let data_slice = &mut pool.data
-                    [sector as usize * SECTOR_SIZE..(sector + num) as usize * SECTOR_SIZE];
-                data_slice.copy_from_slice(buffer);
-            }
-            PrivTarget::File {
-                file,
-                fs_device,
-                fs_interface,
-                ..
-            } => {
-                if !validate_handle_protocol(
-                    bt,
-                    fs_device.as_ptr(),
-                    &SimpleFileSystem::GUID,
-                    *fs_interface as _,
-                ) {
-                    log::error!("file device or FS protocol interface changed");
-                    // XXX: notify error?
-                    return Status::DEVICE_ERROR.to_result();
-                }
-                file.set_position(sector * SECTOR_SIZE as u64).unwrap();
-                if let Err(e) = file.write(buffer) {
-                    log::error!("written {} of {} bytes", e.data(), buffer_size);
-                    return Err(e.to_err_without_payload());
-                }
-            }
-        }
-        Ok(())
+    let _guard = ctx.lock();
+    let buffer = core::slice::from_raw_parts(buffer as *const u8, buffer_size);
+    let total_sectors = mapping::sectors_of_bytes(buffer.len() as u64);
+
+    let res = start_sector_of(ctx, lba).and_then(|start_sector| {
+        for_each_chunk(
+            &mut ctx.table,
+            ctx.watchdog_timeout,
+            start_sector,
+            total_sectors,
+            |target, sector, range| target.write_sectors(sector, &buffer[range]),
+        )
     });
     if let Err(e) = res {
         return e.status();
@@ -224,7 +165,6 @@ unsafe extern "efiapi" fn flush_blocks(this: *mut BlockIoProtocol) -> Status {
     if this.is_null() {
         return Status::INVALID_PARAMETER;
     }
-    let bt = system_table().as_ref().boot_services();
     let ctx = LoopContext::from_block_io_ptr(this);
     if !ctx.media.media_present {
         return Status::NO_MEDIA;
@@ -233,27 +173,10 @@ unsafe extern "efiapi" fn flush_blocks(this: *mut BlockIoProtocol) -> Status {
         return Status::SUCCESS;
     }
 
+    let _guard = ctx.lock();
     for item in &mut ctx.table {
-        if let PrivTarget::File {
-            fs_device,
-            fs_interface,
-            file,
-            ..
-        } = &mut item.target
-        {
-            if !validate_handle_protocol(
-                bt,
-                fs_device.as_ptr(),
-                &SimpleFileSystem::GUID,
-                *fs_interface as _,
-            ) {
-                log::error!("file device or FS protocol interface changed");
-                // XXX: notify error?
-                return Status::DEVICE_ERROR;
-            }
-            if let Err(e) = file.flush() {
-                return e.status();
-            }
+        if let Err(e) = item.target.as_target_mut().flush() {
+            return e.status();
         }
     }
 
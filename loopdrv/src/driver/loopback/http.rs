@@ -0,0 +1,69 @@
+use super::*;
+
+use loop_pt::SECTOR_SIZE;
+use uefi_filesrc::{HttpSource, Source};
+use uefi_loopmap::cache::LruCache;
+
+/// Sectors fetched per range request
+const BLOCK_SECTORS: u64 = 128;
+/// 4MiB of cache
+const CACHE_BLOCKS: usize = 64;
+
+/// Read-only target streaming sectors from HTTP(S) URL with range requests
+#[derive(Debug)]
+pub(super) struct HttpTarget {
+    source: HttpSource<'static>,
+    num_sectors: u64,
+    /// blocks of [BLOCK_SECTORS] sectors
+    cache: LruCache<u64, Vec<u8>>,
+}
+
+impl HttpTarget {
+    pub fn new(nic: Option<Handle>, url: &CStr16) -> Result<Self> {
+        let source = HttpSource::new(boot_services(), nic, url)?;
+        // zero padded to whole sectors
+        let size = source.size();
+        let num_sectors = mapping::sectors_of_bytes(size) + (size % SECTOR_SIZE as u64 != 0) as u64;
+        Ok(Self {
+            num_sectors,
+            source,
+            cache: LruCache::new(CACHE_BLOCKS),
+        })
+    }
+
+    #[inline]
+    pub fn num_sectors(&self) -> u64 {
+        self.num_sectors
+    }
+}
+
+impl Target for HttpTarget {
+    fn read_sectors(&mut self, sector: u64, buffer: &mut [u8]) -> Result {
+        let block_size = BLOCK_SECTORS as usize * SECTOR_SIZE;
+        let mut pos = 0;
+        while pos < buffer.len() {
+            let curr = sector + (pos / SECTOR_SIZE) as u64;
+            let block = curr / BLOCK_SECTORS;
+            let offset = (curr % BLOCK_SECTORS) as usize * SECTOR_SIZE;
+            let len = (block_size - offset).min(buffer.len() - pos);
+
+            let source = &mut self.source;
+            let data = self.cache.get_or_try_insert_with(block, || {
+                // last block is zero padded past end of resource
+                let start = block * block_size as u64;
+                let valid = source.size().saturating_sub(start).min(block_size as u64);
+                let mut data = vec![0u8; block_size];
+                if valid > 0 {
+                    source.read_at(start, &mut data[..valid as usize])?;
+                }
+                Ok::<_, uefi::Error>(data)
+            })?;
+            buffer[pos..][..len].copy_from_slice(&data[offset..][..len]);
+            pos += len;
+        }
+        Ok(())
+    }
+    fn write_sectors(&mut self, _sector: u64, _buffer: &[u8]) -> Result {
+        Status::WRITE_PROTECTED.to_result()
+    }
+}
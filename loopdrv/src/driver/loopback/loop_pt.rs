@@ -1,16 +1,23 @@
 use super::*;
 
-use alloc::alloc::{alloc, Layout};
+use alloc::format;
+use alloc::string::String;
 
 use uefi::proto::device_path::{DevicePath, DeviceSubType, DeviceType};
 use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode, RegularFile};
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::CStr16;
+use uefi_error::{Context, Error, Result};
 
+/// Extended from the table of [LoopProtocolV0] ending at [LoopProtocol::free_pool],
+/// under a new GUID as the layout differs
 #[repr(C)]
 #[derive(Debug)]
-#[unsafe_protocol("8826fb7e-438f-11ee-879a-2cf05d73e0d3")]
+#[unsafe_protocol("55b75fc5-aeb9-46e9-94cc-e8136938081e")]
 pub struct LoopProtocol {
+    /// [LoopProtocol::REVISION] of the driver, entries appended in later revisions
+    /// must not be called if it's lower
+    pub revision: u64,
     pub set_file: unsafe extern "efiapi" fn(
         this: *mut Self,
         read_only: bool,
@@ -31,6 +38,96 @@ pub struct LoopProtocol {
     /// Allocate a device owned 8-bytes aligned memory to be used in mapping table.
     /// The memory pointer became invalid after passing to mapping table,
     /// and must not be passed, accessed or called with [LoopProtocol::free_pool].
+    /// Pools in use are tracked, so such misuse is rejected with an error.
+    pub alloc_pool:
+        unsafe extern "efiapi" fn(this: *mut Self, size: usize, buffer: *mut *mut c_void) -> Status,
+    /// Free pool not passed to mapping table, returns `ACCESS_DENIED` if the pool is in use.
+    pub free_pool: unsafe extern "efiapi" fn(this: *mut Self, buffer: *mut c_void) -> Status,
+    /// Present the device as removable (the default) or fixed media,
+    /// takes effect on next [LoopProtocol::set_file] or [LoopProtocol::set_mapping_table].
+    pub set_removable: unsafe extern "efiapi" fn(this: *mut Self, removable: bool) -> Status,
+    /// Scan file targets of read-only media for all-zero regions no shorter than `min_sectors`
+    /// (0 for default of 1MiB) and map them to [LoopTarget::Zero] to skip file reads.
+    pub sparsify: unsafe extern "efiapi" fn(this: *mut Self, min_sectors: u64) -> Status,
+    /// Discard data of sectors backed by pools and map them to [LoopTarget::Zero],
+    /// pool memory is released once no sector refers to it.
+    pub punch_hole:
+        unsafe extern "efiapi" fn(this: *mut Self, start_sector: u64, num_sectors: u64) -> Status,
+    /// Insert `item` at its start sector of live table, sectors after are shifted
+    /// and the media is changed
+    pub insert_mapping:
+        unsafe extern "efiapi" fn(this: *mut Self, item: *const LoopMappingItem) -> Status,
+    /// Map region covered by `item` to it in place on live table
+    pub replace_mapping:
+        unsafe extern "efiapi" fn(this: *mut Self, item: *const LoopMappingItem) -> Status,
+    /// Remove region from live table, sectors after are shifted and the media is changed
+    pub delete_mapping:
+        unsafe extern "efiapi" fn(this: *mut Self, start_sector: u64, num_sectors: u64) -> Status,
+    /// Stream read-only media from HTTP(S) `url` with range requests through HTTP service
+    /// binding on `nic`, use the first one found if `nic` is null.
+    pub set_url: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        is_partition: bool,
+        nic: RawHandle,
+        url: *const Char16,
+    ) -> Status,
+    /// Override name reported via component name, reset to default if `name` is null
+    pub set_name: unsafe extern "efiapi" fn(this: *mut Self, name: *const Char16) -> Status,
+    /// Get handles of controllers created by recursive connecting on media change,
+    /// e.g. partitions with file systems. `num_handles` is updated to the number of
+    /// children and `BUFFER_TOO_SMALL` is returned if `handles` can't hold all of them.
+    pub get_children: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        num_handles: *mut usize,
+        handles: *mut RawHandle,
+    ) -> Status,
+    /// Cache writes to files in memory and write them out every `flush_interval_ms`
+    /// milliseconds or on flush, 0 (the default) to disable. Takes effect on next
+    /// [LoopProtocol::set_file] or [LoopProtocol::set_mapping_table] of writable media.
+    pub set_write_caching:
+        unsafe extern "efiapi" fn(this: *mut Self, flush_interval_ms: u32) -> Status,
+    /// Reset watchdog timer to `timeout` seconds between bounded chunks of large I/O,
+    /// so long copies through the device won't trigger platform reset.
+    /// 0 (the default) leaves the watchdog timer untouched.
+    pub set_watchdog: unsafe extern "efiapi" fn(this: *mut Self, timeout: usize) -> Status,
+    /// Same as [LoopProtocol::set_file] but expose `num_sectors` of file, 0 for the whole
+    /// file. Sectors beyond end of file read as zero and writing to them extends the file.
+    pub set_file_ex: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        read_only: bool,
+        is_partition: bool,
+        fs_device: RawHandle,
+        path: *const FfiDevicePath,
+        num_sectors: u64,
+    ) -> Status,
+}
+
+impl LoopProtocol {
+    pub const REVISION: u64 = 1;
+}
+
+/// The original table kept installed for callers built against it,
+/// entries forward to [LoopProtocol] of the same device
+#[repr(C)]
+#[derive(Debug)]
+#[unsafe_protocol("8826fb7e-438f-11ee-879a-2cf05d73e0d3")]
+pub struct LoopProtocolV0 {
+    pub set_file: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        read_only: bool,
+        is_partition: bool,
+        fs_device: RawHandle,
+        path: *const FfiDevicePath,
+    ) -> Status,
+    pub set_mapping_table: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        read_only: bool,
+        is_partition: bool,
+        num_table_items: usize,
+        table: *const LoopMappingItem,
+    ) -> Status,
+    pub clear: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+    pub get_info: unsafe extern "efiapi" fn(this: *mut Self, info: *mut LoopInfo) -> Status,
     pub alloc_pool:
         unsafe extern "efiapi" fn(this: *mut Self, size: usize, buffer: *mut *mut c_void) -> Status,
     pub free_pool: unsafe extern "efiapi" fn(this: *mut Self, buffer: *mut c_void) -> Status,
@@ -58,11 +155,22 @@ pub enum LoopTarget {
         fs_device: RawHandle,
         path: *const FfiDevicePath,
     } = 2,
+    /// `buffer` of `size` bytes is borrowed by loop, `release` is called with `context`
+    /// once loop no longer references it, including when the call passing it failed
+    BorrowedPool {
+        buffer: *mut c_void,
+        size: usize,
+        release: Option<ReleaseBufferFn>,
+        context: *mut c_void,
+    } = 3,
 }
 
-pub const SECTOR_SIZE: usize = 512;
+pub type ReleaseBufferFn = unsafe extern "efiapi" fn(context: *mut c_void, buffer: *mut c_void);
+
+pub use uefi_loopmap::mapping::{MAX_SECTORS, SECTOR_SIZE};
 
-/// A sector is 512-bytes
+/// A sector is 512-bytes, end sectors of both device and target regions
+/// must not exceed [MAX_SECTORS]
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct LoopMappingItem {
@@ -79,29 +187,58 @@ impl LoopMappingItem {
 }
 
 impl PrivMappingItem {
+    /// File targets are opened writable unless `read_only`
     unsafe fn from_loop_mapping_item(
         bt: &BootServices,
+        ctx: &mut LoopContext,
+        read_only: bool,
         item: &loopback::LoopMappingItem,
     ) -> Result<Self> {
-        let validate_target_size =
-            |size: u64| (size / SECTOR_SIZE as u64 - item.target_start_sector) >= item.num_sectors;
-        let invalid_err = || uefi::Error::new(Status::INVALID_PARAMETER, ());
+        let bus_handle = ctx.bus_handle;
+        let validate_target_size = |size: u64| {
+            mapping::sectors_of_bytes(size)
+                .checked_sub(item.target_start_sector)
+                .is_some_and(|n| n >= item.num_sectors)
+        };
+        let invalid_err = |msg: String| Error::msg(Status::INVALID_PARAMETER, msg);
         let target = match item.target {
-            LoopTarget::Zero => PrivTarget::Zero,
+            LoopTarget::Zero => PrivTarget::Zero(ZeroTarget),
             LoopTarget::LoopPool { buffer } => {
                 // the pool now owns buffer memory
-                let pool = Pool::boxed_from_data_ptr(buffer as _).ok_or_else(invalid_err)?;
+                let pool = ctx.take_pool(buffer).ok_or_else(|| {
+                    invalid_err(format!(
+                        "pool {:?} is not allocated by this loop or already used",
+                        buffer
+                    ))
+                })?;
 
                 if !validate_target_size(pool.data.len() as _) {
-                    log::error!(
+                    return Err(invalid_err(format!(
                         "pool too small {} {} {}",
                         pool.data.len() / SECTOR_SIZE,
                         item.target_start_sector,
                         item.num_sectors
-                    );
-                    return Err(invalid_err());
+                    )));
+                }
+                PrivTarget::pool(pool)
+            }
+            LoopTarget::BorrowedPool {
+                buffer,
+                size,
+                release,
+                context,
+            } => {
+                // released on drop from now on
+                let pool = BorrowedPoolTarget {
+                    data: buffer as _,
+                    size,
+                    release,
+                    context,
+                };
+                if buffer.is_null() || !validate_target_size(size as _) {
+                    return Err(invalid_err("borrowed pool invalid or too small".into()));
                 }
-                PrivTarget::LoopPool { pool }
+                PrivTarget::borrowed_pool(pool)
             }
             LoopTarget::File { fs_device, path } => {
                 let GetFileInfo {
@@ -110,19 +247,25 @@ impl PrivMappingItem {
                     path,
                     file,
                     info,
-                } = get_file_info(bt, fs_device, path)?;
+                } = get_file_info(bt, fs_device, path, read_only)?;
 
                 if !validate_target_size(info.file_size()) {
-                    log::error!("file too small");
-                    return Err(invalid_err());
+                    return Err(invalid_err(format!("file {} too small", info.file_name())));
                 }
-                PrivTarget::File {
+                let held = hold_backing_fs(bus_handle, fs_device)?;
+                PrivTarget::file(FileTarget {
+                    bus_handle,
                     fs_device,
-                    path: path.to_boxed(),
                     fs_interface,
+                    held,
+                    path: path.to_boxed(),
                     file,
+                    file_size: info.file_size(),
                     info,
-                }
+                    num_unvalidated: 0,
+                    write_back: false,
+                    pending: None,
+                })
             }
         };
         Ok(PrivMappingItem {
@@ -146,38 +289,48 @@ unsafe fn get_file_info<'a, 'b: 'a>(
     bt: &'b BootServices,
     fs_device: RawHandle,
     path: *const FfiDevicePath,
+    read_only: bool,
 ) -> Result<GetFileInfo<'a>> {
     let mut path = DevicePath::from_ffi_ptr(path);
     let fs_device = if let Some(h) = Handle::from_ptr(fs_device) {
         h
     } else {
-        bt.locate_device_path::<SimpleFileSystem>(&mut path)?
+        bt.locate_device_path::<SimpleFileSystem>(&mut path)
+            .context("failed to locate file system of path")?
     };
-    let invalid_err = || uefi::Error::new(Status::INVALID_PARAMETER, ());
+    let invalid_err = || Error::new(Status::INVALID_PARAMETER);
 
     let fs_interface =
         &mut *get_protocol_mut::<SimpleFileSystem>(bt, fs_device)?.ok_or_else(invalid_err)?;
-    let mut root = fs_interface.open_volume()?;
+    let mut root = fs_interface
+        .open_volume()
+        .context("failed to open volume")?;
 
     let path_node = path.node_iter().next().ok_or_else(invalid_err)?;
     if path_node.full_type() != (DeviceType::MEDIA, DeviceSubType::MEDIA_FILE_PATH) {
-        log::error!("path is not a media file device path");
-        return Err(invalid_err());
+        let msg = "path is not a media file device path";
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
     }
     let file_path = CStr16::from_ptr(path_node.data().as_ptr() as _);
 
+    let mode = if read_only {
+        FileMode::Read
+    } else {
+        FileMode::ReadWrite
+    };
     let mut file = root
-        .open(file_path, FileMode::Read, FileAttribute::empty())
-        .map_err(|e| {
-            log::error!("failed to open {}, {}", file_path, e.status());
-            e
-        })?
+        .open(file_path, mode, FileAttribute::empty())
+        .with_context(|| format!("failed to open {}", file_path))?
         .into_regular_file()
         .ok_or_else(|| {
-            log::error!("{} is not a file", file_path);
-            invalid_err()
+            Error::msg(
+                Status::INVALID_PARAMETER,
+                format!("{} is not a file", file_path),
+            )
         })?;
-    let info = file.get_boxed_info::<FileInfo>()?;
+    let info = file
+        .get_boxed_info::<FileInfo>()
+        .with_context(|| format!("failed to get info of {}", file_path))?;
 
     // log::debug!("info {:?}", info);
 
@@ -196,15 +349,28 @@ unsafe extern "efiapi" fn set_file(
     is_partition: bool,
     fs_device: RawHandle,
     path: *const FfiDevicePath,
+) -> Status {
+    set_file_ex(this, read_only, is_partition, fs_device, path, 0)
+}
+
+unsafe extern "efiapi" fn set_file_ex(
+    this: *mut LoopProtocol,
+    read_only: bool,
+    is_partition: bool,
+    fs_device: RawHandle,
+    path: *const FfiDevicePath,
+    num_sectors: u64,
 ) -> Status {
     if this.is_null() {
         return Status::INVALID_PARAMETER;
     }
-    let bt = system_table().as_ref().boot_services();
+    let bt = boot_services();
     let ctx = LoopContext::from_loop_pt_ptr(this);
 
     let res = PrivMappingItem::from_loop_mapping_item(
         bt,
+        ctx,
+        read_only,
         &LoopMappingItem {
             start_sector: 0,
             num_sectors: 0,
@@ -213,42 +379,85 @@ unsafe extern "efiapi" fn set_file(
         },
     );
     let mut item = match res {
-        Err(e) => return e.status(),
+        Err(e) => {
+            let status = e.status();
+            log::error!("{}", e.context("failed to set backing file"));
+            return status;
+        }
         Ok(v) => v,
     };
 
-    let PrivTarget::File { info, .. } = &item.target else {
+    let PrivTarget::File(file) = &item.target else {
         unreachable!()
     };
 
-    let num_sectors = info.file_size() / SECTOR_SIZE as u64;
-    item.num_sectors = num_sectors;
-    set_media(ctx, read_only, is_partition, vec![item]);
+    item.num_sectors = match num_sectors {
+        0 => mapping::sectors_of_bytes(file.borrow().file_size),
+        n => n,
+    };
+    if !set_media(ctx, read_only, is_partition, vec![item]) {
+        log::error!("file too small or too many sectors");
+        return Status::INVALID_PARAMETER;
+    }
 
     let res = bt.connect_controller(ctx.device_handle, None, None, true);
     res.status()
 }
 
-fn set_media(
+pub(super) fn set_media(
     ctx: &mut LoopContext,
     read_only: bool,
     is_partition: bool,
     table: Vec<PrivMappingItem>,
 ) -> bool {
-    let Some(last) = table.last() else {
+    let Ok(total_sectors) = mapping::validate_table(&table) else {
         return false;
     };
-    let total_sectors = last.start_sector + last.num_sectors;
+    let guard = ctx.lock();
     ctx.table = table;
     ctx.media.read_only = read_only;
     ctx.media.logical_partition = is_partition;
+    ctx.media.removable_media = ctx.removable;
     ctx.media.block_size = SECTOR_SIZE as _;
-    ctx.media.last_block = total_sectors;
+    ctx.media.last_block = mapping::last_block(total_sectors, ctx.media.block_size);
     ctx.media.media_id = ctx.media.media_id.wrapping_add(1);
     ctx.media.media_present = true;
+    ctx.media.write_caching = !read_only && ctx.flush_interval_ms != 0;
+    drop(guard);
+    ctx.update_write_caching();
     true
 }
 
+/// Present blank media of `num_sectors`, backed by zero target if read-only or memory otherwise
+pub fn set_blank(ctx: &mut LoopContext, num_sectors: u64, read_only: bool) -> Result {
+    let bt = boot_services();
+    let target = if read_only {
+        PrivTarget::Zero(ZeroTarget)
+    } else {
+        let Some(size) = num_sectors
+            .checked_mul(SECTOR_SIZE as u64)
+            .and_then(|s| usize::try_from(s).ok())
+        else {
+            let msg = format!("blank device too large {}", num_sectors);
+            return Err(Error::msg(Status::BAD_BUFFER_SIZE, msg));
+        };
+        let pool = Pool::alloc(ctx, size).ok_or(Status::OUT_OF_RESOURCES)?;
+        PrivTarget::pool(pool)
+    };
+    let item = PrivMappingItem {
+        start_sector: 0,
+        num_sectors,
+        target,
+        target_start_sector: 0,
+    };
+    if !set_media(ctx, read_only, false, vec![item]) {
+        let msg = format!("invalid blank device size {}", num_sectors);
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    }
+    bt.connect_controller(ctx.device_handle, None, None, true)
+        .context("failed to connect loop device")
+}
+
 unsafe extern "efiapi" fn set_mapping_table(
     this: *mut LoopProtocol,
     read_only: bool,
@@ -259,7 +468,7 @@ unsafe extern "efiapi" fn set_mapping_table(
     if this.is_null() || (num_table_items > 0 && table.is_null()) {
         return Status::INVALID_PARAMETER;
     }
-    let bt = system_table().as_ref().boot_services();
+    let bt = boot_services();
     let ctx = LoopContext::from_loop_pt_ptr(this);
 
     let mut table = core::slice::from_raw_parts(table, num_table_items).to_vec();
@@ -269,21 +478,31 @@ unsafe extern "efiapi" fn set_mapping_table(
     priv_table.reserve(num_table_items);
 
     let mut res = Status::SUCCESS;
-    let mut prev_end = 0;
     for item in &table {
         if res != Status::SUCCESS {
-            if let LoopTarget::LoopPool { buffer } = item.target {
-                let _ = Pool::boxed_from_data_ptr(buffer as _);
+            match item.target {
+                LoopTarget::LoopPool { buffer } => {
+                    let _ = ctx.take_pool(buffer);
+                }
+                LoopTarget::BorrowedPool {
+                    buffer,
+                    release: Some(release),
+                    context,
+                    ..
+                } => release(context, buffer),
+                _ => {}
             }
             continue;
         }
-        let item = PrivMappingItem::from_loop_mapping_item(bt, item);
-        if res != Status::SUCCESS {
-            continue;
-        }
+        let start_sector = item.start_sector;
+        let item = PrivMappingItem::from_loop_mapping_item(bt, ctx, read_only, item);
         let item = match item {
             Err(e) => {
                 res = e.status();
+                log::error!(
+                    "{}",
+                    e.context(format!("invalid mapping item at sector {}", start_sector))
+                );
                 continue;
             }
             Ok(v) => v,
@@ -291,21 +510,16 @@ unsafe extern "efiapi" fn set_mapping_table(
         if item.num_sectors == 0 {
             continue;
         }
-        if item.start_sector != prev_end {
-            log::error!("mapping table not continuous");
-            return Status::INVALID_PARAMETER;
-        }
-        prev_end = item.start_sector + item.num_sectors;
         priv_table.push(item);
     }
-    if prev_end == 0 {
-        log::error!("empty mapping table");
-        return Status::INVALID_PARAMETER;
-    }
 
     if res != Status::SUCCESS {
         return res;
     }
+    if let Err(e) = mapping::validate_table(&priv_table) {
+        log::error!("invalid mapping table: {:?}", e);
+        return Status::INVALID_PARAMETER;
+    }
 
     set_media(ctx, read_only, is_partition, priv_table);
 
@@ -317,11 +531,14 @@ unsafe extern "efiapi" fn clear(this: *mut LoopProtocol) -> Status {
     if this.is_null() {
         return Status::INVALID_PARAMETER;
     }
-    let bt = system_table().as_ref().boot_services();
+    let bt = boot_services();
     let ctx = LoopContext::from_loop_pt_ptr(this);
+    let guard = ctx.lock();
     ctx.media.media_present = false;
     ctx.media.last_block = 0;
     ctx.table = vec![];
+    drop(guard);
+    ctx.update_write_caching();
 
     let res = bt.disconnect_controller(ctx.device_handle, None, None);
     res.status()
@@ -347,48 +564,448 @@ unsafe extern "efiapi" fn alloc_pool(
     }
     let ctx = LoopContext::from_loop_pt_ptr(this);
 
-    let header_size = mem::size_of::<PoolHeader>();
-    let layout = match Layout::from_size_align(header_size + size, POOL_ALIGN) {
+    let Some(pool) = Pool::alloc(ctx, size) else {
+        return Status::OUT_OF_RESOURCES;
+    };
+    let data = pool.into_data_ptr();
+    ctx.pools.push(data);
+    *buffer = data as _;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn free_pool(this: *mut LoopProtocol, buffer: *mut c_void) -> Status {
+    if this.is_null() || buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+
+    if ctx.take_pool(buffer).is_some() {
+        return Status::SUCCESS;
+    }
+    if ctx.is_pool_in_use(buffer) {
+        log::error!("pool {:?} is in use by mapping table", buffer);
+        return Status::ACCESS_DENIED;
+    }
+    log::error!("pool {:?} is invalid or not managed by this loop", buffer);
+    Status::INVALID_PARAMETER
+}
+
+unsafe extern "efiapi" fn set_removable(this: *mut LoopProtocol, removable: bool) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    ctx.removable = removable;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_name(this: *mut LoopProtocol, name: *const Char16) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    if name.is_null() {
+        ctx.name = default_name(ctx.unit_number);
+        return Status::SUCCESS;
+    }
+    let name = CStr16::from_ptr(name);
+    if name.is_empty() {
+        return Status::INVALID_PARAMETER;
+    }
+    ctx.name = name.into();
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn get_children(
+    this: *mut LoopProtocol,
+    num_handles: *mut usize,
+    handles: *mut RawHandle,
+) -> Status {
+    if this.is_null() || num_handles.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let bt = boot_services();
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+
+    let children = child_controllers(bt, ctx.device_handle);
+    let buffer_len = *num_handles;
+    *num_handles = children.len();
+    if buffer_len < children.len() {
+        return Status::BUFFER_TOO_SMALL;
+    }
+    if handles.is_null() {
+        return if children.is_empty() {
+            Status::SUCCESS
+        } else {
+            Status::INVALID_PARAMETER
+        };
+    }
+    for (i, child) in children.iter().enumerate() {
+        *handles.add(i) = child.as_ptr();
+    }
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_write_caching(
+    this: *mut LoopProtocol,
+    flush_interval_ms: u32,
+) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    ctx.flush_interval_ms = flush_interval_ms;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_watchdog(this: *mut LoopProtocol, timeout: usize) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    ctx.watchdog_timeout = timeout;
+    Status::SUCCESS
+}
+
+const SPARSIFY_DEFAULT_MIN_SECTORS: u64 = 2048;
+const SPARSIFY_SCAN_SECTORS: u64 = 128;
+
+unsafe extern "efiapi" fn sparsify(this: *mut LoopProtocol, min_sectors: u64) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    if !ctx.media.media_present {
+        return Status::NO_MEDIA;
+    }
+    if !ctx.media.read_only {
+        // content of file may change
+        log::error!("only read-only media can be sparsified");
+        return Status::ACCESS_DENIED;
+    }
+    let min_sectors = match min_sectors {
+        0 => SPARSIFY_DEFAULT_MIN_SECTORS,
+        n => n,
+    };
+
+    let mut runs = mapping::ZeroRuns::new(min_sectors);
+    let mut buffer = vec![0u8; SPARSIFY_SCAN_SECTORS as usize * SECTOR_SIZE];
+    for item in &mut ctx.table {
+        let PrivTarget::File(file) = &mut item.target else {
+            continue;
+        };
+        let mut offset = 0;
+        while offset < item.num_sectors {
+            let num_sectors = (item.num_sectors - offset).min(SPARSIFY_SCAN_SECTORS);
+            let buffer = &mut buffer[..num_sectors as usize * SECTOR_SIZE];
+            if let Err(e) = file.read_sectors(item.target_start_sector + offset, buffer) {
+                log::error!("failed to scan file: {}", e);
+                return e.status();
+            }
+            runs.feed(item.start_sector + offset, buffer);
+            offset += num_sectors;
+        }
+        runs.finish(item.end_sector());
+    }
+
+    // map all zero runs or none of them
+    let mut table = ctx.table.clone();
+    for run in runs.runs {
+        let zero = PrivMappingItem {
+            start_sector: run.start,
+            num_sectors: run.end - run.start,
+            target: PrivTarget::Zero(ZeroTarget),
+            target_start_sector: 0,
+        };
+        if let Err(e) = mapping::replace_range(&mut table, zero) {
+            log::error!("failed to map zero region: {:?}", e);
+            return Status::ABORTED;
+        }
+    }
+    if let Err(e) = mapping::validate_table(&table) {
+        log::error!("invalid sparsified table: {:?}", e);
+        return Status::ABORTED;
+    }
+
+    let guard = ctx.lock();
+    ctx.table = table;
+    drop(guard);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn punch_hole(
+    this: *mut LoopProtocol,
+    start_sector: u64,
+    num_sectors: u64,
+) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    if !ctx.media.media_present {
+        return Status::NO_MEDIA;
+    }
+    if ctx.media.read_only {
+        return Status::WRITE_PROTECTED;
+    }
+
+    let mut cursor = match mapping::resolve(&ctx.table, start_sector, num_sectors) {
+        Ok(v) => v,
         Err(e) => {
-            log::error!("{}", e);
+            log::error!("hole overflows device region: {:?}", e);
             return Status::INVALID_PARAMETER;
         }
-        Ok(l) => l,
     };
-    let ptr = alloc(layout);
-
-    let meta = &mut *ptr.cast::<PoolHeader>();
-    meta.ctx = ctx;
-    meta.pool_size = size;
+    while let Some(chunk) = cursor.next_chunk(&ctx.table) {
+        if let PrivTarget::File(_) = ctx.table[chunk.index].target {
+            log::error!("punching hole on file target is not supported");
+            return Status::UNSUPPORTED;
+        }
+    }
 
-    *buffer = ptr.add(header_size) as _;
+    let zero = PrivMappingItem {
+        start_sector,
+        num_sectors,
+        target: PrivTarget::Zero(ZeroTarget),
+        target_start_sector: 0,
+    };
+    let mut table = ctx.table.clone();
+    if let Err(e) = mapping::replace_range(&mut table, zero) {
+        log::error!("failed to map zero region: {:?}", e);
+        return Status::INVALID_PARAMETER;
+    }
+    // pools are dropped with their last mapping item
+    let guard = ctx.lock();
+    ctx.table = table;
+    drop(guard);
     Status::SUCCESS
 }
 
-unsafe extern "efiapi" fn free_pool(this: *mut LoopProtocol, buffer: *mut c_void) -> Status {
-    if this.is_null() || buffer.is_null() {
+enum EditMapping {
+    Insert,
+    Replace,
+    Delete(u64, u64),
+}
+
+/// Apply edit on a copy of table and swap it in if the result is valid
+unsafe fn edit_mapping(
+    this: *mut LoopProtocol,
+    item: *const LoopMappingItem,
+    edit: EditMapping,
+) -> Status {
+    let bt = boot_services();
+    let ctx = LoopContext::from_loop_pt_ptr(this);
+    let read_only = ctx.media.read_only;
+    // take the ownership of pool first
+    let item = match item.as_ref() {
+        None => None,
+        Some(item) => match PrivMappingItem::from_loop_mapping_item(bt, ctx, read_only, item) {
+            Err(e) => {
+                log::error!("{}", e);
+                return e.status();
+            }
+            Ok(v) => Some(v),
+        },
+    };
+    if !ctx.media.media_present {
+        return Status::NO_MEDIA;
+    }
+
+    let mut table = ctx.table.clone();
+    let res = match (edit, item) {
+        (EditMapping::Insert, Some(item)) => mapping::insert_range(&mut table, item),
+        (EditMapping::Replace, Some(item)) => mapping::replace_range(&mut table, item),
+        (EditMapping::Delete(start_sector, num_sectors), None) => {
+            mapping::delete_range(&mut table, start_sector, num_sectors)
+        }
+        _ => unreachable!(),
+    };
+    let total_sectors = match res.and_then(|_| mapping::validate_table(&table)) {
+        Err(e) => {
+            log::error!("invalid mapping edit: {:?}", e);
+            return Status::INVALID_PARAMETER;
+        }
+        Ok(v) => v,
+    };
+
+    let last_block = mapping::last_block(total_sectors, ctx.media.block_size);
+    if last_block == ctx.media.last_block {
+        let guard = ctx.lock();
+        ctx.table = table;
+        drop(guard);
+        ctx.update_write_caching();
+        return Status::SUCCESS;
+    }
+
+    // geometry changed
+    let _ = bt.disconnect_controller(ctx.device_handle, None, None);
+    let read_only = ctx.media.read_only;
+    let is_partition = ctx.media.logical_partition;
+    set_media(ctx, read_only, is_partition, table);
+    let res = bt.connect_controller(ctx.device_handle, None, None, true);
+    res.status()
+}
+
+unsafe extern "efiapi" fn insert_mapping(
+    this: *mut LoopProtocol,
+    item: *const LoopMappingItem,
+) -> Status {
+    if this.is_null() || item.is_null() {
         return Status::INVALID_PARAMETER;
     }
-    let Some(pool) = Pool::boxed_from_data_ptr(buffer as _) else {
+    edit_mapping(this, item, EditMapping::Insert)
+}
+
+unsafe extern "efiapi" fn replace_mapping(
+    this: *mut LoopProtocol,
+    item: *const LoopMappingItem,
+) -> Status {
+    if this.is_null() || item.is_null() {
         return Status::INVALID_PARAMETER;
-    };
+    }
+    edit_mapping(this, item, EditMapping::Replace)
+}
+
+unsafe extern "efiapi" fn delete_mapping(
+    this: *mut LoopProtocol,
+    start_sector: u64,
+    num_sectors: u64,
+) -> Status {
+    if this.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    edit_mapping(
+        this,
+        ptr::null(),
+        EditMapping::Delete(start_sector, num_sectors),
+    )
+}
+
+unsafe extern "efiapi" fn set_url(
+    this: *mut LoopProtocol,
+    is_partition: bool,
+    nic: RawHandle,
+    url: *const Char16,
+) -> Status {
+    if this.is_null() || url.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let bt = boot_services();
     let ctx = LoopContext::from_loop_pt_ptr(this);
 
-    if pool.header.ctx != ctx {
-        log::error!("pool {:?} is invalid or not managed by this loop", buffer);
+    let http = match HttpTarget::new(Handle::from_ptr(nic), CStr16::from_ptr(url)) {
+        Err(e) => {
+            log::error!("{}", e);
+            return e.status();
+        }
+        Ok(v) => v,
+    };
+    let item = PrivMappingItem {
+        start_sector: 0,
+        num_sectors: http.num_sectors(),
+        target: PrivTarget::http(http),
+        target_start_sector: 0,
+    };
+    if !set_media(ctx, true, is_partition, vec![item]) {
+        log::error!("remote file too small");
         return Status::INVALID_PARAMETER;
     }
 
-    Status::SUCCESS
+    let res = bt.connect_controller(ctx.device_handle, None, None, true);
+    res.status()
+}
+
+/// [LoopProtocol] of the device `this` is installed on, null if `this` is null
+unsafe fn loop_pt_of_v0(this: *mut LoopProtocolV0) -> *mut LoopProtocol {
+    if this.is_null() {
+        return ptr::null_mut();
+    }
+    ptr::addr_of_mut!(LoopContext::from_loop_pt_v0_ptr(this).loop_pt)
+}
+
+unsafe extern "efiapi" fn set_file_v0(
+    this: *mut LoopProtocolV0,
+    read_only: bool,
+    is_partition: bool,
+    fs_device: RawHandle,
+    path: *const FfiDevicePath,
+) -> Status {
+    set_file(
+        loop_pt_of_v0(this),
+        read_only,
+        is_partition,
+        fs_device,
+        path,
+    )
+}
+
+unsafe extern "efiapi" fn set_mapping_table_v0(
+    this: *mut LoopProtocolV0,
+    read_only: bool,
+    is_partition: bool,
+    num_table_items: usize,
+    table: *const LoopMappingItem,
+) -> Status {
+    set_mapping_table(
+        loop_pt_of_v0(this),
+        read_only,
+        is_partition,
+        num_table_items,
+        table,
+    )
+}
+
+unsafe extern "efiapi" fn clear_v0(this: *mut LoopProtocolV0) -> Status {
+    clear(loop_pt_of_v0(this))
+}
+
+unsafe extern "efiapi" fn get_info_v0(this: *mut LoopProtocolV0, info: *mut LoopInfo) -> Status {
+    get_info(loop_pt_of_v0(this), info)
+}
+
+unsafe extern "efiapi" fn alloc_pool_v0(
+    this: *mut LoopProtocolV0,
+    size: usize,
+    buffer: *mut *mut c_void,
+) -> Status {
+    alloc_pool(loop_pt_of_v0(this), size, buffer)
+}
+
+unsafe extern "efiapi" fn free_pool_v0(this: *mut LoopProtocolV0, buffer: *mut c_void) -> Status {
+    free_pool(loop_pt_of_v0(this), buffer)
+}
+
+pub fn create_loopback_v0() -> LoopProtocolV0 {
+    LoopProtocolV0 {
+        set_file: set_file_v0,
+        set_mapping_table: set_mapping_table_v0,
+        clear: clear_v0,
+        get_info: get_info_v0,
+        alloc_pool: alloc_pool_v0,
+        free_pool: free_pool_v0,
+    }
 }
 
 pub fn create_loopback() -> LoopProtocol {
     LoopProtocol {
+        revision: LoopProtocol::REVISION,
         set_file,
         set_mapping_table,
         clear,
         get_info,
         alloc_pool,
         free_pool,
+        set_removable,
+        sparsify,
+        punch_hole,
+        insert_mapping,
+        replace_mapping,
+        delete_mapping,
+        set_url,
+        set_name,
+        get_children,
+        set_write_caching,
+        set_watchdog,
+        set_file_ex,
     }
 }
@@ -1,28 +1,49 @@
 mod block_io;
+mod http;
 mod loop_pt;
+mod pxe;
+mod target;
 
 use super::*;
+use http::HttpTarget;
+pub(super) use loop_pt::set_blank;
 pub use loop_pt::*;
+pub(super) use pxe::set_tftp_file;
+use target::*;
 
+use alloc::alloc::{alloc_zeroed, Layout};
+use core::ptr::NonNull;
 use ptr_meta::Pointee;
 use uefi::proto::device_path::DevicePath;
 use uefi::proto::media::file::{File, FileInfo, RegularFile};
 use uefi::proto::media::fs::SimpleFileSystem;
-use uefi::table::boot::ScopedProtocol;
+use uefi::table::boot::{EventType, ScopedProtocol, TimerTrigger, Tpl, TplGuard};
+use uefi::Event;
 use uefi::{CString16, Char16};
+use uefi_loopmap::mapping::{self, MappingItem};
 
 #[repr(C)]
 pub(super) struct LoopContext {
     dev_path: dev_path::LoopbackPath,
     loop_pt: LoopProtocol,
+    loop_pt_v0: LoopProtocolV0,
     block_io: block_io::BlockIoProtocol,
     media: block_io::BlockIoMedia,
     unit_number: u32,
+    removable: bool,
+    /// seconds to reset watchdog timer to between chunks of I/O, 0 to not touch it
+    watchdog_timeout: usize,
+    /// interval to write out cached writes, 0 to disable write caching
+    flush_interval_ms: u32,
+    flush_timer: Option<Event>,
     name: CString16,
+    bus_handle: Handle,
     device_handle: Handle,
     loop_ctl: Option<ScopedProtocol<'static, LoopControlProtocol>>,
     protocols: Vec<(Guid, *mut c_void)>,
     table: Vec<PrivMappingItem>,
+    /// data of pools allocated for caller and not yet passed to mapping table
+    pools: Vec<*mut u8>,
 }
 impl LoopContext {
     #[inline]
@@ -30,6 +51,10 @@ impl LoopContext {
         &mut *container_of!(ptr, loopback::LoopContext, loop_pt)
     }
     #[inline]
+    pub unsafe fn from_loop_pt_v0_ptr<'a>(ptr: *mut LoopProtocolV0) -> &'a mut Self {
+        &mut *container_of!(ptr, loopback::LoopContext, loop_pt_v0)
+    }
+    #[inline]
     pub unsafe fn from_block_io_ptr<'a>(ptr: *mut block_io::BlockIoProtocol) -> &'a mut Self {
         &mut *container_of!(ptr, loopback::LoopContext, block_io)
     }
@@ -41,6 +66,117 @@ impl LoopContext {
     pub fn is_free(&self) -> bool {
         !self.media.media_present
     }
+    /// Take ownership of pool allocated for caller, so it can't be freed or used twice
+    fn take_pool(&mut self, data: *mut c_void) -> Option<Box<Pool>> {
+        let idx = self.pools.iter().position(|p| *p == data as *mut u8)?;
+        let data = self.pools.swap_remove(idx);
+        unsafe { Pool::boxed_from_data_ptr(data) }
+    }
+    fn is_pool_in_use(&self, data: *mut c_void) -> bool {
+        self.table.iter().any(|item| match &item.target {
+            PrivTarget::LoopPool(pool) => pool.borrow().pool.data.as_ptr() == data as *const u8,
+            _ => false,
+        })
+    }
+    /// Keep flush timer from running in between while accessing table
+    #[inline]
+    fn lock(&self) -> Option<TplGuard<'static>> {
+        let bt = boot_services();
+        self.flush_timer
+            .is_some()
+            .then(|| unsafe { bt.raise_tpl(Tpl::CALLBACK) })
+    }
+    /// Apply write caching of media to file targets and arm or cancel flush timer
+    fn update_write_caching(&mut self) {
+        let bt = boot_services();
+        let mut enabled = self.media.media_present && self.media.write_caching;
+        if enabled && self.flush_timer.is_none() {
+            let res = unsafe {
+                bt.create_event(
+                    EventType::TIMER | EventType::NOTIFY_SIGNAL,
+                    Tpl::CALLBACK,
+                    Some(flush_timer_notify),
+                    NonNull::new(self as *mut Self as *mut c_void),
+                )
+            };
+            match res {
+                Ok(event) => self.flush_timer = Some(event),
+                Err(e) => {
+                    log::error!("failed to create flush timer, disable write caching: {}", e);
+                    self.media.write_caching = false;
+                    enabled = false;
+                }
+            }
+        }
+
+        let _guard = self.lock();
+        for item in &self.table {
+            if let PrivTarget::File(file) = &item.target {
+                file.borrow_mut().write_back = enabled;
+            }
+        }
+        if let Some(timer) = &self.flush_timer {
+            let trigger = if enabled {
+                // in 100ns units
+                TimerTrigger::Periodic(self.flush_interval_ms as u64 * 10_000)
+            } else {
+                TimerTrigger::Cancel
+            };
+            if let Err(e) = bt.set_timer(timer, trigger) {
+                log::error!("failed to set flush timer: {}", e);
+            }
+        }
+    }
+    #[inline]
+    fn uses_fs(&self, fs_device: Handle) -> bool {
+        self.table.iter().any(|item| match &item.target {
+            PrivTarget::File(file) => file.borrow().fs_device == fs_device,
+            _ => false,
+        })
+    }
+}
+
+/// Remove media of loop that has file on `fs_device`, returns whether the loop was affected
+pub(super) fn detach_backing_fs(ctx: &mut LoopContext, fs_device: Handle) -> bool {
+    if !ctx.uses_fs(fs_device) {
+        return false;
+    }
+    log::warn!(
+        "backing file system {:?} of loop {} is going away",
+        fs_device,
+        ctx.unit_number
+    );
+    let bt = boot_services();
+    let _ = bt.disconnect_controller(ctx.device_handle, None, None);
+    let guard = ctx.lock();
+    ctx.media.media_present = false;
+    ctx.media.last_block = 0;
+    ctx.table = vec![];
+    drop(guard);
+    ctx.update_write_caching();
+    true
+}
+
+/// Write cached writes of file targets out periodically
+unsafe extern "efiapi" fn flush_timer_notify(_event: Event, context: Option<NonNull<c_void>>) {
+    let Some(ctx) = context else {
+        return;
+    };
+    let ctx = &*ctx.as_ptr().cast::<LoopContext>();
+    for item in &ctx.table {
+        let PrivTarget::File(file) = &item.target else {
+            continue;
+        };
+        // skip if it's being accessed
+        let Ok(mut file) = file.try_borrow_mut() else {
+            continue;
+        };
+        if file.pending.is_some() {
+            if let Err(e) = file.flush() {
+                log::error!("failed to flush cached writes: {}", e);
+            }
+        }
+    }
 }
 
 const POOL_ALIGN: usize = 8;
@@ -59,6 +195,33 @@ struct Pool {
     data: [u8],
 }
 impl Pool {
+    /// Allocate a zeroed pool owned by `ctx`
+    fn alloc(ctx: *const LoopContext, size: usize) -> Option<Box<Self>> {
+        let header_size = mem::size_of::<PoolHeader>();
+        let layout = match Layout::from_size_align(header_size.checked_add(size)?, POOL_ALIGN) {
+            Err(e) => {
+                log::error!("{}", e);
+                return None;
+            }
+            Ok(l) => l,
+        };
+        unsafe {
+            let ptr = alloc_zeroed(layout);
+            if ptr.is_null() {
+                return None;
+            }
+            let header = &mut *ptr.cast::<PoolHeader>();
+            header.ctx = ctx;
+            header.pool_size = size;
+            Self::boxed_from_data_ptr(ptr.add(header_size))
+        }
+    }
+
+    #[inline]
+    fn into_data_ptr(self: Box<Self>) -> *mut u8 {
+        unsafe { ptr::addr_of_mut!((*Box::into_raw(self)).data).cast() }
+    }
+
     #[inline]
     #[must_use]
     unsafe fn boxed_from_data_ptr(data: *mut u8) -> Option<Box<Self>> {
@@ -73,36 +236,14 @@ impl Pool {
     }
 }
 
-#[allow(unused)]
-#[derive(Debug)]
-enum PrivTarget {
-    Zero,
-    LoopPool {
-        pool: Box<Pool>,
-    },
-    File {
-        fs_device: Handle,
-        path: Box<DevicePath>,
-        fs_interface: *mut SimpleFileSystem,
-        file: RegularFile,
-        info: Box<FileInfo>,
-    },
-}
-
-#[derive(Debug)]
-struct PrivMappingItem {
-    start_sector: u64,
-    num_sectors: u64,
-    target: PrivTarget,
-    target_start_sector: u64,
-}
+type PrivMappingItem = MappingItem<PrivTarget>;
 
 fn open_loop_ctl_by_child(
     bus_handle: Handle,
     device_handle: Handle,
 ) -> Result<ScopedProtocol<'static, LoopControlProtocol>> {
     unsafe {
-        let bt = system_table().as_ref().boot_services();
+        let bt = boot_services();
         bt.open_protocol::<LoopControlProtocol>(
             OpenProtocolParams {
                 handle: bus_handle,
@@ -115,27 +256,37 @@ fn open_loop_ctl_by_child(
     }
 }
 
+/// Name reported via component name if not set by caller
+fn default_name(unit_number: u32) -> CString16 {
+    let name = alloc::format!("Loopback Device #{}", unit_number);
+    CString16::try_from(name.as_str()).unwrap()
+}
+
 pub(super) fn install_loopback(
     bus_handle: Handle,
     handle: Option<Handle>,
     unit_number: u32,
 ) -> Result<(Handle, *mut LoopContext)> {
-    let bt = unsafe { system_table().as_ref().boot_services() };
+    let bt = boot_services();
     let invalid_handle = unsafe { Handle::from_ptr(mem::align_of::<Handle>() as _).unwrap() };
-    let name = alloc::format!("Loopback Device #{}", unit_number);
-    let name = CString16::try_from(name.as_str()).unwrap();
-
     let mut ctx = Box::new(LoopContext {
         dev_path: dev_path::LoopbackPath::new(unit_number),
         loop_pt: loop_pt::create_loopback(),
+        loop_pt_v0: loop_pt::create_loopback_v0(),
         block_io: block_io::create_block_io(ptr::null()),
         media: block_io::create_default_media(),
         unit_number,
-        name,
+        removable: true,
+        watchdog_timeout: 0,
+        flush_interval_ms: 0,
+        flush_timer: None,
+        name: default_name(unit_number),
+        bus_handle,
         device_handle: invalid_handle,
         loop_ctl: None,
         protocols: vec![],
         table: vec![],
+        pools: vec![],
     });
     ctx.block_io.media = ptr::addr_of_mut!(ctx.media);
 
@@ -143,6 +294,7 @@ pub(super) fn install_loopback(
         ctx.protocols = vec![
             (DevicePath::GUID, ptr::addr_of_mut!(ctx.dev_path) as _),
             (LoopProtocol::GUID, ptr::addr_of_mut!(ctx.loop_pt) as _),
+            (LoopProtocolV0::GUID, ptr::addr_of_mut!(ctx.loop_pt_v0) as _),
             (
                 block_io::BlockIoProtocol::GUID,
                 ptr::addr_of_mut!(ctx.block_io) as _,
@@ -173,7 +325,7 @@ pub(super) fn install_loopback(
 
 pub(super) fn uninstall_loopback(bus_handle: Handle, device_handle: Handle) -> Result {
     unsafe {
-        let bt = system_table().as_ref().boot_services();
+        let bt = boot_services();
         let loop_pt_ptr = get_protocol_mut::<LoopProtocol>(bt, device_handle)?.unwrap();
         let mut ctx = Box::from_raw(container_of!(loop_pt_ptr, LoopContext, loop_pt));
 
@@ -189,6 +341,15 @@ pub(super) fn uninstall_loopback(bus_handle: Handle, device_handle: Handle) -> R
 
             return Err(e.to_err_without_payload());
         };
+
+        if let Some(timer) = ctx.flush_timer.take() {
+            let _ = bt.close_event(timer);
+        }
+
+        // pools never passed to mapping table
+        for data in mem::take(&mut ctx.pools) {
+            let _ = Pool::boxed_from_data_ptr(data);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,96 @@
+use super::*;
+
+use alloc::format;
+
+use uefi::proto::network::pxe::{BaseCode, DhcpV4Packet};
+use uefi::proto::network::IpAddress;
+use uefi::CStr8;
+use uefi_error::{Context, Error, Result};
+
+/// Boot server announced by proxy DHCP offer or DHCP ACK
+fn boot_server_ip(bc: &BaseCode) -> Option<IpAddress> {
+    let mode = bc.mode();
+    let packet = if mode.proxy_offer_received {
+        &mode.proxy_offer
+    } else if mode.dhcp_ack_received {
+        &mode.dhcp_ack
+    } else {
+        return None;
+    };
+    let packet: &DhcpV4Packet = packet.as_ref();
+    let si_addr = packet.bootp_si_addr;
+    (si_addr != [0; 4]).then(|| IpAddress::new_v4(si_addr))
+}
+
+/// Download `filename` into a loop pool with PXE base code on `pxe_device`
+/// and present it as media of `ctx`
+pub fn set_tftp_file(
+    ctx: &mut LoopContext,
+    read_only: bool,
+    is_partition: bool,
+    pxe_device: Option<Handle>,
+    server_ip: Option<&IpAddress>,
+    filename: &CStr8,
+) -> Result {
+    let bt = boot_services();
+    let invalid_err = || Error::new(Status::INVALID_PARAMETER);
+
+    let pxe_device = match pxe_device {
+        Some(h) => h,
+        None => bt
+            .get_handle_for_protocol::<BaseCode>()
+            .context("PXE base code not found")?,
+    };
+    let bc =
+        unsafe { &mut *get_protocol_mut::<BaseCode>(bt, pxe_device)?.ok_or_else(invalid_err)? };
+    if !bc.mode().started {
+        return Err(Error::msg(Status::NOT_STARTED, "PXE base code not started"));
+    }
+
+    let server_ip = match server_ip {
+        Some(ip) => *ip,
+        None => boot_server_ip(bc).ok_or_else(|| {
+            Error::msg(
+                Status::INVALID_PARAMETER,
+                "no boot server found in DHCP packets",
+            )
+        })?,
+    };
+
+    let file_size = bc
+        .tftp_get_file_size(&server_ip, filename)
+        .with_context(|| format!("failed to get size of {}", filename))?;
+    // zero padded to whole sectors
+    let num_sectors =
+        mapping::sectors_of_bytes(file_size) + (file_size % SECTOR_SIZE as u64 != 0) as u64;
+    let Some(pool_size) = num_sectors
+        .checked_mul(SECTOR_SIZE as u64)
+        .and_then(|s| usize::try_from(s).ok())
+    else {
+        let msg = format!("file too large {}", file_size);
+        return Err(Error::msg(Status::BAD_BUFFER_SIZE, msg));
+    };
+    let mut pool = Pool::alloc(ctx, pool_size).ok_or(Status::OUT_OF_RESOURCES)?;
+
+    let read = bc
+        .tftp_read_file(&server_ip, filename, Some(&mut pool.data))
+        .with_context(|| format!("failed to download {}", filename))?;
+    if read != file_size {
+        let msg = format!("downloaded {} of {} bytes", read, file_size);
+        return Err(Error::msg(Status::DEVICE_ERROR, msg));
+    }
+
+    let item = PrivMappingItem {
+        start_sector: 0,
+        num_sectors,
+        target: PrivTarget::pool(pool),
+        target_start_sector: 0,
+    };
+    if !set_media(ctx, read_only, is_partition, vec![item]) {
+        let msg = format!("empty file {}", filename);
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    }
+
+    bt.connect_controller(ctx.device_handle, None, None, true)
+        .context("failed to connect loop device")
+}
@@ -0,0 +1,311 @@
+use super::*;
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use loop_pt::SECTOR_SIZE;
+
+/// Backing storage of a mapping item, sectors are relative to the target itself
+pub(super) trait Target {
+    fn read_sectors(&mut self, sector: u64, buffer: &mut [u8]) -> Result;
+    fn write_sectors(&mut self, sector: u64, buffer: &[u8]) -> Result;
+    fn flush(&mut self) -> Result {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct ZeroTarget;
+
+impl Target for ZeroTarget {
+    fn read_sectors(&mut self, _sector: u64, buffer: &mut [u8]) -> Result {
+        buffer.fill(0);
+        Ok(())
+    }
+    fn write_sectors(&mut self, _sector: u64, _buffer: &[u8]) -> Result {
+        log::warn!("writing to virtual zero block, discard");
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct PoolTarget {
+    pub pool: Box<Pool>,
+}
+
+impl PoolTarget {
+    /// Pool is validated to contain all sectors mapped at creation,
+    /// so the sector fits in usize even on 32-bit targets
+    #[inline]
+    fn data_range(&self, sector: u64, len: usize) -> core::ops::Range<usize> {
+        let start = usize::try_from(sector).unwrap() * SECTOR_SIZE;
+        start..start + len
+    }
+}
+
+impl Target for PoolTarget {
+    fn read_sectors(&mut self, sector: u64, buffer: &mut [u8]) -> Result {
+        let range = self.data_range(sector, buffer.len());
+        buffer.copy_from_slice(&self.pool.data[range]);
+        Ok(())
+    }
+    fn write_sectors(&mut self, sector: u64, buffer: &[u8]) -> Result {
+        let range = self.data_range(sector, buffer.len());
+        self.pool.data[range].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+/// Caller owned memory, released with callback on drop
+#[derive(Debug)]
+pub(super) struct BorrowedPoolTarget {
+    pub data: *mut u8,
+    pub size: usize,
+    pub release: Option<ReleaseBufferFn>,
+    pub context: *mut c_void,
+}
+
+impl BorrowedPoolTarget {
+    #[inline]
+    fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.data, self.size) }
+    }
+}
+
+impl Target for BorrowedPoolTarget {
+    fn read_sectors(&mut self, sector: u64, buffer: &mut [u8]) -> Result {
+        let start = usize::try_from(sector).unwrap() * SECTOR_SIZE;
+        buffer.copy_from_slice(&self.data_mut()[start..][..buffer.len()]);
+        Ok(())
+    }
+    fn write_sectors(&mut self, sector: u64, buffer: &[u8]) -> Result {
+        let start = usize::try_from(sector).unwrap() * SECTOR_SIZE;
+        self.data_mut()[start..][..buffer.len()].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+impl Drop for BorrowedPoolTarget {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self.context, self.data as _) };
+        }
+    }
+}
+
+/// I/O requests between validations of file system not held by driver
+const VALIDATE_INTERVAL: u32 = 32;
+/// Sequential writes are coalesced up to this size in write caching mode, 1MiB
+const WRITE_BACK_MAX_SIZE: usize = 1 << 20;
+
+/// Backing file system is held with [hold_backing_fs] so the loop
+/// loses media once the file system is going to be uninstalled
+#[derive(Debug)]
+pub(super) struct FileTarget {
+    pub bus_handle: Handle,
+    pub fs_device: Handle,
+    pub fs_interface: *mut SimpleFileSystem,
+    /// whether the file system is held by driver
+    pub held: bool,
+    pub path: Box<DevicePath>,
+    pub file: RegularFile,
+    /// current size of file, grows on writing beyond end of file
+    pub file_size: u64,
+    pub info: Box<FileInfo>,
+    /// I/O requests since last validation
+    pub num_unvalidated: u32,
+    /// whether writes are cached until flush
+    pub write_back: bool,
+    /// sector and data of cached writes not yet written to file
+    pub pending: Option<(u64, Vec<u8>)>,
+}
+
+impl FileTarget {
+    fn is_valid(&self) -> bool {
+        let bt = boot_services();
+        validate_handle_protocol(
+            bt,
+            self.fs_device.as_ptr(),
+            &SimpleFileSystem::GUID,
+            self.fs_interface as _,
+        )
+    }
+
+    /// Validate the file system periodically if it's not held by driver
+    fn check(&mut self) -> Result {
+        if self.held {
+            return Ok(());
+        }
+        if self.num_unvalidated == 0 && !self.is_valid() {
+            log::error!("file device or FS protocol interface changed");
+            return Status::DEVICE_ERROR.to_result();
+        }
+        self.num_unvalidated = (self.num_unvalidated + 1) % VALIDATE_INTERVAL;
+        Ok(())
+    }
+
+    /// Revalidate on error of `res`
+    fn revalidate_on_err<T>(&mut self, res: Result<T>) -> Result<T> {
+        if res.is_err() && !self.is_valid() {
+            log::error!("file device or FS protocol interface changed");
+            self.num_unvalidated = 0;
+            return Err(uefi::Error::new(Status::DEVICE_ERROR, ()));
+        }
+        res
+    }
+
+    /// Sectors beyond end of file read as zero
+    fn read_at(&mut self, sector: u64, buffer: &mut [u8]) -> Result {
+        let position = sector * SECTOR_SIZE as u64;
+        let in_file = usize::try_from(self.file_size.saturating_sub(position))
+            .unwrap_or(usize::MAX)
+            .min(buffer.len());
+        let (buffer, tail) = buffer.split_at_mut(in_file);
+        tail.fill(0);
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        self.file.set_position(position)?;
+        if self.file.read(buffer)? != buffer.len() {
+            log::error!("read underflow");
+            return Status::DEVICE_ERROR.to_result();
+        }
+        Ok(())
+    }
+
+    /// Writing beyond end of file extends it, with gap zero-filled by file system
+    fn write_at(&mut self, sector: u64, buffer: &[u8]) -> Result {
+        let position = sector * SECTOR_SIZE as u64;
+        self.file.set_position(position)?;
+        if let Err(e) = self.file.write(buffer) {
+            log::error!("written {} of {} bytes", e.data(), buffer.len());
+            return Err(e.to_err_without_payload());
+        }
+        self.file_size = self.file_size.max(position + buffer.len() as u64);
+        Ok(())
+    }
+
+    /// Write cached writes out to file
+    fn write_pending(&mut self) -> Result {
+        let Some((sector, data)) = self.pending.take() else {
+            return Ok(());
+        };
+        let res = self.write_at(sector, &data);
+        self.revalidate_on_err(res)
+    }
+
+    /// Whether cached writes overlap `len` bytes from `sector`
+    fn pending_overlaps(&self, sector: u64, len: usize) -> bool {
+        self.pending.as_ref().is_some_and(|(start, data)| {
+            let end = start + (data.len() / SECTOR_SIZE) as u64;
+            sector < end && *start < sector + (len / SECTOR_SIZE) as u64
+        })
+    }
+
+    /// Append to cached writes if `sector` continues them, start new ones otherwise
+    fn write_cached(&mut self, sector: u64, buffer: &[u8]) -> Result {
+        if let Some((start, data)) = &mut self.pending {
+            let end = *start + (data.len() / SECTOR_SIZE) as u64;
+            if end == sector && data.len() + buffer.len() <= WRITE_BACK_MAX_SIZE {
+                data.extend_from_slice(buffer);
+                return Ok(());
+            }
+        }
+        self.write_pending()?;
+        self.pending = Some((sector, buffer.to_vec()));
+        Ok(())
+    }
+}
+
+impl Target for FileTarget {
+    fn read_sectors(&mut self, sector: u64, buffer: &mut [u8]) -> Result {
+        self.check()?;
+        if self.pending_overlaps(sector, buffer.len()) {
+            self.write_pending()?;
+        }
+        let res = self.read_at(sector, buffer);
+        self.revalidate_on_err(res)
+    }
+    fn write_sectors(&mut self, sector: u64, buffer: &[u8]) -> Result {
+        self.check()?;
+        if self.write_back && buffer.len() < WRITE_BACK_MAX_SIZE {
+            return self.write_cached(sector, buffer);
+        }
+        self.write_pending()?;
+        let res = self.write_at(sector, buffer);
+        self.revalidate_on_err(res)
+    }
+    fn flush(&mut self) -> Result {
+        self.check()?;
+        self.write_pending()?;
+        let res = self.file.flush();
+        self.revalidate_on_err(res)
+    }
+}
+
+impl Drop for FileTarget {
+    fn drop(&mut self) {
+        if self.pending.is_some() {
+            let res = if self.held || self.is_valid() {
+                self.write_pending()
+            } else {
+                Status::DEVICE_ERROR.to_result()
+            };
+            if let Err(e) = res {
+                log::error!("failed to write cached data: {}", e);
+            }
+        }
+        release_backing_fs(self.bus_handle, self.fs_device);
+    }
+}
+
+/// Targets shared by items split from the same item
+impl<T: Target> Target for Rc<RefCell<T>> {
+    fn read_sectors(&mut self, sector: u64, buffer: &mut [u8]) -> Result {
+        self.borrow_mut().read_sectors(sector, buffer)
+    }
+    fn write_sectors(&mut self, sector: u64, buffer: &[u8]) -> Result {
+        self.borrow_mut().write_sectors(sector, buffer)
+    }
+    fn flush(&mut self) -> Result {
+        self.borrow_mut().flush()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum PrivTarget {
+    Zero(ZeroTarget),
+    LoopPool(Rc<RefCell<PoolTarget>>),
+    BorrowedPool(Rc<RefCell<BorrowedPoolTarget>>),
+    File(Rc<RefCell<FileTarget>>),
+    Http(Rc<RefCell<HttpTarget>>),
+}
+
+impl PrivTarget {
+    #[inline]
+    pub fn pool(pool: Box<Pool>) -> Self {
+        Self::LoopPool(Rc::new(RefCell::new(PoolTarget { pool })))
+    }
+    #[inline]
+    pub fn borrowed_pool(pool: BorrowedPoolTarget) -> Self {
+        Self::BorrowedPool(Rc::new(RefCell::new(pool)))
+    }
+    #[inline]
+    pub fn file(file: FileTarget) -> Self {
+        Self::File(Rc::new(RefCell::new(file)))
+    }
+    #[inline]
+    pub fn http(http: HttpTarget) -> Self {
+        Self::Http(Rc::new(RefCell::new(http)))
+    }
+    #[inline]
+    pub fn as_target_mut(&mut self) -> &mut dyn Target {
+        match self {
+            Self::Zero(t) => t,
+            Self::LoopPool(t) => t,
+            Self::BorrowedPool(t) => t,
+            Self::File(t) => t,
+            Self::Http(t) => t,
+        }
+    }
+}
@@ -5,26 +5,31 @@ mod loop_ctl;
 mod loopback;
 
 pub use loop_ctl::LoopControlProtocol;
-pub use loopback::{LoopInfo, LoopMappingItem, LoopProtocol, LoopTarget, SECTOR_SIZE};
+pub use loopback::{
+    LoopInfo, LoopMappingItem, LoopProtocol, LoopTarget, ReleaseBufferFn, MAX_SECTORS, SECTOR_SIZE,
+};
 
 use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::ffi::c_void;
 use core::{mem, ptr};
+use loop_ctl::LoopControlProtocolV0;
 
 use uefi::prelude::*;
 use uefi::proto::device_path::FfiDevicePath;
 use uefi::proto::unsafe_protocol;
 
+use uefi::helpers::system_table;
 use uefi::proto::device_path::DevicePath;
-use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
 use uefi::Result;
 use uefi::{Identify, Status};
 use uefi_raw::protocol::driver::ComponentName2Protocol;
+use uefi_raw::table::boot::OpenProtocolInformationEntry;
 use uefi_raw::Guid;
 use uefi_raw::Handle as RawHandle;
-use uefi_services::system_table;
 
 #[repr(C)]
 struct ControlContext {
@@ -32,16 +37,88 @@ struct ControlContext {
     driver_binding: binding::DriverBindingProtocol,
     comp_name: ComponentName2Protocol,
     loop_ctl: LoopControlProtocol,
+    loop_ctl_v0: LoopControlProtocolV0,
     bus_handle: Handle,
     protocols: Vec<(Guid, *mut c_void)>,
     loop_list: Vec<(u32, Handle, *mut loopback::LoopContext)>,
+    backing_fs: Vec<BackingFs>,
+}
+
+/// File system used by file targets, opened by driver so that
+/// [binding::DriverBindingProtocol] gets stopped before it's uninstalled
+struct BackingFs {
+    fs_device: Handle,
+    num_refs: usize,
+    fs: Option<ScopedProtocol<'static, SimpleFileSystem>>,
+}
+
+#[inline]
+unsafe fn control_context_of<'a>(bus_handle: Handle) -> Result<&'a mut ControlContext> {
+    let bt = boot_services();
+    let loop_ctl_ptr = get_protocol_mut::<LoopControlProtocol>(bt, bus_handle)?.unwrap();
+    Ok(&mut *container_of!(loop_ctl_ptr, ControlContext, loop_ctl))
+}
+
+/// Returns whether the file system is held by driver,
+/// otherwise users should validate it before access
+fn hold_backing_fs(bus_handle: Handle, fs_device: Handle) -> Result<bool> {
+    let ctx = unsafe { control_context_of(bus_handle)? };
+    if let Some(fs) = ctx.backing_fs.iter_mut().find(|i| i.fs_device == fs_device) {
+        fs.num_refs += 1;
+        return Ok(fs.fs.is_some());
+    }
+    let res = unsafe {
+        let bt = boot_services();
+        bt.open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle: fs_device,
+                agent: bus_handle,
+                controller: Some(fs_device),
+            },
+            OpenProtocolAttributes::ByDriver,
+        )
+    };
+    let fs = match res {
+        Ok(v) => Some(v),
+        Err(e) if e.status() == Status::ACCESS_DENIED => {
+            log::warn!("file system {:?} managed by other driver", fs_device);
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let held = fs.is_some();
+    ctx.backing_fs.push(BackingFs {
+        fs_device,
+        num_refs: 1,
+        fs,
+    });
+    Ok(held)
+}
+
+fn release_backing_fs(bus_handle: Handle, fs_device: Handle) {
+    let Ok(ctx) = (unsafe { control_context_of(bus_handle) }) else {
+        return;
+    };
+    let Some(idx) = ctx.backing_fs.iter().position(|i| i.fs_device == fs_device) else {
+        return;
+    };
+    let fs = &mut ctx.backing_fs[idx];
+    fs.num_refs -= 1;
+    if fs.num_refs == 0 {
+        // close file system protocol
+        ctx.backing_fs.remove(idx);
+    }
 }
 
 pub fn install_loop_control(handle: Option<Handle>) -> Result<Handle> {
-    let bt = unsafe { system_table().as_ref().boot_services() };
+    let bt = boot_services();
     let invalid_handle = unsafe { Handle::from_ptr(mem::align_of::<Handle>() as _).unwrap() };
 
-    if bt.get_handle_for_protocol::<LoopControlProtocol>().is_ok() {
+    if bt.get_handle_for_protocol::<LoopControlProtocol>().is_ok()
+        || bt
+            .get_handle_for_protocol::<LoopControlProtocolV0>()
+            .is_ok()
+    {
         log::error!("Loop control protocol already exists, aborting");
         return Err(uefi::Error::new(Status::ALREADY_STARTED, ()));
     }
@@ -51,8 +128,10 @@ pub fn install_loop_control(handle: Option<Handle>) -> Result<Handle> {
         driver_binding: binding::create_driver_binding(invalid_handle),
         comp_name: comp_name::create_comp_name(),
         loop_ctl: loop_ctl::create_loop_control(),
+        loop_ctl_v0: loop_ctl::create_loop_control_v0(),
         bus_handle: invalid_handle,
         loop_list: vec![],
+        backing_fs: vec![],
         protocols: vec![],
     });
 
@@ -71,6 +150,10 @@ pub fn install_loop_control(handle: Option<Handle>) -> Result<Handle> {
                 LoopControlProtocol::GUID,
                 ptr::addr_of_mut!(ctx.loop_ctl).cast(),
             ),
+            (
+                LoopControlProtocolV0::GUID,
+                ptr::addr_of_mut!(ctx.loop_ctl_v0).cast(),
+            ),
         ];
         install_multiple_protocols(bt, handle, &ctx.protocols)
     };
@@ -92,7 +175,7 @@ pub fn install_loop_control(handle: Option<Handle>) -> Result<Handle> {
 
 pub fn uninstall_loop_control(bus_handle: Handle) -> Result {
     unsafe {
-        let bt = system_table().as_ref().boot_services();
+        let bt = boot_services();
         let loop_ctl_ptr = get_protocol_mut::<LoopControlProtocol>(bt, bus_handle)?.unwrap();
         let ctx = &mut *container_of!(loop_ctl_ptr, ControlContext, loop_ctl);
 
@@ -173,11 +256,64 @@ pub unsafe fn get_protocol_mut<P: uefi::proto::Protocol>(
     Ok(pt.get_mut().map(|r| r as *mut _))
 }
 
+/// Boot services of the global system table set by [uefi::helpers::init]
 #[inline]
+pub fn boot_services() -> &'static BootServices {
+    // the table outlives the `SystemTable` handle returned, valid until exiting boot services
+    let bt: *const BootServices = system_table().boot_services();
+    unsafe { &*bt }
+}
+
 pub fn get_boot_service_raw(bt: &BootServices) -> &uefi_raw::table::boot::BootServices {
     unsafe { &*(bt as *const BootServices as *const _) }
 }
 
+/// Controllers created by drivers consuming `protocol` on `handle`
+fn child_controllers_by_protocol(
+    bt: &BootServices,
+    handle: Handle,
+    protocol: &Guid,
+) -> Vec<Handle> {
+    let bt = get_boot_service_raw(bt);
+    let mut entries: *const OpenProtocolInformationEntry = ptr::null();
+    let mut count = 0;
+    unsafe {
+        let status =
+            (bt.open_protocol_information)(handle.as_ptr(), protocol, &mut entries, &mut count);
+        if status != Status::SUCCESS {
+            return vec![];
+        }
+        let children = core::slice::from_raw_parts(entries, count)
+            .iter()
+            .filter(|e| e.attributes & OpenProtocolAttributes::ByChildController as u32 != 0)
+            .filter_map(|e| Handle::from_ptr(e.controller_handle))
+            .collect();
+        let _ = (bt.free_pool)(entries as _);
+        children
+    }
+}
+
+/// All descendant controllers of `handle`, e.g. partitions and
+/// partitions of them created by recursive connecting
+pub fn child_controllers(bt: &BootServices, handle: Handle) -> Vec<Handle> {
+    let mut children: Vec<Handle> = vec![];
+    let mut parents = vec![handle];
+    while let Some(parent) = parents.pop() {
+        let Ok(protocols) = bt.protocols_per_handle(parent) else {
+            continue;
+        };
+        for protocol in protocols.protocols() {
+            for child in child_controllers_by_protocol(bt, parent, protocol) {
+                if child != handle && !children.contains(&child) {
+                    children.push(child);
+                    parents.push(child);
+                }
+            }
+        }
+    }
+    children
+}
+
 /// Validate if handle is validate and if protocol interface is still the same
 #[inline]
 fn validate_handle_protocol(
@@ -3,15 +3,16 @@
 
 extern crate alloc;
 
+use uefi::helpers::system_table;
 use uefi::prelude::*;
 use uefi::proto::loaded_image::LoadedImage;
-use uefi_services::system_table;
 
 const MIN_UEFI_REVISION: uefi::table::Revision = uefi::table::Revision::EFI_2_00;
 
 #[entry]
 fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
-    uefi_services::init(&mut system_table).unwrap();
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
     let bt = system_table.boot_services();
 
     if system_table.uefi_revision() < MIN_UEFI_REVISION {
@@ -22,6 +23,13 @@ fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         );
         return Status::INCOMPATIBLE_VERSION;
     }
+    log::info!(
+        "uefi-loopdrv {} ({} {} {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("UEFI_TOYS_GIT_COMMIT"),
+        env!("UEFI_TOYS_BUILD_TARGET"),
+        env!("UEFI_TOYS_BUILD_DATE")
+    );
 
     match uefi_loopdrv::install_loop_control(Some(bt.image_handle())) {
         Err(e) => return e.status(),
@@ -36,6 +44,7 @@ fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
 }
 
 extern "efiapi" fn unload(_handle: Handle) -> Status {
-    let bt = unsafe { system_table().as_ref().boot_services() };
+    let st = system_table();
+    let bt = st.boot_services();
     uefi_loopdrv::uninstall_loop_control(bt.image_handle()).status()
 }
@@ -0,0 +1,87 @@
+//! Small LRU cache for remote targets
+
+use alloc::collections::VecDeque;
+
+/// Least recently used cache, linear lookup is fine for the few dozens of entries kept
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// most recently used first
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Get value of `key` and mark it most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let idx = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(idx)?;
+        self.entries.push_front(entry);
+        self.entries.front().map(|(_, v)| v)
+    }
+
+    /// Insert or replace value of `key`, evicting the least recently used one if full
+    pub fn insert(&mut self, key: K, value: V) -> &V {
+        if let Some(idx) = self.entries.iter().position(|(k, _)| *k == key) {
+            self.entries.remove(idx);
+        } else if self.entries.len() >= self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries.push_front((key, value));
+        &self.entries.front().unwrap().1
+    }
+
+    /// Get value of `key` or insert the one returned by `f`
+    pub fn get_or_try_insert_with<E, F>(&mut self, key: K, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        if self.get(&key).is_some() {
+            return Ok(&self.entries.front().unwrap().1);
+        }
+        let value = f()?;
+        Ok(self.insert(key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(Some(&"a"), cache.get(&1));
+        cache.insert(3, "c");
+        assert_eq!(2, cache.entries.len());
+        assert_eq!(None, cache.get(&2));
+        assert_eq!(Some(&"a"), cache.get(&1));
+        assert_eq!(Some(&"c"), cache.get(&3));
+
+        cache.insert(3, "d");
+        assert_eq!(2, cache.entries.len());
+        assert_eq!(Some(&"d"), cache.get(&3));
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut cache = LruCache::new(1);
+        assert_eq!(Err(()), cache.get_or_try_insert_with(1, || Err(())));
+        assert_eq!(0, cache.entries.len());
+        assert_eq!(Ok::<_, ()>(&10), cache.get_or_try_insert_with(1, || Ok(10)));
+        assert_eq!(
+            Ok::<_, ()>(&10),
+            cache.get_or_try_insert_with(1, || Err(()))
+        );
+        assert_eq!(Ok::<_, ()>(&20), cache.get_or_try_insert_with(2, || Ok(20)));
+        assert_eq!(None, cache.get(&1));
+    }
+}
@@ -0,0 +1,8 @@
+//! Sector mapping and caching logic of loopdrv, free of UEFI dependencies
+//! so it can be tested on host.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod cache;
+pub mod mapping;
@@ -0,0 +1,830 @@
+//! Sector mapping and geometry logic of loopback devices
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A sector is 512-bytes
+pub const SECTOR_SIZE: usize = 512;
+
+/// Sectors addressable on device or target, so that byte offset of any sector fits in u64
+pub const MAX_SECTORS: u64 = u64::MAX / SECTOR_SIZE as u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingError {
+    /// Table contains no sectors
+    Empty,
+    /// Item does not start at end of previous item
+    NotContinuous,
+    /// Sector arithmetic overflowed
+    Overflow,
+    /// Accessed region exceeds device region
+    OutOfRange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappingItem<T> {
+    pub start_sector: u64,
+    pub num_sectors: u64,
+    pub target: T,
+    pub target_start_sector: u64,
+}
+impl<T> MappingItem<T> {
+    #[inline]
+    pub fn end_sector(&self) -> u64 {
+        self.start_sector + self.num_sectors
+    }
+}
+
+/// Total sectors of a continuous table
+#[inline]
+pub fn total_sectors<T>(table: &[MappingItem<T>]) -> u64 {
+    table.last().map(|last| last.end_sector()).unwrap_or(0)
+}
+
+/// Validate that items of `table` are continuous from sector 0 and
+/// within [MAX_SECTORS], returns total sectors
+pub fn validate_table<T>(table: &[MappingItem<T>]) -> Result<u64, MappingError> {
+    let end_of = |start: u64, num: u64| {
+        start
+            .checked_add(num)
+            .filter(|end| *end <= MAX_SECTORS)
+            .ok_or(MappingError::Overflow)
+    };
+    let mut prev_end: u64 = 0;
+    for item in table {
+        if item.start_sector != prev_end {
+            return Err(MappingError::NotContinuous);
+        }
+        prev_end = end_of(item.start_sector, item.num_sectors)?;
+        end_of(item.target_start_sector, item.num_sectors)?;
+    }
+    if prev_end == 0 {
+        return Err(MappingError::Empty);
+    }
+    Ok(prev_end)
+}
+
+/// Number of whole sectors in `size` bytes
+#[inline]
+pub fn sectors_of_bytes(size: u64) -> u64 {
+    size / SECTOR_SIZE as u64
+}
+
+/// Convert LBA of `block_size` to sector
+#[inline]
+pub fn lba_to_sector(lba: u64, block_size: u32) -> Option<u64> {
+    lba.checked_mul(block_size as u64)
+        .map(|bytes| bytes / SECTOR_SIZE as u64)
+}
+
+/// Last LBA of a device of `total_sectors` with blocks of `block_size`
+#[inline]
+pub fn last_block(total_sectors: u64, block_size: u32) -> u64 {
+    let blocks = total_sectors.min(MAX_SECTORS) * SECTOR_SIZE as u64 / block_size as u64;
+    blocks.saturating_sub(1)
+}
+
+/// A region of an I/O request that lands in a single mapping item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Index of mapping item
+    pub index: usize,
+    /// Sector relative to the item target
+    pub target_sector: u64,
+    /// Sector offset relative to start of the I/O request
+    pub offset: u64,
+    pub num_sectors: u64,
+}
+impl Chunk {
+    /// Byte range of this chunk in the I/O buffer, casts are lossless
+    /// on 32-bit targets as the chunk lies within the buffer
+    #[inline]
+    pub fn buffer_range(&self) -> Range<usize> {
+        let start = self.offset as usize * SECTOR_SIZE;
+        start..start + self.num_sectors as usize * SECTOR_SIZE
+    }
+}
+
+/// Iterates chunks of an I/O request, the table is passed in on every step
+/// so that targets can be mutably accessed in between
+#[derive(Debug, Clone)]
+pub struct ChunkCursor {
+    index: usize,
+    start_sector: u64,
+    total_sectors: u64,
+    advance: u64,
+    max_sectors: u64,
+}
+impl ChunkCursor {
+    /// Bound sectors of every chunk, so that a long run within one item is
+    /// yielded in several steps
+    pub fn with_max_sectors(mut self, max_sectors: u64) -> Self {
+        self.max_sectors = max_sectors.max(1);
+        self
+    }
+
+    /// Whether all chunks are yielded
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.advance == self.total_sectors
+    }
+
+    pub fn next_chunk<T>(&mut self, table: &[MappingItem<T>]) -> Option<Chunk> {
+        let remaining = self.total_sectors - self.advance;
+        if remaining == 0 {
+            return None;
+        }
+        let index = self.index;
+        let item = table.get(index)?;
+        let curr_sector = self.start_sector + self.advance;
+        let item_remaining = item.end_sector() - curr_sector;
+        let num_sectors = remaining.min(item_remaining).min(self.max_sectors);
+        let chunk = Chunk {
+            index,
+            target_sector: item.target_start_sector + (curr_sector - item.start_sector),
+            offset: self.advance,
+            num_sectors,
+        };
+        self.advance += num_sectors;
+        if num_sectors == item_remaining {
+            self.index += 1;
+        }
+        Some(chunk)
+    }
+}
+
+/// Locate the mapping item of `start_sector` and check that the I/O request
+/// lies within the device region
+pub fn resolve<T>(
+    table: &[MappingItem<T>],
+    start_sector: u64,
+    num_sectors: u64,
+) -> Result<ChunkCursor, MappingError> {
+    let end = start_sector
+        .checked_add(num_sectors)
+        .ok_or(MappingError::Overflow)?;
+    if end > total_sectors(table) {
+        return Err(MappingError::OutOfRange);
+    }
+    let upper_bound = table.partition_point(|x| x.start_sector <= start_sector);
+    // hit if mapping table is empty, unsorted or `start_sector` of first item is not 0
+    if upper_bound == 0 {
+        return Err(MappingError::NotContinuous);
+    }
+    Ok(ChunkCursor {
+        index: upper_bound - 1,
+        start_sector,
+        total_sectors: num_sectors,
+        advance: 0,
+        max_sectors: u64::MAX,
+    })
+}
+
+/// Split the item covering `sector` so that an item starts exactly at `sector`,
+/// returns index of that item or length of table if `sector` is the end
+pub fn split_at<T: Clone>(
+    table: &mut Vec<MappingItem<T>>,
+    sector: u64,
+) -> Result<usize, MappingError> {
+    let total = total_sectors(table);
+    if sector > total {
+        return Err(MappingError::OutOfRange);
+    }
+    if sector == total {
+        return Ok(table.len());
+    }
+    let idx = table.partition_point(|x| x.start_sector <= sector) - 1;
+    let item = &mut table[idx];
+    if item.start_sector == sector {
+        return Ok(idx);
+    }
+    let head_sectors = sector - item.start_sector;
+    let tail = MappingItem {
+        start_sector: sector,
+        num_sectors: item.num_sectors - head_sectors,
+        target: item.target.clone(),
+        target_start_sector: item.target_start_sector + head_sectors,
+    };
+    item.num_sectors = head_sectors;
+    table.insert(idx + 1, tail);
+    Ok(idx + 1)
+}
+
+/// Overlay `item` onto the region it covers, items or parts of items
+/// previously mapped there are dropped
+pub fn replace_range<T: Clone>(
+    table: &mut Vec<MappingItem<T>>,
+    item: MappingItem<T>,
+) -> Result<(), MappingError> {
+    let end = item
+        .start_sector
+        .checked_add(item.num_sectors)
+        .ok_or(MappingError::Overflow)?;
+    if end > total_sectors(table) {
+        return Err(MappingError::OutOfRange);
+    }
+    if item.num_sectors == 0 {
+        return Ok(());
+    }
+    let first = split_at(table, item.start_sector)?;
+    let last = split_at(table, end)?;
+    table.splice(first..last, [item]);
+    Ok(())
+}
+
+/// Insert `item` at its start sector, items after are shifted towards the end
+pub fn insert_range<T: Clone>(
+    table: &mut Vec<MappingItem<T>>,
+    item: MappingItem<T>,
+) -> Result<(), MappingError> {
+    let total = total_sectors(table);
+    if item.start_sector > total {
+        return Err(MappingError::OutOfRange);
+    }
+    total
+        .checked_add(item.num_sectors)
+        .filter(|end| *end <= MAX_SECTORS)
+        .ok_or(MappingError::Overflow)?;
+    if item.num_sectors == 0 {
+        return Ok(());
+    }
+    let idx = split_at(table, item.start_sector)?;
+    for curr in &mut table[idx..] {
+        curr.start_sector += item.num_sectors;
+    }
+    table.insert(idx, item);
+    Ok(())
+}
+
+/// Remove sectors of the region, items after are shifted towards the start
+pub fn delete_range<T: Clone>(
+    table: &mut Vec<MappingItem<T>>,
+    start_sector: u64,
+    num_sectors: u64,
+) -> Result<(), MappingError> {
+    let end = start_sector
+        .checked_add(num_sectors)
+        .ok_or(MappingError::Overflow)?;
+    if end > total_sectors(table) {
+        return Err(MappingError::OutOfRange);
+    }
+    if num_sectors == 0 {
+        return Ok(());
+    }
+    let first = split_at(table, start_sector)?;
+    let last = split_at(table, end)?;
+    table.drain(first..last);
+    for curr in &mut table[first..] {
+        curr.start_sector -= num_sectors;
+    }
+    Ok(())
+}
+
+/// Collect runs of all-zero sectors no shorter than `min_sectors`
+#[derive(Debug)]
+pub struct ZeroRuns {
+    min_sectors: u64,
+    start: Option<u64>,
+    pub runs: Vec<Range<u64>>,
+}
+impl ZeroRuns {
+    pub fn new(min_sectors: u64) -> Self {
+        Self {
+            min_sectors,
+            start: None,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Feed data of sectors starting at `sector`
+    pub fn feed(&mut self, sector: u64, data: &[u8]) {
+        for (i, data) in data.chunks_exact(SECTOR_SIZE).enumerate() {
+            let curr = sector + i as u64;
+            if data.iter().all(|b| *b == 0) {
+                self.start.get_or_insert(curr);
+            } else {
+                self.finish(curr);
+            }
+        }
+    }
+
+    /// End current run at `end_sector`, should be called at every discontinuity
+    pub fn finish(&mut self, end_sector: u64) {
+        if let Some(start) = self.start.take() {
+            if end_sector - start >= self.min_sectors {
+                self.runs.push(start..end_sector);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn item(start_sector: u64, num_sectors: u64, target: u32) -> MappingItem<u32> {
+        MappingItem {
+            start_sector,
+            num_sectors,
+            target,
+            target_start_sector: target as u64 * 1000,
+        }
+    }
+
+    fn chunks(table: &[MappingItem<u32>], start: u64, num: u64) -> Vec<Chunk> {
+        let mut cursor = resolve(table, start, num).unwrap();
+        let mut res = vec![];
+        while let Some(chunk) = cursor.next_chunk(table) {
+            res.push(chunk);
+        }
+        res
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(Err(MappingError::Empty), validate_table::<u32>(&[]));
+        assert_eq!(Err(MappingError::Empty), validate_table(&[item(0, 0, 0)]));
+        assert_eq!(
+            Err(MappingError::NotContinuous),
+            validate_table(&[item(1, 1, 0)])
+        );
+        assert_eq!(
+            Err(MappingError::NotContinuous),
+            validate_table(&[item(0, 2, 0), item(1, 2, 1)])
+        );
+        assert_eq!(
+            Err(MappingError::Overflow),
+            validate_table(&[item(0, 2, 0), item(2, u64::MAX, 1)])
+        );
+        assert_eq!(Ok(5), validate_table(&[item(0, 2, 0), item(2, 3, 1)]));
+    }
+
+    #[test]
+    fn geometry() {
+        assert_eq!(0, sectors_of_bytes(511));
+        assert_eq!(2, sectors_of_bytes(1024));
+        assert_eq!(Some(8), lba_to_sector(2, 2048));
+        assert_eq!(None, lba_to_sector(u64::MAX, 512));
+        assert_eq!(0, last_block(0, 512));
+        assert_eq!(0, last_block(1, 512));
+        assert_eq!(9, last_block(10, 512));
+        assert_eq!(1, last_block(8, 2048));
+    }
+
+    #[test]
+    fn resolve_bounds() {
+        let table = [item(0, 4, 0), item(4, 4, 1)];
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            resolve(&table, 0, 9).map(|_| ())
+        );
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            resolve(&table, 8, 1).map(|_| ())
+        );
+        assert_eq!(
+            Err(MappingError::Overflow),
+            resolve(&table, u64::MAX, 2).map(|_| ())
+        );
+        assert!(chunks(&table, 8, 0).is_empty());
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            resolve::<u32>(&[], 0, 1).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn resolve_single_item() {
+        let table = [item(0, 4, 0), item(4, 4, 1)];
+        assert_eq!(
+            vec![Chunk {
+                index: 1,
+                target_sector: 1001,
+                offset: 0,
+                num_sectors: 2,
+            }],
+            chunks(&table, 5, 2)
+        );
+        // ends exactly at item boundary
+        assert_eq!(
+            vec![Chunk {
+                index: 0,
+                target_sector: 0,
+                offset: 0,
+                num_sectors: 4,
+            }],
+            chunks(&table, 0, 4)
+        );
+    }
+
+    #[test]
+    fn resolve_across_items() {
+        let table = [item(0, 4, 0), item(4, 1, 1), item(5, 4, 2)];
+        let res = chunks(&table, 3, 4);
+        assert_eq!(
+            vec![
+                Chunk {
+                    index: 0,
+                    target_sector: 3,
+                    offset: 0,
+                    num_sectors: 1,
+                },
+                Chunk {
+                    index: 1,
+                    target_sector: 1000,
+                    offset: 1,
+                    num_sectors: 1,
+                },
+                Chunk {
+                    index: 2,
+                    target_sector: 2000,
+                    offset: 2,
+                    num_sectors: 2,
+                },
+            ],
+            res
+        );
+        assert_eq!(0..512, res[0].buffer_range());
+        assert_eq!(512..1024, res[1].buffer_range());
+        assert_eq!(1024..2048, res[2].buffer_range());
+    }
+
+    #[test]
+    fn resolve_max_sectors() {
+        let table = [item(0, 5, 0), item(5, 2, 1)];
+        let mut cursor = resolve(&table, 1, 6).unwrap().with_max_sectors(2);
+        let mut res = vec![];
+        while let Some(chunk) = cursor.next_chunk(&table) {
+            res.push((
+                chunk.index,
+                chunk.target_sector,
+                chunk.offset,
+                chunk.num_sectors,
+            ));
+        }
+        assert!(cursor.is_done());
+        assert_eq!(vec![(0, 1, 0, 2), (0, 3, 2, 2), (1, 1000, 4, 2)], res);
+    }
+
+    type StartPick = (u8, u64, u64, bool);
+
+    /// Continuous table of tiny, small and >4GiB sized items
+    fn table_strategy() -> impl Strategy<Value = Vec<MappingItem<u32>>> {
+        let num_sectors = prop_oneof![1..=4u64, 1..=1u64 << 12, 1..=1u64 << 40];
+        prop::collection::vec((num_sectors, 0..1u64 << 40), 1..=8).prop_map(|items| {
+            let mut start_sector = 0;
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(target, (num_sectors, target_start_sector))| {
+                    let item = MappingItem {
+                        start_sector,
+                        num_sectors,
+                        target: target as u32,
+                        target_start_sector,
+                    };
+                    start_sector += num_sectors;
+                    item
+                })
+                .collect()
+        })
+    }
+
+    fn start_pick() -> impl Strategy<Value = StartPick> {
+        (0..3u8, any::<u64>(), 0..4u64, any::<bool>())
+    }
+
+    /// Request start of `pick`, biased towards item boundaries and device end
+    fn request_start(table: &[MappingItem<u32>], pick: StartPick) -> u64 {
+        let (kind, n, delta, after) = pick;
+        let total = total_sectors(table);
+        match kind {
+            0 => n % total,
+            1 => {
+                let item = &table[(n % table.len() as u64) as usize];
+                if after {
+                    (item.end_sector() + delta).min(total - 1)
+                } else {
+                    item.start_sector.saturating_sub(delta)
+                }
+            }
+            _ => total - 1 - (n % 16).min(total - 1),
+        }
+    }
+
+    /// Reference model, resolves a single sector by linear search
+    fn model_resolve(table: &[MappingItem<u32>], sector: u64) -> Option<(u32, u64)> {
+        table
+            .iter()
+            .find(|i| i.start_sector <= sector && sector < i.end_sector())
+            .map(|i| (i.target, i.target_start_sector + (sector - i.start_sector)))
+    }
+
+    fn resolve_sectors(table: &[MappingItem<u32>], start: u64, num: u64) -> Vec<(u32, u64)> {
+        let mut cursor = resolve(table, start, num).unwrap();
+        let mut res = vec![];
+        while let Some(chunk) = cursor.next_chunk(table) {
+            assert_eq!(res.len() as u64, chunk.offset);
+            assert_ne!(0, chunk.num_sectors);
+            let target = table[chunk.index].target;
+            res.extend((0..chunk.num_sectors).map(|k| (target, chunk.target_sector + k)));
+        }
+        assert_eq!(num, res.len() as u64);
+        res
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(1000))]
+
+        #[test]
+        fn prop_resolve_matches_model(
+            table in table_strategy(),
+            pick in start_pick(),
+            num in 0..64u64,
+        ) {
+            let total = total_sectors(&table);
+            prop_assert_eq!(Ok(total), validate_table(&table));
+
+            let start = request_start(&table, pick);
+            let num = num.min(total - start);
+            let expected: Vec<_> = (start..start + num)
+                .map(|sector| model_resolve(&table, sector).unwrap())
+                .collect();
+            prop_assert_eq!(expected, resolve_sectors(&table, start, num));
+
+            prop_assert_eq!(
+                Err(MappingError::OutOfRange),
+                resolve(&table, start, total - start + 1).map(|_| ())
+            );
+        }
+
+        #[test]
+        fn prop_rw_matches_flat_model(
+            table in table_strategy(),
+            requests in prop::collection::vec((start_pick(), 0..32u64), 32),
+        ) {
+            use std::collections::BTreeMap;
+
+            // sparse flat device buffer and sparse per-target buffers
+            let mut flat = BTreeMap::<u64, u64>::new();
+            let mut targets = BTreeMap::<(u32, u64), u64>::new();
+            let mut accessed = vec![];
+
+            for (value, (pick, num)) in requests.into_iter().enumerate() {
+                let start = request_start(&table, pick);
+                let num = num.min(total_sectors(&table) - start);
+                for (k, target) in resolve_sectors(&table, start, num).into_iter().enumerate() {
+                    flat.insert(start + k as u64, value as u64);
+                    targets.insert(target, value as u64);
+                }
+                accessed.push((start, num));
+            }
+
+            for (start, num) in accessed {
+                for (k, target) in resolve_sectors(&table, start, num).into_iter().enumerate() {
+                    prop_assert_eq!(flat.get(&(start + k as u64)), targets.get(&target));
+                }
+            }
+        }
+
+        #[test]
+        fn prop_overlaps_and_gaps_rejected(
+            mut table in table_strategy().prop_filter("single item", |t| t.len() >= 2),
+            index in any::<prop::sample::Index>(),
+            delta in 1..=1u64 << 20,
+            overlap in any::<bool>(),
+        ) {
+            let idx = 1 + index.index(table.len() - 1);
+            let prev_end = table[idx - 1].end_sector();
+            table[idx].start_sector = if overlap {
+                prev_end.saturating_sub(delta)
+            } else {
+                prev_end + delta
+            };
+            prop_assume!(table[idx].start_sector != prev_end);
+            prop_assert_eq!(Err(MappingError::NotContinuous), validate_table(&table));
+        }
+    }
+
+    #[test]
+    fn split() {
+        let mut table = vec![item(0, 4, 0), item(4, 4, 1)];
+        assert_eq!(Err(MappingError::OutOfRange), split_at(&mut table, 9));
+        assert_eq!(Ok(2), split_at(&mut table, 8));
+        assert_eq!(Ok(1), split_at(&mut table, 4));
+        assert_eq!(2, table.len());
+        assert_eq!(Ok(2), split_at(&mut table, 6));
+        assert_eq!(
+            vec![
+                item(0, 4, 0),
+                item(4, 2, 1),
+                MappingItem {
+                    start_sector: 6,
+                    num_sectors: 2,
+                    target: 1,
+                    target_start_sector: 1002,
+                },
+            ],
+            table
+        );
+        assert_eq!(Ok(8), validate_table(&table));
+    }
+
+    #[test]
+    fn replace() {
+        let mut table = vec![item(0, 4, 0), item(4, 4, 1)];
+        let hole = |start_sector, num_sectors| MappingItem {
+            start_sector,
+            num_sectors,
+            target: 9,
+            target_start_sector: 0,
+        };
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            replace_range(&mut table, hole(6, 3))
+        );
+        assert_eq!(Ok(()), replace_range(&mut table, hole(2, 4)));
+        assert_eq!(
+            vec![
+                item(0, 2, 0),
+                hole(2, 4),
+                MappingItem {
+                    start_sector: 6,
+                    num_sectors: 2,
+                    target: 1,
+                    target_start_sector: 1002,
+                },
+            ],
+            table
+        );
+        assert_eq!(Ok(()), replace_range(&mut table, hole(0, 8)));
+        assert_eq!(vec![hole(0, 8)], table);
+    }
+
+    #[test]
+    fn replace_releases_targets() {
+        use alloc::rc::Rc;
+
+        let pool = Rc::new(0u32);
+        let shared = |start_sector, num_sectors| MappingItem {
+            start_sector,
+            num_sectors,
+            target: Some(pool.clone()),
+            target_start_sector: start_sector,
+        };
+        let hole = |start_sector, num_sectors| MappingItem {
+            start_sector,
+            num_sectors,
+            target: None,
+            target_start_sector: 0,
+        };
+        let mut table = vec![shared(0, 8)];
+        replace_range(&mut table, hole(2, 2)).unwrap();
+        assert_eq!(3, Rc::strong_count(&pool));
+        replace_range(&mut table, hole(0, 2)).unwrap();
+        assert_eq!(2, Rc::strong_count(&pool));
+        replace_range(&mut table, hole(4, 4)).unwrap();
+        assert_eq!(1, Rc::strong_count(&pool));
+        assert_eq!(Ok(8), validate_table(&table));
+    }
+
+    #[test]
+    fn zero_runs() {
+        let zero = [0u8; SECTOR_SIZE];
+        let mut data = [0u8; SECTOR_SIZE];
+        data[SECTOR_SIZE - 1] = 1;
+
+        let mut runs = ZeroRuns::new(2);
+        runs.feed(0, &zero);
+        runs.feed(1, &data);
+        runs.feed(2, &[zero, zero, zero].concat());
+        runs.feed(5, &data);
+        runs.feed(6, &[zero, zero].concat());
+        runs.finish(8);
+        // discontinuity
+        runs.feed(100, &zero);
+        runs.finish(101);
+        assert_eq!(vec![2..5, 6..8], runs.runs);
+    }
+
+    #[test]
+    fn huge_mapping() {
+        const TIB_SECTORS: u64 = (1 << 40) / SECTOR_SIZE as u64;
+        // 6TiB of 2TiB items, sectors and bytes beyond 32-bit
+        let table: Vec<_> = (0..3)
+            .map(|i| MappingItem {
+                start_sector: i * 2 * TIB_SECTORS,
+                num_sectors: 2 * TIB_SECTORS,
+                target: i as u32,
+                target_start_sector: 3 * TIB_SECTORS,
+            })
+            .collect();
+        assert_eq!(Ok(6 * TIB_SECTORS), validate_table(&table));
+        assert_eq!(6 * TIB_SECTORS - 1, last_block(6 * TIB_SECTORS, 512));
+        assert_eq!(6 * TIB_SECTORS / 8 - 1, last_block(6 * TIB_SECTORS, 4096));
+
+        // cross 4TiB boundary with 4KiB blocks
+        let lba = 4 * TIB_SECTORS / 8 - 1;
+        let start = lba_to_sector(lba, 4096).unwrap();
+        assert!(start > u32::MAX as u64);
+        assert_eq!(
+            vec![
+                Chunk {
+                    index: 1,
+                    target_sector: 5 * TIB_SECTORS - 8,
+                    offset: 0,
+                    num_sectors: 8,
+                },
+                Chunk {
+                    index: 2,
+                    target_sector: 3 * TIB_SECTORS,
+                    offset: 8,
+                    num_sectors: 8,
+                },
+            ],
+            chunks(&table, start, 16)
+        );
+        assert_eq!(4096..8192, chunks(&table, start, 16)[1].buffer_range());
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            resolve(&table, 6 * TIB_SECTORS - 1, 2).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn sector_limits() {
+        assert_eq!(Ok(MAX_SECTORS), validate_table(&[item(0, MAX_SECTORS, 0)]));
+        assert_eq!(
+            Err(MappingError::Overflow),
+            validate_table(&[item(0, MAX_SECTORS + 1, 0)])
+        );
+        // target region beyond limit
+        assert_eq!(
+            Err(MappingError::Overflow),
+            validate_table(&[item(0, MAX_SECTORS, 1)])
+        );
+        assert_eq!(MAX_SECTORS - 1, last_block(u64::MAX, 512));
+        assert_eq!(Some(MAX_SECTORS), lba_to_sector(MAX_SECTORS, 512));
+        assert_eq!(None, lba_to_sector(MAX_SECTORS / 8 + 1, 4096));
+    }
+
+    #[test]
+    fn insert_and_delete() {
+        let mut table = vec![item(0, 4, 0), item(4, 4, 1)];
+        let patch = |start_sector, num_sectors| MappingItem {
+            start_sector,
+            num_sectors,
+            target: 9,
+            target_start_sector: 0,
+        };
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            insert_range(&mut table, patch(9, 1))
+        );
+        assert_eq!(
+            Err(MappingError::Overflow),
+            insert_range(&mut table, patch(0, MAX_SECTORS))
+        );
+        assert_eq!(Ok(()), insert_range(&mut table, patch(2, 3)));
+        assert_eq!(Ok(()), insert_range(&mut table, patch(11, 1)));
+        assert_eq!(
+            vec![
+                item(0, 2, 0),
+                patch(2, 3),
+                MappingItem {
+                    start_sector: 5,
+                    num_sectors: 2,
+                    target: 0,
+                    target_start_sector: 2,
+                },
+                item(7, 4, 1),
+                patch(11, 1),
+            ],
+            table
+        );
+        assert_eq!(Ok(12), validate_table(&table));
+
+        assert_eq!(
+            Err(MappingError::OutOfRange),
+            delete_range(&mut table, 10, 3)
+        );
+        assert_eq!(Ok(()), delete_range(&mut table, 2, 3));
+        assert_eq!(Ok(()), delete_range(&mut table, 8, 1));
+        assert_eq!(
+            vec![
+                item(0, 2, 0),
+                MappingItem {
+                    start_sector: 2,
+                    num_sectors: 2,
+                    target: 0,
+                    target_start_sector: 2,
+                },
+                item(4, 4, 1),
+            ],
+            table
+        );
+        assert_eq!(Ok(()), delete_range(&mut table, 0, 8));
+        assert_eq!(Err(MappingError::Empty), validate_table(&table));
+    }
+}
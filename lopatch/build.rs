@@ -0,0 +1,6 @@
+include!("../build/version.rs");
+
+fn main() {
+    emit_build_info();
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,39 @@
+use super::*;
+
+pub fn create_blank_device(
+    bt: &BootServices,
+    id: Option<u32>,
+    num_sectors: u64,
+    read_only: bool,
+) -> Result {
+    let loop_ctl = open_loop_control(bt)?;
+
+    let unit_number = if let Some(id) = id {
+        id
+    } else {
+        let handle = unsafe { find_loop_device(&loop_ctl, None)? };
+        let loop_pt = bt.open_protocol_exclusive::<LoopProtocol>(handle)?;
+        let mut info = uefi_loopdrv::LoopInfo::default();
+        unsafe {
+            (loop_pt.get_info)(loop_pt.get_mut().unwrap(), &mut info).to_result()?;
+        }
+        info.unit_number
+    };
+
+    let handle = unsafe {
+        let mut handle: RawHandle = ptr::null_mut();
+        (loop_ctl.create_blank)(
+            loop_ctl.get_mut().unwrap(),
+            unit_number,
+            num_sectors,
+            read_only,
+            &mut handle,
+        )
+        .to_result()
+        .with_context(|| format!("failed to create blank loop device #{}", unit_number))?;
+        handle
+    };
+    println!("loop({}) 0x{:x}", unit_number, handle as usize);
+
+    Ok(())
+}
@@ -1,14 +1,8 @@
 use super::*;
 
 pub fn detach_loop_device(bt: &BootServices, id: u32) -> Result {
-    let handle = bt.get_handle_for_protocol::<LoopControlProtocol>()?;
-    let loop_ctl = bt.open_protocol_exclusive::<LoopControlProtocol>(handle)?;
-
-    let handle = unsafe {
-        let mut handle: RawHandle = ptr::null_mut();
-        (loop_ctl.find)(loop_ctl.get_mut().unwrap(), id, &mut handle).to_result()?;
-        Handle::from_ptr(handle).unwrap()
-    };
+    let loop_ctl = open_loop_control(bt)?;
+    let handle = unsafe { find_loop_device(&loop_ctl, Some(id))? };
 
     let loop_pt = bt.open_protocol_exclusive::<LoopProtocol>(handle)?;
     unsafe {
@@ -1,5 +1,11 @@
 use super::*;
 
+use alloc::string::ToString;
+
+use uefi::proto::media::block::BlockIO;
+use uefi_cli::Size;
+use uefi_loopdrv::get_protocol_mut;
+
 pub fn list_loop_devices(bt: &BootServices) -> Result {
     let loop_handles = bt.locate_handle_buffer(SearchType::ByProtocol(&LoopProtocol::GUID))?;
 
@@ -10,11 +16,49 @@ pub fn list_loop_devices(bt: &BootServices) -> Result {
             (loop_pt.get_info)(loop_pt.get_mut().unwrap(), &mut info).to_result()?;
         }
 
+        // BlockIO is only opened non-exclusively as partition drivers are using it
+        let media = unsafe { get_protocol_mut::<BlockIO>(bt, handle) }
+            .ok()
+            .flatten()
+            .map(|block_io| unsafe { (*block_io).media() })
+            .filter(|media| media.is_media_present());
+        let size = match media {
+            Some(media) => {
+                Size((media.last_block() + 1) * u64::from(media.block_size())).to_string()
+            }
+            None => "no media".to_string(),
+        };
         println!(
-            "loop({}) 0x{:x}",
+            "loop({}) 0x{:x} {}",
             info.unit_number,
-            handle.as_ptr() as usize
+            handle.as_ptr() as usize,
+            size
         );
+
+        let mut num_handles = 0;
+        let status = unsafe {
+            (loop_pt.get_children)(
+                loop_pt.get_mut().unwrap(),
+                &mut num_handles,
+                ptr::null_mut(),
+            )
+        };
+        if status != Status::BUFFER_TOO_SMALL {
+            status.to_result()?;
+            continue;
+        }
+        let mut children: Vec<RawHandle> = vec![ptr::null_mut(); num_handles];
+        unsafe {
+            (loop_pt.get_children)(
+                loop_pt.get_mut().unwrap(),
+                &mut num_handles,
+                children.as_mut_ptr(),
+            )
+            .to_result()?;
+        }
+        for child in &children[..num_handles] {
+            println!("  - 0x{:x}", *child as usize);
+        }
     }
 
     Ok(())
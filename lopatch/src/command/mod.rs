@@ -1,4 +1,5 @@
 pub mod attach;
+pub mod blank;
 pub mod detach;
 pub mod list;
 
@@ -7,14 +8,40 @@ use crate::utils::*;
 use alloc::boxed::Box;
 use alloc::format;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::ptr;
 
 use uefi::prelude::*;
-use uefi::table::boot::SearchType;
-use uefi::Identify;
-use uefi::Result;
+use uefi::table::boot::{ScopedProtocol, SearchType};
+use uefi::{println, Identify};
+use uefi_error::{Context, Error, Result};
 use uefi_raw::Handle as RawHandle;
-use uefi_services::println;
 
+use uefi_iso9660::{ISO9660, ISO_BLOCK_SIZE};
 use uefi_loopdrv::{LoopControlProtocol, LoopProtocol};
+
+pub fn open_loop_control(bt: &BootServices) -> Result<ScopedProtocol<LoopControlProtocol>> {
+    let handle = bt
+        .get_handle_for_protocol::<LoopControlProtocol>()
+        .context("loop control protocol not found, is loopdrv loaded?")?;
+    Ok(bt.open_protocol_exclusive::<LoopControlProtocol>(handle)?)
+}
+
+/// Find loop device `id`, or a free one if [None]
+pub unsafe fn find_loop_device(
+    loop_ctl: &ScopedProtocol<LoopControlProtocol>,
+    id: Option<u32>,
+) -> Result<Handle> {
+    let mut handle: RawHandle = ptr::null_mut();
+    if let Some(id) = id {
+        (loop_ctl.find)(loop_ctl.get_mut().unwrap(), id, &mut handle)
+            .to_result()
+            .with_context(|| format!("failed to find loop device #{}", id))?;
+    } else {
+        (loop_ctl.get_free)(loop_ctl.get_mut().unwrap(), &mut handle)
+            .to_result()
+            .context("failed to get free loop device")?;
+    }
+    Ok(Handle::from_ptr(handle).unwrap())
+}
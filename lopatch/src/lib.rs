@@ -0,0 +1,46 @@
+//! Setup of loop devices with ISO file patching, shared by lopatch and loopcfg
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod command;
+mod utils;
+
+use alloc::vec::Vec;
+
+use regex::{Regex, RegexBuilder};
+use uefi_loopdrv::SECTOR_SIZE;
+
+pub use command::attach::PatchAction;
+
+/// Files to patch matched by regular expressions and actions applied to them
+pub type PatchList<'a> = Vec<(Regex, Vec<PatchAction<'a>>)>;
+
+/// Regular expression matching files of `path` relative to any parent directory,
+/// or to root if `path` starts with `/`
+pub fn search_regex(path: &str) -> Result<Regex, regex::Error> {
+    let path = path.trim();
+    let pat = alloc::format!(
+        "{}{}$",
+        if path.starts_with('/') { "^" } else { "/" },
+        regex::escape(path)
+    );
+    pattern_regex(&pat)
+}
+
+/// Case insensitive regular expression of `pat` to match file paths in ISO
+pub fn pattern_regex(pat: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pat).case_insensitive(true).build()
+}
+
+/// Parse size of device, which must be non-zero and not overflow when rounded up to sectors
+pub fn parse_size(size: &str) -> Option<u64> {
+    uefi_cli::parse_size(size).filter(|n| *n > 0 && n.checked_add(SECTOR_SIZE as u64).is_some())
+}
+
+/// Round up to whole sectors
+#[inline]
+pub fn sectors_of_size(size: u64) -> u64 {
+    (size + SECTOR_SIZE as u64 - 1) / SECTOR_SIZE as u64
+}
@@ -1,39 +1,54 @@
 #![no_main]
 #![no_std]
 
-mod command;
-mod utils;
-use command::attach::PatchAction;
-
 extern crate alloc;
 
-use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::format;
 use alloc::vec::Vec;
 
 use getargs::{Arg, Options};
-use regex::{Regex, RegexBuilder};
 use uefi::prelude::*;
-use uefi::proto::loaded_image::LoadedImage;
-use uefi::proto::shell_params::ShellParameters;
-use uefi_services::println;
+use uefi::println;
+use uefi_cli::{App, ArgsError, ConOutProgress, NoProgress, Progress};
+use uefi_error::Context;
+use uefi_lopatch::{
+    command, parse_size, pattern_regex, search_regex, sectors_of_size, PatchAction, PatchList,
+};
 
 const MIN_UEFI_REVISION: uefi::table::Revision = uefi::table::Revision::EFI_2_00;
 
-macro_rules! format_help {
-    ($name:expr) => {
-        ::core::format_args!(
-            "\
+const APP: App = App {
+    help: "\
 Usage: {name} [OPTIONS] IMAGE_FILE
 
   Setup a loopback device for IMAGE_FILE with optional ISO file
-  patching for IMAGE_FILE contains an iso9660 filesystem
+  patching for IMAGE_FILE contains an iso9660 filesystem. IMAGE_FILE
+  could also be a HTTP(S) URL to stream read-only image from
 
   -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
   -i, --id NUM          Loopback ID to use, find a free one if omitted
   -r, --read-only       Mark read-only
   -P                    Mark that IMAGE_FILE has disk partitioning
+  -F, --fixed           Present loopback device as fixed media instead of
+                        removable media
+  -S, --sparse          Map all-zero regions of IMAGE_FILE without file reads,
+                        only for read-only device
+  -n, --name NAME       Name of loopback device shown in firmware UIs
+  -q, --quiet           Do not show progress of ISO patching
+  -z, --size SIZE       Expose SIZE bytes of IMAGE_FILE, with optional K, M, G
+                        or T suffix. Beyond end of IMAGE_FILE reads as zero
+                        and writing extends IMAGE_FILE
   -l, --list            List all loopback devices
   -d, --detach          Detach the loopback device specified by -i/--id
+  -b, --blank SIZE      Setup a blank loopback device of SIZE bytes instead,
+                        with optional K, M, G or T suffix. It's backed by
+                        memory, or reads as zero if -r/--read-only
 
 ISO Patching Options:
   -s, --search PATH     Search file in ISO to patch, each --search/--pattern
@@ -43,6 +58,8 @@ ISO Patching Options:
                         directory. The action would applies to all files found.
   -p, --pattern REGEX   Use regular expression instead to match file path
   -a, --append FILE     Append FILE data to end of the matched ISO file
+                        FILE may also be a HTTP(S) URL or device path of
+                        a LoadFile device
   -m, --meta-cpio       Append mapping metadata file as CPIO
   -R, --replace FILE    Replace data of the matched ISO file with FILE data
 
@@ -52,36 +69,33 @@ EXAMPLE:
 
   * Attach a FAT image to a free loopback device
   {name} fat.img
-",
-            name = $name
-        )
-    };
-}
 
-#[derive(Debug)]
-enum ArgsError<'a> {
-    Invalid,
-    GetArgs(getargs::Error<&'a str>),
-}
-impl core::fmt::Display for ArgsError<'_> {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match self {
-            Self::GetArgs(e) => e.fmt(f),
-            Self::Invalid => f.write_str("Invalid argument"),
-        }
-    }
-}
+  * Setup a 64MiB scratch disk in memory
+  {name} -b 64M
+",
+    version: uefi_cli::version!(),
+};
 
 enum Command<'a> {
     NoOp,
     List,
     Detach(u32),
+    Blank {
+        loop_id: Option<u32>,
+        num_sectors: u64,
+        read_only: bool,
+    },
     Attach {
         loop_id: Option<u32>,
         read_only: bool,
         is_parted_disk: bool,
-        patch: Vec<(Regex, Vec<PatchAction<'a>>)>,
+        is_fixed: bool,
+        is_sparse: bool,
+        dev_name: Option<&'a str>,
+        num_sectors: u64,
+        patch: PatchList<'a>,
         image_file: &'a str,
+        quiet: bool,
     },
 }
 
@@ -96,28 +110,26 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(
     let mut loop_id: Option<u32> = None;
     let mut read_only: bool = false;
     let mut is_parted_disk: bool = false;
-    let mut patch_list = Vec::<(Regex, Vec<PatchAction<'a>>)>::new();
+    let mut is_fixed: bool = false;
+    let mut is_sparse: bool = false;
+    let mut dev_name: Option<&'a str> = None;
+    let mut quiet = false;
+    let mut patch_list = PatchList::new();
     let mut image_file = "";
 
     let mut is_list = false;
     let mut is_detach = false;
-
-    #[inline]
-    fn w<T>(res: getargs::Result<&str, T>) -> Result<T, ArgsError<'_>> {
-        res.map_err(ArgsError::GetArgs)
-    }
-
-    let build_regex = |pat: &str| RegexBuilder::new(pat).case_insensitive(true).build();
+    let mut blank_size: Option<u64> = None;
+    let mut image_size: Option<u64> = None;
 
     let mut count = 0;
-    while let Some(arg) = w(opts.next_arg())? {
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
         match arg {
-            Arg::Short('h') | Arg::Long("help") => {
-                println!("{}", format_help!(name));
-                return Ok(Command::NoOp);
-            }
             Arg::Short('i') | Arg::Long("id") => {
-                let id = match w(opts.value())?.parse() {
+                let id = match opts.value()?.parse() {
                     Ok(v) => v,
                     Err(e) => {
                         println!("{}", e);
@@ -126,18 +138,39 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(
                 };
                 loop_id = Some(id);
             }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
             Arg::Short('r') | Arg::Long("read-only") => read_only = true,
             Arg::Short('P') => is_parted_disk = true,
+            Arg::Short('F') | Arg::Long("fixed") => is_fixed = true,
+            Arg::Short('S') | Arg::Long("sparse") => is_sparse = true,
+            Arg::Short('n') | Arg::Long("name") => dev_name = Some(opts.value()?),
+            Arg::Short('q') | Arg::Long("quiet") => quiet = true,
+            Arg::Short('z') | Arg::Long("size") => {
+                let size = opts.value()?;
+                let Some(size) = parse_size(size) else {
+                    println!("Invalid size {}", size);
+                    return Err(ArgsError::Invalid);
+                };
+                image_size = Some(size);
+            }
             Arg::Short('l') | Arg::Long("list") => is_list = true,
             Arg::Short('d') | Arg::Long("detach") => is_detach = true,
+            Arg::Short('b') | Arg::Long("blank") => {
+                let size = opts.value()?;
+                let Some(size) = parse_size(size) else {
+                    println!("Invalid size {}", size);
+                    return Err(ArgsError::Invalid);
+                };
+                blank_size = Some(size);
+            }
             Arg::Short('s') | Arg::Long("search") => {
-                let path = w(opts.value())?.trim();
-                let pat = alloc::format!(
-                    "{}{}$",
-                    if path.starts_with('/') { "^" } else { "/" },
-                    regex::escape(path)
-                );
-                match build_regex(&pat) {
+                match search_regex(opts.value()?) {
                     Err(e) => {
                         log::error!("{}", e);
                         return Err(ArgsError::Invalid);
@@ -146,7 +179,7 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(
                 };
             }
             Arg::Short('p') | Arg::Long("pattern") => {
-                match build_regex(w(opts.value())?) {
+                match pattern_regex(opts.value()?) {
                     Err(e) => {
                         log::error!("{}", e);
                         return Err(ArgsError::Invalid);
@@ -160,24 +193,21 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(
             }
             Arg::Short('a') | Arg::Long("append") => {
                 let last = patch_list.last_mut().ok_or(ArgsError::Invalid)?;
-                last.1.push(PatchAction::Append(w(opts.value())?))
+                last.1.push(PatchAction::Append(opts.value()?))
             }
             Arg::Short('R') | Arg::Long("replace") => {
                 let last = patch_list.last_mut().ok_or(ArgsError::Invalid)?;
-                last.1.push(PatchAction::Replace(w(opts.value())?))
+                last.1.push(PatchAction::Replace(opts.value()?))
             }
             Arg::Positional(path) => {
                 image_file = path;
             }
-            _ => {
-                println!("Unexpected argument {}", arg);
-                return Err(ArgsError::Invalid);
-            }
+            _ => return Err(ArgsError::Unexpected(arg)),
         }
         count += 1;
     }
     if count == 0 {
-        println!("{}", format_help!(name));
+        APP.print_help(name);
         return Ok(Command::NoOp);
     }
 
@@ -197,9 +227,17 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(
     if is_list {
         return Ok(Command::List);
     }
+    if let Some(size) = blank_size {
+        let num_sectors = sectors_of_size(size);
+        return Ok(Command::Blank {
+            loop_id,
+            num_sectors,
+            read_only,
+        });
+    }
 
     if image_file.is_empty() {
-        println!("{}", format_help!(name));
+        APP.print_help(name);
         return Err(ArgsError::Invalid);
     }
 
@@ -209,14 +247,20 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(
         loop_id,
         read_only,
         is_parted_disk,
+        is_fixed,
+        is_sparse,
+        dev_name,
+        num_sectors: image_size.map_or(0, sectors_of_size),
         patch: patch_list,
         image_file,
+        quiet,
     })
 }
 
 #[entry]
 fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
-    uefi_services::init(&mut system_table).unwrap();
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
     let bt = system_table.boot_services();
 
     if system_table.uefi_revision() < MIN_UEFI_REVISION {
@@ -228,77 +272,62 @@ fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         return Status::INCOMPATIBLE_VERSION;
     }
 
-    let image = bt
-        .open_protocol_exclusive::<LoadedImage>(bt.image_handle())
-        .unwrap();
-
-    let mut sh_params = bt
-        .open_protocol_exclusive::<ShellParameters>(bt.image_handle())
-        .ok();
-    sh_params.take();
-    let argv: Vec<String> = if let Some(sh_params) = sh_params {
-        sh_params
-            .args()
-            .map(|arg| {
-                let mut buf = String::new();
-                arg.as_str_in_buf(&mut buf).unwrap();
-                buf
-            })
-            .collect()
-    } else if let Ok(load_options) = image.load_options_as_cstr16() {
-        let mut load_options_str = String::new();
-        load_options_str.reserve(load_options.num_chars());
-        if load_options.as_str_in_buf(&mut load_options_str).is_ok() {
-            uefi_shell_split::split(load_options_str.as_str())
-        } else {
-            Vec::new()
-        }
-    } else {
-        Vec::new()
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
     };
-    if argv.is_empty() {
-        log::error!("Command-line options not passed");
-        return Status::INVALID_PARAMETER;
-    }
     let argv = argv.iter().map(|i| i.as_str());
 
-    match parse_args(argv) {
+    let res = match parse_args(argv) {
         Err(e) => {
             println!("{}", e);
-            return Status::INVALID_PARAMETER;
+            return e.status();
         }
-        Ok(Command::NoOp) => {}
+        Ok(Command::NoOp) => Ok(()),
         Ok(Command::List) => {
-            if let Err(e) = command::list::list_loop_devices(bt) {
-                println!("Failed to list loop devices: {}", e);
-                return e.status();
-            }
-        }
-        Ok(Command::Detach(id)) => {
-            if let Err(e) = command::detach::detach_loop_device(bt, id) {
-                println!("Failed to detach loop device #{}: {}", id, e);
-                return e.status();
-            }
+            command::list::list_loop_devices(bt).context("failed to list loop devices")
         }
+        Ok(Command::Blank {
+            loop_id,
+            num_sectors,
+            read_only,
+        }) => command::blank::create_blank_device(bt, loop_id, num_sectors, read_only)
+            .context("failed to setup blank loop device"),
+        Ok(Command::Detach(id)) => command::detach::detach_loop_device(bt, id)
+            .with_context(|| format!("failed to detach loop device #{}", id)),
         Ok(Command::Attach {
             loop_id,
             read_only,
             is_parted_disk,
+            is_fixed,
+            is_sparse,
+            dev_name,
+            num_sectors,
             patch,
             image_file,
+            quiet,
         }) => {
-            if let Err(e) = command::attach::attach_loop_device(
+            let mut progress: Box<dyn Progress> = if quiet {
+                Box::new(NoProgress)
+            } else {
+                Box::new(ConOutProgress::new())
+            };
+            command::attach::attach_loop_device(
                 bt,
                 loop_id,
                 read_only,
                 !is_parted_disk,
+                !is_fixed,
+                is_sparse,
+                dev_name,
+                num_sectors,
                 &patch,
                 image_file,
-            ) {
-                println!("Failed to setup loop device: {}", e);
-                return e.status();
-            }
+                progress.as_mut(),
+            )
+            .context("failed to setup loop device")
         }
     };
-    Status::SUCCESS
+
+    uefi_cli::exit_status(res)
 }
@@ -0,0 +1,228 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::println;
+use uefi::table::boot::{MemoryDescriptor, MemoryType};
+use uefi_cli::{find_command, App, ArgsError, Size};
+
+const APP: App = App {
+    help: "\
+Usage: {name} [OPTIONS]
+
+  Print UEFI memory map with totals of each memory type and the largest free region
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+  -t, --type TYPES      Only print regions of comma separated memory TYPES, see
+                        below for names
+  -s, --summary         Print totals and the largest free region only
+
+Memory types:
+  reserved, loader-code, loader-data, bs-code, bs-data, rt-code, rt-data,
+  conventional, unusable, acpi-reclaim, acpi-nvs, mmio, mmio-port, pal-code,
+  persistent
+
+EXAMPLE:
+  * Print free memory regions
+  {name} -t conventional
+",
+    version: uefi_cli::version!(),
+};
+
+/// Short names of memory types, for both parsing and display
+const MEMORY_TYPES: &[(&str, MemoryType)] = &[
+    ("reserved", MemoryType::RESERVED),
+    ("loader-code", MemoryType::LOADER_CODE),
+    ("loader-data", MemoryType::LOADER_DATA),
+    ("bs-code", MemoryType::BOOT_SERVICES_CODE),
+    ("bs-data", MemoryType::BOOT_SERVICES_DATA),
+    ("rt-code", MemoryType::RUNTIME_SERVICES_CODE),
+    ("rt-data", MemoryType::RUNTIME_SERVICES_DATA),
+    ("conventional", MemoryType::CONVENTIONAL),
+    ("unusable", MemoryType::UNUSABLE),
+    ("acpi-reclaim", MemoryType::ACPI_RECLAIM),
+    ("acpi-nvs", MemoryType::ACPI_NON_VOLATILE),
+    ("mmio", MemoryType::MMIO),
+    ("mmio-port", MemoryType::MMIO_PORT_SPACE),
+    ("pal-code", MemoryType::PAL_CODE),
+    ("persistent", MemoryType::PERSISTENT_MEMORY),
+];
+
+/// Extra descriptors reserved in buffer for those split by allocating the buffer itself
+const EXTRA_DESCRIPTORS: usize = 8;
+
+enum Command {
+    NoOp,
+    Print {
+        types: Option<Vec<MemoryType>>,
+        summary: bool,
+    },
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(mut argv_iter: I) -> Result<Command, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    let mut types = None;
+    let mut summary = false;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('t') | Arg::Long("type") => {
+                let mut parsed = Vec::new();
+                for item in opts.value()?.split(',').map(str::trim) {
+                    let Some(ty) = find_command(MEMORY_TYPES, item) else {
+                        println!("Unknown memory type {}", item);
+                        return Err(ArgsError::Invalid);
+                    };
+                    parsed.push(ty);
+                }
+                types = Some(parsed);
+            }
+            Arg::Short('s') | Arg::Long("summary") => {
+                summary = true;
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    Ok(Command::Print { types, summary })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::Print { types, summary }) => print_memory_map(bt, types.as_deref(), summary),
+    };
+
+    res.status()
+}
+
+fn type_name(ty: MemoryType) -> &'static str {
+    MEMORY_TYPES
+        .iter()
+        .find(|(_, t)| *t == ty)
+        .map(|(name, _)| *name)
+        .unwrap_or("other")
+}
+
+#[inline]
+fn region_size(desc: &MemoryDescriptor) -> u64 {
+    desc.page_count * MemoryDescriptor::PAGE_SIZE as u64
+}
+
+fn print_memory_map(
+    bt: &BootServices,
+    types: Option<&[MemoryType]>,
+    summary: bool,
+) -> uefi::Result {
+    let map_size = bt.memory_map_size();
+    let mut buffer = vec![0u8; map_size.map_size + EXTRA_DESCRIPTORS * map_size.entry_size];
+    let mut memory_map = bt.memory_map(&mut buffer).map_err(|e| {
+        println!("Failed to get memory map: {}", e.status());
+        e
+    })?;
+    memory_map.sort();
+    let descriptors: Vec<MemoryDescriptor> = memory_map.entries().copied().collect();
+
+    if !summary {
+        println!(
+            "{:<13} {:<33} {:>10} {:>10} ATTRIBUTES",
+            "TYPE", "RANGE", "PAGES", "SIZE"
+        );
+    }
+    let mut totals: Vec<(MemoryType, u64)> = Vec::new();
+    for desc in &descriptors {
+        if types.is_some_and(|t| !t.contains(&desc.ty)) {
+            continue;
+        }
+        match totals.iter_mut().find(|(ty, _)| *ty == desc.ty) {
+            Some((_, pages)) => *pages += desc.page_count,
+            None => totals.push((desc.ty, desc.page_count)),
+        }
+        if !summary {
+            println!(
+                "{:<13} {:016x}-{:016x} {:>10} {:>10} {:016x}",
+                type_name(desc.ty),
+                desc.phys_start,
+                desc.phys_start + region_size(desc) - 1,
+                desc.page_count,
+                Size(region_size(desc)),
+                desc.att.bits()
+            );
+        }
+    }
+
+    println!();
+    println!("{:<13} {:>10} {:>10}", "TYPE", "PAGES", "SIZE");
+    for (ty, pages) in &totals {
+        let size = pages * MemoryDescriptor::PAGE_SIZE as u64;
+        println!("{:<13} {:>10} {:>10}", type_name(*ty), pages, Size(size));
+    }
+
+    // adjacent free regions are merged as they are allocatable at once
+    let mut largest: Option<(u64, u64)> = None;
+    let mut current: Option<(u64, u64)> = None;
+    for desc in descriptors
+        .iter()
+        .filter(|d| d.ty == MemoryType::CONVENTIONAL)
+    {
+        current = match current {
+            Some((start, size)) if start + size == desc.phys_start => {
+                Some((start, size + region_size(desc)))
+            }
+            _ => Some((desc.phys_start, region_size(desc))),
+        };
+        if largest.map_or(true, |(_, size)| current.unwrap().1 > size) {
+            largest = current;
+        }
+    }
+    println!();
+    match largest {
+        Some((start, size)) => println!(
+            "Largest free region: {:016x}-{:016x} {}",
+            start,
+            start + size - 1,
+            Size(size)
+        ),
+        None => println!("No free region"),
+    }
+    Ok(())
+}
@@ -0,0 +1,370 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::println;
+use uefi::proto::media::file::{
+    Directory, File, FileAttribute, FileHandle, FileInfo, FileMode, FileType, RegularFile,
+};
+use uefi::table::runtime::Time;
+use uefi_cli::{App, ArgsError};
+use uefi_cpio::{Entry, Sink, Writer, S_IFDIR, S_IFREG};
+use uefi_devpath::open_shell_path;
+
+const APP: App = App {
+    help: "\
+Usage: {name} [OPTIONS] OUTPUT SOURCE[=DEST]...
+
+  Build cpio archive in newc format at OUTPUT from files and directories
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+  -m, --mode MODE       Permission of files in octal, defaults to 644
+      --dir-mode MODE   Permission of directories in octal, defaults to 755
+  -u, --uid UID         Owner user ID of entries, defaults to 0
+  -g, --gid GID         Owner group ID of entries, defaults to 0
+      --no-trailer      Omit trailer so the archive can be concatenated with
+                        others
+
+SOURCE is archived as path DEST, or its file name if DEST is not given.
+Directories are archived recursively, OUTPUT is overwritten.
+
+EXAMPLE:
+  * Build cpio to patch initramfs with an executable init and a directory
+  {name} -m 755 patch-init.cpio init.sh=init
+  {name} hooks.cpio hooks=usr/lib/initcpio/hooks
+",
+    version: uefi_cli::version!(),
+};
+
+/// Files are read and archive is written in chunks of this size
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Attributes of archived entries
+#[derive(Clone, Copy)]
+struct EntryAttrs {
+    file_mode: u32,
+    dir_mode: u32,
+    uid: u32,
+    gid: u32,
+}
+
+enum Command<'a> {
+    NoOp,
+    Build {
+        output: &'a str,
+        sources: Vec<(&'a str, String)>,
+        attrs: EntryAttrs,
+        trailer: bool,
+    },
+}
+
+/// Archive path of `source`, DEST if given or file name of SOURCE
+fn split_source(source: &str) -> (&str, String) {
+    let (source, dest) = match source.split_once('=') {
+        Some((source, dest)) => (source, dest),
+        None => {
+            let trimmed = source.trim_end_matches(['/', '\\']);
+            (source, trimmed.rsplit(['/', '\\', ':']).next().unwrap())
+        }
+    };
+    let dest = dest.replace('\\', "/").trim_matches('/').to_string();
+    (source, dest)
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    let mut output = None;
+    let mut sources = Vec::new();
+    let mut attrs = EntryAttrs {
+        file_mode: 0o644,
+        dir_mode: 0o755,
+        uid: 0,
+        gid: 0,
+    };
+    let mut trailer = true;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('m') | Arg::Long("mode") | Arg::Long("dir-mode") => {
+                let value = opts.value()?;
+                let Some(mode) = u32::from_str_radix(value, 8).ok().filter(|m| *m <= 0o7777) else {
+                    println!("Invalid mode {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                if let Arg::Long("dir-mode") = arg {
+                    attrs.dir_mode = mode;
+                } else {
+                    attrs.file_mode = mode;
+                }
+            }
+            Arg::Short('u') | Arg::Long("uid") | Arg::Short('g') | Arg::Long("gid") => {
+                let value = opts.value()?;
+                let Ok(id) = value.parse::<u32>() else {
+                    println!("Invalid ID {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                if let Arg::Short('u') | Arg::Long("uid") = arg {
+                    attrs.uid = id;
+                } else {
+                    attrs.gid = id;
+                }
+            }
+            Arg::Long("no-trailer") => {
+                trailer = false;
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(path) if output.is_none() => {
+                output = Some(path);
+            }
+            Arg::Positional(source) => {
+                sources.push(split_source(source));
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(output) = output else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    if sources.is_empty() {
+        println!("No SOURCE specified");
+        return Err(ArgsError::Invalid);
+    }
+    Ok(Command::Build {
+        output,
+        sources,
+        attrs,
+        trailer,
+    })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::Build {
+            output,
+            sources,
+            attrs,
+            trailer,
+        }) => build_archive(bt, output, &sources, attrs, trailer),
+    };
+
+    res.status()
+}
+
+/// Buffered writes to output file
+struct FileSink {
+    file: RegularFile,
+    buffer: Vec<u8>,
+}
+
+impl FileSink {
+    fn write_file(&mut self, data: &[u8]) -> uefi::Result {
+        self.file
+            .write(data)
+            .map_err(|e| e.to_err_without_payload())
+    }
+
+    fn write_buffer(&mut self) -> uefi::Result {
+        let res = self
+            .file
+            .write(&self.buffer)
+            .map_err(|e| e.to_err_without_payload());
+        self.buffer.clear();
+        res
+    }
+
+    fn flush(&mut self) -> uefi::Result {
+        self.write_buffer()?;
+        self.file.flush()
+    }
+}
+
+impl Sink for FileSink {
+    type Error = uefi::Error;
+    fn write_all(&mut self, data: &[u8]) -> uefi::Result {
+        if self.buffer.len() + data.len() > BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+        if data.len() >= BUFFER_SIZE {
+            return self.write_file(data);
+        }
+        self.buffer.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Seconds since Unix epoch of `time`, time zone is ignored
+fn unix_time(time: &Time) -> u32 {
+    let (month, day) = (i64::from(time.month()), i64::from(time.day()));
+    let year = i64::from(time.year()) - i64::from(month <= 2);
+    // days from civil, see <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let secs = days * 86400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    u32::try_from(secs).unwrap_or(0)
+}
+
+fn build_archive(
+    bt: &BootServices,
+    output: &str,
+    sources: &[(&str, String)],
+    attrs: EntryAttrs,
+    trailer: bool,
+) -> uefi::Result {
+    // truncate existing output by re-creating it
+    let file = open_shell_path(bt, output, FileMode::CreateReadWrite)?;
+    file.delete()?;
+    let Some(file) = open_shell_path(bt, output, FileMode::CreateReadWrite)?.into_regular_file()
+    else {
+        println!("{} is not a file", output);
+        return Status::INVALID_PARAMETER.to_result();
+    };
+
+    let mut writer = Writer::new(FileSink {
+        file,
+        buffer: Vec::with_capacity(BUFFER_SIZE),
+    });
+    let mut buffer = vec![0; BUFFER_SIZE];
+    for (source, dest) in sources {
+        let handle = open_shell_path(bt, source, FileMode::Read).map_err(|e| {
+            println!("Failed to open {}: {}", source, e.status());
+            e
+        })?;
+        archive(&mut writer, &mut buffer, handle, dest, attrs)?;
+    }
+    writer.finish(trailer)?.flush()
+}
+
+fn archive(
+    writer: &mut Writer<FileSink>,
+    buffer: &mut [u8],
+    handle: FileHandle,
+    dest: &str,
+    attrs: EntryAttrs,
+) -> uefi::Result {
+    match handle.into_type()? {
+        FileType::Regular(file) => archive_file(writer, buffer, file, dest, attrs),
+        FileType::Dir(dir) => archive_dir(writer, buffer, dir, dest, attrs),
+    }
+}
+
+fn archive_file(
+    writer: &mut Writer<FileSink>,
+    buffer: &mut [u8],
+    mut file: RegularFile,
+    dest: &str,
+    attrs: EntryAttrs,
+) -> uefi::Result {
+    let info = file.get_boxed_info::<FileInfo>()?;
+    let Ok(file_size) = u32::try_from(info.file_size()) else {
+        println!("{} is too large for cpio", dest);
+        return Status::UNSUPPORTED.to_result();
+    };
+    writer.begin_entry(&Entry {
+        name: dest,
+        mode: S_IFREG | attrs.file_mode,
+        uid: attrs.uid,
+        gid: attrs.gid,
+        mtime: unix_time(info.modification_time()),
+        file_size,
+    })?;
+
+    let mut remaining = file_size as usize;
+    while remaining != 0 {
+        let len = remaining.min(buffer.len());
+        let read = file.read(&mut buffer[..len])?;
+        if read == 0 {
+            println!("{} is truncated while archiving", dest);
+            return Status::ABORTED.to_result();
+        }
+        writer.write_data(&buffer[..read])?;
+        remaining -= read;
+    }
+    Ok(())
+}
+
+fn archive_dir(
+    writer: &mut Writer<FileSink>,
+    buffer: &mut [u8],
+    mut dir: Directory,
+    dest: &str,
+    attrs: EntryAttrs,
+) -> uefi::Result {
+    // contents of directory archived as root are placed at root
+    if !dest.is_empty() {
+        let info = dir.get_boxed_info::<FileInfo>()?;
+        writer.begin_entry(&Entry {
+            name: dest,
+            mode: S_IFDIR | attrs.dir_mode,
+            uid: attrs.uid,
+            gid: attrs.gid,
+            mtime: unix_time(info.modification_time()),
+            file_size: 0,
+        })?;
+    }
+
+    while let Some(info) = dir.read_entry_boxed()? {
+        let name = info.file_name();
+        if name == cstr16!(".") || name == cstr16!("..") {
+            continue;
+        }
+        let child_dest = if dest.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", dest, name)
+        };
+        let child = dir.open(name, FileMode::Read, FileAttribute::empty())?;
+        archive(writer, buffer, child, &child_dest, attrs)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::console::text::Input;
+use uefi::{print, println};
+use uefi_cli::{App, ArgsError};
+use uefi_keywait::{countdown, flush_keys, key_name, wait_key};
+use uefi_shell::Shell;
+
+const APP: App = App {
+    help: "\
+Usage: {name} [OPTIONS]
+
+  Wait for a key press and print name of the key. Exit status is TIMEOUT
+  if no key is pressed in time, which is %lasterror% in UEFI shell scripts
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+  -t, --timeout SECS    Give up after SECS seconds, waits forever if omitted
+  -c, --countdown       Show remaining seconds while waiting
+  -p, --prompt TEXT     Print TEXT before waiting
+  -k, --keys KEYS       Comma separated names of keys to accept, others are
+                        ignored. Case insensitive
+  -e, --env NAME        Set volatile shell variable NAME to name of the key,
+                        or to empty on timeout
+  -s, --silent          Do not print name of the key
+
+Names of keys are the characters, or ENTER, TAB, BACKSPACE, SPACE, ESC, UP,
+DOWN, LEFT, RIGHT, HOME, END, INSERT, DELETE, PAGEUP, PAGEDOWN and F1 to F12.
+
+EXAMPLE:
+  * Skip rest of a script if any key is pressed in 5 seconds
+  {name} -c -t 5 -p \"Press any key to skip\"
+  if %lasterror% == 0 then
+    exit
+  endif
+  * Ask for confirmation and store the answer in %answer%
+  {name} -k y,n -e answer -p \"Continue? [y/n]\"
+",
+    version: uefi_cli::version!(),
+};
+
+enum Command<'a> {
+    NoOp,
+    Read(ReadOptions<'a>),
+}
+
+#[derive(Default)]
+struct ReadOptions<'a> {
+    timeout: Option<u64>,
+    countdown: bool,
+    prompt: Option<&'a str>,
+    keys: Vec<&'a str>,
+    env: Option<&'a str>,
+    silent: bool,
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    let mut read_opts = ReadOptions::default();
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('t') | Arg::Long("timeout") => {
+                let value = opts.value()?;
+                let Ok(secs) = value.parse() else {
+                    println!("Invalid timeout {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                read_opts.timeout = Some(secs);
+            }
+            Arg::Short('c') | Arg::Long("countdown") => read_opts.countdown = true,
+            Arg::Short('p') | Arg::Long("prompt") => read_opts.prompt = Some(opts.value()?),
+            Arg::Short('k') | Arg::Long("keys") => {
+                read_opts
+                    .keys
+                    .extend(opts.value()?.split(',').filter(|k| !k.is_empty()));
+            }
+            Arg::Short('e') | Arg::Long("env") => read_opts.env = Some(opts.value()?),
+            Arg::Short('s') | Arg::Long("silent") => read_opts.silent = true,
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    Ok(Command::Read(read_opts))
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    // console input is borrowed mutably along with boot services
+    let mut st = unsafe { system_table.unsafe_clone() };
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::Read(opts)) => read_key(bt, st.stdin(), &opts),
+    };
+
+    res.status()
+}
+
+fn read_key(bt: &BootServices, stdin: &mut Input, opts: &ReadOptions) -> uefi::Result {
+    flush_keys(stdin)?;
+    let prompt = opts.prompt.unwrap_or_default();
+    print!("{}", prompt);
+
+    let mut remaining = opts.timeout;
+    let key = loop {
+        let timeout = remaining;
+        let key = match timeout {
+            None => wait_key(bt, stdin, None)?,
+            Some(secs) => countdown(bt, stdin, secs, |r| {
+                remaining = Some(r);
+                if opts.countdown {
+                    print!("\r{} {} ", prompt, r);
+                }
+            })?,
+        };
+        let Some(key) = key else {
+            break None;
+        };
+        let name = key_name(&key);
+        if opts.keys.is_empty() || opts.keys.iter().any(|k| k.eq_ignore_ascii_case(&name)) {
+            break Some(name);
+        }
+    };
+    if !prompt.is_empty() || opts.countdown {
+        println!();
+    }
+
+    if let Some(var) = opts.env {
+        let value = key.as_deref().unwrap_or_default();
+        match Shell::locate(bt) {
+            Ok(shell) => shell.set_env(var, value, true)?,
+            Err(e) => {
+                println!("Can not set variable {} without UEFI shell", var);
+                return Err(e);
+            }
+        }
+    }
+    match key {
+        Some(name) => {
+            if !opts.silent {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+        None => Status::TIMEOUT.to_result(),
+    }
+}
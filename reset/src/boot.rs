@@ -0,0 +1,282 @@
+//! `Boot####`, `PlatformRecovery####` and `OsRecovery####` load options and `Timeout` of
+//! UEFI boot manager, for the `bootnext`, `boot-entries`, `timeout` and `--list` of
+//! recovery commands
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use uefi::prelude::*;
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::proto::device_path::DevicePath;
+use uefi::table::runtime::{VariableAttributes, VariableVendor};
+use uefi::{println, CStr16, CString16, Guid};
+use uefi_error::Context;
+
+const BOOT_ORDER: &CStr16 = cstr16!("BootOrder");
+const BOOT_CURRENT: &CStr16 = cstr16!("BootCurrent");
+const BOOT_NEXT: &CStr16 = cstr16!("BootNext");
+const OS_RECOVERY_ORDER: &CStr16 = cstr16!("OsRecoveryOrder");
+const TIMEOUT: &CStr16 = cstr16!("Timeout");
+
+/// `Timeout` of boot manager waiting for user input forever
+const TIMEOUT_FOREVER: u16 = 0xffff;
+
+/// Boot manager tries the option only if it's active
+const LOAD_OPTION_ACTIVE: u32 = 0x0000_0001;
+
+/// Parse hex number of boot option, optionally prefixed with `Boot`
+pub fn parse_boot_num(num: &str) -> Option<u16> {
+    let num = num.strip_prefix("Boot").unwrap_or(num);
+    if num.is_empty() || num.len() > 4 || !num.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u16::from_str_radix(num, 16).ok()
+}
+
+fn load_option_name(prefix: &str, num: u16) -> CString16 {
+    CString16::try_from(format!("{}{:04X}", prefix, num).as_str()).unwrap()
+}
+
+/// Set `BootNext` to boot option `num` which must exist
+pub fn set_boot_next(rt: &RuntimeServices, num: u16) -> uefi_error::Result {
+    rt.get_variable_boxed(
+        &load_option_name("Boot", num),
+        &VariableVendor::GLOBAL_VARIABLE,
+    )
+    .with_context(|| format!("failed to get boot option Boot{:04X}", num))?;
+    rt.set_variable(
+        BOOT_NEXT,
+        &VariableVendor::GLOBAL_VARIABLE,
+        VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS,
+        &num.to_le_bytes(),
+    )
+    .context("failed to set UEFI variable \"BootNext\"")
+}
+
+/// Print seconds boot manager waits before booting the first option of `BootOrder`
+pub fn print_timeout(rt: &RuntimeServices) -> uefi_error::Result {
+    match get_u16_array(rt, TIMEOUT)?.first() {
+        None => println!("Timeout: not set"),
+        Some(&TIMEOUT_FOREVER) => println!("Timeout: wait for user input"),
+        Some(secs) => println!("Timeout: {} seconds", secs),
+    }
+    Ok(())
+}
+
+/// Set `Timeout` of boot manager to `secs`, [TIMEOUT_FOREVER] waits for user input
+pub fn set_timeout(rt: &RuntimeServices, secs: u16) -> uefi_error::Result {
+    rt.set_variable(
+        TIMEOUT,
+        &VariableVendor::GLOBAL_VARIABLE,
+        VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS,
+        &secs.to_le_bytes(),
+    )
+    .context("failed to set UEFI variable \"Timeout\"")
+}
+
+/// Read a variable of boot manager containing a single or an array of `UINT16`
+fn get_u16_array(rt: &RuntimeServices, name: &CStr16) -> uefi_error::Result<Vec<u16>> {
+    match rt.get_variable_boxed(name, &VariableVendor::GLOBAL_VARIABLE) {
+        Ok((data, _)) => Ok(data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect()),
+        Err(e) if e.status() == Status::NOT_FOUND => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to get UEFI variable \"{}\"", name)),
+    }
+}
+
+/// Numbers of all load option variables of `vendor` named `prefix` followed by `####`,
+/// sorted
+fn load_option_nums(
+    rt: &RuntimeServices,
+    prefix: &str,
+    vendor: &VariableVendor,
+) -> uefi_error::Result<Vec<u16>> {
+    let mut nums: Vec<u16> = rt
+        .variable_keys()
+        .context("failed to list UEFI variables")?
+        .iter()
+        .filter(|key| key.vendor.0 == vendor.0)
+        .filter_map(|key| {
+            let name = key.name().ok()?.to_string();
+            let num = name.strip_prefix(prefix)?;
+            // #### of Boot#### is uppercase hex digits per spec
+            if num.len() != 4 || !num.bytes().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F')) {
+                return None;
+            }
+            u16::from_str_radix(num, 16).ok()
+        })
+        .collect();
+    nums.sort_unstable();
+    Ok(nums)
+}
+
+/// Header of `EFI_LOAD_OPTION` stored in `Boot####` variables
+struct BootEntry {
+    attributes: u32,
+    description: String,
+    /// Packed device paths, the first one is the path of boot image
+    file_path_list: Vec<u8>,
+}
+
+/// Whether `data` is a sequence of device path nodes ending with end entire node
+fn is_valid_device_path_list(data: &[u8]) -> bool {
+    let mut rest = data;
+    let mut end_entire = false;
+    while rest.len() >= 4 {
+        let len = usize::from(u16::from_le_bytes([rest[2], rest[3]]));
+        if len < 4 || len > rest.len() {
+            return false;
+        }
+        end_entire = rest[0] == 0x7f && rest[1] == 0xff;
+        rest = &rest[len..];
+    }
+    rest.is_empty() && end_entire
+}
+
+impl BootEntry {
+    /// Returns `None` if `data` is malformed
+    fn parse(data: &[u8]) -> Option<Self> {
+        let attributes = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?);
+        let path_len = usize::from(u16::from_le_bytes(data.get(4..6)?.try_into().ok()?));
+        let mut description = Vec::new();
+        let mut pos = 6;
+        loop {
+            let c = u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?);
+            pos += 2;
+            if c == 0 {
+                break;
+            }
+            description.push(c);
+        }
+        let file_path_list = data.get(pos..pos + path_len)?;
+        if !is_valid_device_path_list(file_path_list) {
+            return None;
+        }
+        Some(Self {
+            attributes,
+            description: String::from_utf16_lossy(&description),
+            file_path_list: file_path_list.to_vec(),
+        })
+    }
+
+    /// Path of boot image, validated on parsing
+    fn file_path(&self) -> &DevicePath {
+        unsafe { DevicePath::from_ffi_ptr(self.file_path_list.as_ptr().cast()) }
+    }
+}
+
+/// Print `BootCurrent`, `BootNext`, `BootOrder` and all boot options in boot order
+pub fn list_boot_entries(bt: &BootServices, rt: &RuntimeServices) -> uefi_error::Result {
+    let order = get_u16_array(rt, BOOT_ORDER)?;
+    if let Some(current) = get_u16_array(rt, BOOT_CURRENT)?.first() {
+        println!("BootCurrent: {:04X}", current);
+    }
+    if let Some(next) = get_u16_array(rt, BOOT_NEXT)?.first() {
+        println!("BootNext: {:04X}", next);
+    }
+    let order_str: Vec<_> = order.iter().map(|n| format!("{:04X}", n)).collect();
+    println!("BootOrder: {}", order_str.join(","));
+
+    let mut nums = order.clone();
+    let global = &VariableVendor::GLOBAL_VARIABLE;
+    nums.extend(
+        load_option_nums(rt, "Boot", global)?
+            .into_iter()
+            .filter(|n| !order.contains(n)),
+    );
+    for num in nums {
+        print_load_option(bt, rt, "Boot", num, global);
+    }
+    Ok(())
+}
+
+/// Print `PlatformRecovery####` options firmware tries in order on platform recovery
+pub fn list_platform_recovery(bt: &BootServices, rt: &RuntimeServices) -> uefi_error::Result {
+    let global = &VariableVendor::GLOBAL_VARIABLE;
+    let nums = load_option_nums(rt, "PlatformRecovery", global)?;
+    if nums.is_empty() {
+        println!("No PlatformRecovery#### options");
+    }
+    for num in nums {
+        print_load_option(bt, rt, "PlatformRecovery", num, global);
+    }
+    Ok(())
+}
+
+/// Print `OsRecoveryOrder` and `OsRecovery####` options of each vendor GUID in it
+pub fn list_os_recovery(bt: &BootServices, rt: &RuntimeServices) -> uefi_error::Result {
+    let order = match rt.get_variable_boxed(OS_RECOVERY_ORDER, &VariableVendor::GLOBAL_VARIABLE) {
+        Ok((data, _)) => data
+            .chunks_exact(16)
+            .map(|c| Guid::from_bytes(c.try_into().unwrap()))
+            .collect(),
+        Err(e) if e.status() == Status::NOT_FOUND => Vec::new(),
+        Err(e) => return Err(e).context("failed to get UEFI variable \"OsRecoveryOrder\""),
+    };
+    if order.is_empty() {
+        println!("No OsRecoveryOrder");
+    }
+    for guid in order {
+        println!("{}", guid);
+        let vendor = VariableVendor(guid);
+        for num in load_option_nums(rt, "OsRecovery", &vendor)? {
+            print_load_option(bt, rt, "OsRecovery", num, &vendor);
+        }
+    }
+    Ok(())
+}
+
+/// Set `OsRecoveryOrder` to vendor GUIDs of `OsRecovery####` options, firmware may
+/// refuse it as the variable requires authenticated write per spec
+pub fn set_os_recovery_order(rt: &RuntimeServices, order: &[Guid]) -> uefi_error::Result {
+    let data: Vec<u8> = order.iter().flat_map(|guid| guid.to_bytes()).collect();
+    rt.set_variable(
+        OS_RECOVERY_ORDER,
+        &VariableVendor::GLOBAL_VARIABLE,
+        VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS,
+        &data,
+    )
+    .context("failed to set UEFI variable \"OsRecoveryOrder\"")
+}
+
+/// Print description and image path of load option, skipped if it's gone
+fn print_load_option(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    prefix: &str,
+    num: u16,
+    vendor: &VariableVendor,
+) {
+    let name = load_option_name(prefix, num);
+    let Ok((data, _)) = rt.get_variable_boxed(&name, vendor) else {
+        return;
+    };
+    let Some(entry) = BootEntry::parse(&data) else {
+        println!("{}  <malformed>", name);
+        return;
+    };
+    let active = entry.attributes & LOAD_OPTION_ACTIVE != 0;
+    println!(
+        "{}{} {}",
+        name,
+        if active { "*" } else { " " },
+        entry.description
+    );
+    let path = entry
+        .file_path()
+        .to_string(bt, DisplayOnly(false), AllowShortcuts(false))
+        .ok();
+    match path {
+        Some(path) => println!("    {}", path),
+        None => println!("    <unknown device path>"),
+    }
+}
@@ -0,0 +1,230 @@
+//! Delivering capsules to firmware by `UpdateCapsule` and reading results of processing
+//! them, for the `capsule` and `capsule-result` commands
+
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use uefi::prelude::*;
+use uefi::proto::media::file::{File, FileInfo, FileMode};
+use uefi::table::boot::MemoryType;
+use uefi::table::runtime::{ResetType, VariableVendor};
+use uefi::{guid, println, CString16, Guid};
+use uefi_devpath::open_shell_path;
+use uefi_error::{Context, Error};
+use uefi_raw::capsule::{CapsuleBlockDescriptor, CapsuleFlags, CapsuleHeader};
+
+/// Size of `EFI_CAPSULE_HEADER`
+const HEADER_SIZE: usize = 28;
+
+/// Vendor of `Capsule####` variables of results, `EFI_CAPSULE_REPORT_GUID`
+const CAPSULE_REPORT: VariableVendor =
+    VariableVendor(guid!("39b68c46-f7fb-441b-b6ec-16b0f69821f3"));
+
+pub fn raw_runtime_services(rt: &RuntimeServices) -> &uefi_raw::table::runtime::RuntimeServices {
+    unsafe { &*(rt as *const RuntimeServices as *const _) }
+}
+
+/// Capsule read from file, 8-byte aligned as `EFI_CAPSULE_HEADER` is
+struct Capsule {
+    data: Vec<u64>,
+    len: usize,
+    /// `CapsuleImageSize` of header, bytes of file past it are not delivered
+    image_size: usize,
+}
+
+impl Capsule {
+    fn read(bt: &BootServices, path: &str) -> uefi_error::Result<Self> {
+        let Some(mut file) = open_shell_path(bt, path, FileMode::Read)?.into_regular_file() else {
+            let msg = format!("{} is not a file", path);
+            return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+        };
+        let info = file.get_boxed_info::<FileInfo>()?;
+        let Ok(size) = usize::try_from(info.file_size()) else {
+            return Err(Status::BAD_BUFFER_SIZE.into());
+        };
+        let mut data = vec![0u64; (size + 7) / 8];
+        let len = file.read(&mut bytemuck::cast_slice_mut(&mut data)[..size])?;
+        let mut capsule = Self {
+            data,
+            len,
+            image_size: 0,
+        };
+        capsule.image_size = capsule.validate()?;
+        Ok(capsule)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &bytemuck::cast_slice(&self.data)[..self.len]
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        let bytes = &self.bytes()[offset..offset + 4];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    /// Check sizes of header against the file, returns size of capsule image
+    fn validate(&self) -> uefi_error::Result<usize> {
+        let invalid = |msg| Err(Error::msg(Status::INVALID_PARAMETER, msg));
+        if self.len < HEADER_SIZE {
+            return invalid("file too small for capsule header");
+        }
+        let (header_size, image_size) = (self.u32_at(16) as usize, self.u32_at(24) as usize);
+        if header_size < HEADER_SIZE || header_size > image_size {
+            return invalid("invalid capsule header size");
+        }
+        if image_size > self.len {
+            return invalid("capsule image size larger than file");
+        }
+        Ok(image_size)
+    }
+
+    fn header(&self) -> *const CapsuleHeader {
+        self.data.as_ptr().cast()
+    }
+
+    fn flags(&self) -> CapsuleFlags {
+        CapsuleFlags::from_bits_retain(self.u32_at(20))
+    }
+}
+
+/// Deliver capsule in file at `path` to firmware, returns reset type required to
+/// process it. Firmware may reset by itself if the capsule asks so.
+pub fn update_capsule(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    path: &str,
+) -> uefi_error::Result<ResetType> {
+    let capsule = Capsule::read(bt, path).with_context(|| format!("failed to read {}", path))?;
+    let rt = raw_runtime_services(rt);
+    let headers = [capsule.header()];
+
+    let mut max_size: u64 = 0;
+    let mut reset_type = ResetType::COLD;
+    unsafe { (rt.query_capsule_capabilities)(headers.as_ptr(), 1, &mut max_size, &mut reset_type) }
+        .to_result()
+        .context("failed to query capsule capabilities")?;
+    if capsule.image_size as u64 > max_size {
+        let msg = format!("capsule larger than {} bytes firmware accepts", max_size);
+        return Err(Error::msg(Status::BAD_BUFFER_SIZE, msg));
+    }
+
+    // firmware may access the capsule and its scatter gather list until after reset, copy
+    // them into runtime pool memory which is never freed once delivered
+    let descriptors_offset = capsule.data.len() * mem::size_of::<u64>();
+    let size = descriptors_offset + 2 * mem::size_of::<CapsuleBlockDescriptor>();
+    let pool = bt
+        .allocate_pool(MemoryType::RUNTIME_SERVICES_DATA, size)
+        .context("failed to allocate memory for capsule")?;
+    let header = pool.cast::<CapsuleHeader>();
+    // capsules persisting across reset are found by firmware through the scatter gather
+    // list, a single block ending with a null descriptor
+    let blocks = unsafe {
+        pool.copy_from_nonoverlapping(capsule.bytes().as_ptr(), capsule.image_size);
+        let blocks = pool
+            .add(descriptors_offset)
+            .cast::<CapsuleBlockDescriptor>();
+        blocks.write(CapsuleBlockDescriptor {
+            length: capsule.image_size as u64,
+            address: header as u64,
+        });
+        blocks.add(1).write(CapsuleBlockDescriptor {
+            length: 0,
+            address: 0,
+        });
+        blocks
+    };
+    let scatter_gather_list = if capsule.flags().contains(CapsuleFlags::PERSIST_ACROSS_RESET) {
+        blocks as u64
+    } else {
+        0
+    };
+    let headers = [header.cast_const()];
+    let res = unsafe { (rt.update_capsule)(headers.as_ptr(), 1, scatter_gather_list) }.to_result();
+    if let Err(e) = res {
+        let _ = bt.free_pool(pool);
+        return Err(e).context("failed to update capsule");
+    }
+    Ok(reset_type)
+}
+
+/// `EFI_CAPSULE_RESULT_VARIABLE_HEADER` of a `Capsule####` variable
+struct CapsuleResult {
+    guid: Guid,
+    /// `EFI_TIME` of processing as year, month, day, hour, minute and second
+    processed: (u16, u8, u8, u8, u8, u8),
+    status: Status,
+}
+
+impl CapsuleResult {
+    /// Returns `None` if `data` is malformed
+    fn parse(data: &[u8]) -> Option<Self> {
+        let total_size = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+        let status_end = 40 + mem::size_of::<usize>();
+        if total_size < status_end {
+            return None;
+        }
+        let time = data.get(24..31)?;
+        let status = data.get(40..status_end)?;
+        Some(Self {
+            guid: Guid::from_bytes(data.get(8..24)?.try_into().ok()?),
+            processed: (
+                u16::from_le_bytes([time[0], time[1]]),
+                time[2],
+                time[3],
+                time[4],
+                time[5],
+                time[6],
+            ),
+            status: Status(usize::from_le_bytes(status.try_into().ok()?)),
+        })
+    }
+}
+
+/// Numbers of `Capsule####` variables of results, sorted
+fn capsule_result_nums(rt: &RuntimeServices) -> uefi_error::Result<Vec<u16>> {
+    let mut nums: Vec<u16> = rt
+        .variable_keys()
+        .context("failed to list UEFI variables")?
+        .iter()
+        .filter(|key| key.vendor.0 == CAPSULE_REPORT.0)
+        .filter_map(|key| {
+            let name = key.name().ok()?.to_string();
+            let num = name.strip_prefix("Capsule")?;
+            // CapsuleMax and CapsuleLast are not results
+            if num.len() != 4 || !num.bytes().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F')) {
+                return None;
+            }
+            u16::from_str_radix(num, 16).ok()
+        })
+        .collect();
+    nums.sort_unstable();
+    Ok(nums)
+}
+
+/// Print results of processing capsules, deleting them after if `clear`
+pub fn list_capsule_results(rt: &RuntimeServices, clear: bool) -> uefi_error::Result {
+    for num in capsule_result_nums(rt)? {
+        let name = CString16::try_from(format!("Capsule{:04X}", num).as_str()).unwrap();
+        let (data, _) = rt
+            .get_variable_boxed(&name, &CAPSULE_REPORT)
+            .with_context(|| format!("failed to get UEFI variable \"{}\"", name))?;
+        match CapsuleResult::parse(&data) {
+            Some(result) => {
+                let (year, month, day, hour, minute, second) = result.processed;
+                println!(
+                    "{}: {} {:04}-{:02}-{:02} {:02}:{:02}:{:02} {:?}",
+                    name, result.guid, year, month, day, hour, minute, second, result.status
+                );
+            }
+            None => println!("{}: <malformed>", name),
+        }
+        if clear {
+            rt.delete_variable(&name, &CAPSULE_REPORT)
+                .with_context(|| format!("failed to delete UEFI variable \"{}\"", name))?;
+        }
+    }
+    Ok(())
+}
@@ -3,19 +3,34 @@
 
 extern crate alloc;
 
-use alloc::string::String;
+mod boot;
+mod capsule;
+mod wake;
+
+use alloc::format;
 use alloc::vec::Vec;
+use core::cell::Cell;
 use core::option_env;
 
 use bitflags::{bitflags, Flags};
 use bytemuck::{Pod, Zeroable};
 use getargs::{Arg, Options};
 use uefi::prelude::*;
-use uefi::proto::loaded_image::LoadedImage;
-use uefi::proto::shell_params::ShellParameters;
+use uefi::proto::console::text::{Color, Input, Key, Output};
 use uefi::table::runtime::{ResetType, VariableAttributes, VariableVendor};
-use uefi::Guid;
-use uefi_services::println;
+use uefi::{print, println, CStr16, CString16, Guid};
+use uefi_cli::{
+    find_command, find_command_prefix, parse_hex, parse_status, print_hexdump, App, ArgsError,
+};
+use uefi_error::{Context, Error};
+use uefi_keywait::{countdown, flush_keys, wait_key};
+
+use boot::{
+    list_boot_entries, list_os_recovery, list_platform_recovery, parse_boot_num, print_timeout,
+    set_boot_next, set_os_recovery_order, set_timeout,
+};
+use capsule::{list_capsule_results, update_capsule};
+use wake::{disable_wakeup, print_wakeup_time, set_wakeup_time, DateTime, Wake};
 
 bitflags! {
     #[repr(transparent)]
@@ -34,97 +49,284 @@ bitflags! {
 
 const MIN_UEFI_REVISION: uefi::table::Revision = uefi::table::Revision::EFI_2_00;
 
-macro_rules! format_help {
-    ($name:expr) => {
-        ::core::format_args!(
-            "\
+/// Most bytes of `ResetData` passed to firmware, the spec sets no bound but firmware keeps
+/// it in buffers of its own across reset handlers, so stay within a page
+const MAX_RESET_DATA_SIZE: usize = 4096;
+
+/// Default command-line options set at compile time, split then as well
+const DEFAULT_CMD: &[&str] =
+    uefi_shell_split::split_const!(match option_env!("UEFI_RESET_DEFAULT_CMD") {
+        Some(cmd) => cmd,
+        None => "",
+    });
+
+/// Presets of platform specific reset types as `NAME=GUID` set at compile time, as these
+/// GUIDs are specific to vendors, e.g. of reset to recovery mode of a SoC
+const RESET_PRESETS: &[&str] =
+    uefi_shell_split::split_const!(match option_env!("UEFI_RESET_PRESETS") {
+        Some(presets) => presets,
+        None => "",
+    });
+
+/// GUID of platform specific reset type preset `name`
+fn find_reset_preset(name: &str) -> Option<Guid> {
+    RESET_PRESETS
+        .iter()
+        .filter_map(|preset| preset.split_once('='))
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+        .and_then(|(_, guid)| Guid::try_parse(guid).ok())
+}
+
+const APP: App = App {
+    help: "\
 Usage: {name} <COMMAND> [OPTIONS]
 
   Reset the system with OS indications flag set
 
   -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
 
-Commands:
+Commands, or unambiguous prefixes of them:
   reset                 Reset system only
-  firmware              Boot to firmware
-  os-recovery           Start OS recovery
-  platform-recovery     Start platform recovery
-  flags                 List OS indication flags
+  firmware, fw          Boot to firmware
+  os-recovery           Start OS recovery, or list OsRecoveryOrder and
+                        OsRecovery#### options with --list
+  platform-recovery     Start platform recovery, or list PlatformRecovery####
+                        options firmware would launch with --list
+  shutdown              Shut down system, optionally waking it up later by
+                        --wake-at or --wake-in
+  wake                  Print state of wakeup by RTC, or disable it with
+                        --disable
+  bootnext NUM          Boot boot option `Boot####` of hex NUM once after reset
+  flags                 List OS indication flags and Secure Boot state, in
+                        Standard-Format-Output of UEFI shell with -sfo, or
+                        size, attributes and bytes of OS indication variables
+                        with --raw, supported flags are in green, set ones
+                        in yellow and others in grey unless --no-color
+  flags set FLAG        Set OS indication FLAG without reset, FLAG is either
+                        name listed by \"flags\", short name like `firmware`
+                        or `json-refresh`, or hex value like `0x80`
+  flags clear FLAG      Clear OS indication FLAG without reset
+  check FLAG            Exit with SUCCESS if firmware supports OS indication
+                        FLAG or UNSUPPORTED if not, silently unless -v, e.g.
+                        for %lasterror% in startup.nsh
+  boot-entries          List boot options with BootOrder, BootCurrent and
+                        BootNext, e.g. to find NUM for \"bootnext\"
+  timeout [SECONDS]     Print or set seconds boot manager waits before booting,
+                        65535 waits for user input
+  capsule FILE          Deliver capsule FILE to firmware by UpdateCapsule
+  capsule-result        List results of processing capsules
 
 Options:
   -t, --type TYPE       Reset type, should be one of `cold`, `warm`, `shutdown`
                         or GUID that describe platform specific reset type,
-                        defaults to `cold`
+                        or name of such GUID preset at compile time by
+                        UEFI_RESET_PRESETS, defaults to `cold`
   -f, --force           Force the operation even the support was not announced
-  -c, --clear           Clear OS indication flags for \"reset\" command
+  -l, --list            List options of recovery command instead of reset
+      --order GUID,...  Set OsRecoveryOrder to vendor GUIDs of OsRecovery####
+                        options for \"os-recovery\" instead of reset, firmware
+                        may refuse it without authenticated write
+  -c, --clear           Clear OS indication flags for \"reset\" command, or
+                        delete listed results for \"capsule-result\" command
+  -q, --quiet           Log errors and warnings only
+  -v, --verbose         Log debug messages as well, and print result of
+                        \"check\" command
+  -y, --yes             Reset without asking for confirmation, which is only
+                        asked if launched from shell
+      --also FLAG,...   Set OS indication FLAGs as well in the same write, e.g.
+                        `firmware --also json-refresh`
+  -m, --merge           Add the flag to OS indication flags set already instead
+                        of overwriting them, e.g. these set by other components
+      --no-reset        Only set OS indication flags and BootNext, leaving the
+                        reset to a later reboot, e.g. by the OS
+      --no-color        Print listing of \"flags\" without colors
+      --reset           Reset as required by the capsule after \"capsule\", with
+                        confirmation and --wait like other resets
+  -w, --wait SECONDS    Count down SECONDS before reset, any key press cancels
+                        the reset and leaves OS indication flags unchanged
+      --wake-at TIME    Wake up at TIME of RTC like `2023-09-30T08:00[:00]`
+                        after \"shutdown\"
+      --wake-in SECONDS Wake up SECONDS later after \"shutdown\"
+      --disable         Disable wakeup for \"wake\" command
+  -s, --status CODE     Status passed to ResetSystem as cause of the reset, either
+                        name like `DEVICE_ERROR` or hex code, defaults to
+                        `SUCCESS`
+      --data STRING     Reset reason STRING passed to ResetSystem, defaults to
+                        reset type like `cold`, followed by GUID of platform
+                        specific reset type
+      --data-hex BYTES  Hex BYTES appended to reset data after reason string,
+                        and after GUID of platform specific reset type, reset
+                        data must not exceed 4096 bytes in total
+
+EXIT STATUS:
+  Returned to UEFI shell as %lasterror%.
+
+  SUCCESS               Command succeeded, or flag supported for \"check\"
+  INVALID_PARAMETER     Invalid command-line arguments, capsule file or reset
+                        data
+  UNSUPPORTED           Flag not supported by firmware, see --force, or UEFI
+                        revision of firmware older than 2.0
+  ABORTED               Reset cancelled at confirmation or countdown
+  SECURITY_VIOLATION    Firmware refused to write a protected variable, including
+                        by ACCESS_DENIED or WRITE_PROTECTED
+  Others                Status of the firmware call failed
 
 EXAMPLE:
   * Example
   {name}
 ",
-            name = $name
-        )
-    };
-}
+    version: uefi_cli::version!(),
+};
 
-#[derive(Debug)]
-enum ArgsError<'a> {
-    Invalid,
-    GetArgs(getargs::Error<&'a str>),
-}
-impl core::fmt::Display for ArgsError<'_> {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match self {
-            Self::GetArgs(e) => e.fmt(f),
-            Self::Invalid => f.write_str("Invalid argument"),
-        }
-    }
+struct ResetOptions {
+    indication: Option<OsIndications>,
+    force: bool,
+    /// whether to ask for confirmation before reset
+    confirm: bool,
+    /// whether to only set variables and return instead of reset
+    no_reset: bool,
+    /// whether to OR `indication` into current OS indication flags
+    merge: bool,
+    /// boot option to set `BootNext` to
+    boot_next: Option<u16>,
+    reset_type: ResetType,
+    platform_guid: Option<Guid>,
+    /// cause of the reset
+    status: Status,
+    /// seconds to wait for a key press cancelling the reset
+    wait: Option<u64>,
+    /// when to wake up after shutdown
+    wake: Option<Wake>,
+    /// reset reason string overriding the default
+    reason: Option<CString16>,
+    /// vendor data appended to reset data
+    extra_data: Vec<u8>,
 }
 
-enum Command {
+enum Command<'a> {
     NoOp,
-    ListOsIndications,
-    Reset {
-        indication: Option<OsIndications>,
+    ListOsIndications {
+        sfo: bool,
+        color: bool,
+    },
+    DumpOsIndications,
+    /// set or clear `flag` in OS indication flags
+    UpdateOsIndications {
+        flag: OsIndications,
+        set: bool,
         force: bool,
-        reset_type: ResetType,
-        platform_guid: Option<Guid>,
     },
+    CheckOsIndication {
+        flag: OsIndications,
+        verbose: bool,
+    },
+    Wake {
+        disable: bool,
+    },
+    ListBootEntries,
+    Timeout(Option<u16>),
+    ListPlatformRecovery,
+    ListOsRecovery,
+    SetOsRecoveryOrder(Vec<Guid>),
+    /// deliver capsule, then reset as it requires with `reset` if any
+    Capsule {
+        path: &'a str,
+        reset: Option<ResetOptions>,
+    },
+    ListCapsuleResults {
+        clear: bool,
+    },
+    Reset(ResetOptions),
 }
 
-fn parse_args<'a, I: Iterator<Item = &'a str>>(mut argv_iter: I) -> Result<Command, ArgsError<'a>> {
+/// `interactive` is whether someone may be at the console to confirm the reset
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+    interactive: bool,
+) -> Result<Command<'a>, ArgsError<'a>> {
     let Some(name) = argv_iter.next() else {
         return Err(ArgsError::Invalid);
     };
-    let mut opts = Options::new(argv_iter);
-
-    #[inline]
-    fn w<T>(res: getargs::Result<&str, T>) -> Result<T, ArgsError<'_>> {
-        res.map_err(ArgsError::GetArgs)
-    }
+    // `-sfo` of UEFI shell commands is not a cluster of short options
+    let sfo = Cell::new(false);
+    let mut opts = Options::new(argv_iter.filter(|arg| {
+        let is_sfo = arg.eq_ignore_ascii_case("-sfo");
+        sfo.set(sfo.get() || is_sfo);
+        !is_sfo
+    }));
 
+    #[derive(Clone, Copy)]
     enum CommandType {
         NoOp,
         ListOsIndications,
+        CheckOsIndication,
+        ListBootEntries,
+        Timeout,
         Reset,
         Firmware,
         OsRecovery,
         PlatformRecovery,
+        Shutdown,
+        Wake,
+        BootNext,
+        Capsule,
+        ListCapsuleResults,
     }
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("flags", CommandType::ListOsIndications),
+        ("check", CommandType::CheckOsIndication),
+        ("boot-entries", CommandType::ListBootEntries),
+        ("timeout", CommandType::Timeout),
+        ("reset", CommandType::Reset),
+        ("firmware", CommandType::Firmware),
+        ("fw", CommandType::Firmware),
+        ("os-recovery", CommandType::OsRecovery),
+        ("platform-recovery", CommandType::PlatformRecovery),
+        ("shutdown", CommandType::Shutdown),
+        ("wake", CommandType::Wake),
+        ("bootnext", CommandType::BootNext),
+        ("capsule", CommandType::Capsule),
+        ("capsule-result", CommandType::ListCapsuleResults),
+    ];
 
     let mut command_type = CommandType::NoOp;
     let mut reset_type = ResetType::COLD;
     let mut platform_guid = None;
     let mut clear = false;
     let mut force = false;
-    while let Some(arg) = w(opts.next_arg())? {
+    let mut yes = false;
+    let mut verbose = false;
+    let mut list = false;
+    let mut raw = false;
+    let mut os_recovery_order = None;
+    let mut merge = false;
+    let mut also = OsIndications::empty();
+    let mut boot_next = None;
+    let mut capsule = None;
+    let mut timeout = None;
+    let mut capsule_reset = false;
+    let mut no_reset = false;
+    let mut no_color = false;
+    let mut wait = None;
+    let mut wake = None;
+    let mut disable_wake = false;
+    let mut status = Status::SUCCESS;
+    let mut reason = None;
+    let mut extra_data = Vec::new();
+    let mut flag_set = None;
+    let mut flag = None;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
         match arg {
-            Arg::Short('h') | Arg::Long("help") => {
-                println!("{}", format_help!(name));
-                return Ok(Command::NoOp);
-            }
             Arg::Short('t') | Arg::Long("type") => {
-                let t = w(opts.value())?;
+                let t = opts.value()?;
                 reset_type = if t.eq_ignore_ascii_case("cold") {
                     ResetType::COLD
                 } else if t.eq_ignore_ascii_case("warm") {
@@ -132,7 +334,8 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(mut argv_iter: I) -> Result<Comma
                 } else if t.eq_ignore_ascii_case("shutdown") {
                     ResetType::SHUTDOWN
                 } else {
-                    let Ok(guid) = Guid::try_parse(t) else {
+                    let Some(guid) = find_reset_preset(t).or_else(|| Guid::try_parse(t).ok())
+                    else {
                         println!("Unknown reset type: {}", t);
                         return Err(ArgsError::Invalid);
                     };
@@ -140,58 +343,311 @@ fn parse_args<'a, I: Iterator<Item = &'a str>>(mut argv_iter: I) -> Result<Comma
                     ResetType::PLATFORM_SPECIFIC
                 };
             }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
             Arg::Short('f') | Arg::Long("force") => {
                 force = true;
             }
             Arg::Short('c') | Arg::Long("clear") => {
                 clear = true;
             }
-            Arg::Positional(cmd) => {
-                command_type = if cmd.eq_ignore_ascii_case("flags") {
-                    CommandType::ListOsIndications
-                } else if cmd.eq_ignore_ascii_case("reset") {
-                    CommandType::Reset
-                } else if cmd.eq_ignore_ascii_case("firmware") {
-                    CommandType::Firmware
-                } else if cmd.eq_ignore_ascii_case("os-recovery") {
-                    CommandType::OsRecovery
-                } else if cmd.eq_ignore_ascii_case("platform-recovery") {
-                    CommandType::PlatformRecovery
-                } else {
-                    println!("Unexpected argument {}", arg);
+            Arg::Long("raw") => {
+                raw = true;
+            }
+            Arg::Short('l') | Arg::Long("list") => {
+                list = true;
+            }
+            Arg::Long("order") => {
+                let value = opts.value()?;
+                let Ok(order) = value
+                    .split(',')
+                    .map(Guid::try_parse)
+                    .collect::<Result<Vec<_>, _>>()
+                else {
+                    println!("Invalid GUID list {}", value);
                     return Err(ArgsError::Invalid);
                 };
+                os_recovery_order = Some(order);
             }
-            _ => {
-                println!("Unexpected argument {}", arg);
-                return Err(ArgsError::Invalid);
+            Arg::Short('q') | Arg::Long("quiet") => {
+                log::set_max_level(log::LevelFilter::Warn);
+            }
+            Arg::Short('v') | Arg::Long("verbose") => {
+                log::set_max_level(log::LevelFilter::Debug);
+                verbose = true;
             }
+            Arg::Short('y') | Arg::Long("yes") => {
+                yes = true;
+            }
+            Arg::Long("also") => {
+                let value = opts.value()?;
+                for flag in value.split(',') {
+                    let Some(flag) = parse_os_indication(flag) else {
+                        println!("Unknown OS indication flag {}", flag);
+                        return Err(ArgsError::Invalid);
+                    };
+                    also |= flag;
+                }
+            }
+            Arg::Short('m') | Arg::Long("merge") => {
+                merge = true;
+            }
+            Arg::Short('w') | Arg::Long("wait") => {
+                let value = opts.value()?;
+                let Ok(secs) = value.parse() else {
+                    println!("Invalid wait seconds {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                wait = Some(secs);
+            }
+            Arg::Long("wake-at") => {
+                let value = opts.value()?;
+                let Some(at) = DateTime::parse(value) else {
+                    println!("Invalid wakeup time {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                wake = Some(Wake::At(at));
+            }
+            Arg::Long("wake-in") => {
+                let value = opts.value()?;
+                let Ok(secs) = value.parse() else {
+                    println!("Invalid wakeup seconds {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                wake = Some(Wake::In(secs));
+            }
+            Arg::Long("disable") => {
+                disable_wake = true;
+            }
+            Arg::Short('s') | Arg::Long("status") => {
+                let value = opts.value()?;
+                let Some(value) = parse_status(value) else {
+                    println!("Unknown status {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                status = value;
+            }
+            Arg::Long("data") => {
+                let value = opts.value()?;
+                let Ok(value) = CString16::try_from(value) else {
+                    println!("Reset reason must be UCS-2 string");
+                    return Err(ArgsError::Invalid);
+                };
+                reason = Some(value);
+            }
+            Arg::Long("data-hex") => {
+                let value = opts.value()?;
+                let Some(bytes) = parse_hex(value) else {
+                    println!("Invalid hex bytes {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                extra_data = bytes;
+            }
+            Arg::Positional(num)
+                if matches!(command_type, CommandType::BootNext) && boot_next.is_none() =>
+            {
+                let Some(num) = parse_boot_num(num) else {
+                    println!("Invalid boot option number {}", num);
+                    return Err(ArgsError::Invalid);
+                };
+                boot_next = Some(num);
+            }
+            Arg::Positional(secs)
+                if matches!(command_type, CommandType::Timeout) && timeout.is_none() =>
+            {
+                let Ok(secs) = secs.parse() else {
+                    println!("Invalid timeout seconds {}", secs);
+                    return Err(ArgsError::Invalid);
+                };
+                timeout = Some(secs);
+            }
+            Arg::Positional(path)
+                if matches!(command_type, CommandType::Capsule) && capsule.is_none() =>
+            {
+                capsule = Some(path);
+            }
+            Arg::Positional(action)
+                if matches!(command_type, CommandType::ListOsIndications) && flag_set.is_none() =>
+            {
+                flag_set = Some(match action {
+                    "set" => true,
+                    "clear" => false,
+                    _ => return Err(ArgsError::Unexpected(arg)),
+                });
+            }
+            Arg::Positional(value)
+                if matches!(
+                    command_type,
+                    CommandType::ListOsIndications | CommandType::CheckOsIndication
+                ) && flag.is_none() =>
+            {
+                let Some(value) = parse_os_indication(value) else {
+                    println!("Unknown OS indication flag {}", value);
+                    return Err(ArgsError::Invalid);
+                };
+                flag = Some(value);
+            }
+            Arg::Long("no-color") => {
+                no_color = true;
+            }
+            Arg::Long("no-reset") => {
+                no_reset = true;
+            }
+            Arg::Long("reset") => {
+                capsule_reset = true;
+            }
+            Arg::Positional(cmd) => {
+                command_type =
+                    find_command_prefix(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?;
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
         }
     }
 
+    if wake.is_some() && !matches!(command_type, CommandType::Shutdown) {
+        println!("--wake-at and --wake-in only apply to \"shutdown\" command");
+        return Err(ArgsError::Invalid);
+    }
+
+    if no_reset && wait.is_some() {
+        println!("--no-reset conflicts with --wait");
+        return Err(ArgsError::Invalid);
+    }
+
+    let sfo = sfo.get();
+    if sfo && (!matches!(command_type, CommandType::ListOsIndications) || flag_set.is_some()) {
+        println!("-sfo only applies to listing of \"flags\" command");
+        return Err(ArgsError::Invalid);
+    }
+
+    if clear && merge {
+        println!("--clear conflicts with --merge");
+        return Err(ArgsError::Invalid);
+    }
+
     let indication = match command_type {
         CommandType::NoOp => {
-            println!("{}", format_help!(name));
+            APP.print_help(name);
             return Ok(Command::NoOp);
         }
-        CommandType::ListOsIndications => return Ok(Command::ListOsIndications),
+        CommandType::ListOsIndications => {
+            let Some(set) = flag_set else {
+                if raw {
+                    return Ok(Command::DumpOsIndications);
+                }
+                return Ok(Command::ListOsIndications {
+                    sfo,
+                    color: !no_color && !sfo,
+                });
+            };
+            let Some(flag) = flag else {
+                println!("Missing OS indication flag");
+                return Err(ArgsError::Invalid);
+            };
+            return Ok(Command::UpdateOsIndications { flag, set, force });
+        }
+        CommandType::CheckOsIndication => {
+            let Some(flag) = flag else {
+                println!("Missing OS indication flag");
+                return Err(ArgsError::Invalid);
+            };
+            return Ok(Command::CheckOsIndication { flag, verbose });
+        }
+        CommandType::ListBootEntries => return Ok(Command::ListBootEntries),
+        CommandType::Wake => {
+            return Ok(Command::Wake {
+                disable: disable_wake,
+            })
+        }
+        CommandType::Timeout => return Ok(Command::Timeout(timeout)),
+        CommandType::ListCapsuleResults => return Ok(Command::ListCapsuleResults { clear }),
+        CommandType::Capsule => {
+            let Some(path) = capsule else {
+                println!("Missing capsule file");
+                return Err(ArgsError::Invalid);
+            };
+            if !capsule_reset {
+                return Ok(Command::Capsule { path, reset: None });
+            }
+            None
+        }
         CommandType::Reset => clear.then_some(OsIndications::empty()),
+        CommandType::Shutdown => {
+            reset_type = ResetType::SHUTDOWN;
+            platform_guid = None;
+            clear.then_some(OsIndications::empty())
+        }
+        CommandType::BootNext => {
+            if boot_next.is_none() {
+                println!("Missing boot option number");
+                return Err(ArgsError::Invalid);
+            }
+            clear.then_some(OsIndications::empty())
+        }
         CommandType::Firmware => Some(OsIndications::BOOT_TO_FW_UI),
-        CommandType::OsRecovery => Some(OsIndications::START_OS_RECOVERY),
+        CommandType::OsRecovery if list => return Ok(Command::ListOsRecovery),
+        CommandType::OsRecovery => match os_recovery_order {
+            Some(order) => return Ok(Command::SetOsRecoveryOrder(order)),
+            None => Some(OsIndications::START_OS_RECOVERY),
+        },
+        CommandType::PlatformRecovery if list => return Ok(Command::ListPlatformRecovery),
         CommandType::PlatformRecovery => Some(OsIndications::START_PLATFORM_RECOVERY),
     };
+    let indication = match indication {
+        _ if also.is_empty() => indication,
+        Some(indication) => Some(indication | also),
+        None => Some(also),
+    };
 
-    Ok(Command::Reset {
+    let opts = ResetOptions {
         indication,
         force,
+        confirm: interactive && !yes && !no_reset,
+        no_reset,
+        merge,
+        boot_next,
         reset_type,
         platform_guid,
-    })
+        status,
+        wait,
+        wake,
+        reason,
+        extra_data,
+    };
+    match capsule {
+        Some(path) => Ok(Command::Capsule {
+            path,
+            reset: Some(opts),
+        }),
+        None => Ok(Command::Reset(opts)),
+    }
 }
 
 #[entry]
 fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
-    uefi_services::init(&mut system_table).unwrap();
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    shell_status(run(system_table))
+}
+
+/// Narrow `status` down to ones listed in help, so scripts can branch on `%lasterror%`
+fn shell_status(status: Status) -> Status {
+    match status {
+        Status::BAD_BUFFER_SIZE => Status::INVALID_PARAMETER,
+        Status::INCOMPATIBLE_VERSION => Status::UNSUPPORTED,
+        Status::ACCESS_DENIED | Status::WRITE_PROTECTED => Status::SECURITY_VIOLATION,
+        _ => status,
+    }
+}
+
+fn run(system_table: SystemTable<Boot>) -> Status {
+    // console input is borrowed mutably along with boot services
+    let mut st = unsafe { system_table.unsafe_clone() };
     let bt = system_table.boot_services();
     let rt = system_table.runtime_services();
 
@@ -204,92 +660,195 @@ fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         return Status::INCOMPATIBLE_VERSION;
     }
 
-    let image = bt
-        .open_protocol_exclusive::<LoadedImage>(bt.image_handle())
-        .unwrap();
-
-    let sh_params = bt
-        .open_protocol_exclusive::<ShellParameters>(bt.image_handle())
-        .ok();
-    let mut argv: Vec<String> = if let Some(sh_params) = sh_params {
-        sh_params
-            .args()
-            .map(|arg| {
-                let mut buf = String::new();
-                arg.as_str_in_buf(&mut buf).unwrap();
-                buf
-            })
-            .collect()
-    } else if let Ok(load_options) = image.load_options_as_cstr16() {
-        let mut load_options_str = String::new();
-        load_options_str.reserve(load_options.num_chars());
-        if load_options.as_str_in_buf(&mut load_options_str).is_ok() {
-            uefi_shell_split::split(load_options_str.as_str())
-        } else {
-            Vec::new()
-        }
-    } else {
-        Vec::new()
+    let default_cmd = (!DEFAULT_CMD.is_empty()).then_some(DEFAULT_CMD);
+    // no one may be at the console if not launched from shell, e.g. from boot entry with
+    // load options or the default command
+    let (argv, source) = match uefi_argv::args_with_source(bt, default_cmd) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
     };
-    if argv.len() <= 1 {
-        if let Some(default) = option_env!("UEFI_RESET_DEFAULT_CMD") {
-            argv = uefi_shell_split::split(default)
-        }
-    }
-    if argv.is_empty() {
-        log::error!("Command-line options not passed");
-        return Status::INVALID_PARAMETER;
-    }
     let argv = argv.iter().map(|i| i.as_str());
 
-    let res = match parse_args(argv) {
+    let res = match parse_args(argv, source == uefi_argv::Source::Shell) {
         Err(e) => {
             println!("{}", e);
-            return Status::INVALID_PARAMETER;
+            return e.status();
+        }
+        Ok(Command::NoOp) => Ok(()),
+        Ok(Command::ListOsIndications { sfo, color }) => {
+            list_os_indications(rt, color.then(|| st.stdout()), sfo)
+        }
+        Ok(Command::DumpOsIndications) => dump_os_indications(rt),
+        Ok(Command::UpdateOsIndications { flag, set, force }) => {
+            update_os_indications(rt, flag, set, force)
+        }
+        Ok(Command::CheckOsIndication { flag, verbose }) => {
+            match check_os_indication(rt, flag, verbose) {
+                Ok(status) => return status,
+                Err(e) => Err(e),
+            }
         }
-        Ok(Command::NoOp) => uefi::Result::Ok(()),
-        Ok(Command::ListOsIndications) => list_os_indications(rt),
-        Ok(Command::Reset {
-            indication,
-            force,
-            reset_type,
-            platform_guid,
-        }) => reset(rt, indication, force, reset_type, platform_guid),
+        Ok(Command::ListBootEntries) => list_boot_entries(bt, rt),
+        Ok(Command::Wake { disable: false }) => print_wakeup_time(rt),
+        Ok(Command::Wake { disable: true }) => disable_wakeup(rt),
+        Ok(Command::Timeout(None)) => print_timeout(rt),
+        Ok(Command::Timeout(Some(secs))) => set_timeout(rt, secs),
+        Ok(Command::ListPlatformRecovery) => list_platform_recovery(bt, rt),
+        Ok(Command::ListOsRecovery) => list_os_recovery(bt, rt),
+        Ok(Command::SetOsRecoveryOrder(order)) => set_os_recovery_order(rt, &order),
+        Ok(Command::Capsule { path, reset }) => deliver_capsule(bt, rt, st.stdin(), path, reset),
+        Ok(Command::ListCapsuleResults { clear }) => list_capsule_results(rt, clear),
+        Ok(Command::Reset(opts)) => reset(bt, rt, st.stdin(), &opts),
     };
 
-    res.status()
+    uefi_cli::exit_status(res)
 }
 
-const OS_INDICATIONS_SUPPORTED: &uefi::CStr16 = cstr16!("OsIndicationsSupported");
-const OS_INDICATIONS: &uefi::CStr16 = cstr16!("OsIndications");
+const OS_INDICATIONS_SUPPORTED: &CStr16 = cstr16!("OsIndicationsSupported");
+const OS_INDICATIONS: &CStr16 = cstr16!("OsIndications");
 
-fn list_os_indications(rt: &RuntimeServices) -> uefi::Result {
-    let mut supported = OsIndications::empty();
-    if let Err(e) = rt.get_variable(
-        OS_INDICATIONS_SUPPORTED,
-        &VariableVendor::GLOBAL_VARIABLE,
-        bytemuck::bytes_of_mut(&mut supported),
-    ) {
-        if e.status() == Status::NOT_FOUND {
-            println!("UEFI variable \"OsIndicationsSupported\" not set")
-        }
-        return Err(e);
+/// Short names of OS indication flags, in addition to names of them
+const FLAG_ALIASES: &[(&str, OsIndications)] = &[
+    ("firmware", OsIndications::BOOT_TO_FW_UI),
+    ("os-recovery", OsIndications::START_OS_RECOVERY),
+    ("platform-recovery", OsIndications::START_PLATFORM_RECOVERY),
+    ("json-refresh", OsIndications::JSON_CONFIG_DATA_REFRESH),
+    ("timestamp-revocation", OsIndications::TIMESTAMP_REVOCATION),
+];
+
+/// Parse OS indication flag by name like `BOOT_TO_FW_UI`, short name like `firmware` or
+/// by hex value prefixed with `0x`
+fn parse_os_indication(value: &str) -> Option<OsIndications> {
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        let bits = u64::from_str_radix(hex, 16).ok()?;
+        return (bits != 0).then_some(OsIndications::from_bits_retain(bits));
     }
+    OsIndications::FLAGS
+        .iter()
+        .find(|flag| flag.name().eq_ignore_ascii_case(value))
+        .map(|flag| *flag.value())
+        .or_else(|| find_command(FLAG_ALIASES, value))
+}
+
+/// Read global variable into `value` of fixed size, sized by the firmware instead, some
+/// buggy ones store more bytes than the spec defines. Excess bytes are ignored and
+/// missing ones left untouched.
+fn get_sized_var(rt: &RuntimeServices, name: &CStr16, value: &mut [u8]) -> uefi::Result {
+    let (data, _) = rt.get_variable_boxed(name, &VariableVendor::GLOBAL_VARIABLE)?;
+    if data.len() > value.len() {
+        log::warn!(
+            "UEFI variable \"{}\" of {} bytes, ignoring {} bytes exceeding {}",
+            name,
+            data.len(),
+            data.len() - value.len(),
+            value.len()
+        );
+    } else if data.len() < value.len() {
+        log::warn!(
+            "UEFI variable \"{}\" of {} bytes, less than {}",
+            name,
+            data.len(),
+            value.len()
+        );
+    }
+    let len = data.len().min(value.len());
+    value[..len].copy_from_slice(&data[..len]);
+    Ok(())
+}
+
+/// Read variable of OS indication flags of `UINT64`
+fn get_os_indications_var(rt: &RuntimeServices, name: &CStr16) -> uefi::Result<OsIndications> {
     let mut os_indications = OsIndications::empty();
-    if let Err(e) = rt.get_variable(
-        OS_INDICATIONS,
-        &VariableVendor::GLOBAL_VARIABLE,
-        bytemuck::bytes_of_mut(&mut os_indications),
-    ) {
-        if e.status() != Status::NOT_FOUND {
-            return Err(e);
+    get_sized_var(rt, name, bytemuck::bytes_of_mut(&mut os_indications))?;
+    Ok(os_indications)
+}
+
+fn get_os_indications_supported(rt: &RuntimeServices) -> uefi_error::Result<OsIndications> {
+    get_os_indications_var(rt, OS_INDICATIONS_SUPPORTED)
+        .context("failed to get UEFI variable \"OsIndicationsSupported\"")
+}
+
+/// Current OS indication flags, empty if not set
+fn get_os_indications(rt: &RuntimeServices) -> uefi_error::Result<OsIndications> {
+    match get_os_indications_var(rt, OS_INDICATIONS) {
+        Err(e) if e.status() == Status::NOT_FOUND => Ok(OsIndications::empty()),
+        res => res.context("failed to get UEFI variable \"OsIndications\""),
+    }
+}
+
+/// Print size, attributes and bytes of OS indication variables as stored
+fn dump_os_indications(rt: &RuntimeServices) -> uefi_error::Result {
+    for name in [OS_INDICATIONS_SUPPORTED, OS_INDICATIONS] {
+        println!("{}", name);
+        match rt.get_variable_boxed(name, &VariableVendor::GLOBAL_VARIABLE) {
+            Ok((data, attributes)) => {
+                println!("    Size: {}", data.len());
+                println!("    Attributes: {:?}", attributes);
+                print_hexdump(0, &data);
+            }
+            Err(e) if e.status() == Status::NOT_FOUND => println!("    Not found"),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to get UEFI variable \"{}\"", name))
+            }
         }
+        println!();
     }
+    Ok(())
+}
 
+fn set_os_indications(rt: &RuntimeServices, os_indications: OsIndications) -> uefi_error::Result {
+    rt.set_variable(
+        OS_INDICATIONS,
+        &VariableVendor::GLOBAL_VARIABLE,
+        VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS,
+        bytemuck::bytes_of(&os_indications),
+    )
+    .context("failed to set UEFI variable \"OsIndications\"")
+}
+
+/// List OS indication flags, colored if `stdout` is given
+fn list_os_indications(
+    rt: &RuntimeServices,
+    mut stdout: Option<&mut Output>,
+    sfo: bool,
+) -> uefi_error::Result {
+    let supported = get_os_indications_supported(rt)?;
+    let os_indications = get_os_indications(rt)?;
+
+    if sfo {
+        println!("ShellCommand,\"flags\"");
+    }
     for flag in OsIndications::FLAGS {
         let supported = supported.contains(*flag.value());
         let set = os_indications.contains(*flag.value());
+        if sfo {
+            println!(
+                "OsIndication,\"{}\",\"{:#x}\",\"{}\",\"{}\"",
+                flag.name(),
+                flag.value().bits(),
+                if supported { "Supported" } else { "" },
+                if set { "Set" } else { "" }
+            );
+            continue;
+        }
+        // coloring is cosmetic, failures of it are ignored
+        if let Some(stdout) = stdout.as_deref_mut() {
+            let color = match (set, supported) {
+                (true, _) => Color::Yellow,
+                (false, true) => Color::LightGreen,
+                (false, false) => Color::DarkGray,
+            };
+            let _ = stdout.set_color(color, Color::Black);
+        }
         println!("{}", flag.name());
+        if let Some(stdout) = stdout.as_deref_mut() {
+            let _ = stdout.set_color(Color::LightGray, Color::Black);
+        }
         println!("    Flag: 0x{:08x}", flag.value().bits(),);
         if supported || set {
             println!(
@@ -300,71 +859,256 @@ fn list_os_indications(rt: &RuntimeServices) -> uefi::Result {
         }
         println!("")
     }
+
+    for name in SECURE_BOOT_VARS {
+        let state = get_secure_boot_var(rt, name)?;
+        if sfo {
+            let state = state.map(|on| if on { "1" } else { "0" });
+            println!("SecureBootState,\"{}\",\"{}\"", name, state.unwrap_or(""));
+            continue;
+        }
+        let state = match state {
+            Some(true) => "on",
+            Some(false) => "off",
+            None => "not supported",
+        };
+        println!("{}: {}", name, state);
+    }
     Ok(())
 }
 
-fn reset(
+/// Global variables of `UINT8` showing Secure Boot state and modes
+const SECURE_BOOT_VARS: [&CStr16; 4] = [
+    cstr16!("SecureBoot"),
+    cstr16!("SetupMode"),
+    cstr16!("AuditMode"),
+    cstr16!("DeployedMode"),
+];
+
+/// Whether Secure Boot variable `name` is 1, or [None] if firmware doesn't have it
+fn get_secure_boot_var(rt: &RuntimeServices, name: &CStr16) -> uefi_error::Result<Option<bool>> {
+    let mut value = 0u8;
+    match get_sized_var(rt, name, core::slice::from_mut(&mut value)) {
+        Ok(_) => Ok(Some(value == 1)),
+        Err(e) if e.status() == Status::NOT_FOUND => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to get UEFI variable \"{}\"", name)),
+    }
+}
+
+/// Exit status of `check` command, [Status::UNSUPPORTED] if `flag` is not supported
+fn check_os_indication(
+    rt: &RuntimeServices,
+    flag: OsIndications,
+    verbose: bool,
+) -> uefi_error::Result<Status> {
+    // firmware without the variable supports no flag
+    let supported = match get_os_indications_supported(rt) {
+        Ok(supported) => supported.contains(flag),
+        Err(e) if e.status() == Status::NOT_FOUND => false,
+        Err(e) => return Err(e),
+    };
+    if verbose {
+        let support = if supported {
+            "supported"
+        } else {
+            "not supported"
+        };
+        println!("{:?} {}", flag, support);
+    }
+    Ok(if supported {
+        Status::SUCCESS
+    } else {
+        Status::UNSUPPORTED
+    })
+}
+
+fn update_os_indications(
     rt: &RuntimeServices,
+    flag: OsIndications,
+    set: bool,
+    force: bool,
+) -> uefi_error::Result {
+    // clearing a flag the firmware doesn't support is harmless
+    if set && !force && !get_os_indications_supported(rt)?.contains(flag) {
+        let msg = format!("flag {:?} not supported", flag);
+        return Err(Error::msg(Status::UNSUPPORTED, msg));
+    }
+    let os_indications = get_os_indications(rt)?;
+    let os_indications = if set {
+        os_indications | flag
+    } else {
+        os_indications - flag
+    };
+    set_os_indications(rt, os_indications)
+}
+
+/// Deliver capsule at `path`, then reset with `opts` but the reset type firmware requires
+fn deliver_capsule(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    stdin: &mut Input,
+    path: &str,
+    opts: Option<ResetOptions>,
+) -> uefi_error::Result {
+    let reset_type = update_capsule(bt, rt, path)?;
+    let Some(opts) = opts else {
+        println!(
+            "Capsule delivered, {:?} reset is required to process it",
+            reset_type
+        );
+        return Ok(());
+    };
+    let opts = ResetOptions {
+        reset_type,
+        platform_guid: None,
+        ..opts
+    };
+    reset(bt, rt, stdin, &opts)
+}
+
+/// Ask at console whether to reset, returns whether `y` was pressed
+fn confirm_reset(
+    bt: &BootServices,
+    stdin: &mut Input,
+    reset_type: ResetType,
     indication: Option<OsIndications>,
-    no_check: bool,
+    boot_next: Option<u16>,
+) -> uefi_error::Result<bool> {
+    if reset_type == ResetType::SHUTDOWN {
+        print!("Shut down system? [y/N] ");
+        return read_yes(bt, stdin);
+    }
+    let target = match (indication, boot_next) {
+        (_, Some(num)) => format!(" to boot option Boot{:04X}", num),
+        (Some(OsIndications::BOOT_TO_FW_UI), _) => " to firmware UI".into(),
+        (Some(OsIndications::START_OS_RECOVERY), _) => " to OS recovery".into(),
+        (Some(OsIndications::START_PLATFORM_RECOVERY), _) => " to platform recovery".into(),
+        _ => "".into(),
+    };
+    print!("Reset system{}? [y/N] ", target);
+    read_yes(bt, stdin)
+}
+
+/// Read answer to a yes or no question, returns whether `y` was pressed
+fn read_yes(bt: &BootServices, stdin: &mut Input) -> uefi_error::Result<bool> {
+    flush_keys(stdin).context("failed to read key")?;
+    let key = wait_key(bt, stdin, None).context("failed to wait for key")?;
+    let yes = matches!(key, Some(Key::Printable(c)) if matches!(char::from(c), 'y' | 'Y'));
+    println!("{}", if yes { "y" } else { "n" });
+    Ok(yes)
+}
+
+/// Count down `seconds`, returns whether a key was pressed to cancel
+fn wait_cancel(bt: &BootServices, stdin: &mut Input, seconds: u64) -> uefi_error::Result<bool> {
+    flush_keys(stdin).context("failed to read key")?;
+    let key = countdown(bt, stdin, seconds, |remaining| {
+        print!("\rReset in {} seconds, press any key to cancel ", remaining);
+    })
+    .context("failed to wait for key")?;
+    println!();
+    Ok(key.is_some())
+}
+
+/// `ResetData` of `ResetSystem`, the null-terminated `reason` string defaulting to name of
+/// `reset_type`, followed by GUID of platform specific reset type and then vendor data
+fn reset_data(
     reset_type: ResetType,
     platform_guid: Option<Guid>,
-) -> uefi::Result {
-    if let Some(indication) = indication {
-        let supported = if no_check {
-            OsIndications::all()
-        } else {
-            let mut supported = OsIndications::empty();
-            rt.get_variable(
-                OS_INDICATIONS_SUPPORTED,
-                &VariableVendor::GLOBAL_VARIABLE,
-                bytemuck::bytes_of_mut(&mut supported),
-            )
-            .map_err(|e| {
-                println!("UEFI variable \"OsIndicationsSupported\" not set: {}", e);
-                e
-            })?;
-            supported
+    reason: Option<&CStr16>,
+    extra_data: &[u8],
+) -> uefi_error::Result<Vec<u8>> {
+    let reason = match reason {
+        Some(reason) => reason,
+        None => match reset_type {
+            ResetType::COLD => cstr16!("cold"),
+            ResetType::WARM => cstr16!("warm"),
+            ResetType::SHUTDOWN => cstr16!("shutdown"),
+            ResetType::PLATFORM_SPECIFIC => cstr16!("platform"),
+            _ => return Err(Error::msg(Status::INVALID_PARAMETER, "unknown reset type")),
+        },
+    };
+    let mut data = Vec::from(reason.as_bytes());
+    if reset_type == ResetType::PLATFORM_SPECIFIC {
+        // firmware looks for the GUID right after the string
+        let Some(guid) = platform_guid else {
+            let msg = "platform specific reset requires GUID";
+            return Err(Error::msg(Status::INVALID_PARAMETER, msg));
         };
-        if !supported.contains(indication) {
-            println!("Flag {:?} not supported", indication);
-            return Status::ABORTED.to_result();
+        data.extend(guid.to_bytes());
+    }
+    data.extend(extra_data);
+    if data.len() > MAX_RESET_DATA_SIZE {
+        let msg = format!(
+            "reset data of {} bytes larger than {} bytes",
+            data.len(),
+            MAX_RESET_DATA_SIZE
+        );
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    }
+    Ok(data)
+}
+
+fn reset(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    stdin: &mut Input,
+    opts: &ResetOptions,
+) -> uefi_error::Result {
+    let ResetOptions {
+        indication,
+        force,
+        confirm,
+        no_reset,
+        merge,
+        boot_next,
+        reset_type,
+        platform_guid,
+        status,
+        wait,
+        wake,
+        ref reason,
+        ref extra_data,
+    } = *opts;
+
+    // checked before changing any variable
+    let data = reset_data(reset_type, platform_guid, reason.as_deref(), extra_data)?;
+    if let Some(indication) = indication.filter(|_| !force) {
+        if !get_os_indications_supported(rt)?.contains(indication) {
+            let msg = format!("flag {:?} not supported", indication);
+            return Err(Error::msg(Status::UNSUPPORTED, msg));
         }
-        rt.set_variable(
-            OS_INDICATIONS,
-            &VariableVendor::GLOBAL_VARIABLE,
-            VariableAttributes::NON_VOLATILE
-                | VariableAttributes::BOOTSERVICE_ACCESS
-                | VariableAttributes::RUNTIME_ACCESS,
-            bytemuck::bytes_of(&indication),
-        )
-        .map_err(|e| {
-            println!("Failed to set UEFI variable OSIndications: {}", e);
-            e
-        })?;
     }
 
-    let reason = match reset_type {
-        ResetType::COLD => cstr16!("cold"),
-        ResetType::WARM => cstr16!("warm"),
-        ResetType::SHUTDOWN => cstr16!("shutdown"),
-        ResetType::PLATFORM_SPECIFIC => cstr16!("platform"),
-        _ => unimplemented!(),
-    };
+    if confirm && !confirm_reset(bt, stdin, reset_type, indication, boot_next)? {
+        return Err(Error::msg(Status::ABORTED, "reset cancelled"));
+    }
 
-    let mut data: Vec<u8>;
-    let data = if let Some(guid) = platform_guid {
-        assert_eq!(reset_type, ResetType::PLATFORM_SPECIFIC);
-        let guid = guid.to_bytes();
-        data = Vec::new();
-        data.reserve(reason.as_bytes().len() + guid.len());
-        data.extend(reason.as_bytes());
-        data.extend(guid);
-        data.as_slice()
-    } else {
-        reason.as_bytes()
-    };
+    if let Some(seconds) = wait {
+        if wait_cancel(bt, stdin, seconds)? {
+            return Err(Error::msg(Status::ABORTED, "reset cancelled"));
+        }
+    }
+
+    if let Some(num) = boot_next {
+        set_boot_next(rt, num)?;
+    }
+
+    if let Some(wake) = wake {
+        set_wakeup_time(rt, wake)?;
+    }
+
+    if let Some(indication) = indication {
+        let indication = if merge {
+            get_os_indications(rt)? | indication
+        } else {
+            indication
+        };
+        set_os_indications(rt, indication)?;
+    }
+
+    if no_reset {
+        return Ok(());
+    }
 
-    // TODO: wait for several seconds to cancel on any keyboard input
-    rt.reset(reset_type, Status::SUCCESS, Some(data))
+    rt.reset(reset_type, status, Some(&data))
 }
@@ -0,0 +1,188 @@
+//! Waking up a shut down system later by `SetWakeupTime`, for the `shutdown` and `wake`
+//! commands
+
+use alloc::format;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use uefi::prelude::*;
+use uefi::println;
+use uefi_error::{Context, Error};
+use uefi_raw::time::Time;
+
+use crate::capsule::raw_runtime_services;
+
+/// Date and time of RTC, in time zone of it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+/// When to wake up the system after shutdown
+#[derive(Clone, Copy)]
+pub enum Wake {
+    At(DateTime),
+    /// seconds after now
+    In(u64),
+}
+
+/// Days since 1970-01-01 of a date in proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    // months since March so leap day is the last day of year
+    let month = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of [days_from_civil]
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u8;
+    let month = (if month < 10 { month + 3 } else { month - 9 }) as u8;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl DateTime {
+    /// Parse `YYYY-MM-DDTHH:MM[:SS]`, `T` can be a space as well
+    pub fn parse(time: &str) -> Option<Self> {
+        let (date, time) = time.split_once(['T', ' '])?;
+        let mut date = date.splitn(3, '-');
+        let mut time = time.splitn(3, ':');
+        let datetime = Self {
+            year: date.next()?.parse().ok()?,
+            month: date.next()?.parse().ok()?,
+            day: date.next()?.parse().ok()?,
+            hour: time.next()?.parse().ok()?,
+            minute: time.next()?.parse().ok()?,
+            second: match time.next() {
+                Some(second) => second.parse().ok()?,
+                None => 0,
+            },
+        };
+        datetime.is_valid().then_some(datetime)
+    }
+
+    /// Whether it's a valid date and time in range of `EFI_TIME`
+    fn is_valid(&self) -> bool {
+        let days = days_from_civil(self.year.into(), self.month, self.day);
+        (1900..=9999).contains(&self.year)
+            && civil_from_days(days) == (self.year.into(), self.month, self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    fn from_time(time: &Time) -> Self {
+        Self {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+        }
+    }
+
+    fn add_seconds(&self, seconds: u64) -> Self {
+        let days = days_from_civil(self.year.into(), self.month, self.day);
+        let seconds = (days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second))
+        .saturating_add(seconds.try_into().unwrap_or(i64::MAX));
+        let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+        let seconds = seconds.rem_euclid(86400);
+        Self {
+            year: year.try_into().unwrap_or(u16::MAX),
+            month,
+            day,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+/// Arm RTC to wake up the system at `wake` after shutdown
+pub fn set_wakeup_time(rt: &RuntimeServices, wake: Wake) -> uefi_error::Result {
+    let rt = raw_runtime_services(rt);
+    let mut time = MaybeUninit::<Time>::uninit();
+    unsafe { (rt.get_time)(time.as_mut_ptr(), ptr::null_mut()) }
+        .to_result()
+        .context("failed to get time")?;
+    // keep time zone and daylight saving of RTC
+    let mut time = unsafe { time.assume_init() };
+
+    let now = DateTime::from_time(&time);
+    let at = match wake {
+        Wake::At(at) => at,
+        Wake::In(seconds) => now.add_seconds(seconds),
+    };
+    if !at.is_valid() {
+        return Err(Error::msg(
+            Status::INVALID_PARAMETER,
+            "wakeup time out of range",
+        ));
+    }
+    let key = |t: &DateTime| (t.year, t.month, t.day, t.hour, t.minute, t.second);
+    if key(&at) <= key(&now) {
+        let msg = format!(
+            "wakeup time not after now {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            now.year, now.month, now.day, now.hour, now.minute, now.second
+        );
+        return Err(Error::msg(Status::INVALID_PARAMETER, msg));
+    }
+
+    time.year = at.year;
+    time.month = at.month;
+    time.day = at.day;
+    time.hour = at.hour;
+    time.minute = at.minute;
+    time.second = at.second;
+    time.nanosecond = 0;
+    unsafe { (rt.set_wakeup_time)(1, &time) }
+        .to_result()
+        .context("failed to set wakeup time")
+}
+
+/// Print whether wakeup is enabled or pending, and time of it
+pub fn print_wakeup_time(rt: &RuntimeServices) -> uefi_error::Result {
+    let rt = raw_runtime_services(rt);
+    let (mut enabled, mut pending) = (0u8, 0u8);
+    let mut time = MaybeUninit::<Time>::uninit();
+    unsafe { (rt.get_wakeup_time)(&mut enabled, &mut pending, time.as_mut_ptr()) }
+        .to_result()
+        .context("failed to get wakeup time")?;
+    let at = DateTime::from_time(unsafe { time.assume_init_ref() });
+    println!("Enabled: {}", if enabled != 0 { "yes" } else { "no" });
+    println!("Pending: {}", if pending != 0 { "yes" } else { "no" });
+    println!(
+        "Time: {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        at.year, at.month, at.day, at.hour, at.minute, at.second
+    );
+    Ok(())
+}
+
+/// Disarm wakeup by RTC
+pub fn disable_wakeup(rt: &RuntimeServices) -> uefi_error::Result {
+    let rt = raw_runtime_services(rt);
+    unsafe { (rt.set_wakeup_time)(0, ptr::null()) }
+        .to_result()
+        .context("failed to disable wakeup")
+}
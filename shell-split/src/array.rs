@@ -0,0 +1,116 @@
+//! Collecting arguments without allocator into [ArgArray] of fixed capacity
+
+use core::fmt::Display;
+use core::ops::Index;
+
+use crate::{Indexable, Split, SplitArg, SplitPolicy};
+
+/// More arguments than capacity of [ArgArray]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+impl Display for Overflow {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("too many arguments")
+    }
+}
+
+/// Arguments of at most `N`, e.g. collected by [split_into] in pre-boot code without
+/// allocator
+#[derive(Debug)]
+pub struct ArgArray<A, const N: usize> {
+    args: [Option<A>; N],
+    len: usize,
+}
+
+impl<A, const N: usize> ArgArray<A, N> {
+    pub fn new() -> Self {
+        Self {
+            args: core::array::from_fn(|_| None),
+            len: 0,
+        }
+    }
+
+    /// Append `arg`, or give it back if full
+    pub fn push(&mut self, arg: A) -> Result<(), A> {
+        match self.args.get_mut(self.len) {
+            Some(slot) => {
+                *slot = Some(arg);
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(arg),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&A> {
+        self.args.get(idx)?.as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &A> + '_ {
+        self.args[..self.len].iter().flatten()
+    }
+}
+
+impl<A, const N: usize> Default for ArgArray<A, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A, const N: usize> Index<usize> for ArgArray<A, N> {
+    type Output = A;
+
+    fn index(&self, idx: usize) -> &A {
+        self.get(idx).expect("argument index out of bounds")
+    }
+}
+
+impl<'a, T: Indexable + ?Sized, P: SplitPolicy> Split<'a, T, P> {
+    /// Collect rest of arguments into [ArgArray] of capacity `N`, fails if there are more
+    pub fn collect_array<const N: usize>(
+        self,
+    ) -> Result<ArgArray<SplitArg<'a, T, P>, N>, Overflow> {
+        let mut args = ArgArray::new();
+        for arg in self {
+            args.push(arg).map_err(|_| Overflow)?;
+        }
+        Ok(args)
+    }
+}
+
+/// Split `command_line` by EDK2 shell rules into [ArgArray] of capacity `N` without
+/// allocating, fails if there are more arguments
+pub fn split_into<T: Indexable + ?Sized, const N: usize>(
+    command_line: &T,
+) -> Result<ArgArray<SplitArg<'_, T>, N>, Overflow> {
+    Split::new(command_line).collect_array()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_into_array() {
+        let args = split_into::<_, 4>("a \"b c\" d^ e").unwrap();
+        assert_eq!(3, args.len());
+        assert_eq!("b c", args[1].to_string());
+        let raw: Vec<_> = args.iter().map(|arg| arg.raw()).collect();
+        assert_eq!(["a", "\"b c\"", "d^ e"], raw.as_slice());
+        assert_eq!(None, args.get(3).map(|arg| arg.raw()));
+
+        assert!(split_into::<_, 0>("  ").unwrap().is_empty());
+        assert_eq!(Overflow, split_into::<_, 2>("a b c").unwrap_err());
+    }
+}
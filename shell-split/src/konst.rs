@@ -0,0 +1,109 @@
+//! Splitting in const context, see [split_const!](crate::split_const)
+
+/// Span of next raw argument from `pos`, by EDK2 shell rules
+const fn next_arg(bytes: &[u8], mut pos: usize) -> Option<(usize, usize)> {
+    while pos < bytes.len() && bytes[pos] == b' ' {
+        pos += 1;
+    }
+    if pos == bytes.len() || bytes[pos] == 0 {
+        return None;
+    }
+    let begin = pos;
+    let mut quoted = false;
+    while pos < bytes.len() && bytes[pos] != 0 {
+        match bytes[pos] {
+            // NUL ends command line even if escaped
+            b'^' if pos + 1 < bytes.len() && bytes[pos + 1] != 0 => pos += 1,
+            b'"' => quoted = !quoted,
+            b' ' if !quoted => break,
+            _ => {}
+        }
+        pos += 1;
+    }
+    if quoted {
+        panic!("unclosed quote");
+    }
+    Some((begin, pos))
+}
+
+/// Span of decoded argument in raw argument `begin..end`, which must be one
+const fn decoded_span(bytes: &[u8], begin: usize, end: usize) -> (usize, usize) {
+    let mut quotes = 0;
+    let mut pos = begin;
+    while pos < end {
+        match bytes[pos] {
+            b'^' => panic!("escapes are not supported"),
+            b'"' => quotes += 1,
+            _ => {}
+        }
+        pos += 1;
+    }
+    match quotes {
+        0 => (begin, end),
+        2 if bytes[begin] == b'"' && bytes[end - 1] == b'"' => (begin + 1, end - 1),
+        _ => panic!("quotes must surround the whole argument"),
+    }
+}
+
+/// Number of arguments of `command_line`, see [split_const!](crate::split_const)
+pub const fn const_count(command_line: &str) -> usize {
+    let bytes = command_line.as_bytes();
+    let mut count = 0;
+    let mut pos = 0;
+    while let Some((_, end)) = next_arg(bytes, pos) {
+        count += 1;
+        pos = end;
+    }
+    count
+}
+
+/// First `N` arguments of `command_line`, see [split_const!](crate::split_const)
+pub const fn const_split<const N: usize>(command_line: &'static str) -> [&'static str; N] {
+    let bytes = command_line.as_bytes();
+    let mut args = [""; N];
+    let mut i = 0;
+    let mut pos = 0;
+    while i < N {
+        let Some((begin, end)) = next_arg(bytes, pos) else {
+            panic!("fewer arguments than expected");
+        };
+        pos = end;
+        let (begin, end) = decoded_span(bytes, begin, end);
+        // SAFETY: spans begin and end at ASCII bytes or ends of `command_line`, so they are
+        // on char boundaries
+        args[i] = unsafe {
+            let arg = core::slice::from_raw_parts(bytes.as_ptr().add(begin), end - begin);
+            core::str::from_utf8_unchecked(arg)
+        };
+        i += 1;
+    }
+    args
+}
+
+/// Split `command_line` at compile time into `&'static [&'static str]` like [split] by
+/// EDK2 shell rules, e.g. for default commands. An argument may only be quoted as a whole
+/// and carets are not supported, other command lines fail to compile.
+///
+/// [split]: crate::split
+#[macro_export]
+macro_rules! split_const {
+    ($command_line:expr) => {{
+        const COMMAND_LINE: &str = $command_line;
+        const ARGS: [&str; $crate::const_count(COMMAND_LINE)] =
+            $crate::const_split::<{ $crate::const_count(COMMAND_LINE) }>(COMMAND_LINE);
+        &ARGS
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    const ARGS: &[&str] = split_const!(" reset  \"a b\" \"\" 中文 --\0ignored");
+    const EMPTY: &[&str] = split_const!("   ");
+
+    #[test]
+    fn split_in_const() {
+        assert_eq!(["reset", "a b", "", "中文", "--"], ARGS);
+        assert!(EMPTY.is_empty());
+        assert_eq!(2, super::const_count("a \"^\" b\""));
+    }
+}
@@ -4,39 +4,80 @@
 extern crate alloc;
 #[cfg(feature = "alloc")]
 use alloc::borrow::{Cow, ToOwned};
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 use core::fmt::Display;
 use core::iter::FusedIterator;
 use core::iter::{Enumerate, Peekable};
-use core::ops::{Index, Range, RangeFrom};
+use core::marker::PhantomData;
+use core::ops::Range;
 
+#[cfg(all(feature = "uefi", feature = "alloc"))]
+use uefi::{CStr16, CString16};
 #[cfg(feature = "uefi")]
 use uefi::{Char16, Char8};
 
+mod array;
+mod konst;
+mod policy;
+mod spec;
+#[cfg(feature = "alloc")]
+mod stream;
+mod token;
+#[cfg(feature = "getargs")]
+mod ucs2;
+
+pub use array::{split_into, ArgArray, Overflow};
+pub use konst::{const_count, const_split};
+pub use policy::{Edk2, Edk2TrailingCaret, SplitPolicy, Windows};
+pub use spec::{OptName, OptSpec, Parsed, Parser, PosSpec, Spec, SpecError};
+#[cfg(feature = "alloc")]
+pub use stream::SplitIter;
+pub use token::{Operator, Redirect, Stream, Target, Token, Tokens};
+#[cfg(feature = "getargs")]
+pub use ucs2::Ucs2Arg;
+
 pub mod prelude {
+    #[cfg(feature = "alloc")]
+    pub use super::join as uefi_join;
+    #[cfg(feature = "alloc")]
+    pub use super::join_to as uefi_join_to;
     #[cfg(feature = "alloc")]
     pub use super::split as uefi_split;
+    pub use super::split_into as uefi_split_into;
+    #[cfg(feature = "alloc")]
+    pub use super::split_with as uefi_split_with;
     pub use super::Indexable as UefiSplitIndexable;
     pub use super::Split as UefiSplit;
 }
 
-pub trait Indexable:
-    Index<Range<Self::Idx>, Output = Self::IndexOut>
-    + Index<RangeFrom<Self::Idx>, Output = Self::IndexOut>
-{
+pub trait Indexable {
     type Idx: Eq + Copy;
-    type IndexOut: ?Sized;
+    type IndexOut: Indexable<Idx = Self::Idx, Item = Self::Item> + ?Sized;
     type Item: Eq;
     type AsIter<'a>: Iterator<Item = (Self::Idx, Self::Item)>
     where
         Self: 'a;
 
     fn as_iter(&self) -> Self::AsIter<'_>;
+    /// Index past the last item
+    fn end_idx(&self) -> Self::Idx;
+    /// Item of ASCII character `ch`
+    fn from_ascii(ch: u8) -> Self::Item;
+    /// Items in `range`
+    fn slice(&self, range: Range<Self::Idx>) -> &Self::IndexOut;
+    /// Items from `begin` on
+    fn slice_from(&self, begin: Self::Idx) -> &Self::IndexOut;
 
     const SPACE: Self::Item;
     const CARET: Self::Item;
     const QUOTE: Self::Item;
     const NUL: Self::Item;
+    const TAB: Self::Item;
+    const BACKSLASH: Self::Item;
 }
 
 impl Indexable for str {
@@ -47,11 +88,25 @@ impl Indexable for str {
     fn as_iter(&self) -> Self::AsIter<'_> {
         self.char_indices()
     }
+    fn end_idx(&self) -> usize {
+        self.len()
+    }
+    fn from_ascii(ch: u8) -> char {
+        ch as char
+    }
+    fn slice(&self, range: Range<usize>) -> &str {
+        &self[range]
+    }
+    fn slice_from(&self, begin: usize) -> &str {
+        &self[begin..]
+    }
 
     const SPACE: Self::Item = ' ';
     const CARET: Self::Item = '^';
     const QUOTE: Self::Item = '"';
     const NUL: Self::Item = '\0';
+    const TAB: Self::Item = '\t';
+    const BACKSLASH: Self::Item = '\\';
 }
 
 macro_rules! impl_for_slice {
@@ -60,15 +115,32 @@ macro_rules! impl_for_slice {
             type Idx = usize;
             type IndexOut = [$Item];
             type Item = $Item;
-            type AsIter<'a> = Enumerate<core::iter::Copied<core::slice::Iter<'a, $Item>>> where $Item: 'a;
+            type AsIter<'a>
+                = Enumerate<core::iter::Copied<core::slice::Iter<'a, $Item>>>
+            where
+                $Item: 'a;
             fn as_iter(&self) -> Self::AsIter<'_> {
                 self.iter().copied().enumerate()
             }
+            fn end_idx(&self) -> usize {
+                self.len()
+            }
+            fn from_ascii(ch: u8) -> $Item {
+                $cvt!(ch, $Back)
+            }
+            fn slice(&self, range: Range<usize>) -> &[$Item] {
+                &self[range]
+            }
+            fn slice_from(&self, begin: usize) -> &[$Item] {
+                &self[begin..]
+            }
 
             const SPACE: Self::Item = $cvt!(b' ', $Back);
             const CARET: Self::Item = $cvt!(b'^', $Back);
             const QUOTE: Self::Item = $cvt!(b'"', $Back);
             const NUL: Self::Item = $cvt!(0, $Back);
+            const TAB: Self::Item = $cvt!(b'\t', $Back);
+            const BACKSLASH: Self::Item = $cvt!(b'\\', $Back);
         }
     };
     ($Item:ty, $cvt:ident) => {
@@ -96,27 +168,139 @@ impl_for_slice!(Char8, cvt_transmute, u8);
 #[cfg(feature = "uefi")]
 impl_for_slice!(Char16, cvt_transmute, u16);
 
-pub struct Split<'a, T: Indexable + ?Sized> {
+#[cfg(feature = "uefi")]
+macro_rules! impl_for_cstr {
+    ($CStr:ty, $Item:ty, |$cstr:ident| $as_slice:expr) => {
+        /// Items with the NUL terminator, which ends splitting
+        impl Indexable for $CStr {
+            type Idx = usize;
+            type IndexOut = [$Item];
+            type Item = $Item;
+            type AsIter<'a> = <[$Item] as Indexable>::AsIter<'a>;
+            fn as_iter(&self) -> Self::AsIter<'_> {
+                let $cstr = self;
+                $as_slice.as_iter()
+            }
+            fn end_idx(&self) -> usize {
+                let $cstr = self;
+                $as_slice.len()
+            }
+            fn from_ascii(ch: u8) -> $Item {
+                <[$Item]>::from_ascii(ch)
+            }
+            fn slice(&self, range: Range<usize>) -> &[$Item] {
+                let $cstr = self;
+                &$as_slice[range]
+            }
+            fn slice_from(&self, begin: usize) -> &[$Item] {
+                let $cstr = self;
+                &$as_slice[begin..]
+            }
+
+            const SPACE: Self::Item = <[$Item]>::SPACE;
+            const CARET: Self::Item = <[$Item]>::CARET;
+            const QUOTE: Self::Item = <[$Item]>::QUOTE;
+            const NUL: Self::Item = <[$Item]>::NUL;
+            const TAB: Self::Item = <[$Item]>::TAB;
+            const BACKSLASH: Self::Item = <[$Item]>::BACKSLASH;
+        }
+    };
+}
+
+#[cfg(feature = "uefi")]
+impl_for_cstr!(CStr16, Char16, |cstr| cstr.as_slice_with_nul());
+// SAFETY: Char8 is a transparent wrapper of u8
+#[cfg(feature = "uefi")]
+impl_for_cstr!(CStr8, Char8, |cstr| unsafe {
+    &*(cstr.as_bytes() as *const [u8] as *const [Char8])
+});
+
+/// Iterator of arguments split from command line by policy `P`
+pub struct Split<'a, T: Indexable + ?Sized, P: SplitPolicy = Edk2> {
     command_line: &'a T,
     iter: Peekable<T::AsIter<'a>>,
     fused: bool,
-}
-
-enum Ch<Idx, T> {
-    Found { idx: Idx, ch: T },
-    NotFound(Option<Idx>),
+    /// whether to treat rest of command line after `--` as an argument
+    terminator: bool,
+    /// whether the last argument was the terminator
+    terminated: bool,
+    /// begin of the argument last yielded from back
+    back: Option<T::Idx>,
+    /// separators instead of these of `P`
+    separators: Option<&'a [T::Item]>,
+    /// whether an unquoted `#` ends splitting
+    comments: bool,
+    max_args: usize,
+    max_arg_len: usize,
+    /// number of arguments yielded from front
+    args: usize,
+    policy: PhantomData<P>,
 }
 
 impl<'a, T: Indexable + ?Sized> Split<'a, T> {
+    /// Split `command_line` by EDK2 shell rules
     pub fn new(command_line: &'a T) -> Self {
+        Self::with_policy(command_line)
+    }
+}
+
+impl<'a, T: Indexable + ?Sized, P: SplitPolicy> Split<'a, T, P> {
+    /// Split `command_line` by rules of `P`, e.g. `Split::<_, Windows>::with_policy(cmd)`
+    pub fn with_policy(command_line: &'a T) -> Self {
         let iter = command_line.as_iter().peekable();
         Self {
             command_line,
             iter,
             fused: false,
+            terminator: false,
+            terminated: false,
+            back: None,
+            separators: None,
+            comments: false,
+            max_args: usize::MAX,
+            max_arg_len: usize::MAX,
+            args: 0,
+            policy: PhantomData,
         }
     }
 
+    /// Fail with [SplitError::TooManyArgs] beyond `max_args` arguments, or with
+    /// [SplitError::ArgTooLong] on an argument of more than `max_arg_len` raw items, e.g.
+    /// to bail out on garbage load options instead of looping over them
+    pub fn with_limits(mut self, max_args: usize, max_arg_len: usize) -> Self {
+        self.max_args = max_args;
+        self.max_arg_len = max_arg_len;
+        self
+    }
+
+    /// Ignore rest of command line from an unquoted and unescaped `#`, like comments of
+    /// scripts, e.g. of load options sourced from startup.nsh
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Separate arguments by any of `separators` instead of space, or space and tab of
+    /// Windows rules, e.g. for tab or semicolon separated load options
+    pub fn with_separators(mut self, separators: &'a [T::Item]) -> Self {
+        self.separators = Some(separators);
+        self
+    }
+
+    fn is_separator(separators: Option<&[T::Item]>, ch: &T::Item) -> bool {
+        match separators {
+            Some(separators) => separators.contains(ch),
+            None => P::is_separator::<T>(ch),
+        }
+    }
+
+    /// Yield rest of command line after a `--` argument as a single argument, like
+    /// [Split::remainder], e.g. to pass it as is to another image
+    pub fn with_terminator(mut self) -> Self {
+        self.terminator = true;
+        self
+    }
+
     fn read_ch(&mut self) -> Option<(T::Idx, T::Item)> {
         if self.fused {
             return None;
@@ -133,128 +317,354 @@ impl<'a, T: Indexable + ?Sized> Split<'a, T> {
     }
 
     fn read_space(&mut self) {
-        loop {
-            let Some(item) = self.iter.peek() else {
-                break;
-            };
-            if item.1 != T::SPACE {
-                break;
-            }
-            self.read_ch().unwrap();
-        }
+        let separators = self.separators;
+        while self
+            .iter
+            .next_if(|(_, ch)| Self::is_separator(separators, ch))
+            .is_some()
+        {}
     }
 
-    fn find_next_ch(&mut self, pat: &[T::Item]) -> Ch<T::Idx, T::Item> {
-        loop {
+    /// Index past the argument from `begin`, of a separator or NUL, or `None` at end of
+    /// command line
+    fn find_end_of_arg(&mut self, begin: T::Idx) -> Result<Option<T::Idx>, SplitError<T::Idx>> {
+        let mut state = P::Scan::<T::Idx>::default();
+        let mut len = 0;
+        let end = loop {
             let Some((idx, ch)) = self.read_ch() else {
-                break;
+                break None;
             };
-            if ch == T::CARET && self.read_ch().is_none() {
-                break;
+            // NUL in quotes of EDK2 rules leaves them unclosed as EDK2 shell does
+            if ch == T::NUL && P::unclosed_quote(&state).is_none() {
+                break Some(idx);
+            }
+            if P::scan::<T>(&mut state, idx, &ch) {
+                if self.comments && ch == T::from_ascii(b'#') {
+                    self.fused = true;
+                    break Some(idx);
+                }
+                if Self::is_separator(self.separators, &ch) {
+                    return Ok(Some(idx));
+                }
             }
-            if pat.iter().any(|pat| *pat == ch) {
-                return Ch::Found { idx, ch };
+            len += 1;
+            if len > self.max_arg_len {
+                return Err(SplitError::ArgTooLong(begin));
             }
+        };
+        match P::unclosed_quote(&state) {
+            Some(quote) => Err(SplitError::UnclosedQuote(quote)),
+            None => Ok(end),
         }
-        let end_idx = self.iter.peek().map(|(idx, _)| *idx);
-        Ch::NotFound(end_idx)
     }
 
-    /// See <https://github.com/tianocore/edk2/blob/7f1a8cad9945674f068ff5e98a533280a7f0efb1/ShellPkg/Application/Shell/ShellParametersProtocol.c#L23-L57>
-    fn find_end_of_arg(&mut self) -> Result<Option<T::Idx>, ()> {
-        loop {
-            let ch = self.find_next_ch(&[T::SPACE, T::QUOTE, T::NUL]);
-            // ends only if reaches whitespace or end
-            let (idx, ch) = match ch {
-                Ch::Found { idx, ch } => (idx, ch),
-                Ch::NotFound(end) => return Ok(end),
-            };
-            if ch != T::QUOTE {
-                return Ok(Some(idx));
-            }
-            if let Ch::Found { .. } = self.find_next_ch(&[T::QUOTE]) {
-                continue;
-            }
-            return Err(());
+    /// Span of next argument within limits of [Split::with_limits]
+    fn next_span(&mut self) -> Result<Option<Range<T::Idx>>, SplitError<T::Idx>> {
+        let Some(span) = self.scan_span()? else {
+            return Ok(None);
+        };
+        if self.args == self.max_args {
+            self.fused = true;
+            self.terminated = false;
+            return Err(SplitError::TooManyArgs);
         }
+        self.args += 1;
+        Ok(Some(span))
     }
-}
 
-impl<'a, T: Indexable + ?Sized> Iterator for Split<'a, T> {
-    type Item = Arg<'a, T::IndexOut>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Span of next argument regardless of the argument count limit
+    fn scan_span(&mut self) -> Result<Option<Range<T::Idx>>, SplitError<T::Idx>> {
+        if self.terminated {
+            return Ok(self.rest_span());
+        }
         if self.fused {
-            return None;
+            return Ok(None);
         }
         self.read_space();
-        let &(begin, _) = self.iter.peek()?;
+        let Some(&(begin, _)) = self.iter.peek() else {
+            return Ok(None);
+        };
+        if self.back == Some(begin) {
+            self.fused = true;
+            return Ok(None);
+        }
 
-        let end = match self.find_end_of_arg() {
-            Err(()) => {
+        let end = match self.find_end_of_arg(begin) {
+            Err(e) => {
                 self.fused = true;
-                return None;
+                return Err(e);
             }
             Ok(v) => v,
         };
 
-        let raw_arg = if let Some(end) = end {
-            if begin == end {
-                self.fused = true;
-                return None;
+        let end = end.unwrap_or_else(|| self.command_line.end_idx());
+        if begin == end {
+            self.fused = true;
+            return Ok(None);
+        }
+        self.terminated = self.terminator
+            && eq_ascii::<T, _>(
+                self.command_line.slice(begin..end).as_iter().map(|i| i.1),
+                b"--",
+                false,
+            );
+        Ok(Some(begin..end))
+    }
+
+    /// Span of unconsumed tail of command line
+    fn rest_span(&mut self) -> Option<Range<T::Idx>> {
+        self.terminated = false;
+        if self.fused {
+            return None;
+        }
+        self.read_space();
+        let &(begin, _) = self.iter.peek()?;
+        if self.back == Some(begin) {
+            self.fused = true;
+            return None;
+        }
+        let end = loop {
+            match self.read_ch() {
+                None => break self.command_line.end_idx(),
+                Some((idx, ch)) if ch == T::NUL => break idx,
+                Some(_) => {}
             }
-            &self.command_line[begin..end]
-        } else {
-            &self.command_line[begin..]
         };
+        self.fused = true;
+        (begin != end).then_some(begin..end)
+    }
+
+    fn arg(&self, span: Range<T::Idx>) -> SplitArg<'a, T, P> {
+        Arg {
+            raw_arg: self.command_line.slice(span.clone()),
+            span,
+            policy: PhantomData,
+        }
+    }
+
+    /// Like [Iterator::next] but fails on unclosed quote or exceeded limits instead of
+    /// ending silently, then ends
+    pub fn try_next(&mut self) -> Result<Option<SplitArg<'a, T, P>>, SplitError<T::Idx>> {
+        Ok(self.next_span()?.map(|span| self.arg(span)))
+    }
+
+    /// Unconsumed tail of command line as is, without leading separators and ending
+    /// before NUL. Splitting ends after it.
+    pub fn remainder(&mut self) -> Option<&'a T::IndexOut> {
+        self.rest_span().map(|span| self.command_line.slice(span))
+    }
 
-        Some(Arg { raw_arg })
+    /// Number of arguments left as [Iterator::next] yields, scanning without decoding
+    /// them, e.g. to reserve space or reject long command lines before allocating
+    pub fn count_args(mut self) -> usize {
+        let mut count = 0;
+        while let Ok(Some(_)) = self.next_span() {
+            count += 1;
+        }
+        count
     }
 }
-impl<T: Indexable + ?Sized> FusedIterator for Split<'_, T> {}
 
-pub struct ArgIter<'a, T: 'a + Indexable + ?Sized> {
-    raw_arg_iter: T::AsIter<'a>,
+/// Whether `items` are these of ASCII string `s`, non-ASCII `s` never equals
+fn eq_ascii<T, I>(mut items: I, s: &[u8], ignore_case: bool) -> bool
+where
+    T: Indexable + ?Sized,
+    I: Iterator<Item = T::Item>,
+{
+    let eq = |item: &T::Item, ch: u8| {
+        *item == T::from_ascii(ch)
+            || ignore_case
+                && (*item == T::from_ascii(ch.to_ascii_lowercase())
+                    || *item == T::from_ascii(ch.to_ascii_uppercase()))
+    };
+    s.iter()
+        .all(|&ch| ch.is_ascii() && items.next().is_some_and(|item| eq(&item, ch)))
+        && items.next().is_none()
 }
 
-impl<T: Indexable + ?Sized> Iterator for ArgIter<'_, T> {
-    type Item = T::Item;
+/// Error of splitting, after which splitting ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitError<Idx> {
+    /// Quote at the index in command line never closed
+    UnclosedQuote(Idx),
+    /// More arguments than allowed by [Split::with_limits]
+    TooManyArgs,
+    /// Argument at the index in command line longer than allowed by [Split::with_limits]
+    ArgTooLong(Idx),
+}
 
+impl<Idx: Display> Display for SplitError<Idx> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnclosedQuote(idx) => write!(f, "unclosed quote at {}", idx),
+            Self::TooManyArgs => f.write_str("too many arguments"),
+            Self::ArgTooLong(idx) => write!(f, "argument at {} too long", idx),
+        }
+    }
+}
+
+impl<'a, T: Indexable + ?Sized, P: SplitPolicy> Iterator for Split<'a, T, P> {
+    type Item = SplitArg<'a, T, P>;
+
+    /// Arguments before an error like unclosed quote, see [Split::try_next]
     fn next(&mut self) -> Option<Self::Item> {
-        let iter = self.raw_arg_iter.by_ref();
-        loop {
-            let (_, ch) = iter.next()?;
-            if ch == T::QUOTE {
-                continue;
-            }
-            if ch == T::CARET {
-                return Some(iter.next()?.1);
-            }
-            return Some(ch);
+        self.try_next().ok().flatten()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.fused && !self.terminated {
+            return (0, Some(0));
         }
+        // arguments are separated by at least an item
+        let (_, upper) = self.iter.size_hint();
+        (0, upper.map(|n| (n + 1) / 2))
+    }
+
+    fn count(self) -> usize {
+        self.count_args()
+    }
+
+    /// Skips arguments by their spans only, also used by [Iterator::skip]
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            self.next_span().ok()??;
+        }
+        self.next()
+    }
+}
+
+/// Arguments from back, each found by scanning forward from front as quotes and carets
+/// can only be told apart this way
+impl<T: Indexable + ?Sized, P: SplitPolicy> DoubleEndedIterator for Split<'_, T, P>
+where
+    for<'b> T::AsIter<'b>: Clone,
+    T::Item: Clone,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let mut scan = Split {
+            command_line: self.command_line,
+            iter: self.iter.clone(),
+            fused: self.fused,
+            terminator: self.terminator,
+            terminated: self.terminated,
+            back: self.back,
+            separators: self.separators,
+            comments: self.comments,
+            max_args: self.max_args,
+            max_arg_len: self.max_arg_len,
+            args: self.args,
+            policy: PhantomData::<P>,
+        };
+        let mut last = None;
+        while let Ok(Some(span)) = scan.next_span() {
+            last = Some(span);
+        }
+        let span = last?;
+        self.back = Some(span.start);
+        Some(self.arg(span))
     }
 }
-impl<'a, T: Indexable + ?Sized> FusedIterator for ArgIter<'a, T> where T::AsIter<'a>: FusedIterator {}
+impl<T: Indexable + ?Sized, P: SplitPolicy> FusedIterator for Split<'_, T, P> {}
+
+pub struct ArgIter<'a, T: 'a + Indexable + ?Sized, P: SplitPolicy = Edk2> {
+    raw_arg_iter: Peekable<T::AsIter<'a>>,
+    state: P::Decode,
+}
+
+impl<T: Indexable + ?Sized, P: SplitPolicy> Iterator for ArgIter<'_, T, P> {
+    type Item = T::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        P::decode_next::<T>(&mut self.state, &mut self.raw_arg_iter)
+    }
+}
+impl<'a, T: Indexable + ?Sized, P: SplitPolicy> FusedIterator for ArgIter<'a, T, P> where
+    T::AsIter<'a>: FusedIterator
+{
+}
+
+/// Argument split from command line of `T` by policy `P`
+pub type SplitArg<'a, T, P = Edk2> = Arg<'a, <T as Indexable>::IndexOut, <T as Indexable>::Idx, P>;
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Arg<'a, T: ?Sized> {
+/// Argument split from command line, equal to another if their raw texts are
+#[derive(Debug)]
+pub struct Arg<'a, T: ?Sized, Idx = usize, P = Edk2> {
     raw_arg: &'a T,
+    span: Range<Idx>,
+    policy: PhantomData<P>,
 }
-impl<T: ?Sized> Arg<'_, T> {
+impl<T: ?Sized, Idx: Clone, P> Arg<'_, T, Idx, P> {
     pub fn raw(&self) -> &T {
         self.raw_arg
     }
+
+    /// Range of raw text in command line, e.g. for pointing at it in error messages
+    pub fn span(&self) -> Range<Idx> {
+        self.span.clone()
+    }
+}
+
+impl<T: PartialEq + ?Sized, Idx, P> PartialEq for Arg<'_, T, Idx, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw_arg == other.raw_arg
+    }
 }
+impl<T: Eq + ?Sized, Idx, P> Eq for Arg<'_, T, Idx, P> {}
 
-impl<T: Indexable + ?Sized> Arg<'_, T> {
-    pub fn iter(&self) -> ArgIter<'_, T> {
+impl<T: Indexable + ?Sized, Idx, P: SplitPolicy> Arg<'_, T, Idx, P> {
+    pub fn iter(&self) -> ArgIter<'_, T, P> {
         ArgIter {
-            raw_arg_iter: self.raw_arg.as_iter(),
+            raw_arg_iter: self.raw_arg.as_iter().peekable(),
+            state: Default::default(),
         }
     }
+
+    /// Whether any part of argument is quoted, e.g. `"foo"` or `a" "b` but not `foo`
+    pub fn was_quoted(&self) -> bool {
+        P::quoting(self.raw_arg).0
+    }
+
+    /// Whether argument has escaped items, e.g. `^"` of EDK2 or `\"` of Windows rules
+    pub fn had_escapes(&self) -> bool {
+        P::quoting(self.raw_arg).1
+    }
+
+    /// Whether argument has `*` or `?` neither quoted nor escaped, to be expanded as a
+    /// file pattern like UEFI shell does
+    pub fn contains_wildcards(&self) -> bool {
+        let mut state = P::Scan::<T::Idx>::default();
+        self.raw_arg.as_iter().any(|(idx, ch)| {
+            P::scan::<T>(&mut state, idx, &ch)
+                && (ch == T::from_ascii(b'*') || ch == T::from_ascii(b'?'))
+        })
+    }
 }
 
-impl<T: Indexable + ?Sized> Display for Arg<'_, T>
+macro_rules! impl_ascii_eq {
+    ($Item:ty) => {
+        /// Compare decoded argument with ASCII string, e.g. to match subcommands
+        impl<Idx, P: SplitPolicy> Arg<'_, [$Item], Idx, P> {
+            pub fn eq_ignore_ascii_case(&self, s: &str) -> bool {
+                eq_ascii::<[$Item], _>(self.iter(), s.as_bytes(), true)
+            }
+        }
+
+        impl<Idx, P: SplitPolicy> PartialEq<&str> for Arg<'_, [$Item], Idx, P> {
+            fn eq(&self, s: &&str) -> bool {
+                eq_ascii::<[$Item], _>(self.iter(), s.as_bytes(), false)
+            }
+        }
+    };
+}
+
+impl_ascii_eq!(u8);
+impl_ascii_eq!(u16);
+#[cfg(feature = "uefi")]
+impl_ascii_eq!(Char8);
+#[cfg(feature = "uefi")]
+impl_ascii_eq!(Char16);
+
+impl<T: Indexable + ?Sized, Idx, P: SplitPolicy> Display for Arg<'_, T, Idx, P>
 where
     T::Item: Display,
 {
@@ -267,7 +677,7 @@ where
 }
 
 #[cfg(feature = "alloc")]
-impl<T: ToOwned + Indexable<IndexOut = T> + ?Sized> Arg<'_, T>
+impl<T: ToOwned + Indexable<IndexOut = T> + ?Sized, Idx, P: SplitPolicy> Arg<'_, T, Idx, P>
 where
     T::Owned: FromIterator<T::Item>,
 {
@@ -276,59 +686,51 @@ where
         self.iter().collect()
     }
 
+    /// Decoded argument, borrowed until the first quote or escape that has to be
+    /// removed, from where the rest is decoded into a copy in the same pass
     pub fn decode(&self) -> Cow<T> {
-        let mut first_idx = None;
-        let mut first_quote_and_after = None;
-        let mut last_quote = None;
-        let mut to_owned = false;
-        for (idx, ch) in self.raw_arg.as_iter() {
-            if let Some(quote_idx) = last_quote {
-                // invariant: quote_idx < idx
-                if let Some((first_quote, _)) = first_quote_and_after {
-                    // last quote is neither first char or last char
-                    if quote_idx != first_quote {
-                        to_owned = true;
-                        break;
-                    }
-                // quote is the first quote but not the first char
-                } else if quote_idx != first_idx.unwrap() {
-                    to_owned = true;
-                    break;
-                } else {
-                    // invariant: quote_idx + 1 == idx
-                    first_quote_and_after = Some((quote_idx, idx));
-                }
-            }
-            if first_idx.is_none() {
-                first_idx = Some(idx);
-            }
-            if ch == T::QUOTE {
-                last_quote = Some(idx);
-            }
-            if ch == T::CARET {
-                to_owned = true;
-                break;
-            }
+        let (prefix, rest) = P::decode_borrowed(self.raw_arg);
+        let Some(rest) = rest else {
+            return Cow::Borrowed(prefix);
+        };
+        let rest: ArgIter<T, P> = ArgIter {
+            raw_arg_iter: self.raw_arg.slice_from(rest).as_iter().peekable(),
+            state: Default::default(),
+        };
+        Cow::Owned(prefix.as_iter().map(|(_, ch)| ch).chain(rest).collect())
+    }
+}
+
+impl<'a, Idx, P: SplitPolicy> Arg<'a, [u8], Idx, P> {
+    /// Decoded argument as UTF-8 if decoding it copies nothing and it's valid UTF-8
+    pub fn as_str(&self) -> Option<&'a str> {
+        match P::decode_borrowed(self.raw_arg) {
+            (arg, None) => core::str::from_utf8(arg).ok(),
+            _ => None,
         }
-        if to_owned {
-            return Cow::<T>::Owned(self.iter().collect());
+    }
+
+    /// Decoded argument as UTF-8, with invalid sequences replaced by U+FFFD
+    #[cfg(feature = "alloc")]
+    pub fn to_str_lossy(&self) -> Cow<str> {
+        match self.decode() {
+            Cow::Borrowed(arg) => String::from_utf8_lossy(arg),
+            Cow::Owned(arg) => match String::from_utf8(arg) {
+                Ok(arg) => Cow::Owned(arg),
+                Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+            },
         }
-        let raw_arg = if let Some(last_quote) = last_quote {
-            if let Some((first_quote, begin)) = first_quote_and_after {
-                if first_quote == last_quote {
-                    // no ending quote
-                    &self.raw_arg[begin..]
-                } else {
-                    &self.raw_arg[begin..last_quote]
-                }
-            } else {
-                // no starting quote
-                &self.raw_arg[first_idx.unwrap()..last_quote]
-            }
-        } else {
-            self.raw_arg
-        };
-        Cow::Borrowed(raw_arg)
+    }
+}
+
+#[cfg(all(feature = "uefi", feature = "alloc"))]
+impl<Idx, P: SplitPolicy> Arg<'_, [Char16], Idx, P> {
+    /// Decoded argument with NUL appended, to be passed to UEFI APIs
+    pub fn to_cstring16(&self) -> CString16 {
+        let mut chars: Vec<Char16> = self.iter().collect();
+        chars.push(<[Char16]>::NUL);
+        // split arguments never contain NUL
+        CStr16::from_char16_with_nul(&chars).unwrap().into()
     }
 }
 
@@ -339,17 +741,193 @@ where
     T::Owned: FromIterator<T::Item>,
     B: FromIterator<T::Owned>,
 {
-    Split::new(command_line)
+    split_with::<Edk2, _, _>(command_line)
+}
+
+/// Like [split] but by rules of `P`, e.g. `split_with::<Windows, str, Vec<_>>(cmd)`
+#[cfg(feature = "alloc")]
+pub fn split_with<P, T, B>(command_line: &T) -> B
+where
+    P: SplitPolicy,
+    T: ToOwned + Indexable<IndexOut = T> + ?Sized,
+    T::Owned: FromIterator<T::Item>,
+    B: FromIterator<T::Owned>,
+{
+    Split::<T, P>::with_policy(command_line)
         .map(|arg| arg.iter().collect())
         .collect()
 }
 
+/// Push items of `arg` to `out`, quoted if needed so that it's split back into itself,
+/// quotes and carets are escaped with caret. NUL can not be represented.
+#[cfg(feature = "alloc")]
+fn quote_items<T: Indexable + ?Sized>(arg: &T, out: &mut Vec<T::Item>) {
+    let mut is_empty = true;
+    let mut needs_quote = false;
+    for (_, ch) in arg.as_iter() {
+        is_empty = false;
+        if ch == T::SPACE {
+            needs_quote = true;
+            break;
+        }
+    }
+    let needs_quote = needs_quote || is_empty;
+    if needs_quote {
+        out.push(T::QUOTE);
+    }
+    for (_, ch) in arg.as_iter() {
+        if ch == T::QUOTE || ch == T::CARET {
+            out.push(T::CARET);
+        }
+        out.push(ch);
+    }
+    if needs_quote {
+        out.push(T::QUOTE);
+    }
+}
+
+/// Quote `arg` if needed so that it's split back into itself,
+/// quotes and carets are escaped with caret. NUL can not be represented.
+#[cfg(feature = "alloc")]
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    let needs_escape = |ch| ch == '"' || ch == '^';
+    let needs_quote = arg.is_empty() || arg.contains(' ');
+    if !needs_quote && !arg.contains(needs_escape) {
+        return Cow::Borrowed(arg);
+    }
+    Cow::Owned(quote_to(arg))
+}
+
+/// Like [quote] but for any [Indexable], e.g. UCS-2 `[u16]`, always copying `arg`
+#[cfg(feature = "alloc")]
+pub fn quote_to<T>(arg: &T) -> T::Owned
+where
+    T: ToOwned + Indexable + ?Sized,
+    T::Owned: FromIterator<T::Item>,
+{
+    let mut items = Vec::new();
+    quote_items(arg, &mut items);
+    items.into_iter().collect()
+}
+
+/// Join `args` into a command line, the reverse of [split]
+#[cfg(feature = "alloc")]
+pub fn join<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    join_to::<str, _, _>(args)
+}
+
+/// Like [join] but for any [Indexable], e.g. to construct UCS-2 load options of an image
+/// from `[u16]` or `[Char16]` arguments. Append NUL for a C string.
+#[cfg(feature = "alloc")]
+pub fn join_to<T, I, S>(args: I) -> T::Owned
+where
+    T: ToOwned + Indexable + ?Sized,
+    T::Owned: FromIterator<T::Item>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<T>,
+{
+    let mut items = Vec::new();
+    for (i, arg) in args.into_iter().enumerate() {
+        if i > 0 {
+            items.push(T::SPACE);
+        }
+        quote_items(arg.as_ref(), &mut items);
+    }
+    items.into_iter().collect()
+}
+
+/// Builder of NUL-terminated UCS-2 load options of an image, from arguments quoted as
+/// [join] does
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptionsBuilder {
+    items: Vec<u16>,
+    /// whether any argument has NUL or characters not in UCS-2
+    invalid: bool,
+}
+
+#[cfg(feature = "alloc")]
+impl LoadOptionsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push_ucs2(&mut self, text: &str, quote: bool) {
+        let units: Option<Vec<u16>> = text
+            .chars()
+            .map(|ch| u16::try_from(u32::from(ch)).ok().filter(|&unit| unit != 0))
+            .collect();
+        let Some(units) = units else {
+            self.invalid = true;
+            return;
+        };
+        if !self.items.is_empty() {
+            self.items.push(<[u16]>::SPACE);
+        }
+        if quote {
+            quote_items(units.as_slice(), &mut self.items);
+        } else {
+            self.items.extend(units);
+        }
+    }
+
+    /// Append `arg`, quoted if needed
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.push_ucs2(arg, true);
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for arg in args {
+            self.push_ucs2(arg.as_ref(), true);
+        }
+        self
+    }
+
+    /// Append `raw` as is, e.g. arguments already quoted in a config
+    pub fn raw(mut self, raw: &str) -> Self {
+        if !raw.is_empty() {
+            self.push_ucs2(raw, false);
+        }
+        self
+    }
+
+    /// Load options with NUL, `None` if any argument is not representable. Its size in
+    /// bytes is what `LoadedImage::set_load_options` takes.
+    pub fn build(mut self) -> Option<Vec<u16>> {
+        if self.invalid {
+            return None;
+        }
+        self.items.push(0);
+        Some(self.items)
+    }
+
+    #[cfg(feature = "uefi")]
+    pub fn build_cstring16(self) -> Option<CString16> {
+        let items = self.build()?;
+        CStr16::from_u16_with_nul(&items).ok().map(CString16::from)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Argument to compare with, span is ignored
     fn arg<T: Indexable + ?Sized>(raw_arg: &T) -> Arg<'_, T> {
-        Arg { raw_arg }
+        Arg {
+            raw_arg,
+            span: 0..0,
+            policy: PhantomData,
+        }
     }
 
     #[test]
@@ -383,6 +961,289 @@ mod tests {
         assert_eq!(None, s.next());
     }
 
+    #[test]
+    fn split_unclosed() {
+        let mut s = Split::new("a \"b c\"d ef\"g h");
+        assert_eq!(Ok(Some(arg("a"))), s.try_next());
+        assert_eq!(Ok(Some(arg("\"b c\"d"))), s.try_next());
+        let e = s.try_next().unwrap_err();
+        assert_eq!(SplitError::UnclosedQuote(11), e);
+        assert_eq!("unclosed quote at 11", format!("{}", e));
+        assert_eq!(Ok(None), s.try_next());
+        assert_eq!(None, s.next());
+
+        let mut s = Split::new("a \"b\0c\" d");
+        assert_eq!(Ok(Some(arg("a"))), s.try_next());
+        assert_eq!(Err(SplitError::UnclosedQuote(2)), s.try_next());
+
+        let mut s = Split::new(b"x ^\"\0\"".as_slice());
+        assert_eq!(Ok(Some(arg(b"x".as_slice()))), s.try_next());
+        assert_eq!(Ok(Some(arg(b"^\"".as_slice()))), s.try_next());
+        assert_eq!(Ok(None), s.try_next());
+    }
+
+    #[test]
+    fn split_windows() {
+        let split = |command_line: &'static str| {
+            Split::<str, Windows>::with_policy(command_line)
+                .map(|a| format!("{}", a))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(["a", "b c", "d"], split(" a\t\"b c\"  d ").as_slice());
+        assert_eq!(["a\"b", "c"], split("a\\\"b c").as_slice());
+        assert_eq!(["a\\b c"], split("a\\\\\"b c\"").as_slice());
+        assert_eq!(
+            ["C:\\dir\\", "x\\y"],
+            split("C:\\dir\\ \"x\\y\"").as_slice()
+        );
+        assert_eq!(["a\\\\b"], split("a\\\\b").as_slice());
+        assert_eq!(["un closed"], split("\"un closed").as_slice());
+        assert_eq!(["a^b"], split("a^b\0c").as_slice());
+        assert_eq!(["a\"b", "c"], split("\"a\"\"b\" c").as_slice());
+        assert_eq!(["a\"b c"], split("\"a\"\"b c\"").as_slice());
+        assert_eq!(["ab", "c"], split("\"a\"b\"\" c").as_slice());
+        assert_eq!(["a"], split("\"a\0b\" c").as_slice());
+
+        let mut s = Split::<_, Windows>::with_policy("\"a b");
+        assert_eq!(0..4, s.try_next().unwrap().unwrap().span());
+        assert_eq!(Ok(None), s.try_next());
+    }
+
+    #[test]
+    fn split_remainder() {
+        let mut s = Split::new("chain  img.efi  -a \"b c\"  \0x");
+        assert_eq!(Some(arg("chain")), s.next());
+        assert_eq!(Some("img.efi  -a \"b c\"  "), s.remainder());
+        assert_eq!(None, s.remainder());
+        assert_eq!(None, s.next());
+
+        let mut s = Split::new("a \"--\" b -- c  \"d -- e").with_terminator();
+        assert_eq!(Some(arg("a")), s.next());
+        assert_eq!(Some(arg("\"--\"")), s.next());
+        assert_eq!(Some(arg("b")), s.next());
+        assert_eq!(Some(arg("--")), s.next());
+        let rest = s.try_next().unwrap().unwrap();
+        assert_eq!(("c  \"d -- e", 12..22), (rest.raw(), rest.span()));
+        assert_eq!(None, s.next());
+
+        let mut s = Split::new(b"x --".as_slice()).with_terminator();
+        assert_eq!(Some(arg(b"x".as_slice())), s.next());
+        assert_eq!(Some(arg(b"--".as_slice())), s.next());
+        assert_eq!(None, s.next());
+    }
+
+    #[test]
+    fn count_args() {
+        let command_line = "a \"b c\" d^ e -- f g\0h";
+        assert_eq!(6, Split::new(command_line).count());
+        assert_eq!(5, Split::new(command_line).with_terminator().count_args());
+        assert_eq!(2, Split::new("a b \"c").count_args());
+        assert_eq!(0, Split::new("  ").count_args());
+
+        let mut s = Split::new("ab c");
+        assert_eq!((0, Some(2)), s.size_hint());
+        s.next();
+        s.next();
+        assert_eq!((0, Some(0)), s.size_hint());
+    }
+
+    #[test]
+    fn split_limits() {
+        let mut s = Split::new("a bc \"d e\"").with_limits(2, 5);
+        assert_eq!(Some(arg("a")), s.next());
+        assert_eq!(Some(arg("bc")), s.next());
+        assert_eq!(Err(SplitError::TooManyArgs), s.try_next());
+        assert_eq!(None, s.next());
+
+        let mut s = Split::new("abc \"d e\" f").with_limits(3, 4);
+        assert_eq!(Some(arg("abc")), s.next());
+        assert_eq!(Err(SplitError::ArgTooLong(4)), s.try_next());
+        assert_eq!(3, Split::new("a b c").with_limits(3, 1).count());
+        assert_eq!(1, Split::new("a -- b c").with_limits(2, 1).count());
+    }
+
+    #[test]
+    fn trailing_caret() {
+        let command_line = "a^ b c^\0d";
+        let edk2: Vec<_> = Split::new(command_line).map(|a| a.to_string()).collect();
+        assert_eq!(["a b", "c"], edk2.as_slice());
+        let kept: Vec<_> = Split::<_, Edk2TrailingCaret>::with_policy(command_line)
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(["a b", "c^"], kept.as_slice());
+
+        let a = Split::<_, Edk2TrailingCaret>::with_policy("c^")
+            .next()
+            .unwrap();
+        assert!(!a.had_escapes());
+        assert!(Split::new("c^").next().unwrap().had_escapes());
+        #[cfg(feature = "alloc")]
+        {
+            assert_eq!(Cow::<str>::Borrowed("c^"), a.decode());
+            let a = Split::<_, Edk2TrailingCaret>::with_policy("\"c\"^")
+                .next()
+                .unwrap();
+            assert_eq!("c^", a.decode());
+        }
+    }
+
+    #[test]
+    fn utf8_args() {
+        let command_line = b"\"a b\" c^\"\xe4\xb8\xad \xff^^";
+        let args: Vec<_> = Split::new(command_line.as_slice()).collect();
+        assert_eq!(Some("a b"), args[0].as_str());
+        assert_eq!(None, args[1].as_str());
+        assert_eq!(None, args[2].as_str());
+        #[cfg(feature = "alloc")]
+        {
+            assert_eq!(Cow::<str>::Borrowed("a b"), args[0].to_str_lossy());
+            assert_eq!("c\"中", args[1].to_str_lossy());
+            assert_eq!("\u{fffd}^", args[2].to_str_lossy());
+        }
+    }
+
+    #[test]
+    fn split_nth() {
+        let command_line = "a \"b c\" d^ e -- f";
+        let mut s = Split::new(command_line).with_terminator();
+        assert_eq!(Some(arg("d^ e")), s.nth(2));
+        assert_eq!(Some(arg("f")), s.nth(1));
+        assert_eq!(None, s.next());
+
+        let last: Vec<_> = Split::new(command_line).skip(4).collect();
+        assert_eq!([arg("f")], last.as_slice());
+        assert_eq!(None, Split::new("a b \"c").nth(2));
+        assert_eq!(None, Split::new("a \"b c").nth(1));
+    }
+
+    #[test]
+    fn split_back() {
+        let mut s = Split::new("lopatch -s \"a b\" ^\"x y^\" archlinux.iso");
+        assert_eq!(Some(arg("archlinux.iso")), s.next_back());
+        assert_eq!(Some(arg("lopatch")), s.next());
+        assert_eq!(Some(arg("y^\"")), s.next_back());
+        assert_eq!(Some(arg("-s")), s.next());
+        assert_eq!(Some(arg("\"a b\"")), s.next());
+        assert_eq!(Some(arg("^\"x")), s.next_back());
+        assert_eq!(None, s.next());
+        assert_eq!(None, s.next_back());
+
+        let args: Vec<_> = Split::new(b"a \"b\0c".as_slice()).rev().collect();
+        assert_eq!(vec![arg(b"a".as_slice())], args);
+        let mut s = Split::new("a -- b c").with_terminator();
+        assert_eq!(Some(arg("b c")), s.next_back());
+        assert_eq!(Some(arg("--")), s.next_back());
+        assert_eq!(Some(arg("a")), s.next());
+        assert_eq!(None, s.next());
+    }
+
+    #[test]
+    fn arg_eq_ascii() {
+        let utf16 = |s: &str| s.encode_utf16().collect::<Vec<u16>>();
+        let command_line = utf16("Attach \"a b\" ^\"q^\" 中");
+        let args: Vec<_> = Split::new(command_line.as_slice()).collect();
+        assert!(args[0] == "Attach");
+        assert!(args[0] != "attach");
+        assert!(args[0].eq_ignore_ascii_case("aTTACH"));
+        assert!(!args[0].eq_ignore_ascii_case("attac"));
+        assert!(args[1] == "a b");
+        assert!(args[2] == "\"q\"");
+        assert!(args[3] != "中");
+
+        let arg = Split::new(b"--Help".as_slice()).next().unwrap();
+        assert!(arg == "--Help");
+        assert!(arg.eq_ignore_ascii_case("--HELP"));
+        assert!(!arg.eq_ignore_ascii_case("--HELP!"));
+    }
+
+    #[test]
+    fn split_separators() {
+        let mut s = Split::new("a;b c\t\"d;e\";;f^;g").with_separators(&[';', '\t']);
+        assert_eq!(Some(arg("a")), s.next());
+        assert_eq!(Some(arg("b c")), s.next());
+        assert_eq!(Some(arg("\"d;e\"")), s.next());
+        assert_eq!(Some(arg("f^;g")), s.next());
+        assert_eq!(None, s.next());
+
+        let command_line = b"x\ty z".as_slice();
+        let args: Vec<_> = Split::<_, Windows>::with_policy(command_line)
+            .with_separators(b"\t")
+            .collect();
+        assert_eq!(2, args.len());
+        assert_eq!(b"y z", args[1].raw());
+    }
+
+    #[test]
+    fn split_comments() {
+        let command_line = "a \"#b\" c^#d e# f # g";
+        let mut s = Split::new(command_line).with_comments();
+        assert_eq!(Some(arg("a")), s.next());
+        assert_eq!(Some(arg("\"#b\"")), s.next());
+        assert_eq!(Some(arg("c^#d")), s.next());
+        assert_eq!(Some(arg("e")), s.next());
+        assert_eq!(None, s.next());
+        assert_eq!(7, Split::new(command_line).count());
+        assert_eq!(None, Split::new("  # a").with_comments().next());
+    }
+
+    #[test]
+    fn arg_quoting() {
+        let quoting = |a: SplitArg<'_, str>| (a.was_quoted(), a.had_escapes());
+        let args: Vec<_> = Split::new("foo \"foo\" a\" \"b ^\"q ^^\"x\"")
+            .map(quoting)
+            .collect();
+        assert_eq!(
+            vec![
+                (false, false),
+                (true, false),
+                (true, false),
+                (false, true),
+                (true, true)
+            ],
+            args
+        );
+
+        let args: Vec<_> = Split::<_, Windows>::with_policy(r#"a\b \"q \\"x y" "z" "a""b""#)
+            .map(|a| (a.was_quoted(), a.had_escapes()))
+            .collect();
+        assert_eq!(
+            vec![
+                (false, false),
+                (false, true),
+                (true, true),
+                (true, false),
+                (true, true)
+            ],
+            args
+        );
+    }
+
+    #[test]
+    fn arg_wildcards() {
+        let wildcards: Vec<_> = Split::new("*.efi \"*.efi\" ^*.efi a?b \"a\"? ^?")
+            .map(|a| a.contains_wildcards())
+            .collect();
+        assert_eq!(vec![true, false, false, true, true, false], wildcards);
+
+        let wildcards: Vec<_> = Split::<_, Windows>::with_policy(r#"\* "a*" a\"*"#)
+            .map(|a| a.contains_wildcards())
+            .collect();
+        assert_eq!(vec![true, false, true], wildcards);
+    }
+
+    #[test]
+    fn arg_span() {
+        let command_line = " a \"b c\"  中文 d";
+        let spans: Vec<_> = Split::new(command_line).map(|a| a.span()).collect();
+        assert_eq!(vec![1..2, 3..8, 10..16, 17..18], spans);
+        assert_eq!("\"b c\"", &command_line[spans[1].clone()]);
+
+        let mut s = Split::new(b"ab cd\0ef".as_slice());
+        assert_eq!(0..2, s.next().unwrap().span());
+        assert_eq!(3..5, s.next().unwrap().span());
+        assert_eq!(None, s.next());
+    }
+
     #[test]
     fn split_unicode() {
         let mut s = Split::new("早上好 hi 中国 现在我有冰淇淋");
@@ -438,6 +1299,76 @@ mod tests {
         assert_eq!(Cow::<str>::Borrowed("abc"), arg("\"abc\"").decode());
         assert_eq!(Cow::<str>::Borrowed("abc"), arg("abc\"").decode());
         assert_eq!(Cow::<str>::Borrowed("abc"), arg("\"abc").decode());
+        assert_eq!(
+            Cow::<str>::Owned(String::from("ab c\"d")),
+            arg("\"ab\" c^\"d").decode()
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn windows_decode() {
+        let decode = |raw: &'static str| {
+            let a: Arg<'_, str, usize, Windows> = Arg {
+                raw_arg: raw,
+                span: 0..0,
+                policy: PhantomData,
+            };
+            let decoded = a.decode();
+            (matches!(decoded, Cow::Borrowed(_)), decoded.into_owned())
+        };
+        assert_eq!((true, String::from("C:\\dir\\")), decode("C:\\dir\\"));
+        assert_eq!((false, String::from("a b")), decode("\"a b\""));
+        assert_eq!((false, String::from("\\\"")), decode("\\\\\\\""));
+        assert_eq!((false, String::from("a\\b\\c d")), decode("a\\b\\\\\"c d"));
+        assert_eq!((false, String::from("a\"b")), decode("\"a\"\"b\""));
+
+        let args: Vec<String> = split_with::<Windows, _, _>("x \"y z\" \\\\\"\"");
+        assert_eq!(["x", "y z", "\\"], args.as_slice());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn join_args() {
+        assert_eq!(Cow::<str>::Borrowed("abc"), quote("abc"));
+        assert_eq!("\"a b\"", quote("a b"));
+        assert_eq!("\"\"", quote(""));
+        assert_eq!("a^\"b^^c", quote("a\"b^c"));
+
+        let args = ["prog", "", "a b", "q\"uo^te", "-o"];
+        let command_line = join(args);
+        assert_eq!("prog \"\" \"a b\" q^\"uo^^te -o", command_line);
+        assert_eq!(args.as_slice(), split::<_, Vec<_>>(command_line.as_str()));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn join_slices() {
+        let utf16 = |s: &str| s.encode_utf16().collect::<Vec<u16>>();
+        assert_eq!(utf16("\"a^\" b\""), quote_to(utf16("a\" b").as_slice()));
+
+        let args = [utf16("prog"), utf16(""), utf16("中 文"), utf16("^")];
+        let command_line: Vec<u16> = join_to::<[u16], _, _>(&args);
+        assert_eq!(utf16("prog \"\" \"中 文\" ^^"), command_line);
+        assert_eq!(args.as_slice(), split::<_, Vec<_>>(command_line.as_slice()));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn load_options() {
+        let utf16 = |s: &str| s.encode_utf16().chain([0]).collect::<Vec<u16>>();
+        let options = LoadOptionsBuilder::new()
+            .arg("vmlinuz")
+            .args(["a b", ""])
+            .raw("initrd=\\initrd.img  quiet")
+            .build();
+        assert_eq!(
+            Some(utf16("vmlinuz \"a b\" \"\" initrd=\\initrd.img  quiet")),
+            options
+        );
+        assert_eq!(Some(vec![0]), LoadOptionsBuilder::new().raw("").build());
+        assert_eq!(None, LoadOptionsBuilder::new().arg("😀").build());
+        assert_eq!(None, LoadOptionsBuilder::new().raw("a\0b").build());
     }
 
     #[test]
@@ -474,6 +1405,17 @@ mod tests {
         assert_eq!(None, it.next());
         assert_eq!(None, it.next());
 
+        #[cfg(feature = "alloc")]
+        {
+            let mut it = Split::new(cstr16!("\"a b\" c").as_slice_with_nul());
+            assert_eq!(cstr16!("a b"), &*it.next().unwrap().to_cstring16());
+        }
+
+        let mut it = Split::new(cstr16!("english 中文"));
+        assert_eq!(Some(arg(cstr16!("english").as_slice())), it.next());
+        let spans: Vec<_> = Split::new(cstr8!("a bc")).map(|a| a.span()).collect();
+        assert_eq!(vec![0..1, 2..4], spans);
+
         let cstr = cstr8!("argument option");
         let mut it = Split::new(cstr.as_bytes());
         assert_eq!(Some(arg(b"argument".as_slice())), it.next());
@@ -0,0 +1,346 @@
+//! Rules of splitting command line into arguments and decoding them
+
+use core::iter::Peekable;
+
+use crate::Indexable;
+
+/// Rules of splitting command line, [Edk2] shell rules or [Windows] `CommandLineToArgvW` rules
+pub trait SplitPolicy {
+    /// State of scanning raw text of an argument for its end
+    type Scan<Idx>: Default;
+    /// State of decoding raw text of an argument
+    type Decode: Default;
+
+    /// Whether `ch` separates arguments outside of quotes by default
+    fn is_separator<T: Indexable + ?Sized>(ch: &T::Item) -> bool;
+
+    /// Feed `ch` at `idx` of an argument, returns whether it's neither quoted nor escaped,
+    /// so that the argument ends before it if it's a separator
+    fn scan<T: Indexable + ?Sized>(
+        state: &mut Self::Scan<T::Idx>,
+        idx: T::Idx,
+        ch: &T::Item,
+    ) -> bool;
+
+    /// Index of quote still open at end of command line, which fails the splitting
+    fn unclosed_quote<Idx: Copy>(state: &Self::Scan<Idx>) -> Option<Idx>;
+
+    /// Next item of decoded argument read from its raw text `iter`
+    fn decode_next<T: Indexable + ?Sized>(
+        state: &mut Self::Decode,
+        iter: &mut Peekable<T::AsIter<'_>>,
+    ) -> Option<T::Item>;
+
+    /// Longest prefix of decoded argument that is part of `raw`, and index in `raw` to
+    /// decode the rest from with a fresh state if the argument has to be copied
+    fn decode_borrowed<T: Indexable<IndexOut = T> + ?Sized>(raw: &T) -> (&T, Option<T::Idx>);
+
+    /// Whether raw argument `raw` has quotes and escapes, as `(quoted, escaped)`
+    fn quoting<T: Indexable + ?Sized>(raw: &T) -> (bool, bool);
+}
+
+/// EDK2 shell rules, quotes group spaces and caret escapes the next character
+///
+/// See <https://github.com/tianocore/edk2/blob/7f1a8cad9945674f068ff5e98a533280a7f0efb1/ShellPkg/Application/Shell/ShellParametersProtocol.c#L23-L57>
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Edk2;
+
+pub struct Edk2Scan<Idx> {
+    escaped: bool,
+    quote: Option<Idx>,
+}
+
+impl<Idx> Default for Edk2Scan<Idx> {
+    fn default() -> Self {
+        Self {
+            escaped: false,
+            quote: None,
+        }
+    }
+}
+
+impl SplitPolicy for Edk2 {
+    type Scan<Idx> = Edk2Scan<Idx>;
+    type Decode = ();
+
+    fn is_separator<T: Indexable + ?Sized>(ch: &T::Item) -> bool {
+        *ch == T::SPACE
+    }
+
+    fn scan<T: Indexable + ?Sized>(
+        state: &mut Edk2Scan<T::Idx>,
+        idx: T::Idx,
+        ch: &T::Item,
+    ) -> bool {
+        if state.escaped {
+            state.escaped = false;
+            return false;
+        }
+        if *ch == T::CARET {
+            state.escaped = true;
+        } else if *ch == T::QUOTE {
+            state.quote = match state.quote {
+                Some(_) => None,
+                None => Some(idx),
+            };
+        } else {
+            return state.quote.is_none();
+        }
+        false
+    }
+
+    fn unclosed_quote<Idx: Copy>(state: &Edk2Scan<Idx>) -> Option<Idx> {
+        state.quote
+    }
+
+    fn decode_next<T: Indexable + ?Sized>(
+        _state: &mut (),
+        iter: &mut Peekable<T::AsIter<'_>>,
+    ) -> Option<T::Item> {
+        edk2_decode_next::<T>(iter, false)
+    }
+
+    fn decode_borrowed<T: Indexable<IndexOut = T> + ?Sized>(raw: &T) -> (&T, Option<T::Idx>) {
+        edk2_decode_borrowed(raw, false)
+    }
+
+    fn quoting<T: Indexable + ?Sized>(raw: &T) -> (bool, bool) {
+        edk2_quoting(raw, false)
+    }
+}
+
+/// [Edk2] rules except that a caret at end of command line is kept literally as some
+/// shells do, instead of being dropped
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Edk2TrailingCaret;
+
+impl SplitPolicy for Edk2TrailingCaret {
+    type Scan<Idx> = Edk2Scan<Idx>;
+    type Decode = ();
+
+    fn is_separator<T: Indexable + ?Sized>(ch: &T::Item) -> bool {
+        Edk2::is_separator::<T>(ch)
+    }
+
+    fn scan<T: Indexable + ?Sized>(
+        state: &mut Edk2Scan<T::Idx>,
+        idx: T::Idx,
+        ch: &T::Item,
+    ) -> bool {
+        Edk2::scan::<T>(state, idx, ch)
+    }
+
+    fn unclosed_quote<Idx: Copy>(state: &Edk2Scan<Idx>) -> Option<Idx> {
+        state.quote
+    }
+
+    fn decode_next<T: Indexable + ?Sized>(
+        _state: &mut (),
+        iter: &mut Peekable<T::AsIter<'_>>,
+    ) -> Option<T::Item> {
+        edk2_decode_next::<T>(iter, true)
+    }
+
+    fn decode_borrowed<T: Indexable<IndexOut = T> + ?Sized>(raw: &T) -> (&T, Option<T::Idx>) {
+        edk2_decode_borrowed(raw, true)
+    }
+
+    fn quoting<T: Indexable + ?Sized>(raw: &T) -> (bool, bool) {
+        edk2_quoting(raw, true)
+    }
+}
+
+fn edk2_decode_next<T: Indexable + ?Sized>(
+    iter: &mut Peekable<T::AsIter<'_>>,
+    keep_trailing_caret: bool,
+) -> Option<T::Item> {
+    loop {
+        let (_, ch) = iter.next()?;
+        if ch == T::QUOTE {
+            continue;
+        }
+        if ch == T::CARET {
+            return match iter.next() {
+                Some((_, ch)) => Some(ch),
+                None => keep_trailing_caret.then_some(ch),
+            };
+        }
+        return Some(ch);
+    }
+}
+
+fn edk2_decode_borrowed<T: Indexable<IndexOut = T> + ?Sized>(
+    raw: &T,
+    keep_trailing_caret: bool,
+) -> (&T, Option<T::Idx>) {
+    let mut iter = raw.as_iter().peekable();
+    // opening quote is skipped
+    iter.next_if(|(_, ch)| *ch == T::QUOTE);
+    let Some(&(begin, _)) = iter.peek() else {
+        return (raw.slice_from(raw.end_idx()), None);
+    };
+    while let Some((idx, ch)) = iter.next() {
+        if ch == T::CARET && !(keep_trailing_caret && iter.peek().is_none()) {
+            return (raw.slice(begin..idx), Some(idx));
+        }
+        if ch == T::QUOTE {
+            // closing quote is skipped, quotes in the middle are not
+            return (raw.slice(begin..idx), iter.peek().is_some().then_some(idx));
+        }
+    }
+    (raw.slice_from(begin), None)
+}
+
+fn edk2_quoting<T: Indexable + ?Sized>(raw: &T, keep_trailing_caret: bool) -> (bool, bool) {
+    let (mut quoted, mut escaped) = (false, false);
+    let mut iter = raw.as_iter();
+    while let Some((_, ch)) = iter.next() {
+        if ch == T::CARET {
+            escaped |= iter.next().is_some() || !keep_trailing_caret;
+        } else if ch == T::QUOTE {
+            quoted = true;
+        }
+    }
+    (quoted, escaped)
+}
+
+/// Windows `CommandLineToArgvW` rules for arguments after the program name, quotes
+/// group spaces and tabs, backslashes escape quotes but are literal elsewhere and `""`
+/// in quotes is a literal quote. Unclosed quotes extend to end of command line.
+///
+/// See <https://learn.microsoft.com/en-us/cpp/c-language/parsing-c-command-line-arguments>
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Windows;
+
+#[derive(Default)]
+pub struct WindowsScan {
+    backslashes: usize,
+    in_quote: bool,
+    /// whether the previous item closed quotes, so a quote now is a literal one of `""`
+    closed_quote: bool,
+}
+
+#[derive(Default)]
+pub struct WindowsDecode {
+    /// backslashes to emit before reading on
+    backslashes: usize,
+    /// whether to emit an escaped quote after the backslashes
+    quote: bool,
+    in_quote: bool,
+}
+
+impl SplitPolicy for Windows {
+    type Scan<Idx> = WindowsScan;
+    type Decode = WindowsDecode;
+
+    fn is_separator<T: Indexable + ?Sized>(ch: &T::Item) -> bool {
+        *ch == T::SPACE || *ch == T::TAB
+    }
+
+    fn scan<T: Indexable + ?Sized>(state: &mut WindowsScan, _idx: T::Idx, ch: &T::Item) -> bool {
+        let closed_quote = core::mem::take(&mut state.closed_quote);
+        if *ch == T::BACKSLASH {
+            state.backslashes += 1;
+            return false;
+        }
+        let escaped = state.backslashes % 2 == 1;
+        state.backslashes = 0;
+        if *ch == T::QUOTE {
+            if !escaped && closed_quote {
+                // `""` in quotes is a literal quote and quotes go on
+                state.in_quote = true;
+            } else if !escaped {
+                state.closed_quote = state.in_quote;
+                state.in_quote = !state.in_quote;
+            }
+            return false;
+        }
+        !state.in_quote
+    }
+
+    fn unclosed_quote<Idx: Copy>(_state: &WindowsScan) -> Option<Idx> {
+        None
+    }
+
+    fn decode_next<T: Indexable + ?Sized>(
+        state: &mut WindowsDecode,
+        iter: &mut Peekable<T::AsIter<'_>>,
+    ) -> Option<T::Item> {
+        loop {
+            if state.backslashes > 0 {
+                state.backslashes -= 1;
+                return Some(T::BACKSLASH);
+            }
+            if state.quote {
+                state.quote = false;
+                return Some(T::QUOTE);
+            }
+            let (_, ch) = iter.next()?;
+            if ch == T::QUOTE {
+                if state.in_quote && iter.next_if(|(_, ch)| *ch == T::QUOTE).is_some() {
+                    return Some(T::QUOTE);
+                }
+                state.in_quote = !state.in_quote;
+                continue;
+            }
+            if ch != T::BACKSLASH {
+                return Some(ch);
+            }
+            let mut count = 1;
+            while iter.next_if(|(_, ch)| *ch == T::BACKSLASH).is_some() {
+                count += 1;
+            }
+            // 2n backslashes and a quote are n backslashes, 2n+1 are n and a literal quote
+            if iter.next_if(|(_, ch)| *ch == T::QUOTE).is_some() {
+                state.backslashes = count / 2;
+                state.quote = count % 2 == 1;
+            } else {
+                state.backslashes = count;
+            }
+        }
+    }
+
+    fn decode_borrowed<T: Indexable<IndexOut = T> + ?Sized>(raw: &T) -> (&T, Option<T::Idx>) {
+        // backslashes are literal unless a run of them ends at a quote
+        let (mut begin, mut backslashes) = (None, None);
+        for (idx, ch) in raw.as_iter() {
+            let begin = *begin.get_or_insert(idx);
+            if ch == T::QUOTE {
+                let end = backslashes.unwrap_or(idx);
+                return (raw.slice(begin..end), Some(end));
+            }
+            if ch != T::BACKSLASH {
+                backslashes = None;
+            } else if backslashes.is_none() {
+                backslashes = Some(idx);
+            }
+        }
+        (raw, None)
+    }
+
+    fn quoting<T: Indexable + ?Sized>(raw: &T) -> (bool, bool) {
+        let (mut quoted, mut escaped) = (false, false);
+        let (mut backslashes, mut in_quote) = (0, false);
+        let mut iter = raw.as_iter().peekable();
+        while let Some((_, ch)) = iter.next() {
+            if ch == T::BACKSLASH {
+                backslashes += 1;
+                continue;
+            }
+            if ch == T::QUOTE {
+                // backslashes before quote are escapes, of the quote if odd
+                escaped |= backslashes > 0;
+                if backslashes % 2 == 0 {
+                    quoted = true;
+                    if in_quote && iter.next_if(|(_, ch)| *ch == T::QUOTE).is_some() {
+                        escaped = true;
+                    } else {
+                        in_quote = !in_quote;
+                    }
+                }
+            }
+            backslashes = 0;
+        }
+        (quoted, escaped)
+    }
+}
@@ -0,0 +1,390 @@
+//! Declarative parsing of split arguments by a static table of options and positionals,
+//! see [Spec]
+
+use core::fmt::{self, Display, Write};
+
+/// Option of a [Spec] like `-t, --type TYPE`, identified by `id` when parsed
+#[derive(Debug, Clone, Copy)]
+pub struct OptSpec<Id> {
+    pub id: Id,
+    pub short: Option<char>,
+    pub long: Option<&'static str>,
+    /// Name of its value in help text, or `None` for a flag
+    pub value: Option<&'static str>,
+    /// Help text, lines after the first are indented in [Spec] help
+    pub help: &'static str,
+}
+
+impl<Id> OptSpec<Id> {
+    pub const fn new(id: Id) -> Self {
+        Self {
+            id,
+            short: None,
+            long: None,
+            value: None,
+            help: "",
+        }
+    }
+
+    pub const fn short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    pub const fn long(mut self, long: &'static str) -> Self {
+        self.long = Some(long);
+        self
+    }
+
+    /// Take a value named `value` in help text
+    pub const fn value(mut self, value: &'static str) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    pub const fn help(mut self, help: &'static str) -> Self {
+        self.help = help;
+        self
+    }
+}
+
+/// Positional argument of a [Spec]
+#[derive(Debug, Clone, Copy)]
+pub struct PosSpec {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub required: bool,
+}
+
+/// Options and positionals of an app, defined once as a static table for parsing
+/// arguments with [Spec::parse] and printing help text with [Display]
+#[derive(Debug, Clone, Copy)]
+pub struct Spec<Id: 'static> {
+    pub opts: &'static [OptSpec<Id>],
+    pub positionals: &'static [PosSpec],
+}
+
+/// Name of an option as given in arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptName<'a> {
+    Short(char),
+    Long(&'a str),
+}
+
+impl Display for OptName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Short(short) => write!(f, "-{}", short),
+            Self::Long(long) => write!(f, "--{}", long),
+        }
+    }
+}
+
+/// Error of parsing arguments by a [Spec]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecError<'a> {
+    UnknownOpt(OptName<'a>),
+    RequiresValue(OptName<'a>),
+    DoesNotRequireValue(OptName<'a>),
+    /// Positional beyond those of the spec
+    Unexpected(&'a str),
+    /// Required positional not given, by its name
+    Missing(&'static str),
+}
+
+impl Display for SpecError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpt(opt) => write!(f, "Unknown option {}", opt),
+            Self::RequiresValue(opt) => write!(f, "Option {} requires a value", opt),
+            Self::DoesNotRequireValue(opt) => write!(f, "Option {} does not take a value", opt),
+            Self::Unexpected(arg) => write!(f, "Unexpected argument {}", arg),
+            Self::Missing(name) => write!(f, "Missing argument {}", name),
+        }
+    }
+}
+
+/// Argument parsed by a [Spec]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parsed<'a, Id> {
+    /// Option of `id` with its value if it takes one
+    Opt(Id, Option<&'a str>),
+    /// Positional at `index` of [Spec::positionals]
+    Positional(usize, &'a str),
+}
+
+impl<Id: Copy> Spec<Id> {
+    /// Parse `args`, excluding the program name, into options and positionals
+    pub fn parse<'a, I: Iterator<Item = &'a str>>(&self, args: I) -> Parser<'_, 'a, Id, I> {
+        Parser {
+            spec: self,
+            args,
+            cluster: None,
+            positionals: 0,
+            opts_ended: false,
+            fused: false,
+        }
+    }
+
+    fn find(&self, name: OptName) -> Option<&OptSpec<Id>> {
+        self.opts.iter().find(|opt| match name {
+            OptName::Short(short) => opt.short == Some(short),
+            OptName::Long(long) => opt.long == Some(long),
+        })
+    }
+}
+
+/// Width of names column in help text
+const HELP_INDENT: usize = 24;
+
+/// Write line of `names` and `help` aligned to [HELP_INDENT]
+fn write_help_line<W: Write>(w: &mut W, names: &dyn Display, help: &str) -> fmt::Result {
+    struct Counter<'w, W>(&'w mut W, usize);
+    impl<W: Write> Write for Counter<'_, W> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.1 += s.chars().count();
+            self.0.write_str(s)
+        }
+    }
+    let mut counter = Counter(w, 0);
+    write!(counter, "  {}", names)?;
+    let width = counter.1;
+    let mut lines = help.lines();
+    if let Some(line) = lines.next() {
+        if width + 2 > HELP_INDENT {
+            writeln!(w)?;
+            write!(w, "{:1$}", "", HELP_INDENT)?;
+        } else {
+            write!(w, "{:1$}", "", HELP_INDENT - width)?;
+        }
+        w.write_str(line)?;
+    }
+    writeln!(w)?;
+    for line in lines {
+        writeln!(w, "{:2$}{}", "", line, HELP_INDENT)?;
+    }
+    Ok(())
+}
+
+/// Names of an option in help text, like `-t, --type TYPE`
+struct OptNames<'s, Id>(&'s OptSpec<Id>);
+
+impl<Id> Display for OptNames<'_, Id> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.0.short, self.0.long) {
+            (Some(short), Some(long)) => write!(f, "-{}, --{}", short, long)?,
+            (Some(short), None) => write!(f, "-{}", short)?,
+            (None, Some(long)) => write!(f, "    --{}", long)?,
+            (None, None) => {}
+        }
+        match self.0.value {
+            Some(value) => write!(f, " {}", value),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<Id> Display for Spec<Id> {
+    /// Help text of positionals and options, in lines like
+    /// `  -t, --type TYPE       Reset type`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pos in self.positionals {
+            write_help_line(f, &pos.name, pos.help)?;
+        }
+        for opt in self.opts {
+            write_help_line(f, &OptNames(opt), opt.help)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator of arguments parsed by a [Spec], see [Spec::parse]. Short options may be
+/// clustered like `-rq` and take values like `-t cold` or `-tcold`, long options take
+/// values like `--type cold` or `--type=cold`. Arguments after `--` are positionals.
+pub struct Parser<'s, 'a, Id: 'static, I> {
+    spec: &'s Spec<Id>,
+    args: I,
+    /// rest of short options cluster
+    cluster: Option<&'a str>,
+    positionals: usize,
+    opts_ended: bool,
+    fused: bool,
+}
+
+impl<'a, Id: Copy, I: Iterator<Item = &'a str>> Parser<'_, 'a, Id, I> {
+    fn short(&mut self, cluster: &'a str) -> Result<Parsed<'a, Id>, SpecError<'a>> {
+        let mut chars = cluster.chars();
+        let short = chars.next().unwrap();
+        let rest = chars.as_str();
+        let name = OptName::Short(short);
+        let opt = self.spec.find(name).ok_or(SpecError::UnknownOpt(name))?;
+        if opt.value.is_none() {
+            self.cluster = (!rest.is_empty()).then_some(rest);
+            return Ok(Parsed::Opt(opt.id, None));
+        }
+        let value = match rest {
+            "" => self.args.next().ok_or(SpecError::RequiresValue(name))?,
+            rest => rest,
+        };
+        Ok(Parsed::Opt(opt.id, Some(value)))
+    }
+
+    fn long(&mut self, long: &'a str) -> Result<Parsed<'a, Id>, SpecError<'a>> {
+        let (long, value) = match long.split_once('=') {
+            Some((long, value)) => (long, Some(value)),
+            None => (long, None),
+        };
+        let name = OptName::Long(long);
+        let opt = self.spec.find(name).ok_or(SpecError::UnknownOpt(name))?;
+        let value = match (opt.value, value) {
+            (None, None) => None,
+            (None, Some(_)) => return Err(SpecError::DoesNotRequireValue(name)),
+            (Some(_), Some(value)) => Some(value),
+            (Some(_), None) => Some(self.args.next().ok_or(SpecError::RequiresValue(name))?),
+        };
+        Ok(Parsed::Opt(opt.id, value))
+    }
+
+    fn positional(&mut self, arg: &'a str) -> Result<Parsed<'a, Id>, SpecError<'a>> {
+        let index = self.positionals;
+        if index >= self.spec.positionals.len() {
+            return Err(SpecError::Unexpected(arg));
+        }
+        self.positionals += 1;
+        Ok(Parsed::Positional(index, arg))
+    }
+
+    /// Next parsed argument, or error of missing required positional at end
+    fn parse_next(&mut self) -> Result<Option<Parsed<'a, Id>>, SpecError<'a>> {
+        if let Some(cluster) = self.cluster.take() {
+            return self.short(cluster).map(Some);
+        }
+        let Some(arg) = self.args.next() else {
+            let missing = self.spec.positionals[self.positionals..]
+                .iter()
+                .find(|pos| pos.required);
+            return match missing {
+                Some(pos) => Err(SpecError::Missing(pos.name)),
+                None => Ok(None),
+            };
+        };
+        if self.opts_ended {
+            return self.positional(arg).map(Some);
+        }
+        if arg == "--" {
+            self.opts_ended = true;
+            return self.parse_next();
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            return self.long(long).map(Some);
+        }
+        match arg.strip_prefix('-') {
+            Some(cluster) if !cluster.is_empty() => self.short(cluster).map(Some),
+            _ => self.positional(arg).map(Some),
+        }
+    }
+}
+
+impl<'a, Id: Copy, I: Iterator<Item = &'a str>> Iterator for Parser<'_, 'a, Id, I> {
+    type Item = Result<Parsed<'a, Id>, SpecError<'a>>;
+
+    /// Next parsed argument, or an error after which the iterator ends
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fused {
+            return None;
+        }
+        let res = self.parse_next().transpose();
+        self.fused = !matches!(res, Some(Ok(_)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Id {
+        Type,
+        Force,
+        Parted,
+        Log,
+    }
+
+    const SPEC: Spec<Id> = Spec {
+        opts: &[
+            OptSpec::new(Id::Type)
+                .short('t')
+                .long("type")
+                .value("TYPE")
+                .help("Reset type,\ndefaults to `cold`"),
+            OptSpec::new(Id::Force)
+                .short('f')
+                .long("force")
+                .help("Force it"),
+            OptSpec::new(Id::Parted).short('P').help("Partitioned"),
+            OptSpec::new(Id::Log)
+                .long("log")
+                .value("SPEC")
+                .help("Log spec"),
+        ],
+        positionals: &[PosSpec {
+            name: "COMMAND",
+            help: "Command to run",
+            required: true,
+        }],
+    };
+
+    fn parse(args: &[&'static str]) -> Result<Vec<Parsed<'static, Id>>, SpecError<'static>> {
+        SPEC.parse(args.iter().copied()).collect()
+    }
+
+    #[test]
+    fn parse_spec() {
+        assert_eq!(
+            Ok(vec![
+                Parsed::Opt(Id::Force, None),
+                Parsed::Opt(Id::Type, Some("warm")),
+                Parsed::Opt(Id::Type, Some("cold")),
+                Parsed::Opt(Id::Log, Some("debug")),
+                Parsed::Positional(0, "reset"),
+            ]),
+            parse(&["-ftwarm", "--type=cold", "--log", "debug", "reset"])
+        );
+        assert_eq!(
+            Ok(vec![
+                Parsed::Opt(Id::Parted, None),
+                Parsed::Positional(0, "-f")
+            ]),
+            parse(&["-P", "--", "-f"])
+        );
+        assert_eq!(
+            Err(SpecError::UnknownOpt(OptName::Short('x'))),
+            parse(&["-fx"])
+        );
+        assert_eq!(
+            Err(SpecError::RequiresValue(OptName::Long("type"))),
+            parse(&["--type"])
+        );
+        assert_eq!(
+            Err(SpecError::DoesNotRequireValue(OptName::Long("force"))),
+            parse(&["--force=1"])
+        );
+        assert_eq!(Err(SpecError::Unexpected("b")), parse(&["a", "b"]));
+        assert_eq!(Err(SpecError::Missing("COMMAND")), parse(&["-f"]));
+    }
+
+    #[test]
+    fn spec_help() {
+        assert_eq!(
+            "  COMMAND               Command to run
+  -t, --type TYPE       Reset type,
+                        defaults to `cold`
+  -f, --force           Force it
+  -P                    Partitioned
+      --log SPEC        Log spec
+",
+            SPEC.to_string()
+        );
+    }
+}
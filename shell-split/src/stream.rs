@@ -0,0 +1,155 @@
+//! Splitting command line read item by item, see [SplitIter]
+
+use alloc::borrow::{Borrow, ToOwned};
+use core::iter::{Enumerate, FusedIterator, Peekable};
+use core::marker::PhantomData;
+
+use crate::{Arg, Edk2, Indexable, SplitError, SplitPolicy};
+
+/// Iterator of owned arguments split from command line items of iterator `I`, e.g.
+/// `char`s of `str` or `u16`s of `[u16]` read from a file or serial console, so that
+/// only one argument is buffered at a time. `T` is the unsized type of an argument like
+/// `str` or `[u16]`, yielded arguments are its owned type.
+pub struct SplitIter<T: Indexable + ?Sized, I: Iterator, P: SplitPolicy = Edk2> {
+    iter: Peekable<Enumerate<I>>,
+    fused: bool,
+    arg: PhantomData<fn(&T)>,
+    policy: PhantomData<P>,
+}
+
+impl<T, I> SplitIter<T, I>
+where
+    T: Indexable + ?Sized,
+    I: Iterator<Item = T::Item>,
+{
+    /// Split items of `iter` by EDK2 shell rules
+    pub fn new(iter: I) -> Self {
+        Self::with_policy(iter)
+    }
+}
+
+impl<T, I, P> SplitIter<T, I, P>
+where
+    T: Indexable + ?Sized,
+    I: Iterator<Item = T::Item>,
+    P: SplitPolicy,
+{
+    /// Split items of `iter` by rules of policy `P`
+    pub fn with_policy(iter: I) -> Self {
+        Self {
+            iter: iter.enumerate().peekable(),
+            fused: false,
+            arg: PhantomData,
+            policy: PhantomData,
+        }
+    }
+}
+
+impl<T, I, P> SplitIter<T, I, P>
+where
+    T: Indexable<Idx = usize, IndexOut = T> + ToOwned + ?Sized,
+    T::Owned: Default + Extend<T::Item> + FromIterator<T::Item>,
+    I: Iterator<Item = T::Item>,
+    P: SplitPolicy,
+{
+    /// Like [Iterator::next] but fails on unclosed quote instead of dropping the last
+    /// argument, then ends. Index of the quote is the number of items before it.
+    pub fn try_next(&mut self) -> Result<Option<T::Owned>, SplitError<usize>> {
+        if self.fused {
+            return Ok(None);
+        }
+        while self
+            .iter
+            .next_if(|(_, ch)| P::is_separator::<T>(ch))
+            .is_some()
+        {}
+        let begin = match self.iter.peek() {
+            Some(&(idx, ref ch)) if *ch != T::NUL => idx,
+            _ => {
+                self.fused = true;
+                return Ok(None);
+            }
+        };
+
+        let mut raw = T::Owned::default();
+        let mut end = begin;
+        let mut state = P::Scan::<usize>::default();
+        for (idx, ch) in self.iter.by_ref() {
+            end = idx;
+            // NUL ends command line even if escaped or quoted
+            if ch == T::NUL {
+                self.fused = true;
+                break;
+            }
+            if P::scan::<T>(&mut state, idx, &ch) && P::is_separator::<T>(&ch) {
+                break;
+            }
+            end = idx + 1;
+            raw.extend(Some(ch));
+        }
+        if let Some(quote) = P::unclosed_quote(&state) {
+            self.fused = true;
+            return Err(SplitError::UnclosedQuote(quote));
+        }
+
+        let arg: Arg<T, usize, P> = Arg {
+            raw_arg: raw.borrow(),
+            span: begin..end,
+            policy: PhantomData,
+        };
+        Ok(Some(arg.decode_to_owned()))
+    }
+}
+
+impl<T, I, P> Iterator for SplitIter<T, I, P>
+where
+    T: Indexable<Idx = usize, IndexOut = T> + ToOwned + ?Sized,
+    T::Owned: Default + Extend<T::Item> + FromIterator<T::Item>,
+    I: Iterator<Item = T::Item>,
+    P: SplitPolicy,
+{
+    type Item = T::Owned;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().ok().flatten()
+    }
+}
+impl<T, I, P> FusedIterator for SplitIter<T, I, P>
+where
+    T: Indexable<Idx = usize, IndexOut = T> + ToOwned + ?Sized,
+    T::Owned: Default + Extend<T::Item> + FromIterator<T::Item>,
+    I: Iterator<Item = T::Item>,
+    P: SplitPolicy,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{split, Windows};
+
+    #[test]
+    fn split_chars() {
+        let command_line = "  ls  \"a b\"^^ \"\" c^ d\0ignored";
+        let args: Vec<String> = SplitIter::<str, _>::new(command_line.chars()).collect();
+        let expected: Vec<String> = split(command_line);
+        assert_eq!(expected, args);
+
+        let mut iter = SplitIter::<str, _>::new("a \"b".chars());
+        assert_eq!(Ok(Some(String::from("a"))), iter.try_next());
+        assert_eq!(Err(SplitError::UnclosedQuote(2)), iter.try_next());
+        assert_eq!(None, iter.next());
+    }
+
+    #[test]
+    fn split_ucs2() {
+        let command_line: Vec<u16> = "x\t\"y z\" \\\"".encode_utf16().collect();
+        let args: Vec<Vec<u16>> =
+            SplitIter::<[u16], _, Windows>::with_policy(command_line.into_iter()).collect();
+        let expected: Vec<Vec<u16>> = ["x", "y z", "\""]
+            .iter()
+            .map(|arg| arg.encode_utf16().collect())
+            .collect();
+        assert_eq!(expected, args);
+    }
+}
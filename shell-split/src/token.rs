@@ -0,0 +1,206 @@
+//! Redirection and pipe operators of EDK2 shell among split arguments
+
+use core::iter::{FusedIterator, Peekable};
+
+use crate::{Indexable, Split, SplitArg, SplitError};
+
+/// Stream redirected by an operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// `<`
+    StdIn,
+    /// `>` or `1>`
+    StdOut,
+    /// `2>`
+    StdErr,
+}
+
+/// Where a redirected stream goes to or comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// UCS-2 file
+    File,
+    /// ASCII file, operator with `a` suffix
+    AsciiFile,
+    /// Environment variable, operator with `v` suffix
+    Variable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redirect {
+    pub stream: Stream,
+    /// `>>` appends instead of overwriting
+    pub append: bool,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// Redirection like `>`, `2>>` or `<a`, its target is the next token
+    Redirect(Redirect),
+    /// `|`, or `|a` for ASCII pipe
+    Pipe { ascii: bool },
+}
+
+/// Token of command line, an argument or an operator with its raw argument
+#[derive(Debug, PartialEq, Eq)]
+pub enum Token<A> {
+    Arg(A),
+    Operator(Operator, A),
+}
+
+fn eat<T, I>(iter: &mut Peekable<I>, ch: u8) -> bool
+where
+    T: Indexable + ?Sized,
+    I: Iterator<Item = (T::Idx, T::Item)>,
+{
+    iter.next_if(|(_, item)| *item == T::from_ascii(ch))
+        .is_some()
+}
+
+/// Operator of whole raw argument `raw`, quoted or escaped ones are not
+fn parse_operator<T: Indexable + ?Sized>(raw: &T) -> Option<Operator> {
+    let mut iter = raw.as_iter().peekable();
+    let op = if eat::<T, _>(&mut iter, b'|') {
+        Operator::Pipe {
+            ascii: eat::<T, _>(&mut iter, b'a'),
+        }
+    } else {
+        let stream = if eat::<T, _>(&mut iter, b'<') {
+            Stream::StdIn
+        } else {
+            let stream = if eat::<T, _>(&mut iter, b'2') {
+                Stream::StdErr
+            } else {
+                eat::<T, _>(&mut iter, b'1');
+                Stream::StdOut
+            };
+            if !eat::<T, _>(&mut iter, b'>') {
+                return None;
+            }
+            stream
+        };
+        let append = stream != Stream::StdIn && eat::<T, _>(&mut iter, b'>');
+        let target = if eat::<T, _>(&mut iter, b'a') {
+            Target::AsciiFile
+        } else if eat::<T, _>(&mut iter, b'v') {
+            Target::Variable
+        } else {
+            Target::File
+        };
+        Operator::Redirect(Redirect {
+            stream,
+            append,
+            target,
+        })
+    };
+    iter.next().is_none().then_some(op)
+}
+
+/// Iterator of tokens split from command line by EDK2 shell rules, operators must be
+/// separated from arguments by spaces as EDK2 shell requires
+pub struct Tokens<'a, T: Indexable + ?Sized> {
+    split: Split<'a, T>,
+}
+
+impl<'a, T: Indexable + ?Sized> Split<'a, T> {
+    /// Tokenize instead, recognizing redirection and pipe operators
+    pub fn tokens(self) -> Tokens<'a, T> {
+        Tokens { split: self }
+    }
+}
+
+impl<'a, T: Indexable + ?Sized> Tokens<'a, T> {
+    /// Like [Iterator::next] but fails on splitting errors, see [Split::try_next]
+    pub fn try_next(&mut self) -> Result<Option<Token<SplitArg<'a, T>>>, SplitError<T::Idx>> {
+        let Some(arg) = self.split.try_next()? else {
+            return Ok(None);
+        };
+        Ok(Some(match parse_operator(arg.raw()) {
+            Some(op) => Token::Operator(op, arg),
+            None => Token::Arg(arg),
+        }))
+    }
+}
+
+impl<'a, T: Indexable + ?Sized> Iterator for Tokens<'a, T> {
+    type Item = Token<SplitArg<'a, T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().ok().flatten()
+    }
+}
+impl<T: Indexable + ?Sized> FusedIterator for Tokens<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(stream: Stream, append: bool, target: Target) -> Option<Operator> {
+        Some(Operator::Redirect(Redirect {
+            stream,
+            append,
+            target,
+        }))
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            redirect(Stream::StdOut, false, Target::File),
+            parse_operator(">")
+        );
+        assert_eq!(
+            redirect(Stream::StdOut, true, Target::File),
+            parse_operator("1>>")
+        );
+        assert_eq!(
+            redirect(Stream::StdOut, false, Target::AsciiFile),
+            parse_operator(">a")
+        );
+        assert_eq!(
+            redirect(Stream::StdErr, true, Target::Variable),
+            parse_operator("2>>v")
+        );
+        assert_eq!(
+            redirect(Stream::StdIn, false, Target::AsciiFile),
+            parse_operator("<a")
+        );
+        assert_eq!(Some(Operator::Pipe { ascii: false }), parse_operator("|"));
+        assert_eq!(
+            Some(Operator::Pipe { ascii: true }),
+            parse_operator(b"|a".as_slice())
+        );
+        for raw in ["1", "3>", "<<", ">b", ">>>", "|>", "\">\"", "^>", "a>"] {
+            assert_eq!(None, parse_operator(raw));
+        }
+    }
+
+    #[test]
+    fn tokens() {
+        let tokens: Vec<_> = Split::new("ls -r > \">\" | grep a 2>>v err")
+            .tokens()
+            .collect();
+        let ops: Vec<_> = tokens
+            .iter()
+            .map(|t| match t {
+                Token::Arg(arg) => Err(arg.raw()),
+                Token::Operator(op, _) => Ok(*op),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                Err("ls"),
+                Err("-r"),
+                redirect(Stream::StdOut, false, Target::File).ok_or(""),
+                Err("\">\""),
+                Ok(Operator::Pipe { ascii: false }),
+                Err("grep"),
+                Err("a"),
+                redirect(Stream::StdErr, true, Target::Variable).ok_or(""),
+                Err("err"),
+            ],
+            ops
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! [getargs] support of UCS-2 arguments
+
+use core::fmt::Debug;
+
+use getargs::Argument;
+
+use crate::{Arg, Indexable, SplitPolicy};
+
+/// Argument of `[u16]` or `[Char16]` items for [getargs::Options], e.g. of decoded
+/// arguments of UCS-2 load options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ucs2Arg<'a, T>(pub &'a [T]);
+
+impl<'a, T> Argument for Ucs2Arg<'a, T>
+where
+    T: Copy + Eq + Debug,
+    [T]: Indexable<Idx = usize, Item = T>,
+{
+    type ShortOpt = T;
+
+    fn ends_opts(self) -> bool {
+        *self.0 == [<[T]>::from_ascii(b'-'); 2]
+    }
+
+    fn parse_long_opt(self) -> Option<(Self, Option<Self>)> {
+        let dash = <[T]>::from_ascii(b'-');
+        let opt = self.0.strip_prefix(&[dash, dash])?;
+        let eq = <[T]>::from_ascii(b'=');
+        Some(match opt.iter().position(|&ch| ch == eq) {
+            Some(pos) => (Self(&opt[..pos]), Some(Self(&opt[pos + 1..]))),
+            None => (Self(opt), None),
+        })
+    }
+
+    fn parse_short_cluster(self) -> Option<Self> {
+        let cluster = self.0.strip_prefix(&[<[T]>::from_ascii(b'-')])?;
+        (!cluster.is_empty()).then_some(Self(cluster))
+    }
+
+    fn consume_short_opt(self) -> (T, Option<Self>) {
+        let (&opt, rest) = self.0.split_first().unwrap();
+        (opt, (!rest.is_empty()).then_some(Self(rest)))
+    }
+
+    fn consume_short_val(self) -> Self {
+        self
+    }
+}
+
+impl<'a, T, Idx, P: SplitPolicy> Arg<'a, [T], Idx, P>
+where
+    [T]: Indexable<IndexOut = [T]>,
+{
+    /// Argument for getargs if decoding it copies nothing, e.g. it's not escaped
+    pub fn as_getargs(&self) -> Option<Ucs2Arg<'a, T>> {
+        match P::decode_borrowed(self.raw_arg) {
+            (arg, None) => Some(Ucs2Arg(arg)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Split;
+
+    fn utf16(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn ucs2_argument() {
+        let (opt, value) = (utf16("--opt=a=b"), utf16("a=b"));
+        let long = Ucs2Arg(opt.as_slice()).parse_long_opt();
+        assert_eq!(
+            Some((Ucs2Arg(&opt[2..5]), Some(Ucs2Arg(value.as_slice())))),
+            long
+        );
+        assert!(Ucs2Arg(utf16("--").as_slice()).ends_opts());
+        assert_eq!(None, Ucs2Arg(utf16("-").as_slice()).parse_short_cluster());
+
+        let cluster = utf16("-ab");
+        let cluster = Ucs2Arg(cluster.as_slice()).parse_short_cluster().unwrap();
+        let (a, rest) = cluster.consume_short_opt();
+        assert_eq!((b'a' as u16, Some(Ucs2Arg(&[b'b' as u16][..]))), (a, rest));
+    }
+
+    #[test]
+    fn split_getargs() {
+        let command_line = utf16("-v \"x\" ^-a");
+        let args: Vec<_> = Split::new(command_line.as_slice())
+            .map(|a| a.as_getargs())
+            .collect();
+        assert_eq!(3, args.len());
+        assert_eq!(Some(Ucs2Arg(&command_line[..2])), args[0]);
+        assert_eq!(Some(Ucs2Arg(&command_line[4..5])), args[1]);
+        assert_eq!(None, args[2]);
+    }
+}
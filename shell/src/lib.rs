@@ -0,0 +1,239 @@
+//! Safe wrapper of UEFI Shell protocol, for current directories, file system mappings,
+//! environment variables and conversion between file paths and device paths.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod text;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ops::Deref;
+use core::ptr;
+
+use uefi::prelude::*;
+use uefi::proto::device_path::{DevicePath, FfiDevicePath};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::{unsafe_protocol, ProtocolPointer};
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType};
+use uefi::{CStr16, CString16, Char16, Result};
+
+pub use text::split_mappings;
+
+/// Leading part of `EFI_SHELL_PROTOCOL` up to the functions used here
+#[repr(C)]
+#[unsafe_protocol("6302d008-7f9b-4f30-87ac-60c9fef5da4e")]
+struct ShellProtocol {
+    execute: *const c_void,
+    get_env: unsafe extern "efiapi" fn(name: *const Char16) -> *const Char16,
+    set_env: unsafe extern "efiapi" fn(
+        name: *const Char16,
+        value: *const Char16,
+        volatile: bool,
+    ) -> Status,
+    get_alias: *const c_void,
+    set_alias: *const c_void,
+    get_help_text: *const c_void,
+    get_device_path_from_map:
+        unsafe extern "efiapi" fn(mapping: *const Char16) -> *const FfiDevicePath,
+    get_map_from_device_path:
+        unsafe extern "efiapi" fn(device_path: *mut *const FfiDevicePath) -> *const Char16,
+    get_device_path_from_file_path:
+        unsafe extern "efiapi" fn(path: *const Char16) -> *const FfiDevicePath,
+    get_file_path_from_device_path:
+        unsafe extern "efiapi" fn(path: *const FfiDevicePath) -> *const Char16,
+    set_map: unsafe extern "efiapi" fn(
+        device_path: *const FfiDevicePath,
+        mapping: *const Char16,
+    ) -> Status,
+    get_cur_dir: unsafe extern "efiapi" fn(file_system_mapping: *const Char16) -> *const Char16,
+    set_cur_dir:
+        unsafe extern "efiapi" fn(file_system: *const Char16, dir: *const Char16) -> Status,
+}
+
+/// Device path allocated from pool, freed on drop
+pub struct PoolDevicePath<'a> {
+    bt: &'a BootServices,
+    dp: *const FfiDevicePath,
+}
+impl<'a> PoolDevicePath<'a> {
+    /// # Safety
+    ///
+    /// `dp` must be a valid device path allocated from pool and not freed elsewhere
+    pub unsafe fn from_ffi_ptr(bt: &'a BootServices, dp: *const FfiDevicePath) -> Self {
+        Self { bt, dp }
+    }
+}
+impl Deref for PoolDevicePath<'_> {
+    type Target = DevicePath;
+    fn deref(&self) -> &Self::Target {
+        unsafe { DevicePath::from_ffi_ptr(self.dp) }
+    }
+}
+impl Drop for PoolDevicePath<'_> {
+    fn drop(&mut self) {
+        let _ = self.bt.free_pool(self.dp as _);
+    }
+}
+
+/// Names of file system mapped on `handle`, e.g. `FS0:`
+#[derive(Debug)]
+pub struct FsMapping {
+    pub handle: Handle,
+    pub names: Vec<String>,
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others are using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+fn to_cstring(s: &str) -> Result<CString16> {
+    CString16::try_from(s).map_err(|_| Status::INVALID_PARAMETER.into())
+}
+
+/// Copy of string owned by shell, [None] if null
+unsafe fn string_from_ptr(s: *const Char16) -> Option<String> {
+    (!s.is_null()).then(|| String::from(CStr16::from_ptr(s)))
+}
+
+/// UEFI shell the image is running in
+pub struct Shell<'a> {
+    bt: &'a BootServices,
+    pt: ScopedProtocol<'a, ShellProtocol>,
+}
+
+impl<'a> Shell<'a> {
+    /// Fails with `NOT_FOUND` if not running in UEFI shell
+    pub fn locate(bt: &'a BootServices) -> Result<Self> {
+        let handle = bt.get_handle_for_protocol::<ShellProtocol>()?;
+        let pt = get_protocol::<ShellProtocol>(bt, handle)?;
+        Ok(Self { bt, pt })
+    }
+
+    /// Current directory of file system `fs` mapping, or of current file system if [None]
+    pub fn current_dir(&self, fs: Option<&str>) -> Option<String> {
+        let fs = fs.map(to_cstring).transpose().ok()?;
+        let fs = fs.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        unsafe { string_from_ptr((self.pt.get_cur_dir)(fs)) }
+    }
+
+    /// Change current directory to `dir`, and current file system to `fs` if not [None]
+    pub fn set_current_dir(&self, fs: Option<&str>, dir: &str) -> Result {
+        let fs = fs.map(to_cstring).transpose()?;
+        let fs = fs.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        let dir = to_cstring(dir)?;
+        unsafe { (self.pt.set_cur_dir)(fs, dir.as_ptr()) }.to_result()
+    }
+
+    /// Value of environment variable `name`
+    pub fn env(&self, name: &str) -> Option<String> {
+        let name = to_cstring(name).ok()?;
+        unsafe { string_from_ptr((self.pt.get_env)(name.as_ptr())) }
+    }
+
+    /// Names of all environment variables
+    pub fn env_names(&self) -> Vec<String> {
+        let list = unsafe { (self.pt.get_env)(ptr::null()) };
+        if list.is_null() {
+            return Vec::new();
+        }
+        let units = unsafe {
+            let list = list.cast::<u16>();
+            let mut len = 0;
+            while *list.add(len) != 0 || *list.add(len + 1) != 0 {
+                len += 1;
+            }
+            core::slice::from_raw_parts(list, len + 1)
+        };
+        text::decode_multi_sz(units)
+    }
+
+    /// Set environment variable `name` to `value`, an empty `value` deletes it.
+    /// `volatile` variables are lost on reboot.
+    pub fn set_env(&self, name: &str, value: &str, volatile: bool) -> Result {
+        let name = to_cstring(name)?;
+        let value = to_cstring(value)?;
+        unsafe { (self.pt.set_env)(name.as_ptr(), value.as_ptr(), volatile) }.to_result()
+    }
+
+    /// Device path `mapping` like `FS0:` is mapped to
+    pub fn device_path_from_map(&self, mapping: &str) -> Option<&DevicePath> {
+        let mapping = to_cstring(mapping).ok()?;
+        let dp = unsafe { (self.pt.get_device_path_from_map)(mapping.as_ptr()) };
+        (!dp.is_null()).then(|| unsafe { DevicePath::from_ffi_ptr(dp) })
+    }
+
+    /// Mapping names of `dp`, e.g. `FS0:` and `BLK1:` of the same device
+    pub fn mappings_of(&self, dp: &DevicePath) -> Vec<String> {
+        let mut dp = dp.as_ffi_ptr();
+        let list = unsafe { string_from_ptr((self.pt.get_map_from_device_path)(&mut dp)) };
+        list.as_deref()
+            .map(|l| split_mappings(l).map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Map `mapping` like `FS9:` to `dp`, replacing existing mapping of that name
+    pub fn set_map(&self, dp: &DevicePath, mapping: &str) -> Result {
+        let mapping = to_cstring(mapping)?;
+        unsafe { (self.pt.set_map)(dp.as_ffi_ptr(), mapping.as_ptr()) }.to_result()
+    }
+
+    /// Delete `mapping`
+    pub fn delete_map(&self, mapping: &str) -> Result {
+        let mapping = to_cstring(mapping)?;
+        unsafe { (self.pt.set_map)(ptr::null(), mapping.as_ptr()) }.to_result()
+    }
+
+    /// Mappings of all file systems
+    pub fn fs_mappings(&self) -> Result<Vec<FsMapping>> {
+        let handles = self
+            .bt
+            .locate_handle_buffer(SearchType::from_proto::<SimpleFileSystem>())?;
+        let mut res = Vec::new();
+        for &handle in handles.iter() {
+            let Ok(dp) = get_protocol::<DevicePath>(self.bt, handle) else {
+                continue;
+            };
+            let names = self.mappings_of(&dp);
+            if !names.is_empty() {
+                res.push(FsMapping { handle, names });
+            }
+        }
+        Ok(res)
+    }
+
+    /// Device path of `path` relative to current directory, in which `/` is not
+    /// accepted as separator
+    pub fn device_path_from_file_path(&self, path: &str) -> Option<PoolDevicePath<'a>> {
+        let path = to_cstring(path).ok()?;
+        let dp = unsafe { (self.pt.get_device_path_from_file_path)(path.as_ptr()) };
+        (!dp.is_null()).then(|| unsafe { PoolDevicePath::from_ffi_ptr(self.bt, dp) })
+    }
+
+    /// Shell file path of `dp`, e.g. `FS0:\EFI\BOOT`
+    pub fn file_path_from_device_path(&self, dp: &DevicePath) -> Option<String> {
+        unsafe {
+            let path = (self.pt.get_file_path_from_device_path)(dp.as_ffi_ptr());
+            let res = string_from_ptr(path);
+            if !path.is_null() {
+                let _ = self.bt.free_pool(path as _);
+            }
+            res
+        }
+    }
+}
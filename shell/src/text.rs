@@ -0,0 +1,52 @@
+//! Decoding of string lists returned by UEFI shell
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Names in `;` separated mapping list, e.g. `FS0:;BLK1:`
+pub fn split_mappings(list: &str) -> impl Iterator<Item = &str> {
+    list.split(';').map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// Strings of null separated list terminated by double null, `units` may omit the
+/// terminating nulls
+pub fn decode_multi_sz(units: &[u16]) -> Vec<String> {
+    units
+        .split(|c| *c == 0)
+        .take_while(|s| !s.is_empty())
+        .map(|s| {
+            char::decode_utf16(s.iter().copied())
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn mappings() {
+        let names: Vec<_> = split_mappings("FS0:;BLK1:").collect();
+        assert_eq!(["FS0:", "BLK1:"], names[..]);
+        let names: Vec<_> = split_mappings("FS0:; ;").collect();
+        assert_eq!(["FS0:"], names[..]);
+        assert_eq!(None, split_mappings("").next());
+    }
+
+    #[test]
+    fn multi_sz() {
+        assert_eq!(
+            ["path", "profiles", "uefishellsupport"],
+            decode_multi_sz(&utf16("path\0profiles\0uefishellsupport\0\0"))[..]
+        );
+        assert_eq!(["cwd"], decode_multi_sz(&utf16("cwd"))[..]);
+        assert_eq!(["a"], decode_multi_sz(&utf16("a\0\0b\0\0"))[..]);
+        assert!(decode_multi_sz(&utf16("\0\0")).is_empty());
+    }
+}
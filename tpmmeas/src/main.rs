@@ -0,0 +1,287 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::tcg::v2::{
+    EventLogFormat, HashLogExtendEventFlags, PcrEvent, PcrEventInputs, Tcg,
+};
+use uefi::proto::tcg::{EventType, PcrIndex};
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::{print, println};
+use uefi_cli::{find_command, App, ArgsError};
+
+const APP: App = App {
+    help: "\
+Usage: {name} <COMMAND> [OPTIONS] [INPUT]
+
+  Measure a file or a string into a PCR with TCG2 protocol, or print event log
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+
+Commands:
+  file                  Extend PCR with hash of content of file INPUT, which
+                        may also be a HTTP(S) URL
+  string                Extend PCR with hash of INPUT in UTF-8
+  log                   Print entries of event log
+
+Options:
+  -p, --pcr INDEX       PCR to extend, defaults to 8, or PCR to print entries
+                        of, defaults to all
+  -e, --event TEXT      Event data to log along with measurement, defaults
+                        to INPUT
+
+Measurements are logged as EV_IPL events, as GRUB does for files and commands.
+
+EXAMPLE:
+  * Measure kernel command line into PCR 12
+  {name} string -p 12 \"root=/dev/sda2 rw\"
+  * Print entries of PCR 9
+  {name} log -p 9
+",
+    version: uefi_cli::version!(),
+};
+
+/// PCR measurements go to if not specified, as GRUB does for strings
+const DEFAULT_PCR: u32 = 8;
+/// PCR 0 to 23 of PC Client platforms
+const MAX_PCR: u32 = 23;
+
+#[derive(Clone, Copy)]
+enum CommandType {
+    File,
+    String,
+    Log,
+}
+
+enum Command<'a> {
+    NoOp,
+    Measure {
+        is_file: bool,
+        input: &'a str,
+        pcr: u32,
+        event: &'a str,
+    },
+    Log {
+        pcr: Option<u32>,
+    },
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("file", CommandType::File),
+        ("string", CommandType::String),
+        ("log", CommandType::Log),
+    ];
+
+    let mut command_type = None;
+    let mut input = None;
+    let mut pcr = None;
+    let mut event = None;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('p') | Arg::Long("pcr") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(index) if index <= MAX_PCR => pcr = Some(index),
+                    _ => {
+                        println!("Invalid PCR index {}", value);
+                        return Err(ArgsError::Invalid);
+                    }
+                }
+            }
+            Arg::Short('e') | Arg::Long("event") => {
+                event = Some(opts.value()?);
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(cmd) if command_type.is_none() => {
+                command_type = Some(find_command(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?);
+            }
+            Arg::Positional(value) if input.is_none() => {
+                input = Some(value);
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(command_type) = command_type else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    if let CommandType::Log = command_type {
+        if let Some(value) = input {
+            return Err(ArgsError::Unexpected(Arg::Positional(value)));
+        }
+        return Ok(Command::Log { pcr });
+    }
+    let Some(input) = input else {
+        println!("INPUT not specified");
+        return Err(ArgsError::Invalid);
+    };
+    Ok(Command::Measure {
+        is_file: matches!(command_type, CommandType::File),
+        input,
+        pcr: pcr.unwrap_or(DEFAULT_PCR),
+        event: event.unwrap_or(input),
+    })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::Measure {
+            is_file,
+            input,
+            pcr,
+            event,
+        }) => measure(bt, is_file, input, pcr, event),
+        Ok(Command::Log { pcr }) => print_event_log(bt, pcr),
+    };
+
+    res.status()
+}
+
+/// Open protocol `P` on `handle` without exclusive access as others are using it
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    bt: &BootServices,
+    handle: Handle,
+) -> uefi::Result<ScopedProtocol<P>> {
+    unsafe {
+        bt.open_protocol::<P>(
+            OpenProtocolParams {
+                handle,
+                agent: bt.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+fn open_tcg(bt: &BootServices) -> uefi::Result<ScopedProtocol<Tcg>> {
+    let handle = bt.get_handle_for_protocol::<Tcg>().map_err(|e| {
+        println!("No TPM 2.0 available");
+        e
+    })?;
+    get_protocol::<Tcg>(bt, handle)
+}
+
+fn print_event(event: &PcrEvent) {
+    println!(
+        "PCR {:>2} type 0x{:08x}",
+        event.pcr_index().0,
+        event.event_type().0
+    );
+    for (alg, digest) in event.digests() {
+        print!("  {:?} ", alg);
+        for b in digest {
+            print!("{:02x}", b);
+        }
+        println!();
+    }
+    let data = event.event_data();
+    if !data.is_empty() && data.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        println!("  {}", String::from_utf8_lossy(data));
+    } else {
+        println!("  {} bytes of event data", data.len());
+    }
+}
+
+fn measure(bt: &BootServices, is_file: bool, input: &str, pcr: u32, event: &str) -> uefi::Result {
+    let data = if is_file {
+        let mut source = uefi_filesrc::open(bt, input)?;
+        let Ok(size) = usize::try_from(source.size()) else {
+            return Err(Status::BAD_BUFFER_SIZE.into());
+        };
+        let mut data = vec![0u8; size];
+        source.read_to_end(&mut data)?;
+        data
+    } else {
+        Vec::from(input.as_bytes())
+    };
+
+    let mut tcg = open_tcg(bt)?;
+    // header of TCG_PCR_EVENT2 input is 18 bytes
+    let mut buffer = vec![MaybeUninit::<u8>::uninit(); event.len() + 32];
+    let inputs =
+        PcrEventInputs::new_in_buffer(&mut buffer, PcrIndex(pcr), EventType::IPL, event.as_bytes())
+            .discard_errdata()?;
+    tcg.hash_log_extend_event(HashLogExtendEventFlags::empty(), &data, inputs)
+        .map_err(|e| {
+            println!("Failed to extend PCR {}, {}", pcr, e.status());
+            e
+        })?;
+
+    let log = tcg.get_event_log_v2(EventLogFormat::TCG_2)?;
+    let created = log.iter().filter(|e| {
+        e.pcr_index() == PcrIndex(pcr)
+            && e.event_type() == EventType::IPL
+            && e.event_data() == event.as_bytes()
+    });
+    match created.last() {
+        Some(entry) => print_event(&entry),
+        None => println!("Extended PCR {}, event log entry not found", pcr),
+    }
+    if log.is_truncated() {
+        println!("Event log is truncated");
+    }
+    Ok(())
+}
+
+fn print_event_log(bt: &BootServices, pcr: Option<u32>) -> uefi::Result {
+    let mut tcg = open_tcg(bt)?;
+    let log = tcg.get_event_log_v2(EventLogFormat::TCG_2)?;
+    for event in log.iter() {
+        if pcr.map_or(true, |pcr| event.pcr_index() == PcrIndex(pcr)) {
+            print_event(&event);
+        }
+    }
+    if log.is_truncated() {
+        println!("Event log is truncated");
+    }
+    Ok(())
+}
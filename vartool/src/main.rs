@@ -0,0 +1,423 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use getargs::{Arg, Options};
+use uefi::prelude::*;
+use uefi::proto::media::file::{File, FileInfo, FileMode};
+use uefi::table::runtime::{VariableAttributes, VariableVendor};
+use uefi::{println, CStr16, CString16, Guid};
+use uefi_cli::{find_command, parse_hex, print_hexdump, App, ArgsError};
+use uefi_devpath::open_shell_path;
+
+const MIN_UEFI_REVISION: uefi::table::Revision = uefi::table::Revision::EFI_2_00;
+
+const APP: App = App {
+    help: "\
+Usage: {name} <COMMAND> [OPTIONS] [NAME]
+
+  List, dump, set and delete UEFI variables
+
+  -h, --help            Print this help and exit
+  -V, --version         Print version and exit
+      --log SPEC        Comma separated log level and sinks, e.g. \"debug,file\".
+                        Sinks are `conout`, `serial` and `file`, the last is
+                        uefi-toys.log in root of file system of this image.
+                        Overrides \"UefiToysLog\" variable
+
+Commands:
+  list                  List variables, only these of vendor GUID if given
+  dump                  Dump data of variable NAME
+  set                   Set data of variable NAME from file or inline hex
+  delete                Delete variable NAME
+
+Options:
+  -g, --guid GUID       Vendor GUID of variable, defaults to EFI global variable
+                        8be4df61-93ca-11d2-aa0d-00e098032b8c except for `list`
+  -t, --text            Dump data as UTF-8 text instead of hex
+  -u, --ucs2            Dump data as UCS-2 text instead of hex
+  -f, --file FILE       Set data to content of FILE
+  -x, --hex HEX         Set data to HEX bytes, e.g. \"0a0b0c\"
+  -a, --attrs ATTRS     Comma separated attributes of variable to set, `nv`, `bs`,
+                        `rt`, `hr`, `aw`, `at` or `ea`, defaults to these of
+                        existing variable or \"nv,bs,rt\"
+      --append          Append data to variable instead of replacing it
+
+EXAMPLE:
+  * List variables of EFI global variable vendor
+  {name} list -g 8be4df61-93ca-11d2-aa0d-00e098032b8c
+  * Dump boot order
+  {name} dump BootOrder
+  * Set log level of tools in the workspace to debug
+  {name} set -g e6d863c9-137e-49d9-84f3-474508363174 -x 6465627567 UefiToysLog
+",
+    version: uefi_cli::version!(),
+};
+
+/// Short names of variable attributes, for both parsing and display
+const ATTRIBUTES: &[(&str, VariableAttributes)] = &[
+    ("nv", VariableAttributes::NON_VOLATILE),
+    ("bs", VariableAttributes::BOOTSERVICE_ACCESS),
+    ("rt", VariableAttributes::RUNTIME_ACCESS),
+    ("hr", VariableAttributes::HARDWARE_ERROR_RECORD),
+    ("aw", VariableAttributes::AUTHENTICATED_WRITE_ACCESS),
+    (
+        "at",
+        VariableAttributes::TIME_BASED_AUTHENTICATED_WRITE_ACCESS,
+    ),
+    ("ea", VariableAttributes::ENHANCED_AUTHENTICATED_ACCESS),
+];
+
+#[derive(Clone, Copy)]
+enum DumpFormat {
+    Hex,
+    Text,
+    Ucs2,
+}
+
+enum Data<'a> {
+    File(&'a str),
+    Hex(Vec<u8>),
+}
+
+enum Command<'a> {
+    NoOp,
+    List {
+        vendor: Option<VariableVendor>,
+    },
+    Dump {
+        name: CString16,
+        vendor: VariableVendor,
+        format: DumpFormat,
+    },
+    Set {
+        name: CString16,
+        vendor: VariableVendor,
+        data: Data<'a>,
+        attributes: Option<VariableAttributes>,
+        append: bool,
+    },
+    Delete {
+        name: CString16,
+        vendor: VariableVendor,
+    },
+}
+
+fn parse_args<'a, I: Iterator<Item = &'a str>>(
+    mut argv_iter: I,
+) -> Result<Command<'a>, ArgsError<'a>> {
+    let Some(name) = argv_iter.next() else {
+        return Err(ArgsError::Invalid);
+    };
+    let mut opts = Options::new(argv_iter);
+
+    #[derive(Clone, Copy)]
+    enum CommandType {
+        List,
+        Dump,
+        Set,
+        Delete,
+    }
+    const COMMANDS: &[(&str, CommandType)] = &[
+        ("list", CommandType::List),
+        ("dump", CommandType::Dump),
+        ("set", CommandType::Set),
+        ("delete", CommandType::Delete),
+    ];
+
+    let mut command_type = None;
+    let mut var_name = None;
+    let mut vendor = None;
+    let mut format = DumpFormat::Hex;
+    let mut data = None;
+    let mut attributes = None;
+    let mut append = false;
+    while let Some(arg) = opts.next_arg()? {
+        if APP.handle_common(name, &arg) {
+            return Ok(Command::NoOp);
+        }
+        match arg {
+            Arg::Short('g') | Arg::Long("guid") => {
+                let guid = opts.value()?;
+                let Ok(guid) = Guid::try_parse(guid) else {
+                    println!("Invalid GUID: {}", guid);
+                    return Err(ArgsError::Invalid);
+                };
+                vendor = Some(VariableVendor(guid));
+            }
+            Arg::Short('t') | Arg::Long("text") => {
+                format = DumpFormat::Text;
+            }
+            Arg::Short('u') | Arg::Long("ucs2") => {
+                format = DumpFormat::Ucs2;
+            }
+            Arg::Short('f') | Arg::Long("file") => {
+                data = Some(Data::File(opts.value()?));
+            }
+            Arg::Short('x') | Arg::Long("hex") => {
+                let hex = opts.value()?;
+                let Some(bytes) = parse_hex(hex) else {
+                    println!("Invalid hex data: {}", hex);
+                    return Err(ArgsError::Invalid);
+                };
+                data = Some(Data::Hex(bytes));
+            }
+            Arg::Short('a') | Arg::Long("attrs") => {
+                let attrs = opts.value()?;
+                let mut parsed = VariableAttributes::empty();
+                for item in attrs.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+                    let Some(attr) = find_command(ATTRIBUTES, item) else {
+                        println!("Unknown attribute {}", item);
+                        return Err(ArgsError::Invalid);
+                    };
+                    parsed |= attr;
+                }
+                attributes = Some(parsed);
+            }
+            Arg::Long("append") => {
+                append = true;
+            }
+            Arg::Long("log") => {
+                let spec = opts.value()?;
+                if let Err(item) = uefi_logger::configure(spec) {
+                    println!("Unknown log spec item {}", item);
+                    return Err(ArgsError::Invalid);
+                }
+            }
+            Arg::Positional(cmd) if command_type.is_none() => {
+                command_type = Some(find_command(COMMANDS, cmd).ok_or(ArgsError::Unexpected(arg))?);
+            }
+            Arg::Positional(n) if var_name.is_none() => {
+                let Ok(n) = CString16::try_from(n) else {
+                    println!("Invalid variable name: {}", n);
+                    return Err(ArgsError::Invalid);
+                };
+                var_name = Some(n);
+            }
+            _ => return Err(ArgsError::Unexpected(arg)),
+        }
+    }
+
+    let Some(command_type) = command_type else {
+        APP.print_help(name);
+        return Ok(Command::NoOp);
+    };
+    if let CommandType::List = command_type {
+        return Ok(Command::List { vendor });
+    }
+
+    let Some(var_name) = var_name else {
+        println!("Variable NAME not specified");
+        return Err(ArgsError::Invalid);
+    };
+    let vendor = vendor.unwrap_or(VariableVendor::GLOBAL_VARIABLE);
+    Ok(match command_type {
+        CommandType::List => unreachable!(),
+        CommandType::Dump => Command::Dump {
+            name: var_name,
+            vendor,
+            format,
+        },
+        CommandType::Set => {
+            let Some(data) = data else {
+                println!("Either --file or --hex is required to set variable");
+                return Err(ArgsError::Invalid);
+            };
+            Command::Set {
+                name: var_name,
+                vendor,
+                data,
+                attributes,
+                append,
+            }
+        }
+        CommandType::Delete => Command::Delete {
+            name: var_name,
+            vendor,
+        },
+    })
+}
+
+#[entry]
+fn main(_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    uefi::helpers::init(&mut system_table).unwrap();
+    uefi_logger::init();
+    let bt = system_table.boot_services();
+    let rt = system_table.runtime_services();
+
+    if system_table.uefi_revision() < MIN_UEFI_REVISION {
+        log::error!(
+            "system UEFI revision {} smaller than required {}",
+            system_table.uefi_revision(),
+            MIN_UEFI_REVISION
+        );
+        return Status::INCOMPATIBLE_VERSION;
+    }
+
+    let argv = match uefi_argv::args(bt, None) {
+        Err(e) => return e.status(),
+        Ok(v) => v,
+    };
+    let argv = argv.iter().map(|i| i.as_str());
+
+    let res = match parse_args(argv) {
+        Err(e) => {
+            println!("{}", e);
+            return e.status();
+        }
+        Ok(Command::NoOp) => uefi::Result::Ok(()),
+        Ok(Command::List { vendor }) => list_variables(rt, vendor),
+        Ok(Command::Dump {
+            name,
+            vendor,
+            format,
+        }) => dump_variable(rt, &name, &vendor, format),
+        Ok(Command::Set {
+            name,
+            vendor,
+            data,
+            attributes,
+            append,
+        }) => set_variable(bt, rt, &name, &vendor, data, attributes, append),
+        Ok(Command::Delete { name, vendor }) => rt.delete_variable(&name, &vendor).map_err(|e| {
+            println!("Failed to delete variable {}: {}", name, e.status());
+            e
+        }),
+    };
+
+    res.status()
+}
+
+fn attributes_str(attributes: VariableAttributes) -> String {
+    let mut s = String::new();
+    for (name, attr) in ATTRIBUTES {
+        if attributes.contains(*attr) {
+            if !s.is_empty() {
+                s.push(',');
+            }
+            s.push_str(&name.to_ascii_uppercase());
+        }
+    }
+    s
+}
+
+fn list_variables(rt: &RuntimeServices, vendor: Option<VariableVendor>) -> uefi::Result {
+    println!("{:<36} {:<14} {:>8} NAME", "VENDOR", "ATTRIBUTES", "SIZE");
+    for key in rt.variable_keys()? {
+        if vendor.is_some_and(|v| v.0 != key.vendor.0) {
+            continue;
+        }
+        let Ok(name) = key.name() else {
+            log::warn!(
+                "variable name of vendor {} is not valid UCS-2",
+                key.vendor.0
+            );
+            continue;
+        };
+        match rt.get_variable_boxed(name, &key.vendor) {
+            Ok((data, attributes)) => println!(
+                "{} {:<14} {:>8} {}",
+                key.vendor.0,
+                attributes_str(attributes),
+                data.len(),
+                name
+            ),
+            Err(e) => log::warn!("failed to get variable {}: {}", name, e.status()),
+        }
+    }
+    Ok(())
+}
+
+fn dump_variable(
+    rt: &RuntimeServices,
+    name: &CStr16,
+    vendor: &VariableVendor,
+    format: DumpFormat,
+) -> uefi::Result {
+    let (data, attributes) = rt.get_variable_boxed(name, vendor).map_err(|e| {
+        println!("Failed to get variable {}: {}", name, e.status());
+        e
+    })?;
+    println!("Vendor: {}", vendor.0);
+    println!("Attributes: {}", attributes_str(attributes));
+    println!("Size: {}", data.len());
+
+    match format {
+        DumpFormat::Hex => print_hexdump(0, &data),
+        DumpFormat::Text => {
+            println!("{}", String::from_utf8_lossy(&data).trim_end_matches('\0'));
+        }
+        DumpFormat::Ucs2 => {
+            let units = data
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]));
+            let text: String = char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .take_while(|c| *c != '\0')
+                .collect();
+            println!("{}", text);
+        }
+    }
+    Ok(())
+}
+
+fn set_variable(
+    bt: &BootServices,
+    rt: &RuntimeServices,
+    name: &CStr16,
+    vendor: &VariableVendor,
+    data: Data,
+    attributes: Option<VariableAttributes>,
+    append: bool,
+) -> uefi::Result {
+    let data = match data {
+        Data::Hex(bytes) => bytes,
+        Data::File(path) => read_file(bt, path).map_err(|e| {
+            println!("Failed to read {}: {}", path, e.status());
+            e
+        })?,
+    };
+    let mut attributes = match attributes {
+        Some(attributes) => attributes,
+        None => match rt.get_variable_boxed(name, vendor) {
+            Ok((_, attributes)) => attributes,
+            Err(e) if e.status() == Status::NOT_FOUND => {
+                VariableAttributes::NON_VOLATILE
+                    | VariableAttributes::BOOTSERVICE_ACCESS
+                    | VariableAttributes::RUNTIME_ACCESS
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    if append {
+        attributes |= VariableAttributes::APPEND_WRITE;
+    }
+    rt.set_variable(name, vendor, attributes, &data)
+        .map_err(|e| {
+            println!("Failed to set variable {}: {}", name, e.status());
+            e
+        })
+}
+
+/// Read whole content of file at shell path or device path text of `path`
+fn read_file(bt: &BootServices, path: &str) -> uefi::Result<Vec<u8>> {
+    let mut file = open_shell_path(bt, path, FileMode::Read)?
+        .into_regular_file()
+        .ok_or_else(|| {
+            log::error!("{} is not a file", path);
+            uefi::Error::from(Status::INVALID_PARAMETER)
+        })?;
+    let info = file.get_boxed_info::<FileInfo>()?;
+    let Ok(size) = usize::try_from(info.file_size()) else {
+        return Err(Status::BAD_BUFFER_SIZE.into());
+    };
+    let mut data = vec![0; size];
+    let len = file.read(&mut data)?;
+    data.truncate(len);
+    Ok(data)
+}
@@ -0,0 +1,304 @@
+//! Build tasks of uefi-toys, run with `cargo run --manifest-path xtask/Cargo.toml -- dist`
+
+use std::env;
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+const HELP: &str = "\
+Usage: xtask dist [OPTIONS]
+
+  Build all binaries in release mode and lay out a ready-to-boot ESP tree
+  per target, then pack it into a FAT image and a zip archive
+
+  -h, --help            Print this help and exit
+  -t, --target TARGET   Build for TARGET, can be repeated. Defaults to
+                        x86_64-unknown-uefi
+  -o, --out DIR         Write artifacts to DIR, defaults to target/dist
+  -s, --shell FILE      Install UEFI shell FILE as the default boot loader
+                        EFI/BOOT/BOOT<ARCH>.EFI, only with a single target
+";
+
+const DEFAULT_TARGET: &str = "x86_64-unknown-uefi";
+/// Binaries installed in EFI/drivers and loaded by startup.nsh
+const DRIVERS: &[&str] = &["uefi-loopdrv"];
+/// Volume label of FAT image, space padded
+const VOLUME_LABEL: [u8; 11] = *b"UEFI-TOYS  ";
+const MIB: u64 = 1024 * 1024;
+
+type Result<T = ()> = std::result::Result<T, String>;
+
+struct DistOptions {
+    targets: Vec<String>,
+    out: Option<PathBuf>,
+    shell: Option<PathBuf>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.first().map(String::as_str) {
+        Some("dist") => parse_dist(&args[1..]).and_then(|opts| opts.map_or(Ok(()), dist)),
+        Some("-h" | "--help") | None => {
+            print!("{}", HELP);
+            Ok(())
+        }
+        Some(task) => Err(format!("unknown task {}", task)),
+    };
+    if let Err(e) = res {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_dist(args: &[String]) -> Result<Option<DistOptions>> {
+    let mut opts = DistOptions {
+        targets: Vec::new(),
+        out: None,
+        shell: None,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value of {}", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", HELP);
+                return Ok(None);
+            }
+            "-t" | "--target" => opts.targets.push(value()?),
+            "-o" | "--out" => opts.out = Some(value()?.into()),
+            "-s" | "--shell" => opts.shell = Some(value()?.into()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    if opts.targets.is_empty() {
+        opts.targets.push(DEFAULT_TARGET.to_string());
+    }
+    if opts.shell.is_some() && opts.targets.len() > 1 {
+        return Err("--shell is only allowed with a single target".to_string());
+    }
+    Ok(Some(opts))
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// Suffix of default boot loader file name, e.g. "X64" of BOOTX64.EFI
+fn boot_arch(target: &str) -> Result<&'static str> {
+    match target.split('-').next() {
+        Some("x86_64") => Ok("X64"),
+        Some("i686") => Ok("IA32"),
+        Some("aarch64") => Ok("AA64"),
+        _ => Err(format!("unsupported target {}", target)),
+    }
+}
+
+fn dist(opts: DistOptions) -> Result {
+    let root = workspace_root();
+    let out = opts.out.unwrap_or_else(|| root.join("target/dist"));
+    let version = env!("CARGO_PKG_VERSION");
+    for target in &opts.targets {
+        let arch = boot_arch(target)?;
+        build(&root, target)?;
+
+        let esp = out.join(target).join("esp");
+        if esp.exists() {
+            fs::remove_dir_all(&esp).map_err(|e| format!("failed to clean {:?}: {}", esp, e))?;
+        }
+        let binaries = root.join("target").join(target).join("release");
+        layout_esp(&esp, &binaries, opts.shell.as_deref(), arch)?;
+
+        let name = format!("uefi-toys-{}-{}", version, arch.to_ascii_lowercase());
+        let image = out.join(format!("{}.img", name));
+        pack_image(&esp, &image)?;
+        let archive = out.join(format!("{}.zip", name));
+        pack_zip(&esp, &archive)?;
+        println!("Packed {} and {}", image.display(), archive.display());
+    }
+    Ok(())
+}
+
+fn build(root: &Path, target: &str) -> Result {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .current_dir(root)
+        .args(["build", "--workspace", "--release", "--target", target])
+        .status()
+        .map_err(|e| format!("failed to run cargo: {}", e))?;
+    if !status.success() {
+        return Err(format!("failed to build for {}", target));
+    }
+    Ok(())
+}
+
+fn copy(from: &Path, to: &Path) -> Result {
+    fs::create_dir_all(to.parent().unwrap())
+        .and_then(|_| fs::copy(from, to))
+        .map_err(|e| format!("failed to copy {:?} to {:?}: {}", from, to, e))?;
+    Ok(())
+}
+
+/// Copy binaries and example scripts to `esp`, and generate startup.nsh loading drivers
+fn layout_esp(esp: &Path, binaries: &Path, shell: Option<&Path>, arch: &str) -> Result {
+    let entries =
+        fs::read_dir(binaries).map_err(|e| format!("failed to list {:?}: {}", binaries, e))?;
+    let mut efi_files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "efi"))
+        .collect();
+    efi_files.sort();
+
+    let mut tools = Vec::new();
+    let mut drivers = Vec::new();
+    for path in &efi_files {
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let dir = if DRIVERS.iter().any(|d| name == format!("{}.efi", d)) {
+            drivers.push(name.clone());
+            "EFI/drivers"
+        } else {
+            tools.push(name.clone());
+            "EFI/tools"
+        };
+        copy(path, &esp.join(dir).join(&name))?;
+    }
+    if tools.is_empty() {
+        return Err(format!("no EFI binaries found in {:?}", binaries));
+    }
+
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("files/examples");
+    for entry in fs::read_dir(&examples).map_err(|e| format!("{:?}: {}", examples, e))? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let dest = esp
+            .join("EFI/tools/examples")
+            .join(path.file_name().unwrap());
+        copy(&path, &dest)?;
+    }
+
+    if let Some(shell) = shell {
+        copy(shell, &esp.join(format!("EFI/BOOT/BOOT{}.EFI", arch)))?;
+    }
+
+    // locate the ESP by a file on it as startup.nsh runs with no current directory
+    let marker = match drivers.first() {
+        Some(driver) => format!("EFI\\drivers\\{}", driver),
+        None => format!("EFI\\tools\\{}", tools[0]),
+    };
+    let mut script = String::from(
+        "@echo -off\n\
+         # Generated by xtask, loads drivers of uefi-toys and adds its tools to path\n\
+         for %i in 0 1 2 3 4 5 6 7 8 9\n",
+    );
+    script += &format!("  if exist FS%i:\\{} then\n", marker);
+    for driver in &drivers {
+        script += &format!("    load FS%i:\\EFI\\drivers\\{}\n", driver);
+    }
+    script += "    set path \"%path%;FS%i:\\EFI\\tools\"\n    goto done\n  endif\nendfor\n";
+    script += "echo \"uefi-toys not found\"\n:done\n";
+    fs::write(esp.join("startup.nsh"), script).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Files under `dir` relative to it, with directories before their contents
+fn walk(dir: &Path, prefix: &Path, entries: &mut Vec<(PathBuf, bool)>) -> io::Result<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    children.sort_by_key(|e| e.file_name());
+    for child in children {
+        let rel = prefix.join(child.file_name());
+        let is_dir = child.file_type()?.is_dir();
+        entries.push((rel.clone(), is_dir));
+        if is_dir {
+            walk(&child.path(), &rel, entries)?;
+        }
+    }
+    Ok(())
+}
+
+fn esp_entries(esp: &Path) -> Result<Vec<(PathBuf, bool)>> {
+    let mut entries = Vec::new();
+    walk(esp, Path::new(""), &mut entries)
+        .map_err(|e| format!("failed to walk {:?}: {}", esp, e))?;
+    Ok(entries)
+}
+
+/// Path with `/` separators as both FAT and zip take
+fn slash_path(path: &Path) -> String {
+    let parts: Vec<_> = path.iter().map(|p| p.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+/// Create a FAT image of files in `esp`, sized by them with space left
+fn pack_image(esp: &Path, image: &Path) -> Result {
+    let entries = esp_entries(esp)?;
+    let mut total = 0;
+    for (rel, is_dir) in &entries {
+        if !is_dir {
+            total += fs::metadata(esp.join(rel))
+                .map_err(|e| e.to_string())?
+                .len();
+        }
+    }
+    // at least 16MiB for FAT16 that firmwares commonly support
+    let size = ((total + total / 4) / MIB + 1).max(16) * MIB;
+
+    let err = |e: io::Error| format!("failed to create {:?}: {}", image, e);
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(image)
+        .map_err(err)?;
+    file.set_len(size).map_err(err)?;
+    fatfs::format_volume(
+        &mut file,
+        fatfs::FormatVolumeOptions::new().volume_label(VOLUME_LABEL),
+    )
+    .map_err(err)?;
+    file.seek(SeekFrom::Start(0)).map_err(err)?;
+
+    let fs = fatfs::FileSystem::new(&mut file, fatfs::FsOptions::new()).map_err(err)?;
+    let root = fs.root_dir();
+    for (rel, is_dir) in &entries {
+        let path = slash_path(rel);
+        if *is_dir {
+            root.create_dir(&path).map_err(err)?;
+        } else {
+            let data = fs::read(esp.join(rel)).map_err(err)?;
+            root.create_file(&path)
+                .and_then(|mut f| f.write_all(&data))
+                .map_err(err)?;
+        }
+    }
+    drop(root);
+    fs.unmount().map_err(err)?;
+    Ok(())
+}
+
+/// Create a zip of files in `esp` to be extracted to root of an existing ESP
+fn pack_zip(esp: &Path, archive: &Path) -> Result {
+    let err = |e: zip::result::ZipError| format!("failed to create {:?}: {}", archive, e);
+    let file = fs::File::create(archive).map_err(|e| err(e.into()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (rel, is_dir) in esp_entries(esp)? {
+        let path = slash_path(&rel);
+        if is_dir {
+            zip.add_directory(path, options).map_err(err)?;
+        } else {
+            let data = fs::read(esp.join(&rel)).map_err(|e| err(e.into()))?;
+            zip.start_file(path, options).map_err(err)?;
+            zip.write_all(&data).map_err(|e| err(e.into()))?;
+        }
+    }
+    zip.finish().map_err(err)?;
+    Ok(())
+}